@@ -0,0 +1,48 @@
+//! Global allocator shared by the benchmarks, counting the allocations made and tracking the
+//! bytes allocated and their peak
+
+// A tracking allocator needs `unsafe impl GlobalAlloc`
+#![allow(unsafe_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocations and reallocations made so far
+pub static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Bytes allocated now
+pub static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+/// Most bytes allocated at once, since the benchmark last stored the bytes allocated in it
+pub static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator counting the allocations made and tracking the bytes allocated
+struct Tracking;
+
+fn allocated(bytes: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let now = ALLOCATED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        allocated(layout.size());
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        allocated(new_size);
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Tracking = Tracking;
@@ -0,0 +1,38 @@
+//! Generated USFX documents shared by the benchmarks
+
+use std::fmt::Write as _;
+use usfx_to_tsv::versification::Versification;
+
+/// A document with every verse of the given books of the KJV versification, each with a tagged
+/// word, an entity, a footnote and the divine name, so the hot paths of the parser are all
+/// exercised
+pub fn document<'a>(books: impl IntoIterator<Item = &'a str>) -> String {
+    let kjv = Versification::kjv();
+    let mut xml = String::from("<usfx>\n");
+    for book in books {
+        writeln!(xml, r#"<book id="{book}"><h>{book}</h>"#).unwrap();
+        for chapter in 1..=kjv.chapters(book).unwrap_or(0) {
+            writeln!(
+                xml,
+                r#"<c id="{chapter}"/><s>Heading of chapter {chapter}</s><p>"#
+            )
+            .unwrap();
+            for verse in 1..=kjv.verses(book, chapter).unwrap_or(0) {
+                writeln!(
+                    xml,
+                    r#"<v id="{verse}"/>And <w s="H430">God</w> said, Let there be light &amp; there was light<f caller="+"><fr>{chapter}:{verse} </fr><ft>Or day</ft></f>, saith the <nd>Lord</nd>.<ve/>"#
+                )
+                .unwrap();
+            }
+            xml.push_str("</p>\n");
+        }
+        xml.push_str("</book>\n");
+    }
+    xml.push_str("</usfx>\n");
+    xml
+}
+
+/// A whole Bible: every book of the KJV versification
+pub fn whole_bible() -> String {
+    document(Versification::kjv().books())
+}
@@ -0,0 +1,97 @@
+//! Measure the memory used converting a corpus far larger than any Bible
+//!
+//! Run with `cargo bench --bench memory [-- <copies>]`. The corpus is a whole generated Bible
+//! repeated `copies` times (64 by default, about 320 MB) in one document, as if translations
+//! had been concatenated. It is produced as it is read, so the only memory in use is the
+//! parser's, and the peak heap size is reported after converting it to verse, chapter and book
+//! rows.
+
+mod allocator;
+mod fixtures;
+
+use std::io::{BufReader, Read};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use usfx_to_tsv::{Granularity, UsfxConfigBuilder, UsfxParser};
+
+/// The books of a document repeated `copies` times within one `<usfx>` element
+struct Corpus {
+    body: &'static [u8],
+    copies: usize,
+    position: usize,
+    part: usize,
+}
+
+impl Corpus {
+    const START: &'static [u8] = b"<usfx>\n";
+    const END: &'static [u8] = b"</usfx>\n";
+
+    fn new(document: &'static str, copies: usize) -> Self {
+        let body = document
+            .strip_prefix("<usfx>\n")
+            .and_then(|body| body.strip_suffix("</usfx>\n"))
+            .expect("generated document");
+        Self {
+            body: body.as_bytes(),
+            copies,
+            position: 0,
+            part: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        Self::START.len() + self.body.len() * self.copies + Self::END.len()
+    }
+}
+
+impl Read for Corpus {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let part = match self.part {
+                0 => Self::START,
+                part if part <= self.copies => self.body,
+                part if part == self.copies + 1 => Self::END,
+                _ => return Ok(0),
+            };
+            let rest = &part[self.position..];
+            if rest.is_empty() {
+                self.part += 1;
+                self.position = 0;
+                continue;
+            }
+            let read = rest.len().min(buf.len());
+            buf[..read].copy_from_slice(&rest[..read]);
+            self.position += read;
+            return Ok(read);
+        }
+    }
+}
+
+fn main() {
+    let copies = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .map_or(64, |copies| copies.parse().expect("number of copies"));
+    let document: &'static str = fixtures::whole_bible().leak();
+    let baseline = allocator::ALLOCATED.load(Ordering::Relaxed);
+    for granularity in [Granularity::Verse, Granularity::Chapter, Granularity::Book] {
+        let corpus = Corpus::new(document, copies);
+        let megabytes = corpus.len() / 1_000_000;
+        let config = UsfxConfigBuilder::new().granularity(granularity).build();
+        allocator::PEAK.store(
+            allocator::ALLOCATED.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        let start = Instant::now();
+        let mut parser =
+            UsfxParser::from_reader(BufReader::new(corpus), Box::new(std::io::sink()), config);
+        parser.parse().expect("convertible corpus");
+        let elapsed = start.elapsed();
+        let peak = allocator::PEAK.load(Ordering::Relaxed) - baseline;
+        println!(
+            "{granularity:?} rows: {megabytes} MB ({copies} Bibles) in {elapsed:.2?}, peak heap {} KB, {} warnings kept",
+            peak / 1000,
+            parser.warnings().len()
+        );
+    }
+}
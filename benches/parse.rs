@@ -0,0 +1,76 @@
+//! Time converting a whole Bible to TSV
+//!
+//! Run with `cargo bench --bench parse [--features mmap] [-- <usfx_input.xml>]`. Without a
+//! file, a document with every verse of the KJV versification is generated, each with a tagged
+//! word, an entity, a footnote and the divine name, so the hot paths of the parser are all
+//! exercised. The document is converted from memory, from a file (on one thread, with the rows
+//! formatted on a second thread, and on one thread per core) and, with the `mmap` feature, from
+//! a memory-mapped file.
+
+mod allocator;
+mod fixtures;
+
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use usfx_to_tsv::{UsfxConfig, UsfxConfigBuilder, UsfxParser};
+
+/// Number of timed conversions; the fastest is reported
+const RUNS: usize = 10;
+
+/// Time converting a document with parsers made by `parser`, reporting the fastest run and the
+/// allocations made
+fn time(label: &str, megabytes: usize, parser: impl Fn() -> UsfxParser) {
+    let mut fastest = Duration::MAX;
+    let mut allocations = 0;
+    for _ in 0..RUNS {
+        let before = allocator::ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        parser().parse().expect("convertible document");
+        fastest = fastest.min(start.elapsed());
+        allocations = allocator::ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+    println!(
+        "{label}: {megabytes} MB in {fastest:.2?} (fastest of {RUNS}), {allocations} allocations"
+    );
+}
+
+fn main() {
+    let generated =
+        std::env::temp_dir().join(format!("usfx_to_tsv_bench_{}.xml", std::process::id()));
+    let path = if let Some(path) = std::env::args().skip(1).find(|arg| !arg.starts_with("--")) {
+        PathBuf::from(path)
+    } else {
+        std::fs::write(&generated, fixtures::whole_bible()).expect("writable temporary file");
+        generated.clone()
+    };
+    let xml = std::fs::read(&path).expect("readable USFX file");
+    let megabytes = xml.len() / 1_000_000;
+    let output = || -> Box<dyn std::io::Write> { Box::new(std::io::sink()) };
+
+    time("parse (in memory)", megabytes, || {
+        UsfxParser::from_reader(Cursor::new(xml.clone()), output(), UsfxConfig::default())
+    });
+    time("parse (file)", megabytes, || {
+        UsfxParser::new(&path, output(), UsfxConfig::default()).expect("readable USFX file")
+    });
+    time("parse (file, pipelined)", megabytes, || {
+        let config = UsfxConfigBuilder::new().pipeline(true).build();
+        UsfxParser::new(&path, output(), config).expect("readable USFX file")
+    });
+    let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    time(
+        &format!("parse (file, {threads} threads)"),
+        megabytes,
+        || {
+            let config = UsfxConfigBuilder::new().threads(threads).build();
+            UsfxParser::new(&path, output(), config).expect("readable USFX file")
+        },
+    );
+    #[cfg(feature = "mmap")]
+    time("parse (memory-mapped file)", megabytes, || {
+        UsfxParser::mapped(&path, output(), UsfxConfig::default()).expect("readable USFX file")
+    });
+    let _ = std::fs::remove_file(&generated);
+}
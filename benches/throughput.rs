@@ -0,0 +1,115 @@
+//! Throughput of the parser and of each output format, for catching performance regressions
+//!
+//! Run with `cargo bench --bench throughput`. Two generated documents are measured: a small
+//! one (the book of Ruth) and a whole Bible. The parser is reported in MB and in XML events
+//! per second; each format is reported in MB written and in rows per second, from verses
+//! already parsed.
+
+mod fixtures;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::hint::black_box;
+use usfx_to_tsv::{OutputFormat, RowWriter, UsfxConfig, UsfxConfigBuilder, UsfxParser, Verse};
+
+/// A generated document, kept for the whole run
+struct Fixture {
+    name: &'static str,
+    xml: &'static [u8],
+}
+
+fn fixtures() -> Vec<Fixture> {
+    let leak = |xml: String| -> &'static [u8] { Box::leak(xml.into_bytes().into_boxed_slice()) };
+    vec![
+        Fixture {
+            name: "small",
+            xml: leak(fixtures::document(["RUT"])),
+        },
+        Fixture {
+            name: "whole_bible",
+            xml: leak(fixtures::whole_bible()),
+        },
+    ]
+}
+
+/// Number of XML events in a document
+fn events(xml: &[u8]) -> u64 {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut events = 0;
+    loop {
+        match reader.read_event_into(&mut buf).expect("well-formed XML") {
+            Event::Eof => return events,
+            _ => events += 1,
+        }
+        buf.clear();
+    }
+}
+
+fn verses(xml: &'static [u8]) -> Vec<Verse> {
+    parser(xml)
+        .collect::<Result<_, _>>()
+        .expect("convertible document")
+}
+
+fn parser(xml: &'static [u8]) -> UsfxParser {
+    UsfxParser::from_reader(xml, Box::new(std::io::sink()), UsfxConfig::default())
+}
+
+/// Write verses in a format, returning the number of bytes written
+fn write(verses: &[Verse], config: &UsfxConfig) -> usize {
+    let mut writer = RowWriter::new(Vec::new(), config);
+    for verse in verses {
+        writer.write_verse(verse).expect("writable verse");
+    }
+    writer.finish().expect("writable rows");
+    writer.into_inner().len()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for fixture in fixtures() {
+        let throughputs = [
+            ("bytes", Throughput::BytesDecimal(fixture.xml.len() as u64)),
+            ("events", Throughput::Elements(events(fixture.xml))),
+        ];
+        for (unit, throughput) in throughputs {
+            group.throughput(throughput);
+            group.bench_function(BenchmarkId::new(unit, fixture.name), |b| {
+                b.iter(|| parser(black_box(fixture.xml)).count());
+            });
+        }
+    }
+    group.finish();
+}
+
+fn format(c: &mut Criterion) {
+    let mut group = c.benchmark_group("format");
+    group.sample_size(10);
+    for fixture in fixtures() {
+        let verses = verses(fixture.xml);
+        for format in [OutputFormat::Tsv, OutputFormat::Json] {
+            let config = UsfxConfigBuilder::new().format(format).build();
+            let name = format!("{format:?}/{}", fixture.name).to_lowercase();
+            let throughputs = [
+                (
+                    "bytes",
+                    Throughput::BytesDecimal(write(&verses, &config) as u64),
+                ),
+                ("rows", Throughput::Elements(verses.len() as u64)),
+            ];
+            for (unit, throughput) in throughputs {
+                group.throughput(throughput);
+                group.bench_function(BenchmarkId::new(unit, &name), |b| {
+                    b.iter(|| write(black_box(&verses), &config));
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, format);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+fn main() {
+    // Linker arguments for loading the Node.js addon into node
+    #[cfg(feature = "node")]
+    napi_build::setup();
+
+    // Messages and service traits of the gRPC service, compiled without needing protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/usfx.proto");
+        let descriptors = protox::compile(["proto/usfx.proto"], ["proto"])
+            .expect("proto/usfx.proto should compile");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("gRPC code should generate");
+    }
+}
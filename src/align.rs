@@ -0,0 +1,217 @@
+//! Alignment of several translations verse by verse
+
+use crate::output::escape_field;
+use crate::reference::Reference;
+use crate::{ParserError, Verse, canon};
+use std::io::Write;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+/// What happens to a reference that some of the aligned sources lack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unaligned {
+    /// Leave the reference out (default)
+    #[default]
+    Skip,
+    /// Write the row with `None` for each source lacking the verse
+    Empty,
+    /// Stop with an error naming the reference and the source
+    Error,
+}
+
+impl FromStr for Unaligned {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "empty" => Ok(Self::Empty),
+            "error" => Ok(Self::Error),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown unaligned verse handling: {s} (expected skip, empty or error)"
+            ))),
+        }
+    }
+}
+
+/// The verses of the aligned sources at one reference, one per source in the order given
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignedRow {
+    /// Reference shared by the verses
+    pub reference: Reference,
+    /// Verse of each source, or `None` if the source lacks it
+    pub verses: Vec<Option<Verse>>,
+}
+
+/// Iterator over the rows of aligned sources; see [`align_by_reference`]
+pub struct Aligned<I: Iterator> {
+    sources: Vec<Peekable<I>>,
+    unaligned: Unaligned,
+}
+
+/// Zip the verses of several sources, such as parsers of different translations, into rows of
+/// verses sharing a reference
+///
+/// Each source must yield its verses in canonical order (Paratext book order, then chapter
+/// and verse), as documents in the usual Protestant order do. Verse parts such as `1a` and
+/// `1b` are aligned separately. An error from any source is returned in place of the row.
+pub fn align_by_reference<S, I>(sources: S, unaligned: Unaligned) -> Aligned<I::IntoIter>
+where
+    S: IntoIterator<Item = I>,
+    I: IntoIterator<Item = Result<Verse, ParserError>>,
+{
+    Aligned {
+        sources: sources
+            .into_iter()
+            .map(|source| source.into_iter().peekable())
+            .collect(),
+        unaligned,
+    }
+}
+
+/// Write aligned rows as a parallel corpus: one TSV line per row holding the text of each
+/// source in turn (empty where a source lacks the verse), as machine translation training
+/// expects; returns the number of lines written
+pub fn write_parallel<W, R>(rows: R, mut writer: W) -> Result<usize, ParserError>
+where
+    W: Write,
+    R: IntoIterator<Item = Result<AlignedRow, ParserError>>,
+{
+    let mut lines = 0;
+    for row in rows {
+        let texts: Vec<_> = row?
+            .verses
+            .iter()
+            .map(|verse| {
+                escape_field(verse.as_ref().map_or("", |verse| verse.text.as_str())).into_owned()
+            })
+            .collect();
+        writeln!(writer, "{}", texts.join("\t"))?;
+        lines += 1;
+    }
+    writer.flush()?;
+    Ok(lines)
+}
+
+/// Sort key of a verse in canonical order
+fn key(verse: &Verse) -> (usize, u32, u32, Option<String>) {
+    (
+        canon::book_number(&verse.book).unwrap_or(usize::MAX),
+        verse.chapter.parse().unwrap_or(0),
+        verse.verse.parse().unwrap_or(0),
+        verse.subverse.clone(),
+    )
+}
+
+impl<I> Iterator for Aligned<I>
+where
+    I: Iterator<Item = Result<Verse, ParserError>>,
+{
+    type Item = Result<AlignedRow, ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for source in &mut self.sources {
+                if let Some(Err(_)) = source.peek()
+                    && let Some(Err(error)) = source.next()
+                {
+                    return Some(Err(error));
+                }
+            }
+            let first = self
+                .sources
+                .iter_mut()
+                .filter_map(|source| source.peek()?.as_ref().ok().map(key))
+                .min()?;
+            let verses: Vec<Option<Verse>> = self
+                .sources
+                .iter_mut()
+                .map(|source| {
+                    source
+                        .next_if(|next| next.as_ref().is_ok_and(|verse| key(verse) == first))
+                        .and_then(Result::ok)
+                })
+                .collect();
+            let reference = Reference {
+                book: verses.iter().flatten().next()?.book.clone(),
+                chapter: first.1,
+                verse: Some(first.2),
+            };
+            if let Some(source) = verses.iter().position(Option::is_none) {
+                match self.unaligned {
+                    Unaligned::Skip => continue,
+                    Unaligned::Empty => (),
+                    Unaligned::Error => {
+                        return Some(Err(ParserError::ParseError(format!(
+                            "Verse {reference} missing from source {}",
+                            source + 1
+                        ))));
+                    }
+                }
+            }
+            return Some(Ok(AlignedRow { reference, verses }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UsfxConfig, UsfxParser};
+
+    fn parser(xml: &'static str) -> UsfxParser {
+        UsfxParser::from_reader(
+            xml.as_bytes(),
+            Box::new(std::io::sink()),
+            UsfxConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_align_by_reference() {
+        const KJV: &str = r#"<usfx><book id="MRK"><c id="9"/><p><v id="43"/>And if thy hand<ve/><v id="44"/>Where their worm<ve/><v id="45"/>And if thy foot<ve/></p></book></usfx>"#;
+        const WEB: &str = r#"<usfx><book id="MRK"><c id="9"/><p><v id="43"/>If your hand<ve/><v id="45"/>If your foot<ve/></p></book></usfx>"#;
+
+        let texts = |unaligned| {
+            align_by_reference([parser(KJV), parser(WEB)], unaligned)
+                .map(|row| {
+                    let row = row?;
+                    let texts: Vec<String> = row
+                        .verses
+                        .iter()
+                        .map(|verse| verse.as_ref().map_or("-", |v| v.text.as_str()).to_string())
+                        .collect();
+                    Ok(format!("{} {}", row.reference, texts.join("|")))
+                })
+                .collect::<Result<Vec<_>, ParserError>>()
+        };
+        assert_eq!(
+            texts(Unaligned::Skip).unwrap(),
+            [
+                "MRK.9.43 And if thy hand|If your hand",
+                "MRK.9.45 And if thy foot|If your foot"
+            ]
+        );
+        assert_eq!(
+            texts(Unaligned::Empty).unwrap()[1],
+            "MRK.9.44 Where their worm|-"
+        );
+        assert!(texts(Unaligned::Error).is_err());
+    }
+
+    #[test]
+    fn test_write_parallel() {
+        const SOURCE: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>The Jews said<ve/></p></book></usfx>"#;
+        const TARGET: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jésus pleura.<ve/></p></book></usfx>"#;
+
+        let mut tsv = Vec::new();
+        let rows = align_by_reference([parser(SOURCE), parser(TARGET)], Unaligned::Empty);
+        assert_eq!(write_parallel(rows, &mut tsv).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "Jesus wept.\tJésus pleura.\nThe Jews said\t\n"
+        );
+        assert_eq!("error".parse::<Unaligned>().unwrap(), Unaligned::Error);
+        assert!("drop".parse::<Unaligned>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Verses as Arrow record batches, for consumers such as `DataFusion`, `DuckDB` and Arrow Flight
+//! without intermediate files
+
+use crate::{ParserError, UsfxParser, Verse};
+use arrow_array::builder::{StringBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use std::sync::Arc;
+
+fn arrow_error(error: ParserError) -> ArrowError {
+    match error {
+        ParserError::FileError(error) => ArrowError::IoError(error.to_string(), error),
+        ParserError::XmlError(error) => ArrowError::ParseError(error.to_string()),
+        ParserError::ParseError(message) => ArrowError::ParseError(message),
+    }
+}
+
+/// Schema of the batches: `book` and `text` (strings), `chapter`, `verse` and `verse_end`
+/// (`u32`, null when not a number or, for `verse_end`, not a bridge) and `subverse`,
+/// `paragraph` and `section` (strings, null when absent)
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("book", DataType::Utf8, false),
+        Field::new("chapter", DataType::UInt32, true),
+        Field::new("verse", DataType::UInt32, true),
+        Field::new("verse_end", DataType::UInt32, true),
+        Field::new("subverse", DataType::Utf8, true),
+        Field::new("text", DataType::Utf8, false),
+        Field::new("paragraph", DataType::Utf8, true),
+        Field::new("section", DataType::Utf8, true),
+    ]))
+}
+
+/// Columns of a batch, filled a verse at a time
+struct Builders {
+    book: StringBuilder,
+    chapter: UInt32Builder,
+    verse: UInt32Builder,
+    verse_end: UInt32Builder,
+    subverse: StringBuilder,
+    text: StringBuilder,
+    paragraph: StringBuilder,
+    section: StringBuilder,
+    rows: usize,
+}
+
+impl Builders {
+    fn with_capacity(rows: usize) -> Self {
+        let strings = || StringBuilder::with_capacity(rows, 0);
+        Self {
+            book: strings(),
+            chapter: UInt32Builder::with_capacity(rows),
+            verse: UInt32Builder::with_capacity(rows),
+            verse_end: UInt32Builder::with_capacity(rows),
+            subverse: strings(),
+            text: strings(),
+            paragraph: strings(),
+            section: strings(),
+            rows: 0,
+        }
+    }
+
+    fn push(&mut self, verse: &Verse) {
+        self.book.append_value(&verse.book);
+        self.chapter.append_option(verse.chapter.parse().ok());
+        self.verse.append_option(verse.verse.parse().ok());
+        self.verse_end
+            .append_option(verse.verse_end.as_deref().and_then(|end| end.parse().ok()));
+        self.subverse.append_option(verse.subverse.as_deref());
+        self.text.append_value(&verse.text);
+        self.paragraph.append_option(verse.paragraph.as_deref());
+        self.section.append_option(verse.section.as_deref());
+        self.rows += 1;
+    }
+
+    fn finish(mut self, schema: SchemaRef) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.book.finish()),
+            Arc::new(self.chapter.finish()),
+            Arc::new(self.verse.finish()),
+            Arc::new(self.verse_end.finish()),
+            Arc::new(self.subverse.finish()),
+            Arc::new(self.text.finish()),
+            Arc::new(self.paragraph.finish()),
+            Arc::new(self.section.finish()),
+        ];
+        RecordBatch::try_new(schema, columns)
+    }
+}
+
+/// Iterator over the verses of a document in record batches of [`schema`], read from the
+/// input as each batch is asked for; created by [`UsfxParser::record_batches`]
+pub struct RecordBatches {
+    parser: UsfxParser,
+    batch_size: usize,
+    schema: SchemaRef,
+    done: bool,
+}
+
+impl Iterator for RecordBatches {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut builders = Builders::with_capacity(self.batch_size);
+        while builders.rows < self.batch_size {
+            match self.parser.next_verse() {
+                Ok(Some(verse)) => builders.push(&verse),
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(arrow_error(error)));
+                }
+            }
+        }
+        (builders.rows > 0).then(|| builders.finish(self.schema.clone()))
+    }
+}
+
+impl RecordBatchReader for RecordBatches {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl UsfxParser {
+    /// Read the remaining verses as record batches of up to `batch_size` rows (at least one),
+    /// one row per verse whatever the configured granularity; the configured output is not
+    /// written
+    pub fn record_batches(self, batch_size: usize) -> RecordBatches {
+        RecordBatches {
+            parser: self,
+            batch_size: batch_size.max(1),
+            schema: schema(),
+            done: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsfxConfig;
+    use arrow_array::{Array, StringArray, UInt32Array};
+
+    #[test]
+    fn test_record_batches() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/><v id="37-38"/>And some of them said...<ve/></p></book></usfx>"#;
+
+        let input = std::io::Cursor::new(XML.as_bytes().to_vec());
+        let parser =
+            UsfxParser::from_reader(input, Box::new(std::io::sink()), UsfxConfig::default());
+        let batches: Vec<_> = parser.record_batches(2).collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            batches
+                .iter()
+                .map(RecordBatch::num_rows)
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+        assert_eq!(batches[0].schema(), schema());
+        let text = batches[0].column(5).as_any().downcast_ref::<StringArray>();
+        assert_eq!(text.unwrap().value(0), "Jesus wept.");
+        let verses = batches[1].column(2).as_any().downcast_ref::<UInt32Array>();
+        assert_eq!(verses.unwrap().value(0), 37);
+        let ends = batches[1].column(3).as_any().downcast_ref::<UInt32Array>();
+        assert_eq!(ends.unwrap().value(0), 38);
+        assert!(batches[0].column(3).is_null(0));
+    }
+}
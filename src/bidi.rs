@@ -0,0 +1,140 @@
+//! Unicode bidirectional control characters in right-to-left text
+
+use crate::{Bidi, Verse};
+
+/// Left-to-right mark, which keeps a reference such as `3:16` in order inside right-to-left text
+const LRM: char = '\u{200e}';
+
+/// Whether a character is a bidirectional formatting character (marks, embeddings, overrides
+/// and isolates)
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200e}' | '\u{200f}' | '\u{061c}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Whether a character belongs to a right-to-left script such as Hebrew or Arabic
+fn is_rtl(c: char) -> bool {
+    matches!(c, '\u{0590}'..='\u{08ff}' | '\u{fb1d}'..='\u{fdff}' | '\u{fe70}'..='\u{feff}')
+}
+
+/// Apply the bidirectional text mode to every text field of a verse that may hold references
+pub(crate) fn apply(mode: Bidi, verse: &mut Verse) {
+    if mode == Bidi::Preserve {
+        return;
+    }
+    let optional = [
+        &mut verse.section,
+        &mut verse.parallel,
+        &mut verse.major_section,
+        &mut verse.title,
+    ];
+    for text in optional.into_iter().flatten() {
+        apply_text(mode, text);
+    }
+    apply_text(mode, &mut verse.text);
+    for footnote in &mut verse.footnotes {
+        apply_text(mode, &mut footnote.reference);
+        apply_text(mode, &mut footnote.text);
+        apply_text(mode, &mut footnote.content);
+    }
+    for cross_reference in &mut verse.cross_references {
+        apply_text(mode, &mut cross_reference.origin);
+        apply_text(mode, &mut cross_reference.text);
+    }
+}
+
+fn apply_text(mode: Bidi, text: &mut String) {
+    match mode {
+        Bidi::Preserve => (),
+        Bidi::Strip => {
+            if text.contains(is_bidi_control) {
+                text.retain(|c| !is_bidi_control(c));
+            }
+        }
+        Bidi::Mark => {
+            if text.contains(is_rtl) {
+                *text = mark_references(text);
+            }
+        }
+    }
+}
+
+/// Surround each numeric reference (`3:16`, `3.16-18`) with left-to-right marks, unless it
+/// already has them
+fn mark_references(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut marked = String::with_capacity(text.len() + 8);
+    let mut i = 0;
+    while i < chars.len() {
+        let end = reference_end(&chars, i);
+        if end > i {
+            let before = i.checked_sub(1).map(|j| chars[j]);
+            let after = chars.get(end).copied();
+            if before != Some(LRM) {
+                marked.push(LRM);
+            }
+            marked.extend(&chars[i..end]);
+            if after != Some(LRM) {
+                marked.push(LRM);
+            }
+            i = end;
+        } else {
+            marked.push(chars[i]);
+            i += 1;
+        }
+    }
+    marked
+}
+
+/// End of a reference of the form `digits (':' | '.') digits [('-' | '–') digits]` starting
+/// at `start`, or `start` if there is none there
+fn reference_end(chars: &[char], start: usize) -> usize {
+    if start > 0 && chars[start - 1].is_ascii_digit() {
+        return start;
+    }
+    let digits = |from: usize| {
+        chars[from..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    };
+    let chapter = digits(start);
+    let separator = start + chapter;
+    if chapter == 0 || !matches!(chars.get(separator), Some(':' | '.')) {
+        return start;
+    }
+    let verse = digits(separator + 1);
+    if verse == 0 {
+        return start;
+    }
+    let end = separator + 1 + verse;
+    match chars.get(end) {
+        Some('-' | '–') if digits(end + 1) > 0 => end + 1 + digits(end + 1),
+        _ => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_references_in_rtl_text() {
+        let mut text = "ראה 3:16-18 ו 4.2.".to_string();
+        apply_text(Bidi::Mark, &mut text);
+        assert_eq!(text, "ראה \u{200e}3:16-18\u{200e} ו \u{200e}4.2\u{200e}.");
+
+        // Marking twice adds nothing, and left-to-right text is left alone
+        apply_text(Bidi::Mark, &mut text);
+        assert_eq!(text, "ראה \u{200e}3:16-18\u{200e} ו \u{200e}4.2\u{200e}.");
+        let mut english = "See 3:16".to_string();
+        apply_text(Bidi::Mark, &mut english);
+        assert_eq!(english, "See 3:16");
+    }
+
+    #[test]
+    fn test_strip_bidi_controls() {
+        let mut text = "\u{202b}ראה \u{200e}3:16\u{200e}\u{202c}".to_string();
+        apply_text(Bidi::Strip, &mut text);
+        assert_eq!(text, "ראה 3:16");
+    }
+}
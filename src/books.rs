@@ -0,0 +1,161 @@
+//! Names written in the book column in place of USFM book codes
+
+use crate::canon::{self, USFM_BOOKS};
+use crate::{BookAbbreviations, ParserError, UsfxConfig};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Book names keyed by USFM code, e.g. `GEN` to `Genèse`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookNames {
+    names: BTreeMap<String, String>,
+}
+
+impl BookNames {
+    /// Load names from a JSON object (`{"GEN": "Genèse"}`) or, for any other extension, from
+    /// TSV lines of code and name
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ParserError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+        {
+            Self::from_json(&text)
+        } else {
+            Self::from_tsv(&text)
+        }
+    }
+
+    /// Parse names from a JSON object mapping codes to names
+    pub fn from_json(text: &str) -> Result<Self, ParserError> {
+        let names = serde_json::from_str(text)
+            .map_err(|e| ParserError::ParseError(format!("Invalid book names: {e}")))?;
+        Ok(Self { names })
+    }
+
+    /// Parse names from lines of code, tab and name; blank lines and lines starting with `#`
+    /// are skipped
+    pub fn from_tsv(text: &str) -> Result<Self, ParserError> {
+        let mut names = BTreeMap::new();
+        for (number, line) in (1..).zip(text.lines()) {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (code, name) = line.split_once('\t').ok_or_else(|| {
+                ParserError::ParseError(format!(
+                    "Invalid book names line {number}: expected code and name separated by a tab"
+                ))
+            })?;
+            names.insert(code.trim().to_string(), name.trim().to_string());
+        }
+        Ok(Self { names })
+    }
+
+    /// The name for a book code, if there is one
+    pub fn get(&self, code: &str) -> Option<&str> {
+        self.names.get(code).map(String::as_str)
+    }
+
+    /// Set the name for a book code
+    pub fn insert(&mut self, code: impl Into<String>, name: impl Into<String>) {
+        self.names.insert(code.into(), name.into());
+    }
+
+    /// Names of every book that has an abbreviation in a scheme; Paratext's abbreviations are
+    /// the book codes themselves, so that scheme has no names
+    pub fn abbreviations(scheme: BookAbbreviations) -> Self {
+        let mut names = Self::default();
+        if scheme == BookAbbreviations::Paratext {
+            return names;
+        }
+        for code in USFM_BOOKS {
+            let Some(osis) = canon::osis_id(code) else {
+                continue;
+            };
+            let name = match scheme {
+                BookAbbreviations::Sbl => sbl_abbreviation(osis),
+                _ => osis.to_string(),
+            };
+            names.insert(*code, name);
+        }
+        names
+    }
+}
+
+/// The names written in the book column under a configuration: its book names over its
+/// abbreviation scheme, or `None` if book codes are written as they are
+pub(crate) fn labels(config: &UsfxConfig) -> Option<BookNames> {
+    let mut labels = BookNames::abbreviations(config.book_abbreviations);
+    if let Some(names) = &config.book_names {
+        labels.names.extend(names.names.clone());
+    }
+    (!labels.names.is_empty()).then_some(labels)
+}
+
+/// The SBL abbreviation for an OSIS identifier: mostly the same, with a space after the number
+/// of a numbered book
+fn sbl_abbreviation(osis: &str) -> String {
+    match osis {
+        "EsthGr" => "Gk. Esth".to_string(),
+        "DanGr" => "Gk. Dan".to_string(),
+        "EpJer" => "Ep Jer".to_string(),
+        "PrAzar" => "Pr Azar".to_string(),
+        "PrMan" => "Pr Man".to_string(),
+        "Ps151" => "Ps 151".to_string(),
+        _ => match osis.split_at_checked(1) {
+            Some((number, name)) if number.chars().all(|c| c.is_ascii_digit()) => {
+                format!("{number} {name}")
+            }
+            _ => osis.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_names() {
+        let names = BookNames::from_json(r#"{"GEN": "Genèse", "EXO": "Exode"}"#).unwrap();
+        assert_eq!(names.get("GEN"), Some("Genèse"));
+        assert_eq!(names.get("LEV"), None);
+        assert!(BookNames::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let sbl = BookNames::abbreviations(BookAbbreviations::Sbl);
+        assert_eq!(sbl.get("1SA"), Some("1 Sam"));
+        assert_eq!(sbl.get("PHM"), Some("Phlm"));
+        assert_eq!(sbl.get("LJE"), Some("Ep Jer"));
+        let osis = BookNames::abbreviations(BookAbbreviations::Osis);
+        assert_eq!(osis.get("1SA"), Some("1Sam"));
+        assert_eq!(osis.get("FRT"), None);
+        assert_eq!(
+            BookNames::abbreviations(BookAbbreviations::Paratext),
+            BookNames::default()
+        );
+
+        let mut names = BookNames::default();
+        names.insert("GEN", "Genèse");
+        let config = crate::UsfxConfigBuilder::new()
+            .book_abbreviations(BookAbbreviations::Sbl)
+            .book_names(names)
+            .build();
+        let names = labels(&config).unwrap();
+        assert_eq!(names.get("GEN"), Some("Genèse"));
+        assert_eq!(names.get("EXO"), Some("Exod"));
+        assert!(labels(&crate::UsfxConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_tsv_names() {
+        let names = BookNames::from_tsv("# code\tname\nGEN\tGenèse\r\n\n1SA\t1 Samuel\n").unwrap();
+        assert_eq!(names.get("GEN"), Some("Genèse"));
+        assert_eq!(names.get("1SA"), Some("1 Samuel"));
+        assert!(BookNames::from_tsv("GEN Genèse").is_err());
+    }
+}
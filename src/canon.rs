@@ -0,0 +1,443 @@
+//! Canonical book orders, using USFM book codes
+
+use crate::ParserError;
+use crate::reference;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The part of the Bible a book belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Testament {
+    /// The 39 books of the Old Testament common to every canon
+    Old,
+    /// The 27 books of the New Testament
+    New,
+    /// Deuterocanonical and other books outside the Protestant canon, such as Tobit, the
+    /// Greek forms of Esther and Daniel (`ESG`, `DAG`), 1 Maccabees or Psalm 151
+    Deuterocanon,
+    /// Front and back matter, glossaries and other peripheral books (`FRT`, `GLO`, ...)
+    Peripheral,
+}
+
+impl Testament {
+    /// Lowercase name of the testament (e.g. `deuterocanon`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Old => "old",
+            Self::New => "new",
+            Self::Deuterocanon => "deuterocanon",
+            Self::Peripheral => "peripheral",
+        }
+    }
+}
+
+/// A standard USFM book code, e.g. `GEN`
+///
+/// Parsing accepts anything `usfm_code` does, so `"Gen"`, `"1Sam"` and `"Song of Solomon"`
+/// all give a code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BookCode(&'static str);
+
+impl BookCode {
+    /// The code as a string, e.g. `GEN`
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+
+    /// The part of the Bible the book belongs to
+    pub fn testament(self) -> Testament {
+        testament(self.0).unwrap_or(Testament::Peripheral)
+    }
+
+    /// Position of the book within a canon, from 0, or `None` if the canon leaves it out
+    pub fn canon_order(self, canon: Canon) -> Option<usize> {
+        canon.position(self.0)
+    }
+
+    /// Paratext's number for the book, from 1 for `GEN`
+    pub fn number(self) -> usize {
+        book_number(self.0).unwrap_or_default()
+    }
+
+    /// The OSIS identifier of the book, e.g. `Matt` for `MAT`
+    pub fn osis_id(self) -> Option<&'static str> {
+        osis_id(self.0)
+    }
+}
+
+impl FromStr for BookCode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        usfm_code(s)
+            .map(Self)
+            .ok_or_else(|| ParserError::ParseError(format!("Unknown book: {s}")))
+    }
+}
+
+impl fmt::Display for BookCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A canonical arrangement of the books of the Bible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canon {
+    /// 66 books, Old Testament in the order of the English Bible
+    Protestant,
+    /// Protestant canon plus the deuterocanonical books, in the order of the Vulgate
+    Catholic,
+    /// Catholic canon plus the additional Septuagint books, in the order of the Septuagint
+    Orthodox,
+}
+
+const PROTESTANT: &[&str] = &[
+    "GEN", "EXO", "LEV", "NUM", "DEU", "JOS", "JDG", "RUT", "1SA", "2SA", "1KI", "2KI", "1CH",
+    "2CH", "EZR", "NEH", "EST", "JOB", "PSA", "PRO", "ECC", "SNG", "ISA", "JER", "LAM", "EZK",
+    "DAN", "HOS", "JOL", "AMO", "OBA", "JON", "MIC", "NAM", "HAB", "ZEP", "HAG", "ZEC", "MAL",
+    "MAT", "MRK", "LUK", "JHN", "ACT", "ROM", "1CO", "2CO", "GAL", "EPH", "PHP", "COL", "1TH",
+    "2TH", "1TI", "2TI", "TIT", "PHM", "HEB", "JAS", "1PE", "2PE", "1JN", "2JN", "3JN", "JUD",
+    "REV",
+];
+
+const CATHOLIC: &[&str] = &[
+    "GEN", "EXO", "LEV", "NUM", "DEU", "JOS", "JDG", "RUT", "1SA", "2SA", "1KI", "2KI", "1CH",
+    "2CH", "EZR", "NEH", "TOB", "JDT", "EST", "ESG", "1MA", "2MA", "JOB", "PSA", "PRO", "ECC",
+    "SNG", "WIS", "SIR", "ISA", "JER", "LAM", "BAR", "LJE", "EZK", "DAN", "DAG", "S3Y", "SUS",
+    "BEL", "HOS", "JOL", "AMO", "OBA", "JON", "MIC", "NAM", "HAB", "ZEP", "HAG", "ZEC", "MAL",
+    "MAT", "MRK", "LUK", "JHN", "ACT", "ROM", "1CO", "2CO", "GAL", "EPH", "PHP", "COL", "1TH",
+    "2TH", "1TI", "2TI", "TIT", "PHM", "HEB", "JAS", "1PE", "2PE", "1JN", "2JN", "3JN", "JUD",
+    "REV",
+];
+
+const ORTHODOX: &[&str] = &[
+    "GEN", "EXO", "LEV", "NUM", "DEU", "JOS", "JDG", "RUT", "1SA", "2SA", "1KI", "2KI", "1CH",
+    "2CH", "MAN", "1ES", "EZR", "NEH", "TOB", "JDT", "EST", "ESG", "1MA", "2MA", "3MA", "PSA",
+    "PS2", "JOB", "PRO", "ECC", "SNG", "WIS", "SIR", "HOS", "AMO", "MIC", "JOL", "OBA", "JON",
+    "NAM", "HAB", "ZEP", "HAG", "ZEC", "MAL", "ISA", "JER", "BAR", "LAM", "LJE", "EZK", "DAN",
+    "DAG", "S3Y", "SUS", "BEL", "4MA", "MAT", "MRK", "LUK", "JHN", "ACT", "ROM", "1CO", "2CO",
+    "GAL", "EPH", "PHP", "COL", "1TH", "2TH", "1TI", "2TI", "TIT", "PHM", "HEB", "JAS", "1PE",
+    "2PE", "1JN", "2JN", "3JN", "JUD", "REV",
+];
+
+/// Every USFM book code, in the order of Paratext's book numbering, so that a book's number
+/// is its position from 1: the Old and New Testaments (1-66), the deuterocanon and other books
+/// of various traditions, the extra books `XXA` to `XXG` (93-99), the front and back matter
+/// (100-102), the retired `3ES` (103), more books (104-106), the peripheral books (107-111)
+/// and the books numbered since (112-123)
+pub const USFM_BOOKS: &[&str] = &[
+    "GEN", "EXO", "LEV", "NUM", "DEU", "JOS", "JDG", "RUT", "1SA", "2SA", "1KI", "2KI", "1CH",
+    "2CH", "EZR", "NEH", "EST", "JOB", "PSA", "PRO", "ECC", "SNG", "ISA", "JER", "LAM", "EZK",
+    "DAN", "HOS", "JOL", "AMO", "OBA", "JON", "MIC", "NAM", "HAB", "ZEP", "HAG", "ZEC", "MAL",
+    "MAT", "MRK", "LUK", "JHN", "ACT", "ROM", "1CO", "2CO", "GAL", "EPH", "PHP", "COL", "1TH",
+    "2TH", "1TI", "2TI", "TIT", "PHM", "HEB", "JAS", "1PE", "2PE", "1JN", "2JN", "3JN", "JUD",
+    "REV", "TOB", "JDT", "ESG", "WIS", "SIR", "BAR", "LJE", "S3Y", "SUS", "BEL", "1MA", "2MA",
+    "3MA", "4MA", "1ES", "2ES", "MAN", "PS2", "ODA", "PSS", "JSA", "JDB", "TBS", "SST", "DNT",
+    "BLT", "XXA", "XXB", "XXC", "XXD", "XXE", "XXF", "XXG", "FRT", "BAK", "OTH", "3ES", "EZA",
+    "5EZ", "6EZ", "INT", "CNC", "GLO", "TDX", "NDX", "DAG", "PS3", "2BA", "LBA", "JUB", "ENO",
+    "1MQ", "2MQ", "3MQ", "REP", "4BA", "LAO",
+];
+
+/// OSIS book identifiers of the USFM books that have one
+const OSIS: &[(&str, &str)] = &[
+    ("GEN", "Gen"),
+    ("EXO", "Exod"),
+    ("LEV", "Lev"),
+    ("NUM", "Num"),
+    ("DEU", "Deut"),
+    ("JOS", "Josh"),
+    ("JDG", "Judg"),
+    ("RUT", "Ruth"),
+    ("1SA", "1Sam"),
+    ("2SA", "2Sam"),
+    ("1KI", "1Kgs"),
+    ("2KI", "2Kgs"),
+    ("1CH", "1Chr"),
+    ("2CH", "2Chr"),
+    ("EZR", "Ezra"),
+    ("NEH", "Neh"),
+    ("EST", "Esth"),
+    ("JOB", "Job"),
+    ("PSA", "Ps"),
+    ("PRO", "Prov"),
+    ("ECC", "Eccl"),
+    ("SNG", "Song"),
+    ("ISA", "Isa"),
+    ("JER", "Jer"),
+    ("LAM", "Lam"),
+    ("EZK", "Ezek"),
+    ("DAN", "Dan"),
+    ("HOS", "Hos"),
+    ("JOL", "Joel"),
+    ("AMO", "Amos"),
+    ("OBA", "Obad"),
+    ("JON", "Jonah"),
+    ("MIC", "Mic"),
+    ("NAM", "Nah"),
+    ("HAB", "Hab"),
+    ("ZEP", "Zeph"),
+    ("HAG", "Hag"),
+    ("ZEC", "Zech"),
+    ("MAL", "Mal"),
+    ("MAT", "Matt"),
+    ("MRK", "Mark"),
+    ("LUK", "Luke"),
+    ("JHN", "John"),
+    ("ACT", "Acts"),
+    ("ROM", "Rom"),
+    ("1CO", "1Cor"),
+    ("2CO", "2Cor"),
+    ("GAL", "Gal"),
+    ("EPH", "Eph"),
+    ("PHP", "Phil"),
+    ("COL", "Col"),
+    ("1TH", "1Thess"),
+    ("2TH", "2Thess"),
+    ("1TI", "1Tim"),
+    ("2TI", "2Tim"),
+    ("TIT", "Titus"),
+    ("PHM", "Phlm"),
+    ("HEB", "Heb"),
+    ("JAS", "Jas"),
+    ("1PE", "1Pet"),
+    ("2PE", "2Pet"),
+    ("1JN", "1John"),
+    ("2JN", "2John"),
+    ("3JN", "3John"),
+    ("JUD", "Jude"),
+    ("REV", "Rev"),
+    ("TOB", "Tob"),
+    ("JDT", "Jdt"),
+    ("ESG", "EsthGr"),
+    ("WIS", "Wis"),
+    ("SIR", "Sir"),
+    ("BAR", "Bar"),
+    ("LJE", "EpJer"),
+    ("S3Y", "PrAzar"),
+    ("SUS", "Sus"),
+    ("BEL", "Bel"),
+    ("1MA", "1Macc"),
+    ("2MA", "2Macc"),
+    ("3MA", "3Macc"),
+    ("4MA", "4Macc"),
+    ("1ES", "1Esd"),
+    ("2ES", "2Esd"),
+    ("MAN", "PrMan"),
+    ("PS2", "Ps151"),
+    ("DAG", "DanGr"),
+];
+
+/// The OSIS identifier of a USFM book code, e.g. `Matt` for `MAT`
+pub fn osis_id(code: &str) -> Option<&'static str> {
+    OSIS.iter()
+        .find(|(usfm, _)| *usfm == code)
+        .map(|(_, osis)| *osis)
+}
+
+/// Paratext's number for a USFM book code, from 1 for `GEN` through 66 for `REV` and on into
+/// the deuterocanon and peripheral books
+pub fn book_number(code: &str) -> Option<usize> {
+    USFM_BOOKS
+        .iter()
+        .position(|book| *book == code)
+        .map(|i| i + 1)
+}
+
+/// Books of a single chapter, whose sources may leave the chapter out of verse references
+const SINGLE_CHAPTER_BOOKS: &[&str] = &["OBA", "PHM", "2JN", "3JN", "JUD", "MAN", "PS2"];
+
+/// Whether a USFM book code names a book of a single chapter, such as Obadiah or Jude
+pub fn is_single_chapter(code: &str) -> bool {
+    SINGLE_CHAPTER_BOOKS.contains(&code)
+}
+
+/// The part of the Bible a USFM book code belongs to, or `None` if it is not a USFM code
+pub fn testament(code: &str) -> Option<Testament> {
+    // The extra books, front and back matter and peripheral books sit among the others
+    Some(match book_number(code)? {
+        1..=39 => Testament::Old,
+        40..=66 => Testament::New,
+        93..=102 | 107..=111 => Testament::Peripheral,
+        _ => Testament::Deuterocanon,
+    })
+}
+
+/// Load a custom book order from a file; see [`parse_order`]
+pub fn load_order<P: AsRef<Path>>(path: P) -> Result<Vec<String>, ParserError> {
+    parse_order(&std::fs::read_to_string(path)?)
+}
+
+/// Parse a custom book order: book identifiers separated by whitespace or commas, in any form
+/// `usfm_code` accepts, with `#` starting a comment that runs to the end of the line
+pub fn parse_order(text: &str) -> Result<Vec<String>, ParserError> {
+    let mut order: Vec<String> = Vec::new();
+    let tokens = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split([',', ' ', '\t']))
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        let code = usfm_code(token).ok_or_else(|| {
+            ParserError::ParseError(format!("Unknown book in book order: {token}"))
+        })?;
+        if order.iter().any(|book| book == code) {
+            return Err(ParserError::ParseError(format!(
+                "Book listed twice in book order: {token}"
+            )));
+        }
+        order.push(code.to_string());
+    }
+    Ok(order)
+}
+
+/// The standard USFM code for a book identifier found in `<book id>` or a `bcv` attribute,
+/// accepting USFM codes in any case, OSIS identifiers (`Gen`, `1Sam`, `Matt`), older
+/// three-letter codes (`EZE`, `JOE`, `NAH`) and common English abbreviations
+pub fn usfm_code(id: &str) -> Option<&'static str> {
+    USFM_BOOKS
+        .iter()
+        .find(|code| code.eq_ignore_ascii_case(id))
+        .copied()
+        .or_else(|| reference::book_code(id))
+}
+
+impl Canon {
+    /// The book codes of this canon, in canonical order
+    pub fn books(self) -> &'static [&'static str] {
+        match self {
+            Self::Protestant => PROTESTANT,
+            Self::Catholic => CATHOLIC,
+            Self::Orthodox => ORTHODOX,
+        }
+    }
+
+    /// Position of a book within this canon, or `None` if the book is not part of it
+    pub fn position(self, book: &str) -> Option<usize> {
+        self.books().iter().position(|code| *code == book)
+    }
+}
+
+impl FromStr for Canon {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "protestant" => Ok(Self::Protestant),
+            "catholic" => Ok(Self::Catholic),
+            "orthodox" => Ok(Self::Orthodox),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown canon: {s} (expected protestant, catholic or orthodox)"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canon_sizes() {
+        assert_eq!(Canon::Protestant.books().len(), 66);
+        assert_eq!(Canon::Protestant.position("REV"), Some(65));
+        assert_eq!(Canon::Protestant.position("TOB"), None);
+    }
+
+    #[test]
+    fn test_canon_orders_differ() {
+        assert!(Canon::Catholic.position("TOB") < Canon::Catholic.position("JOB"));
+        assert!(Canon::Orthodox.position("PSA") < Canon::Orthodox.position("JOB"));
+        assert!(Canon::Orthodox.position("MAL") < Canon::Orthodox.position("ISA"));
+    }
+
+    #[test]
+    fn test_usfm_codes() {
+        assert_eq!(usfm_code("GEN"), Some("GEN"));
+        assert_eq!(usfm_code("gen"), Some("GEN"));
+        assert_eq!(usfm_code("1Sam"), Some("1SA"));
+        assert_eq!(usfm_code("Matt"), Some("MAT"));
+        assert_eq!(usfm_code("EZE"), Some("EZK"));
+        assert_eq!(usfm_code("PrAzar"), Some("S3Y"));
+        assert_eq!(usfm_code("DanGr"), Some("DAG"));
+        assert_eq!(usfm_code("frt"), Some("FRT"));
+        assert_eq!(usfm_code("XYZ"), None);
+    }
+
+    #[test]
+    fn test_deuterocanon() {
+        for book in [
+            "TOB", "JDT", "ESG", "DAG", "S3Y", "1MA", "4MA", "PS2", "MAN",
+        ] {
+            assert_eq!(testament(book), Some(Testament::Deuterocanon), "{book}");
+            assert!(osis_id(book).is_some(), "{book}");
+            assert!(Canon::Orthodox.position(book).is_some(), "{book}");
+        }
+        for book in [
+            "TOB", "JDT", "ESG", "WIS", "SIR", "BAR", "1MA", "2MA", "DAG", "SUS",
+        ] {
+            assert!(Canon::Catholic.position(book).is_some(), "{book}");
+        }
+        assert_eq!(testament("MAL"), Some(Testament::Old));
+        assert_eq!(testament("MAT"), Some(Testament::New));
+        assert_eq!(testament("FRT"), Some(Testament::Peripheral));
+        assert_eq!(testament("XXG"), Some(Testament::Peripheral));
+        assert_eq!(testament("NDX"), Some(Testament::Peripheral));
+        assert_eq!(testament("XYZ"), None);
+        assert_eq!(usfm_code("Additions to Esther"), Some("ESG"));
+        assert_eq!(usfm_code("Song of the Three Young Men"), Some("S3Y"));
+    }
+
+    #[test]
+    fn test_parse_order() {
+        let order =
+            parse_order("# Hebrews after the Gospels\nMAT, MRK, LUK, JHN\nHeb\tACT ROM").unwrap();
+        assert_eq!(order, ["MAT", "MRK", "LUK", "JHN", "HEB", "ACT", "ROM"]);
+        assert!(parse_order("MAT Hezekiah").is_err());
+        assert!(parse_order("MAT Matt").is_err());
+    }
+
+    #[test]
+    fn test_book_code() {
+        let tobit: BookCode = "Tobit".parse().unwrap();
+        assert_eq!(tobit.as_str(), "TOB");
+        assert_eq!(tobit.testament(), Testament::Deuterocanon);
+        assert_eq!(tobit.canon_order(Canon::Protestant), None);
+        assert_eq!(tobit.canon_order(Canon::Catholic), Some(16));
+        let hebrews: BookCode = "heb".parse().unwrap();
+        assert_eq!(hebrews.to_string(), "HEB");
+        assert_eq!(hebrews.testament(), Testament::New);
+        assert_eq!(hebrews.number(), 58);
+        assert_eq!(hebrews.osis_id(), Some("Heb"));
+        assert!("Hezekiah".parse::<BookCode>().is_err());
+    }
+
+    #[test]
+    fn test_osis_ids_round_trip() {
+        for (code, osis) in OSIS {
+            assert_eq!(usfm_code(osis), Some(*code), "{osis}");
+        }
+        assert_eq!(osis_id("FRT"), None);
+        assert_eq!(book_number("REV"), Some(66));
+        assert_eq!(book_number("XXA"), Some(93));
+        assert_eq!(book_number("FRT"), Some(100));
+        assert_eq!(book_number("NDX"), Some(111));
+        assert_eq!(book_number("DAG"), Some(112));
+        assert_eq!(book_number("LAO"), Some(123));
+    }
+
+    #[test]
+    fn test_books_are_unique() {
+        for canon in [Canon::Protestant, Canon::Catholic, Canon::Orthodox] {
+            let books = canon.books();
+            for (i, book) in books.iter().enumerate() {
+                assert_eq!(
+                    canon.position(book),
+                    Some(i),
+                    "{book} repeated in {canon:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Detection and decompression of compressed documents and zip archives
+
+use std::io::{self, BufRead, Read};
+
+/// Compression format of a document, told by its magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    /// A zip archive holding the document among other files, as eBible's `*_usfx.zip` bundles do
+    Zip,
+}
+
+impl Compression {
+    /// The compression of a document starting with `head`, if any
+    fn detect(head: &[u8]) -> Option<Self> {
+        match head {
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Self::Zstd),
+            [0xfd, b'7', b'z', b'X', b'Z', 0, ..] => Some(Self::Xz),
+            [b'P', b'K', 3, 4, ..] => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+/// Wrap `input` so that it yields the uncompressed document if it is compressed with gzip, zstd
+/// or xz, or the USFX document in it if it is a zip archive
+///
+/// Without the `compression` feature a compressed document fails to read with an error saying
+/// so, rather than being parsed as garbled XML.
+pub(crate) fn decompressed<R: BufRead + 'static>(mut input: R) -> Box<dyn BufRead> {
+    match input.fill_buf().ok().and_then(Compression::detect) {
+        Some(compression) => decompress(input, compression),
+        None => Box::new(input),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress<R: BufRead + 'static>(input: R, compression: Compression) -> Box<dyn BufRead> {
+    use std::io::BufReader;
+
+    match compression {
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(input))),
+        Compression::Zstd => match ruzstd::decoding::StreamingDecoder::new(input) {
+            Ok(decoder) => Box::new(BufReader::new(decoder)),
+            Err(error) => Box::new(Unreadable(format!(
+                "Invalid {} input: {error}",
+                compression.name()
+            ))),
+        },
+        Compression::Xz => Box::new(BufReader::new(lzma_rust2::XzReader::new(input, true))),
+        Compression::Zip => match unzipped(input) {
+            Ok(document) => Box::new(io::Cursor::new(document)),
+            Err(error) => Box::new(Unreadable(format!("Invalid zip input: {error}"))),
+        },
+    }
+}
+
+/// The USFX document in a zip archive, read into memory along with the archive
+///
+/// Members named like eBible's `engkjv_usfx.xml` are tried first, then any other XML file, so
+/// metadata and stylesheets bundled alongside are passed over.
+#[cfg(feature = "compression")]
+fn unzipped(mut input: impl Read) -> io::Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    input.read_to_end(&mut archive)?;
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(archive)).map_err(io::Error::other)?;
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+    names.sort_by_key(|name| !name.to_ascii_lowercase().ends_with("usfx.xml"));
+    for name in names {
+        let mut document = Vec::new();
+        archive
+            .by_name(&name)
+            .map_err(io::Error::other)?
+            .read_to_end(&mut document)?;
+        // The root element comes within the first few lines, after any declaration or comments
+        let head = &document[..document.len().min(4096)];
+        if head.windows(5).any(|window| window == b"<usfx") {
+            return Ok(document);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "no USFX document in the archive",
+    ))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress<R: BufRead + 'static>(_input: R, compression: Compression) -> Box<dyn BufRead> {
+    Box::new(Unreadable(format!(
+        "{} compressed input requires building with the compression feature",
+        compression.name()
+    )))
+}
+
+/// Input that fails to read with an error message
+struct Unreadable(String);
+
+impl Read for Unreadable {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::InvalidData, self.0.clone()))
+    }
+}
+
+impl BufRead for Unreadable {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Err(io::Error::new(io::ErrorKind::InvalidData, self.0.clone()))
+    }
+
+    fn consume(&mut self, _amount: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str =
+        r#"<usfx><book id="JUD"><c id="1"/><p><v id="1"/>Jude, a servant<ve/></p></book></usfx>"#;
+
+    fn read(bytes: Vec<u8>) -> io::Result<String> {
+        let mut text = String::new();
+        decompressed(io::Cursor::new(bytes)).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(
+            Compression::detect(b"\xfd7zXZ\0\0\x01"),
+            Some(Compression::Xz)
+        );
+        assert_eq!(Compression::detect(DOCUMENT.as_bytes()), None);
+        assert_eq!(read(DOCUMENT.into()).unwrap(), DOCUMENT);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompressed() {
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(DOCUMENT.as_bytes()).unwrap();
+        assert_eq!(read(gzip.finish().unwrap()).unwrap(), DOCUMENT);
+        let zstd = ruzstd::encoding::compress_to_vec(
+            DOCUMENT.as_bytes(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        assert_eq!(read(zstd).unwrap(), DOCUMENT);
+        assert!(read(b"\x28\xb5\x2f\xfd garbage".to_vec()).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_zip_archive() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let archive = |members: &[(&str, &str)]| {
+            let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+            for (name, content) in members {
+                zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(content.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap().into_inner()
+        };
+        let bundle = archive(&[
+            ("engkjvmetadata.xml", "<DBLMetadata/>"),
+            ("engkjv.css", "p { }"),
+            ("engkjv_usfx.xml", DOCUMENT),
+        ]);
+        assert_eq!(Compression::detect(&bundle), Some(Compression::Zip));
+        assert_eq!(read(bundle).unwrap(), DOCUMENT);
+        assert_eq!(read(archive(&[("bible.xml", DOCUMENT)])).unwrap(), DOCUMENT);
+        let error = read(archive(&[("engkjvmetadata.xml", "<DBLMetadata/>")])).unwrap_err();
+        assert!(error.to_string().contains("no USFX document"));
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn test_compression_feature_required() {
+        let error = read(b"\x1f\x8b\x08\0".to_vec()).unwrap_err();
+        assert!(error.to_string().contains("compression feature"));
+    }
+}
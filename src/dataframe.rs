@@ -0,0 +1,96 @@
+//! Parsing into polars `DataFrame`s, for analytics in the same process without writing and
+//! reading back TSV
+
+use crate::{ParserError, UsfxParser, Verse};
+use polars::prelude::{Column, DataFrame, PolarsError};
+
+fn polars_error(error: PolarsError) -> ParserError {
+    ParserError::ParseError(format!("DataFrame error: {error}"))
+}
+
+/// Columns of the `DataFrame`, filled a verse at a time
+#[derive(Default)]
+struct Columns {
+    book: Vec<String>,
+    chapter: Vec<Option<u32>>,
+    verse: Vec<Option<u32>>,
+    verse_end: Vec<Option<u32>>,
+    subverse: Vec<Option<String>>,
+    text: Vec<String>,
+    paragraph: Vec<Option<String>>,
+    section: Vec<Option<String>>,
+}
+
+impl Columns {
+    fn push(&mut self, verse: Verse) {
+        self.book.push(verse.book);
+        self.chapter.push(verse.chapter.parse().ok());
+        self.verse.push(verse.verse.parse().ok());
+        self.verse_end
+            .push(verse.verse_end.and_then(|end| end.parse().ok()));
+        self.subverse.push(verse.subverse);
+        self.text.push(verse.text);
+        self.paragraph.push(verse.paragraph);
+        self.section.push(verse.section);
+    }
+
+    fn into_dataframe(self) -> Result<DataFrame, ParserError> {
+        let height = self.book.len();
+        DataFrame::new(
+            height,
+            vec![
+                Column::new("book".into(), self.book),
+                Column::new("chapter".into(), self.chapter),
+                Column::new("verse".into(), self.verse),
+                Column::new("verse_end".into(), self.verse_end),
+                Column::new("subverse".into(), self.subverse),
+                Column::new("text".into(), self.text),
+                Column::new("paragraph".into(), self.paragraph),
+                Column::new("section".into(), self.section),
+            ],
+        )
+        .map_err(polars_error)
+    }
+}
+
+impl UsfxParser {
+    /// Read the remaining verses into a `DataFrame` with one row per verse, whatever the
+    /// configured granularity
+    ///
+    /// The columns are `book`, `text` (strings), `chapter`, `verse`, `verse_end` (`u32`, null
+    /// when not a number or, for `verse_end`, not a bridge) and `subverse`, `paragraph` and
+    /// `section` (strings, null when absent). The configured output is not written.
+    pub fn to_dataframe(mut self) -> Result<DataFrame, ParserError> {
+        let mut columns = Columns::default();
+        while let Some(verse) = self.next_verse()? {
+            columns.push(verse);
+        }
+        columns.into_dataframe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{UsfxConfig, UsfxParser};
+    use polars::prelude::DataType;
+
+    #[test]
+    fn test_to_dataframe() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><s>Lazarus Raised</s><p><v id="35"/>Jesus wept.<ve/><v id="36-37"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        let input = std::io::Cursor::new(XML.as_bytes().to_vec());
+        let parser =
+            UsfxParser::from_reader(input, Box::new(std::io::sink()), UsfxConfig::default());
+        let frame = parser.to_dataframe().unwrap();
+        assert_eq!(frame.height(), 2);
+        assert_eq!(frame.column("chapter").unwrap().dtype(), &DataType::UInt32);
+        let verses = frame.column("verse").unwrap().u32().unwrap();
+        assert_eq!(verses.get(1), Some(36));
+        let ends = frame.column("verse_end").unwrap().u32().unwrap();
+        assert_eq!((ends.get(0), ends.get(1)), (None, Some(37)));
+        let text = frame.column("text").unwrap().str().unwrap();
+        assert_eq!(text.get(0), Some("Jesus wept."));
+        let section = frame.column("section").unwrap().str().unwrap();
+        assert_eq!(section.get(0), Some("Lazarus Raised"));
+    }
+}
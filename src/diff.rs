@@ -0,0 +1,179 @@
+//! Word-level comparison of two revisions of a translation
+
+use crate::align::AlignedRow;
+use crate::reference::Reference;
+use std::fmt;
+
+/// One step of the edit turning the words of an old text into those of a new one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordEdit {
+    /// Words found in both texts
+    Same(String),
+    /// Words of the old text only
+    Removed(String),
+    /// Words of the new text only
+    Added(String),
+}
+
+/// How a verse differs between two revisions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The verse is only in the new revision
+    Added,
+    /// The verse is only in the old revision
+    Removed,
+    /// The verse's text differs
+    Changed,
+}
+
+impl ChangeKind {
+    /// Name of the kind, as written in a change report
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+/// The change to one verse between two revisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerseChange {
+    /// Reference of the verse
+    pub reference: Reference,
+    /// How the verse differs
+    pub kind: ChangeKind,
+    /// The edit turning the old text into the new one
+    pub edits: Vec<WordEdit>,
+}
+
+impl VerseChange {
+    /// The change between the old and new verse of an aligned row (old first), or `None` if
+    /// the texts are the same
+    pub fn from_row(row: &AlignedRow) -> Option<Self> {
+        let text = |index: usize| {
+            row.verses
+                .get(index)
+                .and_then(Option::as_ref)
+                .map(|verse| verse.text.as_str())
+        };
+        let (kind, edits) = match (text(0), text(1)) {
+            (Some(old), Some(new)) if old == new => return None,
+            (Some(old), Some(new)) => (ChangeKind::Changed, diff_words(old, new)),
+            (Some(old), None) => (
+                ChangeKind::Removed,
+                vec![WordEdit::Removed(old.to_string())],
+            ),
+            (None, Some(new)) => (ChangeKind::Added, vec![WordEdit::Added(new.to_string())]),
+            (None, None) => return None,
+        };
+        Some(Self {
+            reference: row.reference.clone(),
+            kind,
+            edits,
+        })
+    }
+}
+
+impl fmt::Display for VerseChange {
+    /// One line of a change report: the reference in `bcv` style, the kind of change and the
+    /// text with removed words as `[-old-]` and added words as `{+new+}`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t", self.reference, self.kind.as_str())?;
+        for (i, edit) in self.edits.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match edit {
+                WordEdit::Same(words) => write!(f, "{words}")?,
+                WordEdit::Removed(words) => write!(f, "[-{words}-]")?,
+                WordEdit::Added(words) => write!(f, "{{+{words}+}}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The shortest edit turning the words of `old` into those of `new`, with consecutive words
+/// of the same kind joined by spaces
+pub fn diff_words(old: &str, new: &str) -> Vec<WordEdit> {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+    // Length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits: Vec<WordEdit> = Vec::new();
+    let mut push = |edit: WordEdit| match (edits.last_mut(), edit) {
+        (Some(WordEdit::Same(words)), WordEdit::Same(word))
+        | (Some(WordEdit::Removed(words)), WordEdit::Removed(word))
+        | (Some(WordEdit::Added(words)), WordEdit::Added(word)) => {
+            words.push(' ');
+            words.push_str(&word);
+        }
+        (_, edit) => edits.push(edit),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push(WordEdit::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            push(WordEdit::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            push(WordEdit::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Verse;
+
+    #[test]
+    fn test_verse_changes() {
+        let verse = |text: &str| Verse {
+            book: "JHN".to_string(),
+            chapter: "3".to_string(),
+            verse: "16".to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let reference = Reference {
+            book: "JHN".to_string(),
+            chapter: 3,
+            verse: Some(16),
+        };
+        let row = |old: Option<&str>, new: Option<&str>| AlignedRow {
+            reference: reference.clone(),
+            verses: vec![old.map(verse), new.map(verse)],
+        };
+
+        let change = VerseChange::from_row(&row(
+            Some("For God so loved the world, that he gave"),
+            Some("For God so loved the world that he gave his only Son"),
+        ))
+        .unwrap();
+        assert_eq!(
+            change.to_string(),
+            "JHN.3.16\tchanged\tFor God so loved the [-world,-] {+world+} that he gave {+his only Son+}"
+        );
+        let change = VerseChange::from_row(&row(None, Some("For God"))).unwrap();
+        assert_eq!(change.to_string(), "JHN.3.16\tadded\t{+For God+}");
+        assert!(VerseChange::from_row(&row(Some("For God"), Some("For God"))).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+//! Loading verses into `DuckDB` database files
+//!
+//! Verses are appended to typed tables with the columns of the `polars` and `arrow` features'
+//! output: `book` and `text` (`VARCHAR NOT NULL`), `chapter`, `verse` and `verse_end`
+//! (`UINTEGER`) and `subverse`, `paragraph` and `section` (`VARCHAR`). Each translation is
+//! loaded in a transaction and replaces any earlier load of the same translation.
+
+use crate::{ParserError, UsfxParser};
+use duckdb::{Connection, params};
+use std::path::Path;
+
+/// Table holding every translation in the combined layout
+const COMBINED_TABLE: &str = "verses";
+
+const COLUMNS: &str = "book VARCHAR NOT NULL, chapter UINTEGER, verse UINTEGER, \
+                       verse_end UINTEGER, subverse VARCHAR, text VARCHAR NOT NULL, \
+                       paragraph VARCHAR, section VARCHAR";
+
+fn duckdb_error(error: duckdb::Error) -> ParserError {
+    ParserError::ParseError(format!("DuckDB error: {error}"))
+}
+
+/// How translations are laid out in tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// A single `verses` table, with the translation in a leading `translation` column
+    /// (default)
+    #[default]
+    Combined,
+    /// A table per translation, named after it
+    PerTranslation,
+}
+
+/// A `DuckDB` database file verses are loaded into
+pub struct VerseDatabase {
+    connection: Connection,
+    layout: Layout,
+}
+
+impl VerseDatabase {
+    /// Open a database file, creating it if it does not exist
+    pub fn open(path: impl AsRef<Path>, layout: Layout) -> Result<Self, ParserError> {
+        let connection = Connection::open(path).map_err(duckdb_error)?;
+        if layout == Layout::Combined {
+            connection
+                .execute_batch(&format!(
+                    "CREATE TABLE IF NOT EXISTS {COMBINED_TABLE} \
+                     (translation VARCHAR NOT NULL, {COLUMNS})"
+                ))
+                .map_err(duckdb_error)?;
+        }
+        Ok(Self { connection, layout })
+    }
+
+    /// Load the parser's remaining verses as a translation, one row per verse whatever the
+    /// configured granularity, returning the number of rows loaded
+    pub fn load(
+        &mut self,
+        parser: &mut UsfxParser,
+        translation: &str,
+    ) -> Result<usize, ParserError> {
+        let transaction = self.connection.transaction().map_err(duckdb_error)?;
+        let table = match self.layout {
+            Layout::Combined => {
+                transaction
+                    .execute(
+                        &format!("DELETE FROM {COMBINED_TABLE} WHERE translation = ?"),
+                        params![translation],
+                    )
+                    .map_err(duckdb_error)?;
+                COMBINED_TABLE
+            }
+            Layout::PerTranslation => {
+                let quoted = format!("\"{}\"", translation.replace('"', "\"\""));
+                transaction
+                    .execute_batch(&format!("CREATE OR REPLACE TABLE {quoted} ({COLUMNS})"))
+                    .map_err(duckdb_error)?;
+                translation
+            }
+        };
+        let mut rows = 0;
+        {
+            let mut appender = transaction.appender(table).map_err(duckdb_error)?;
+            while let Some(verse) = parser.next_verse()? {
+                let number = |value: &str| value.parse::<u32>().ok();
+                let chapter = number(&verse.chapter);
+                let number_in_chapter = number(&verse.verse);
+                let verse_end = verse.verse_end.as_deref().and_then(number);
+                let result = match self.layout {
+                    Layout::Combined => appender.append_row(params![
+                        translation,
+                        verse.book,
+                        chapter,
+                        number_in_chapter,
+                        verse_end,
+                        verse.subverse,
+                        verse.text,
+                        verse.paragraph,
+                        verse.section,
+                    ]),
+                    Layout::PerTranslation => appender.append_row(params![
+                        verse.book,
+                        chapter,
+                        number_in_chapter,
+                        verse_end,
+                        verse.subverse,
+                        verse.text,
+                        verse.paragraph,
+                        verse.section,
+                    ]),
+                };
+                result.map_err(duckdb_error)?;
+                rows += 1;
+            }
+            appender.flush().map_err(duckdb_error)?;
+        }
+        transaction.commit().map_err(duckdb_error)?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsfxConfig;
+
+    fn parser(xml: &str) -> UsfxParser {
+        let input = std::io::Cursor::new(xml.as_bytes().to_vec());
+        UsfxParser::from_reader(input, Box::new(std::io::sink()), UsfxConfig::default())
+    }
+
+    #[test]
+    fn test_verse_database() {
+        const KJV: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36-37"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+        const WEB: &str =
+            r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/></p></book></usfx>"#;
+
+        let dir = std::env::temp_dir().join(format!("usfx_duckdb_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bible.duckdb");
+        let mut database = VerseDatabase::open(&path, Layout::Combined).unwrap();
+        assert_eq!(database.load(&mut parser(KJV), "kjv").unwrap(), 2);
+        assert_eq!(database.load(&mut parser(WEB), "web").unwrap(), 1);
+        // Loading a translation again replaces it
+        assert_eq!(database.load(&mut parser(KJV), "kjv").unwrap(), 2);
+        let (rows, verse_end): (u32, u32) = database
+            .connection
+            .query_row(
+                "SELECT count(*), max(verse_end) FROM verses WHERE translation = 'kjv'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((rows, verse_end), (2, 37));
+
+        let mut database = VerseDatabase::open(&path, Layout::PerTranslation).unwrap();
+        assert_eq!(database.load(&mut parser(WEB), "web").unwrap(), 1);
+        let text: String = database
+            .connection
+            .query_row("SELECT text FROM web", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(text, "Jesus wept.");
+        drop(database);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+//! Downloading translations from eBible.org
+//!
+//! eBible publishes the USFX of each translation as a zip bundle named after its translation
+//! ID, such as `https://ebible.org/Scriptures/engnet_usfx.zip`. A downloaded bundle is verified
+//! before it is used: it must be a zip archive holding a USFX document with at least one verse,
+//! and match the expected SHA-256 when one is given.
+
+use crate::{ParserError, UsfxConfig, UsfxParser};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Where eBible publishes translation bundles
+pub const SCRIPTURES_URL: &str = "https://ebible.org/Scriptures";
+
+/// URL of the USFX bundle of a translation under `base` (normally [`SCRIPTURES_URL`])
+///
+/// Translation IDs are letters, digits, `-` and `_`, such as `engnet` or `grc-tisch`.
+pub fn bundle_url(base: &str, translation: &str) -> Result<String, ParserError> {
+    let valid = !translation.is_empty()
+        && translation
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(ParserError::ParseError(format!(
+            "Invalid translation ID: {translation}"
+        )));
+    }
+    Ok(format!(
+        "{}/{translation}_usfx.zip",
+        base.trim_end_matches('/')
+    ))
+}
+
+/// A downloaded and verified USFX bundle
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// Translation ID the bundle was downloaded for
+    pub translation: String,
+    /// The zip archive as downloaded
+    pub archive: Vec<u8>,
+    /// SHA-256 of the archive, as lowercase hex
+    pub sha256: String,
+    /// Number of verses in the USFX document
+    pub verses: usize,
+}
+
+/// Download and verify the USFX bundle of a translation from `base` (normally
+/// [`SCRIPTURES_URL`]), checking it against `expected_sha256` when given
+pub fn fetch(
+    base: &str,
+    translation: &str,
+    expected_sha256: Option<&str>,
+) -> Result<Bundle, ParserError> {
+    let url = bundle_url(base, translation)?;
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|error| ParserError::ParseError(format!("Cannot fetch {url}: {error}")))?;
+    let mut archive = Vec::new();
+    response
+        .into_body()
+        .into_reader()
+        .read_to_end(&mut archive)?;
+    let sha256: String = Sha256::digest(&archive)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if let Some(expected) = expected_sha256
+        && !expected.eq_ignore_ascii_case(&sha256)
+    {
+        return Err(ParserError::ParseError(format!(
+            "{url} has SHA-256 {sha256}, expected {expected}"
+        )));
+    }
+    if !archive.starts_with(b"PK\x03\x04") {
+        return Err(ParserError::ParseError(format!(
+            "{url} is not a zip archive"
+        )));
+    }
+    let mut bundle = Bundle {
+        translation: translation.to_string(),
+        archive,
+        sha256,
+        verses: 0,
+    };
+    let mut parser = bundle.parser(Box::new(std::io::sink()), UsfxConfig::default());
+    while parser.next_verse()?.is_some() {
+        bundle.verses += 1;
+    }
+    if bundle.verses == 0 {
+        return Err(ParserError::ParseError(format!("{url} holds no verses")));
+    }
+    Ok(bundle)
+}
+
+impl Bundle {
+    /// File name the bundle is published under (`engnet_usfx.zip`)
+    pub fn file_name(&self) -> String {
+        format!("{}_usfx.zip", self.translation)
+    }
+
+    /// Save the archive in `directory`, creating it if needed, and return its path
+    pub fn save(&self, directory: &Path) -> Result<PathBuf, ParserError> {
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(self.file_name());
+        std::fs::write(&path, &self.archive)?;
+        Ok(path)
+    }
+
+    /// A parser converting the USFX document of the bundle
+    pub fn parser(&self, output: Box<dyn Write>, config: UsfxConfig) -> UsfxParser {
+        UsfxParser::from_reader(Cursor::new(self.archive.clone()), output, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use zip::write::SimpleFileOptions;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/></p></book></usfx>"#;
+
+    #[test]
+    fn test_fetch() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("engtest_usfx.xml", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(DOCUMENT.as_bytes()).unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+        let empty = b"<html>Not here</html>".to_vec();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/Scriptures", listener.local_addr().unwrap());
+        let responses = [archive.clone(), archive.clone(), empty];
+        let server = std::thread::spawn(move || {
+            for body in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                request.read_line(&mut line).unwrap();
+                assert!(line.starts_with("GET /Scriptures/engtest_usfx.zip "));
+                while request.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        let bundle = fetch(&base, "engtest", None).unwrap();
+        assert_eq!(bundle.archive, archive);
+        assert_eq!(bundle.verses, 1);
+        assert_eq!(bundle.file_name(), "engtest_usfx.zip");
+        let mismatch = fetch(&base, "engtest", Some("00"));
+        assert!(
+            matches!(mismatch, Err(ParserError::ParseError(message)) if message.contains(&bundle.sha256))
+        );
+        let error = fetch(&base, "engtest", None);
+        assert!(
+            matches!(error, Err(ParserError::ParseError(message)) if message.contains("not a zip archive"))
+        );
+        server.join().unwrap();
+
+        assert!(bundle_url(SCRIPTURES_URL, "../engnet").is_err());
+        assert_eq!(
+            bundle_url(SCRIPTURES_URL, "engnet").unwrap(),
+            "https://ebible.org/Scriptures/engnet_usfx.zip"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Detection and transcoding of documents that are not UTF-8
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::io::{BufRead, BufReader};
+
+/// The UTF-8 encoding of U+FEFF, written at the start of output when a byte order mark is wanted
+pub(crate) const UTF_8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Wrap `input` so that it yields UTF-8, whatever encoding the document is in
+///
+/// The encoding comes from a byte order mark if there is one, then from the layout of the
+/// first bytes, which identifies UTF-16 without a byte order mark, and finally from the
+/// `encoding` declared in the XML prolog. Unknown declared encodings are read as UTF-8.
+pub(crate) fn decoded<R: BufRead + 'static>(mut input: R) -> Box<dyn BufRead> {
+    // A UTF-8 byte order mark needs no transcoding, only skipping
+    if input
+        .fill_buf()
+        .is_ok_and(|head| head.starts_with(UTF_8_BOM))
+    {
+        input.consume(UTF_8_BOM.len());
+    }
+    let (bom, encoding) = match input.fill_buf() {
+        Ok(head) => (Encoding::for_bom(head).is_some(), detect(head)),
+        Err(_) => (false, None),
+    };
+    match encoding {
+        Some(encoding) if bom || encoding != UTF_8 => Box::new(BufReader::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .build(input),
+        )),
+        _ => Box::new(input),
+    }
+}
+
+/// The encoding of a document starting with `head`, if it can be told from those bytes
+fn detect(head: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return Some(encoding);
+    }
+    match head {
+        [b'<', 0, b'?', 0, ..] => return Some(UTF_16LE),
+        [0, b'<', 0, b'?', ..] => return Some(UTF_16BE),
+        _ => (),
+    }
+    let prolog = head.strip_prefix(b"<?xml")?;
+    let prolog = &prolog[..prolog.windows(2).position(|w| w == b"?>")?];
+    let start = prolog.windows(8).position(|w| w == b"encoding")? + 8;
+    let value = prolog[start..]
+        .trim_ascii_start()
+        .strip_prefix(b"=")?
+        .trim_ascii_start();
+    let (&quote, value) = value.split_first()?;
+    let end = value.iter().position(|&b| b == quote)?;
+    // A document transcoded from UTF-16 is read as UTF-8 whatever it declares
+    Encoding::for_label(&value[..end]).map(Encoding::output_encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+    use std::io::Read;
+
+    fn read(bytes: Vec<u8>) -> String {
+        let mut text = String::new();
+        decoded(std::io::Cursor::new(bytes))
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_declared_encoding() {
+        let mut bytes = b"<?xml version=\"1.0\" encoding='ISO-8859-1'?><usfx>".to_vec();
+        bytes.extend_from_slice(&[b'J', 0xe9, b's', b'u', b's']);
+        assert_eq!(detect(&bytes), Some(WINDOWS_1252));
+        assert!(read(bytes).ends_with("<usfx>Jésus"));
+    }
+
+    #[test]
+    fn test_utf16() {
+        let xml = "<?xml version=\"1.0\"?><usfx>Jésus</usfx>";
+        let le: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = [0xfeff]
+            .into_iter()
+            .chain(xml.encode_utf16())
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(read(le), xml);
+        assert_eq!(read(be), xml);
+    }
+
+    #[test]
+    fn test_utf8_bom_is_skipped() {
+        let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?><usfx>Jésus</usfx>";
+        assert_eq!(read([UTF_8_BOM, xml.as_bytes()].concat()), xml);
+    }
+
+    #[test]
+    fn test_utf8_passes_through() {
+        let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?><usfx>Jésus</usfx>";
+        assert_eq!(read(xml.as_bytes().to_vec()), xml);
+        assert_eq!(detect(b"<usfx>"), None);
+    }
+}
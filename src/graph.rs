@@ -0,0 +1,177 @@
+//! Graph of the cross references between verses, for visualization tools
+
+use crate::reference::ReferenceRange;
+use crate::{ParserError, Verse};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::str::FromStr;
+
+/// File format of a graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// Graphviz DOT (default)
+    #[default]
+    Dot,
+    /// `GraphML`, as read by Gephi and yEd
+    GraphMl,
+}
+
+impl FromStr for GraphFormat {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "graphml" => Ok(Self::GraphMl),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown graph format: {s} (expected dot or graphml)"
+            ))),
+        }
+    }
+}
+
+/// Directed graph whose nodes are verses and whose edges lead from each verse to the targets
+/// of its cross references
+///
+/// Nodes are named by `bcv` reference (`GEN.1.1`); a target spanning several verses or a whole
+/// chapter is one node named by its range (`JHN.1.1-3`, `PSA.23`).
+#[derive(Debug, Clone, Default)]
+pub struct CrossReferenceGraph {
+    nodes: Vec<String>,
+    edges: Vec<(usize, usize)>,
+    indexes: HashMap<String, usize>,
+    seen: HashSet<(usize, usize)>,
+}
+
+impl CrossReferenceGraph {
+    /// Add the cross references of a verse; an edge already in the graph is not repeated
+    pub fn add(&mut self, verse: &Verse) {
+        let targets: Vec<&ReferenceRange> = verse
+            .cross_references
+            .iter()
+            .flat_map(|cross_reference| &cross_reference.targets)
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+        let source = self.node(format!("{}.{}.{}", verse.book, verse.chapter, verse.verse));
+        for target in targets {
+            let target = self.node(target.to_string());
+            if self.seen.insert((source, target)) {
+                self.edges.push((source, target));
+            }
+        }
+    }
+
+    fn node(&mut self, name: String) -> usize {
+        if let Some(&index) = self.indexes.get(&name) {
+            return index;
+        }
+        self.nodes.push(name.clone());
+        self.indexes.insert(name, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Number of verses and passages in the graph
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of distinct cross references in the graph
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Write the graph in a format, with nodes and edges in the order first read
+    pub fn write<W: Write>(&self, mut writer: W, format: GraphFormat) -> Result<(), ParserError> {
+        match format {
+            GraphFormat::Dot => {
+                writeln!(writer, "digraph cross_references {{")?;
+                for &(source, target) in &self.edges {
+                    writeln!(
+                        writer,
+                        "  \"{}\" -> \"{}\";",
+                        self.nodes[source].replace('"', "\\\""),
+                        self.nodes[target].replace('"', "\\\"")
+                    )?;
+                }
+                writeln!(writer, "}}")?;
+            }
+            GraphFormat::GraphMl => {
+                writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+                writeln!(
+                    writer,
+                    r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+                )?;
+                writeln!(
+                    writer,
+                    r#"  <key id="book" for="node" attr.name="book" attr.type="string"/>"#
+                )?;
+                writeln!(
+                    writer,
+                    r#"  <graph id="cross_references" edgedefault="directed">"#
+                )?;
+                for node in &self.nodes {
+                    let book = node.split('.').next().unwrap_or_default();
+                    writeln!(
+                        writer,
+                        r#"    <node id="{}"><data key="book">{}</data></node>"#,
+                        quick_xml::escape::escape(node.as_str()),
+                        quick_xml::escape::escape(book)
+                    )?;
+                }
+                for &(source, target) in &self.edges {
+                    writeln!(
+                        writer,
+                        r#"    <edge source="{}" target="{}"/>"#,
+                        quick_xml::escape::escape(self.nodes[source].as_str()),
+                        quick_xml::escape::escape(self.nodes[target].as_str())
+                    )?;
+                }
+                writeln!(writer, "  </graph>")?;
+                writeln!(writer, "</graphml>")?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrossReference;
+    use crate::reference::parse;
+
+    #[test]
+    fn test_cross_reference_graph() {
+        let verse = |book: &str, targets: &str| Verse {
+            book: book.to_string(),
+            chapter: "1".to_string(),
+            verse: "1".to_string(),
+            cross_references: vec![CrossReference {
+                targets: parse(targets).unwrap(),
+                ..CrossReference::default()
+            }],
+            ..Verse::default()
+        };
+        let mut graph = CrossReferenceGraph::default();
+        graph.add(&verse("GEN", "JHN.1.1-3; HEB.11.3"));
+        graph.add(&verse("JHN", "GEN.1.1; GEN.1.1"));
+        graph.add(&Verse::default());
+        assert_eq!((graph.node_count(), graph.edge_count()), (4, 3));
+
+        let mut dot = Vec::new();
+        graph.write(&mut dot, GraphFormat::Dot).unwrap();
+        assert_eq!(
+            String::from_utf8(dot).unwrap(),
+            "digraph cross_references {\n  \"GEN.1.1\" -> \"JHN.1.1-3\";\n  \"GEN.1.1\" -> \"HEB.11.3\";\n  \"JHN.1.1\" -> \"GEN.1.1\";\n}\n"
+        );
+        assert!("gexf".parse::<GraphFormat>().is_err());
+        let mut graphml = Vec::new();
+        graph.write(&mut graphml, GraphFormat::GraphMl).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert!(graphml.contains(r#"<node id="HEB.11.3"><data key="book">HEB</data></node>"#));
+        assert!(graphml.contains(r#"<edge source="JHN.1.1" target="GEN.1.1"/>"#));
+    }
+}
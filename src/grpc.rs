@@ -0,0 +1,174 @@
+//! gRPC conversion service, built with tonic from `proto/usfx.proto`
+//!
+//! `Convert` returns a document's rows at once, while `StreamVerses` sends its verses as they
+//! are parsed. The parser runs on a blocking thread and hands verses over a bounded channel, so
+//! it pauses whenever the client (and HTTP/2 flow control) falls behind rather than buffering
+//! the whole document. Options are the command line options in camel case, as for the HTTP
+//! service.
+
+use crate::options::{ConvertOptions, convert_document, error_message};
+use crate::{ParserError, UsfxParser};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+/// Messages and service traits generated from `proto/usfx.proto`
+#[allow(clippy::pedantic)]
+pub mod proto {
+    tonic::include_proto!("usfx_to_tsv");
+}
+
+use proto::converter_server::{Converter, ConverterServer};
+use proto::{ConvertRequest, ConvertResponse};
+
+/// Largest request accepted, as for the HTTP service
+const MAX_MESSAGE: usize = 64 * 1024 * 1024;
+
+/// Verses parsed ahead of the client before the parser waits
+const STREAM_BUFFER: usize = 64;
+
+fn invalid(error: ParserError) -> Status {
+    Status::invalid_argument(error_message(error))
+}
+
+fn options(request: &ConvertRequest) -> Result<ConvertOptions, Status> {
+    ConvertOptions::from_pairs(
+        request
+            .options
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    )
+    .map_err(invalid)
+}
+
+fn message(verse: &crate::Verse) -> Result<proto::Verse, ParserError> {
+    let json = serde_json::to_string(verse)
+        .map_err(|error| ParserError::ParseError(format!("Cannot serialize verse: {error}")))?;
+    Ok(proto::Verse {
+        book: verse.book.clone(),
+        chapter: verse.chapter.clone(),
+        verse: verse.verse.clone(),
+        text: verse.text.clone(),
+        json,
+    })
+}
+
+/// The service, converting the documents sent with each request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConversionService;
+
+#[tonic::async_trait]
+impl Converter for ConversionService {
+    type StreamVersesStream = ReceiverStream<Result<proto::Verse, Status>>;
+
+    async fn convert(
+        &self,
+        request: Request<ConvertRequest>,
+    ) -> Result<Response<ConvertResponse>, Status> {
+        let request = request.into_inner();
+        let config = options(&request)?.config().map_err(invalid)?;
+        let rows = tokio::task::spawn_blocking(move || convert_document(&request.usfx, config))
+            .await
+            .map_err(|error| Status::internal(error.to_string()))?
+            .map_err(invalid)?;
+        Ok(Response::new(ConvertResponse { rows }))
+    }
+
+    async fn stream_verses(
+        &self,
+        request: Request<ConvertRequest>,
+    ) -> Result<Response<Self::StreamVersesStream>, Status> {
+        let request = request.into_inner();
+        let config = options(&request)?.config().map_err(invalid)?;
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let input = std::io::Cursor::new(request.usfx.into_bytes());
+            let mut parser = UsfxParser::from_reader(input, Box::new(std::io::sink()), config);
+            loop {
+                let verse = match parser.next_verse() {
+                    Ok(Some(verse)) => message(&verse),
+                    Ok(None) => break,
+                    Err(error) => Err(error),
+                };
+                let failed = verse.is_err();
+                // A closed channel means the client has gone away
+                if sender.blocking_send(verse.map_err(invalid)).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Serve the service on `address` (e.g. `127.0.0.1:50051`) until the process is stopped
+pub fn serve(address: &str) -> Result<(), ParserError> {
+    let address = address
+        .parse()
+        .map_err(|error| ParserError::ParseError(format!("Invalid address {address}: {error}")))?;
+    let service = ConverterServer::new(ConversionService)
+        .max_decoding_message_size(MAX_MESSAGE)
+        .max_encoding_message_size(MAX_MESSAGE);
+    tokio::runtime::Runtime::new()?
+        .block_on(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve(address),
+        )
+        .map_err(|error| ParserError::ParseError(format!("gRPC server failed: {error}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_grpc_service() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        let request = |options: &[(&str, &str)]| {
+            Request::new(ConvertRequest {
+                usfx: XML.to_string(),
+                options: options
+                    .iter()
+                    .map(|&(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            })
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let service = ConversionService;
+            let response = service
+                .convert(request(&[("granularity", "chapter")]))
+                .await
+                .unwrap();
+            assert!(
+                response
+                    .into_inner()
+                    .rows
+                    .starts_with("JHN\t11\tJesus wept. Then said")
+            );
+
+            let stream = service.stream_verses(request(&[])).await.unwrap();
+            let verses: Vec<_> = stream.into_inner().collect().await;
+            assert_eq!(verses.len(), 2);
+            let verse = verses[0].as_ref().unwrap();
+            assert_eq!(
+                (
+                    verse.book.as_str(),
+                    verse.verse.as_str(),
+                    verse.text.as_str()
+                ),
+                ("JHN", "35", "Jesus wept.")
+            );
+            assert!(verse.json.contains(r#""text":"Jesus wept.""#));
+
+            let status = service
+                .convert(request(&[("format", "yaml")]))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        });
+    }
+}
@@ -0,0 +1,111 @@
+//! Full-text search index of verses, built with tantivy
+
+use crate::{ParserError, Verse};
+use std::path::Path;
+use tantivy::schema::{Field, STORED, STRING, Schema, TEXT};
+use tantivy::{Index, IndexWriter, TantivyDocument, TantivyError, doc};
+
+/// Memory the index writer may use before writing a segment to disk
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Writer of a tantivy index holding one document per verse, with the fields `reference`
+/// (`GEN.1.1`), `book` (`GEN`), both stored and matched exactly, and `text`, stored and
+/// tokenized for full-text search
+pub struct VerseIndex {
+    writer: IndexWriter,
+    reference: Field,
+    book: Field,
+    text: Field,
+    verses: usize,
+}
+
+fn index_error(error: TantivyError) -> ParserError {
+    ParserError::ParseError(format!("Search index error: {error}"))
+}
+
+impl VerseIndex {
+    /// Create an index in a directory, creating the directory if needed; fails if the
+    /// directory already holds an index
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, ParserError> {
+        let mut builder = Schema::builder();
+        let reference = builder.add_text_field("reference", STRING | STORED);
+        let book = builder.add_text_field("book", STRING | STORED);
+        let text = builder.add_text_field("text", TEXT | STORED);
+        std::fs::create_dir_all(&path)?;
+        let index = Index::create_in_dir(path, builder.build()).map_err(index_error)?;
+        Ok(Self {
+            writer: index.writer(WRITER_MEMORY).map_err(index_error)?,
+            reference,
+            book,
+            text,
+            verses: 0,
+        })
+    }
+
+    /// Add a verse to the index
+    pub fn add(&mut self, verse: &Verse) -> Result<(), ParserError> {
+        let document: TantivyDocument = doc!(
+            self.reference => format!("{}.{}.{}", verse.book, verse.chapter, verse.verse),
+            self.book => verse.book.as_str(),
+            self.text => verse.text.as_str(),
+        );
+        self.writer.add_document(document).map_err(index_error)?;
+        self.verses += 1;
+        Ok(())
+    }
+
+    /// Write the verses added to disk, returning their number
+    pub fn commit(mut self) -> Result<usize, ParserError> {
+        self.writer.commit().map_err(index_error)?;
+        self.writer.wait_merging_threads().map_err(index_error)?;
+        Ok(self.verses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::collector::TopDocs;
+    use tantivy::query::QueryParser;
+    use tantivy::schema::Value;
+
+    #[test]
+    fn test_verse_index() {
+        let path = std::env::temp_dir().join(format!("usfx_to_tsv_index_{}", std::process::id()));
+        let verse = |chapter: &str, verse: &str, text: &str| Verse {
+            book: "JHN".to_string(),
+            chapter: chapter.to_string(),
+            verse: verse.to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let mut index = VerseIndex::create(&path).unwrap();
+        index
+            .add(&verse("3", "16", "For God so loved the world"))
+            .unwrap();
+        index.add(&verse("11", "35", "Jesus wept.")).unwrap();
+        assert_eq!(index.commit().unwrap(), 2);
+        assert!(VerseIndex::create(&path).is_err());
+
+        let index = Index::open_in_dir(&path).unwrap();
+        let schema = index.schema();
+        let text = schema.get_field("text").unwrap();
+        let reference = schema.get_field("reference").unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let query = QueryParser::for_index(&index, vec![text])
+            .parse_query("wept")
+            .unwrap();
+        let hits = searcher
+            .search(&query, &TopDocs::with_limit(10).order_by_score())
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        let document: TantivyDocument = searcher.doc(hits[0].1).unwrap();
+        assert_eq!(
+            document
+                .get_first(reference)
+                .and_then(|value| value.as_str()),
+            Some("JHN.11.35")
+        );
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+//! Word-aligned rows for interlinear study of texts with tagged words (`<w>`)
+
+use crate::output::escape_field;
+use crate::{ParserError, Verse};
+use std::io::Write;
+
+/// Column names of an interlinear TSV file
+pub const HEADER: &str = "book\tchapter\tverse\tposition\tsurface\tlemma\tstrongs\tmorph\tgloss";
+
+/// Write one interlinear row per tagged word of a verse, numbered from 1 in the order the words
+/// occur, with empty fields for attributes the word lacks; returns the number of rows written
+///
+/// The verse must have been read with the `words` column or JSON output, as words are not
+/// recorded otherwise.
+pub fn write_interlinear<W: Write>(verse: &Verse, mut writer: W) -> Result<usize, ParserError> {
+    for (position, word) in verse.words.iter().enumerate() {
+        let fields = [
+            word.text.as_str(),
+            word.lemma().unwrap_or_default(),
+            word.strongs().unwrap_or_default(),
+            word.morph().unwrap_or_default(),
+            word.gloss().unwrap_or_default(),
+        ]
+        .map(|field| escape_field(field).into_owned());
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            verse.book,
+            verse.chapter,
+            verse.verse,
+            position + 1,
+            fields.join("\t")
+        )?;
+    }
+    Ok(verse.words.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Column, UsfxConfigBuilder, UsfxParser};
+
+    #[test]
+    fn test_interlinear_rows() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="1"/><p><v id="1"/><w s="G1722" l="ἐν" m="PREP">Ἐν</w> <w strong="G746" lemma="ἀρχή" x-morph="N-DSF" gloss="beginning">ἀρχῇ</w> ἦν<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().column(Column::Words).build();
+        let mut parser = UsfxParser::from_reader(XML.as_bytes(), Box::new(std::io::sink()), config);
+        let verse = parser.next_verse().unwrap().unwrap();
+        let mut tsv = Vec::new();
+        assert_eq!(write_interlinear(&verse, &mut tsv).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "JHN\t1\t1\t1\tἘν\tἐν\tG1722\tPREP\t\nJHN\t1\t1\t2\tἀρχῇ\tἀρχή\tG746\tN-DSF\tbeginning\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Rules deciding where a space goes when text fragments are joined into verse text
+
+use crate::ParserError;
+use std::str::FromStr;
+
+/// Whether a space separates two joined fragments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinRule {
+    /// Only if the source had whitespace between them (default)
+    #[default]
+    Source,
+    /// Always
+    Always,
+    /// Never, even if the source had whitespace between them
+    Never,
+    /// If the source had whitespace between them or they were separated by a tag
+    TagBoundary,
+}
+
+impl JoinRule {
+    fn space(self, source_space: bool, tag_boundary: bool) -> bool {
+        match self {
+            Self::Source => source_space,
+            Self::Always => true,
+            Self::Never => false,
+            Self::TagBoundary => source_space || tag_boundary,
+        }
+    }
+}
+
+impl FromStr for JoinRule {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(Self::Source),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            "tag-boundary" => Ok(Self::TagBoundary),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown join rule: {s} (expected source, always, never or tag-boundary)"
+            ))),
+        }
+    }
+}
+
+/// The join rule for each kind of fragment boundary
+///
+/// Text after opening punctuation such as `(` or `“` is never pulled apart from it, and no
+/// space is ever added next to whitespace that is already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JoinRules {
+    /// Between a word and a following word or opening punctuation
+    pub word_word: JoinRule,
+    /// Between a word and following punctuation, e.g. `<w>God</w>,`
+    pub word_punctuation: JoinRule,
+    /// Between punctuation and a following word or opening punctuation
+    pub punctuation_word: JoinRule,
+}
+
+/// What a character at the edge of a fragment is, for the purpose of joining
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Word,
+    Opening,
+    Punctuation,
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        match c {
+            '(' | '[' | '{' | '“' | '‘' | '«' | '‹' | '„' | '‚' | '¿' | '¡' => {
+                Self::Opening
+            }
+            _ if c.is_alphanumeric() => Self::Word,
+            _ => Self::Punctuation,
+        }
+    }
+}
+
+impl JoinRules {
+    /// Whether a space goes between text ending in `before` and a fragment starting with `after`
+    pub fn space_between(
+        &self,
+        before: char,
+        after: char,
+        source_space: bool,
+        tag_boundary: bool,
+    ) -> bool {
+        if before.is_whitespace() || after.is_whitespace() {
+            return false;
+        }
+        let rule = match (CharClass::of(before), CharClass::of(after)) {
+            (CharClass::Opening, _) | (CharClass::Punctuation, CharClass::Punctuation) => {
+                JoinRule::Source
+            }
+            (CharClass::Word, CharClass::Word | CharClass::Opening) => self.word_word,
+            (CharClass::Word, CharClass::Punctuation) => self.word_punctuation,
+            (CharClass::Punctuation, CharClass::Word | CharClass::Opening) => self.punctuation_word,
+        };
+        rule.space(source_space, tag_boundary)
+    }
+}
+
+impl FromStr for JoinRules {
+    type Err = ParserError;
+
+    /// Parse a comma separated list such as `word-word=tag-boundary,word-punctuation=never`,
+    /// leaving unlisted boundaries at their default
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Self::default();
+        for setting in s.split(',') {
+            let (boundary, rule) = setting.split_once('=').ok_or_else(|| {
+                ParserError::ParseError(format!(
+                    "Invalid join rule: {setting} (expected boundary=rule)"
+                ))
+            })?;
+            let rule = rule.parse()?;
+            match boundary {
+                "word-word" => rules.word_word = rule,
+                "word-punctuation" => rules.word_punctuation = rule,
+                "punctuation-word" => rules.punctuation_word = rule,
+                _ => {
+                    return Err(ParserError::ParseError(format!(
+                        "Unknown join boundary: {boundary} (expected word-word, word-punctuation or punctuation-word)"
+                    )));
+                }
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_rules_follow_the_source() {
+        let rules = JoinRules::default();
+        assert!(rules.space_between('n', 'G', true, false));
+        assert!(!rules.space_between('n', 'G', false, true));
+        assert!(!rules.space_between('d', ',', false, true));
+        assert!(rules.space_between(',', 'a', true, false));
+    }
+
+    #[test]
+    fn test_no_doubled_spaces() {
+        let rules = JoinRules {
+            word_word: JoinRule::Always,
+            word_punctuation: JoinRule::Always,
+            punctuation_word: JoinRule::Always,
+        };
+        assert!(!rules.space_between(' ', 'G', true, true));
+        assert!(!rules.space_between('n', '\u{a0}', true, true));
+    }
+
+    #[test]
+    fn test_boundary_rules() {
+        let rules: JoinRules =
+            "word-word=tag-boundary,word-punctuation=never,punctuation-word=always"
+                .parse()
+                .unwrap();
+        assert!(rules.space_between('n', 'G', false, true));
+        assert!(!rules.space_between('n', 'G', false, false));
+        assert!(rules.space_between('n', '“', false, true));
+        assert!(!rules.space_between('d', ';', true, true));
+        assert!(rules.space_between('.', 'T', false, false));
+        assert!(rules.space_between('.', '(', false, false));
+        assert!(!rules.space_between('“', 'T', false, true));
+        assert!(!rules.space_between('.', '”', false, true));
+    }
+
+    #[test]
+    fn test_rejects_bad_rules() {
+        assert!("word-word".parse::<JoinRules>().is_err());
+        assert!("word-word=sometimes".parse::<JoinRules>().is_err());
+        assert!("verse-word=always".parse::<JoinRules>().is_err());
+    }
+}
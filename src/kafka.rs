@@ -0,0 +1,156 @@
+//! Publishing verses to Kafka, one message per verse
+//!
+//! Each message is keyed by the verse's reference (`JHN.11.35`, with the part of a split verse
+//! as in `1a`), so a topic's partitioning keeps a verse's revisions in order, and carries the
+//! verse as a row of JSON output.
+
+use crate::{ParserError, UsfxParser, Verse};
+use kafka::producer::{Producer, Record, RequiredAcks};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Messages sent to the brokers at once
+const BATCH: usize = 1000;
+
+/// Port of brokers given without one
+const DEFAULT_PORT: u16 = 9092;
+
+fn kafka_error(error: kafka::Error) -> ParserError {
+    ParserError::ParseError(format!("Kafka error: {error}"))
+}
+
+/// Brokers and a topic to publish verses to, written as a URL:
+/// `kafka://host[:port][,host[:port]...]/topic`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    /// Brokers to fetch the cluster's metadata from, as `host:port`
+    pub brokers: Vec<String>,
+    /// Topic the verses are published to
+    pub topic: String,
+}
+
+impl FromStr for Destination {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ParserError::ParseError(format!(
+                "Invalid Kafka URL: {s} (expected kafka://host[:port][,host[:port]...]/topic)"
+            ))
+        };
+        let rest = s.strip_prefix("kafka://").ok_or_else(invalid)?;
+        let (brokers, topic) = rest.split_once('/').ok_or_else(invalid)?;
+        if topic.is_empty() || topic.contains('/') || brokers.split(',').any(str::is_empty) {
+            return Err(invalid());
+        }
+        let brokers = brokers
+            .split(',')
+            .map(|broker| {
+                if broker.contains(':') {
+                    broker.to_string()
+                } else {
+                    format!("{broker}:{DEFAULT_PORT}")
+                }
+            })
+            .collect();
+        Ok(Self {
+            brokers,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+/// Key and JSON payload of a verse's message
+fn message(verse: &Verse) -> Result<(String, Vec<u8>), ParserError> {
+    let key = format!(
+        "{}.{}.{}{}",
+        verse.book,
+        verse.chapter,
+        verse.verse,
+        verse.subverse.as_deref().unwrap_or("")
+    );
+    let payload = serde_json::to_vec(verse)
+        .map_err(|error| ParserError::ParseError(format!("Cannot serialize verse: {error}")))?;
+    Ok((key, payload))
+}
+
+/// Send a batch of messages, waiting for every broker to acknowledge its share
+fn send(
+    producer: &mut Producer,
+    topic: &str,
+    batch: &[(String, Vec<u8>)],
+) -> Result<(), ParserError> {
+    let records: Vec<_> = batch
+        .iter()
+        .map(|(key, payload)| Record::from_key_value(topic, key.as_str(), payload.as_slice()))
+        .collect();
+    for confirm in producer.send_all(&records).map_err(kafka_error)? {
+        for partition in confirm.partition_confirms {
+            if let Err(code) = partition.offset {
+                return Err(ParserError::ParseError(format!(
+                    "Kafka error: {code:?} publishing to {} partition {}",
+                    confirm.topic, partition.partition
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Publish the parser's remaining verses to the destination topic, one message per verse
+/// whatever the configured granularity, returning the number of messages published
+pub fn publish_verses(
+    parser: &mut UsfxParser,
+    destination: &Destination,
+) -> Result<usize, ParserError> {
+    let mut producer = Producer::from_hosts(destination.brokers.clone())
+        .with_client_id("usfx_to_tsv".to_string())
+        .with_required_acks(RequiredAcks::All)
+        .with_ack_timeout(Duration::from_secs(10))
+        .create()
+        .map_err(kafka_error)?;
+    let mut batch = Vec::with_capacity(BATCH);
+    let mut published = 0;
+    while let Some(verse) = parser.next_verse()? {
+        batch.push(message(&verse)?);
+        if batch.len() == BATCH {
+            send(&mut producer, &destination.topic, &batch)?;
+            published += batch.len();
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        send(&mut producer, &destination.topic, &batch)?;
+        published += batch.len();
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let destination: Destination = "kafka://broker1,broker2:9093/verses".parse().unwrap();
+        assert_eq!(destination.brokers, ["broker1:9092", "broker2:9093"]);
+        assert_eq!(destination.topic, "verses");
+        assert!("kafka://broker1".parse::<Destination>().is_err());
+        assert!("kafka://broker1,/verses".parse::<Destination>().is_err());
+
+        let verse = Verse {
+            book: "JHN".to_string(),
+            chapter: "11".to_string(),
+            verse: "35".to_string(),
+            text: "Jesus wept.".to_string(),
+            ..Verse::default()
+        };
+        let (key, payload) = message(&verse).unwrap();
+        assert_eq!(key, "JHN.11.35");
+        assert!(
+            String::from_utf8(payload)
+                .unwrap()
+                .contains(r#""text":"Jesus wept.""#)
+        );
+    }
+}
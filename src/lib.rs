@@ -0,0 +1,1196 @@
+//! USFX to TSV Converter
+//!
+//! This crate provides functionality to convert USFX (Unified Scripture Format XML) files to TSV format.
+//! USFX files can be found at <https://ebible.org/> (e.g., <https://ebible.org/find/show.php?id=engnet>)
+//!
+//! # Example
+//! ```no_run
+//! use usfx_to_tsv::{UsfxConfig, UsfxParser};
+//! use std::fs::File;
+//!
+//! let config = UsfxConfig::default();
+//! let output = Box::new(File::create("output.tsv").unwrap());
+//! let mut parser = UsfxParser::new("input.xml", output, config).unwrap();
+//! parser.parse().unwrap();
+//! ```
+
+pub mod align;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod bidi;
+pub mod books;
+pub mod canon;
+mod compression;
+#[cfg(feature = "polars")]
+mod dataframe;
+pub mod diff;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+#[cfg(feature = "fetch")]
+pub mod ebible;
+mod encoding;
+pub mod graph;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "index")]
+pub mod index;
+pub mod interlinear;
+mod joiner;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod mapping;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod options;
+mod output;
+mod parser;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod reference;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "s3")]
+pub mod storage;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "validate")]
+pub mod validate;
+mod verse;
+pub mod versification;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod words;
+
+pub use align::{AlignedRow, Unaligned, align_by_reference};
+pub use books::BookNames;
+pub use canon::{BookCode, Canon, Testament};
+pub use joiner::{JoinRule, JoinRules};
+pub use mapping::VersificationMap;
+pub use output::RowWriter;
+pub use parser::UsfxParser;
+pub use verse::{
+    BookTitles, CrossReference, Figure, Footnote, IntroParagraph, Metadata, ParseSummary,
+    PoetryLine, Remark, Verse, Word,
+};
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How many verses are combined into a single output row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    /// One row per verse (default)
+    #[default]
+    Verse,
+    /// One row per chapter
+    Chapter,
+    /// One row per book
+    Book,
+    /// One row per section heading (`<s>`), containing the heading and the verses under it.
+    /// Each heading starts a new row, even one repeating the last, and a section that runs
+    /// into the next chapter stays one row, referenced by its first verse.
+    Section,
+}
+
+impl FromStr for Granularity {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verse" => Ok(Self::Verse),
+            "chapter" => Ok(Self::Chapter),
+            "book" => Ok(Self::Book),
+            "section" => Ok(Self::Section),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown granularity: {s} (expected verse, chapter, book or section)"
+            ))),
+        }
+    }
+}
+
+/// Serialization used for output rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Tab-separated values (default)
+    #[default]
+    Tsv,
+    /// JSON Lines: one `Verse` object per row, including structured footnotes
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown format: {s} (expected tsv or json)"
+            ))),
+        }
+    }
+}
+
+/// Abbreviation scheme for book names in the book column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BookAbbreviations {
+    /// Paratext's USFM book codes, e.g. `1SA` (default)
+    #[default]
+    Paratext,
+    /// OSIS book identifiers, e.g. `1Sam`
+    Osis,
+    /// Abbreviations of the SBL Handbook of Style, e.g. `1 Sam`
+    Sbl,
+}
+
+impl FromStr for BookAbbreviations {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "paratext" | "usfm" => Ok(Self::Paratext),
+            "osis" => Ok(Self::Osis),
+            "sbl" => Ok(Self::Sbl),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown book abbreviations: {s} (expected paratext, osis or sbl)"
+            ))),
+        }
+    }
+}
+
+/// How the reference of each row is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferenceFormat {
+    /// Separate book, chapter and verse columns (default)
+    #[default]
+    Columns,
+    /// A single column such as `GEN 1:1`, `GEN 1` or `GEN`, depending on the granularity
+    Single,
+    /// A single OSIS identifier such as `Gen.1.1`
+    Osis,
+    /// A single sortable number of the form `BBBCCCVVV`, e.g. `001001001`, where `BBB` is
+    /// Paratext's book number and chapter and verse are 0 when the row covers all of them; a
+    /// subverse part follows as its letter (`017001001a`)
+    Numeric,
+}
+
+impl FromStr for ReferenceFormat {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "columns" => Ok(Self::Columns),
+            "single" => Ok(Self::Single),
+            "osis" => Ok(Self::Osis),
+            "numeric" => Ok(Self::Numeric),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown reference format: {s} (expected columns, single, osis or numeric)"
+            ))),
+        }
+    }
+}
+
+/// How the `tokens` column counts the tokens a language model would see in a row's text
+#[derive(Clone, Default)]
+pub enum TokenCounter {
+    /// Estimate the tokens of a byte-pair encoding such as GPT's: one per four bytes of a
+    /// word, one per three digits and one per punctuation mark (default)
+    #[default]
+    Estimate,
+    /// One token per whitespace-separated word
+    Words,
+    /// One token per character, whitespace excepted
+    Characters,
+    /// A tokenizer supplied by the caller, such as a binding to a model's own vocabulary
+    Custom(Arc<dyn Fn(&str) -> usize + Send + Sync>),
+}
+
+impl TokenCounter {
+    /// Number of tokens in a text
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Estimate => words::estimate_tokens(text),
+            Self::Words => text.split_whitespace().count(),
+            Self::Characters => text.chars().filter(|c| !c.is_whitespace()).count(),
+            Self::Custom(count) => count(text),
+        }
+    }
+}
+
+impl std::fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Estimate => f.write_str("Estimate"),
+            Self::Words => f.write_str("Words"),
+            Self::Characters => f.write_str("Characters"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl FromStr for TokenCounter {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "estimate" => Ok(Self::Estimate),
+            "words" => Ok(Self::Words),
+            "characters" => Ok(Self::Characters),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown token counter: {s} (expected estimate, words or characters)"
+            ))),
+        }
+    }
+}
+
+/// How verse bridges (e.g. `17-18`) are written in verse-per-row output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BridgeMode {
+    /// One row per bridge, with the first verse in the verse column (default)
+    #[default]
+    Range,
+    /// One row per bridged verse, each repeating the bridge's text
+    Expand,
+}
+
+impl FromStr for BridgeMode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "range" => Ok(Self::Range),
+            "expand" => Ok(Self::Expand),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown bridge mode: {s} (expected range or expand)"
+            ))),
+        }
+    }
+}
+
+/// How chapter titles (`<d>`, e.g. Psalm superscriptions) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TitleMode {
+    /// Attach the title to each verse of the chapter, for the `title` column (default)
+    #[default]
+    Column,
+    /// Write the title as its own row with verse number `0`
+    VerseZero,
+}
+
+impl FromStr for TitleMode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "column" => Ok(Self::Column),
+            "verse0" => Ok(Self::VerseZero),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown title mode: {s} (expected column or verse0)"
+            ))),
+        }
+    }
+}
+
+/// How acrostic headings (`<qa>`, e.g. `Aleph` in Psalm 119) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AcrosticMode {
+    /// Attach the heading to the verses under it, for the `acrostic` column (default)
+    #[default]
+    Column,
+    /// Write the heading as its own row before the verse under it, numbered as that verse and
+    /// with the heading in the `acrostic` column
+    Rows,
+}
+
+impl FromStr for AcrosticMode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "column" => Ok(Self::Column),
+            "rows" => Ok(Self::Rows),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown acrostic mode: {s} (expected column or rows)"
+            ))),
+        }
+    }
+}
+
+/// How Selah interjections (`<qs>`) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelahMode {
+    /// Keep Selah in the verse text (default)
+    #[default]
+    Inline,
+    /// Keep Selah in the verse text, wrapped in square brackets
+    Bracketed,
+    /// Remove Selah from the verse text, for the `selah` column
+    Column,
+}
+
+impl FromStr for SelahMode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inline" => Ok(Self::Inline),
+            "bracketed" => Ok(Self::Bracketed),
+            "column" => Ok(Self::Column),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown Selah mode: {s} (expected inline, bracketed or column)"
+            ))),
+        }
+    }
+}
+
+/// How words supplied by the translators (`<add>`, italics in the KJV) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddedWords {
+    /// Keep the words as ordinary text (default)
+    #[default]
+    Plain,
+    /// Leave the words out of the verse text
+    Omit,
+    /// Wrap the words in square brackets
+    Brackets,
+    /// Wrap the words in asterisks, as Markdown italics
+    Italics,
+}
+
+impl FromStr for AddedWords {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "omit" => Ok(Self::Omit),
+            "brackets" => Ok(Self::Brackets),
+            "italics" => Ok(Self::Italics),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown added words mode: {s} (expected plain, omit, brackets or italics)"
+            ))),
+        }
+    }
+}
+
+/// How the divine name (`<nd>`, small capitals in most English translations) is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DivineName {
+    /// Keep the name as ordinary text (default)
+    #[default]
+    Plain,
+    /// Write the name in capitals (`LORD`)
+    Uppercase,
+    /// Wrap the name in curly braces (`{Lord}`)
+    Markers,
+}
+
+impl FromStr for DivineName {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "uppercase" => Ok(Self::Uppercase),
+            "markers" => Ok(Self::Markers),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown divine name mode: {s} (expected plain, uppercase or markers)"
+            ))),
+        }
+    }
+}
+
+/// How character styles (`<it>`, `<bd>`, `<bdit>`, `<em>`, `<sc>`) are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CharStyles {
+    /// Drop the styling, keeping the text and its spacing (default)
+    #[default]
+    Plain,
+    /// Mark the text with lightweight Markdown-style markers: `*it*`, `**bd**`, `***bdit***`, `_em_`, `{sc}`
+    Markers,
+}
+
+impl FromStr for CharStyles {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "markers" => Ok(Self::Markers),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown character style mode: {s} (expected plain or markers)"
+            ))),
+        }
+    }
+}
+
+/// What a discretionary line break (`<optbreak/>`) becomes in the verse text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptBreak {
+    /// Nothing: the text on either side is joined (default)
+    #[default]
+    Nothing,
+    /// A space
+    Space,
+    /// A soft hyphen (U+00AD), so renderers may still break there
+    SoftHyphen,
+}
+
+impl FromStr for OptBreak {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::Nothing),
+            "space" => Ok(Self::Space),
+            "soft-hyphen" => Ok(Self::SoftHyphen),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown optional break mode: {s} (expected none, space or soft-hyphen)"
+            ))),
+        }
+    }
+}
+
+/// What happens to a verse whose reference has already been read, which usually means a
+/// misplaced milestone in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateVerses {
+    /// Write it as another row with the same reference (default)
+    #[default]
+    Keep,
+    /// Leave it out
+    Drop,
+    /// Append it to the preceding verse when that verse has the same reference, otherwise keep it
+    Merge,
+}
+
+impl FromStr for DuplicateVerses {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "drop" => Ok(Self::Drop),
+            "merge" => Ok(Self::Merge),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown duplicate verse mode: {s} (expected keep, drop or merge)"
+            ))),
+        }
+    }
+}
+
+/// What happens to control characters (other than tab and line breaks) and noncharacters
+/// found in the text, which would otherwise corrupt a database load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlChars {
+    /// Remove them (default)
+    #[default]
+    Strip,
+    /// Replace them with a `\u{..}` escape showing the code point
+    Escape,
+    /// Leave them in the text
+    Keep,
+}
+
+impl FromStr for ControlChars {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strip" => Ok(Self::Strip),
+            "escape" => Ok(Self::Escape),
+            "keep" => Ok(Self::Keep),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown control character mode: {s} (expected strip, escape or keep)"
+            ))),
+        }
+    }
+}
+
+/// What happens to Unicode bidirectional control characters (LRM, RLM, embeddings and
+/// isolates), for translations in right-to-left scripts such as Hebrew and Arabic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bidi {
+    /// Leave the text as it is (default)
+    #[default]
+    Preserve,
+    /// Remove all bidirectional control characters
+    Strip,
+    /// In text containing right-to-left script, surround numeric references such as `3:16`
+    /// with left-to-right marks so they display in the right order
+    Mark,
+}
+
+impl FromStr for Bidi {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "strip" => Ok(Self::Strip),
+            "mark" => Ok(Self::Mark),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown bidi mode: {s} (expected preserve, strip or mark)"
+            ))),
+        }
+    }
+}
+
+/// Initial size of the buffer XML events are read into, which grows to the largest event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferSize {
+    /// Chosen from the size of the input: about a thousandth of a file, between 4 and 64 KiB,
+    /// or 8 KiB when the size is not known, such as for a URL (default)
+    #[default]
+    Auto,
+    /// A fixed number of bytes
+    Bytes(usize),
+}
+
+impl BufferSize {
+    /// Number of bytes to use for an input of `input_len` bytes, if known
+    pub fn bytes(self, input_len: Option<u64>) -> usize {
+        match self {
+            Self::Bytes(bytes) => bytes,
+            Self::Auto => input_len.map_or(8 * 1024, |len| {
+                usize::try_from(len / 1024)
+                    .unwrap_or(usize::MAX)
+                    .clamp(4 * 1024, 64 * 1024)
+            }),
+        }
+    }
+}
+
+impl From<usize> for BufferSize {
+    fn from(bytes: usize) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl FromStr for BufferSize {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(Self::Auto);
+        }
+        s.parse().map(Self::Bytes).map_err(|e| {
+            ParserError::ParseError(format!(
+                "Invalid buffer size: {s} ({e}; expected auto or bytes)"
+            ))
+        })
+    }
+}
+
+/// Terminator written after each output record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`, as on Unix (default)
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows
+    CrLf,
+}
+
+impl LineEnding {
+    /// The terminator itself
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lf" => Ok(Self::Lf),
+            "crlf" => Ok(Self::CrLf),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown line ending: {s} (expected lf or crlf)"
+            ))),
+        }
+    }
+}
+
+/// What happens to introductions and peripheral material (`<ip>`, `<is>`, `<periph>`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntroMode {
+    /// Leave it out of the output (default)
+    #[default]
+    Skip,
+    /// Collect it for the separate intro output (`UsfxParser::intro`)
+    Export,
+    /// Write it as a row with verse number `0`: in chapter `0` for a book's introduction and
+    /// front matter, or in the chapter it introduces; each row joins the paragraphs before the
+    /// next verse
+    VerseZero,
+}
+
+/// How quoted text (`<qt>`, e.g. Old Testament quotations in the New) is marked in the verse text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotationMode {
+    /// Leave the text unmarked (default)
+    #[default]
+    Plain,
+    /// Wrap the text in double angle brackets: `<<...>>`
+    Markers,
+}
+
+impl FromStr for QuotationMode {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "markers" => Ok(Self::Markers),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown quotation mode: {s} (expected plain or markers)"
+            ))),
+        }
+    }
+}
+
+/// Optional column appended to each row after the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// The verse's inline USFX markup, verbatim, with tabs and line breaks backslash-escaped
+    Raw,
+    /// SHA-256 of the whitespace-normalized text, as lowercase hex
+    Checksum,
+    /// Last verse of a verse bridge, empty for a single verse
+    VerseEnd,
+    /// Alternate verse number (`<va>`)
+    AltVerse,
+    /// Published verse number (`<vp>`)
+    PublishedVerse,
+    /// Verse part letter (`a` for `1a`); when absent it stays in the verse column
+    Subverse,
+    /// Parallel passages (`<r>`) of the verse's section, e.g. `MAT.5.1-12; LUK.6.20-23`
+    Parallel,
+    /// Speaker label (`<sp>`) in effect for the verse
+    Speaker,
+    /// Major section heading and range (`<ms>`, `<mr>`), e.g. `BOOK ONE (Psalms 1-41)`
+    MajorSection,
+    /// Published chapter label (`<cl>`, `<cp>`), e.g. `Psalm 23`
+    ChapterLabel,
+    /// Alternate chapter number (`<ca>`)
+    AltChapter,
+    /// Acrostic heading (`<qa>`) in effect for the verse
+    Acrostic,
+    /// Title (`<d>`) of the verse's chapter, such as a Psalm superscription
+    Title,
+    /// Selah interjections (`<qs>`) removed from the verse text
+    Selah,
+    /// Footnotes of the verse, separated by ` | `
+    Footnotes,
+    /// Cross-reference targets of the verse (e.g. `JHN.1.1-3; HEB.11.3`), separated by ` | `
+    CrossReferences,
+    /// Style of the paragraph the verse starts in, e.g. `p`, `q2` or `li1` for a list item
+    Paragraph,
+    /// Indentation levels of the verse's poetic lines (e.g. `1,2`), empty for prose
+    Poetry,
+    /// Quoted text (`<qt>`) in the verse, separated by ` | `; empty when the verse quotes nothing
+    Quotations,
+    /// Marked words with their attributes (e.g. `God|s=H430`), separated by `; `
+    Words,
+    /// Figure captions and files (e.g. `The Ark (ark.jpg)`), separated by ` | `
+    Figures,
+    /// Number of tokens in the row's text, as counted by the configured token counter
+    Tokens,
+}
+
+impl FromStr for Column {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "checksum" => Ok(Self::Checksum),
+            "verse_end" => Ok(Self::VerseEnd),
+            "subverse" => Ok(Self::Subverse),
+            "alt_verse" => Ok(Self::AltVerse),
+            "published_verse" => Ok(Self::PublishedVerse),
+            "parallel" => Ok(Self::Parallel),
+            "speaker" => Ok(Self::Speaker),
+            "major_section" => Ok(Self::MajorSection),
+            "chapter_label" => Ok(Self::ChapterLabel),
+            "alt_chapter" => Ok(Self::AltChapter),
+            "acrostic" => Ok(Self::Acrostic),
+            "title" => Ok(Self::Title),
+            "selah" => Ok(Self::Selah),
+            "footnotes" => Ok(Self::Footnotes),
+            "crossrefs" => Ok(Self::CrossReferences),
+            "poetry" => Ok(Self::Poetry),
+            "paragraph" => Ok(Self::Paragraph),
+            "figures" => Ok(Self::Figures),
+            "tokens" => Ok(Self::Tokens),
+            "words" => Ok(Self::Words),
+            "quotations" => Ok(Self::Quotations),
+            _ => Err(ParserError::ParseError(format!("Unknown column: {s}"))),
+        }
+    }
+}
+
+/// Configuration options for the USFX parser
+#[derive(Debug, Clone)]
+pub struct UsfxConfig {
+    /// Initial size of the buffer for XML parsing, also used to read files (default: auto)
+    pub buffer_size: BufferSize,
+    /// Whether to trim whitespace from text (default: true)
+    pub trim_text: bool,
+    /// Whether to include debug output (default: false)
+    pub debug_output: bool,
+    /// How many verses make up one output row (default: verse)
+    pub granularity: Granularity,
+    /// Reorder books into this canon's order instead of source order (default: none)
+    pub canonical_order: Option<Canon>,
+    /// Reorder books into this order of book codes instead, e.g. from a community's own
+    /// arrangement; books not listed follow in source order (default: none)
+    pub book_order: Vec<String>,
+    /// Optional columns to append to each row, in order (default: none)
+    pub columns: Vec<Column>,
+    /// How verse bridges are written (default: range)
+    pub bridge_mode: BridgeMode,
+    /// Serialization of output rows (default: tsv)
+    pub format: OutputFormat,
+    /// How chapter titles are written (default: column)
+    pub title_mode: TitleMode,
+    /// How acrostic headings are written (default: column)
+    pub acrostic_mode: AcrosticMode,
+    /// How Selah interjections are written (default: inline)
+    pub selah_mode: SelahMode,
+    /// How translator-supplied words are written (default: plain)
+    pub added_words: AddedWords,
+    /// How the divine name is written (default: plain)
+    pub divine_name: DivineName,
+    /// How character styles are written (default: plain)
+    pub char_styles: CharStyles,
+    /// How quoted text is marked (default: plain)
+    pub quotation_mode: QuotationMode,
+    /// Separator written between the cells of a table row (default: ` | `)
+    pub table_separator: String,
+    /// What discretionary line breaks become (default: nothing)
+    pub opt_break: OptBreak,
+    /// Keep non-breaking and other Unicode spaces at the edges of text when trimming,
+    /// removing only ASCII whitespace (default: false)
+    pub preserve_unicode_spaces: bool,
+    /// What happens to introductions and peripheral material (default: skip)
+    pub intro_mode: IntroMode,
+    /// Marker left in the verse text where each footnote occurred, with `{n}` replaced by the
+    /// footnote's number within the verse and `{caller}` by its caller (default: none)
+    pub footnote_marker: Option<String>,
+    /// Give each verse the full breadcrumb of nested section headings (`s1`..`s4`), joined with
+    /// ` > `, instead of only the innermost heading (default: false)
+    pub section_breadcrumb: bool,
+    /// Token written into the verse text for a stanza break (`<b/>`), e.g. `/` (default: none)
+    pub stanza_break: Option<String>,
+    /// Where spaces go when text fragments from different elements are joined (default: follow
+    /// the source's whitespace)
+    pub join_rules: JoinRules,
+    /// Start the output with a UTF-8 byte order mark, which spreadsheet applications such as
+    /// Excel need to recognize UTF-8 text (default: false)
+    pub byte_order_mark: bool,
+    /// Flush the output after every this many rows, so a reader following the output sees
+    /// rows as they are converted; otherwise it is flushed only when full and at the end
+    /// (default: none)
+    pub flush_every_n_rows: Option<usize>,
+    /// Number of threads [`UsfxParser::parse`] reads the books of the document on; with more
+    /// than one, the whole document is loaded into memory and split at each `<book>` (default: 1)
+    pub threads: usize,
+    /// Largest verse, or row of verses, held in memory, in bytes of text; a verse or row
+    /// growing beyond it, or an XML event larger than it, stops the conversion with an error
+    /// instead of using more memory (default: none)
+    ///
+    /// This bounds the text held at a time only. The record of the verses and books read
+    /// still grows with the document, and with more than one thread or a canonical or custom
+    /// book order the whole document is held anyway.
+    pub max_verse_size: Option<usize>,
+    /// Format the rows on a second thread while the parser reads on, handing verses over a
+    /// bounded channel; has no effect with more than one thread, which the command line rejects,
+    /// or a book order (default: false)
+    pub pipeline: bool,
+    /// On malformed XML, record a warning and skip to the next verse boundary instead of
+    /// failing (default: false)
+    pub recover: bool,
+    /// What happens to verses whose reference was already read; each one is also recorded as a
+    /// warning (default: keep)
+    pub duplicate_verses: DuplicateVerses,
+    /// What happens to control characters in the text; each occurrence is also recorded as a
+    /// warning (default: strip)
+    pub control_chars: ControlChars,
+    /// What happens to bidirectional control characters (default: preserve)
+    pub bidi: Bidi,
+    /// Terminator written after each output record, whatever the platform (default: lf)
+    pub line_ending: LineEnding,
+    /// Normalize book identifiers from `<book id>` and `bcv` attributes to standard USFM codes
+    /// (e.g. `Gen` or `gen` to `GEN`), writing each unknown one as a synthetic extra book code
+    /// (`XXA`, `XXB`, ...) with a warning (default: true)
+    pub normalize_book_codes: bool,
+    /// Names written in the book column instead of book codes, e.g. `Genèse` for `GEN`; books
+    /// without a name keep their code (default: none)
+    pub book_names: Option<BookNames>,
+    /// Abbreviations written in the book column for books without a name in `book_names`
+    /// (default: paratext)
+    pub book_abbreviations: BookAbbreviations,
+    /// Mapping applied to every verse reference, from the versification of the source onto
+    /// another scheme (default: none)
+    pub versification_map: Option<VersificationMap>,
+    /// Only verses within these ranges are written; empty writes every verse (default: empty)
+    pub ranges: Vec<reference::ReferenceRange>,
+    /// How the reference of each row is written; in JSON output, anything but separate
+    /// columns adds a `reference` field (default: columns)
+    pub reference_format: ReferenceFormat,
+    /// How the `tokens` column counts tokens (default: estimate)
+    pub token_counter: TokenCounter,
+}
+
+impl Default for UsfxConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: BufferSize::Auto,
+            trim_text: true,
+            debug_output: false,
+            granularity: Granularity::Verse,
+            canonical_order: None,
+            book_order: Vec::new(),
+            columns: Vec::new(),
+            bridge_mode: BridgeMode::Range,
+            format: OutputFormat::Tsv,
+            title_mode: TitleMode::Column,
+            acrostic_mode: AcrosticMode::Column,
+            selah_mode: SelahMode::Inline,
+            added_words: AddedWords::Plain,
+            divine_name: DivineName::Plain,
+            char_styles: CharStyles::Plain,
+            quotation_mode: QuotationMode::Plain,
+            table_separator: " | ".to_string(),
+            opt_break: OptBreak::Nothing,
+            preserve_unicode_spaces: false,
+            intro_mode: IntroMode::Skip,
+            footnote_marker: None,
+            section_breadcrumb: false,
+            stanza_break: None,
+            join_rules: JoinRules::default(),
+            byte_order_mark: false,
+            flush_every_n_rows: None,
+            threads: 1,
+            max_verse_size: None,
+            pipeline: false,
+            recover: false,
+            duplicate_verses: DuplicateVerses::Keep,
+            control_chars: ControlChars::Strip,
+            bidi: Bidi::Preserve,
+            line_ending: LineEnding::Lf,
+            normalize_book_codes: true,
+            book_names: None,
+            book_abbreviations: BookAbbreviations::Paratext,
+            versification_map: None,
+            ranges: Vec::new(),
+            reference_format: ReferenceFormat::Columns,
+            token_counter: TokenCounter::Estimate,
+        }
+    }
+}
+
+/// Builder for `UsfxConfig`
+#[derive(Debug, Default)]
+pub struct UsfxConfigBuilder {
+    config: UsfxConfig,
+}
+
+impl UsfxConfigBuilder {
+    /// Create a new builder with default settings
+    pub fn new() -> Self {
+        Self {
+            config: UsfxConfig::default(),
+        }
+    }
+
+    /// Set the buffer size
+    pub fn buffer_size(mut self, size: impl Into<BufferSize>) -> Self {
+        self.config.buffer_size = size.into();
+        self
+    }
+
+    /// Set whether to trim text
+    pub fn trim_text(mut self, trim: bool) -> Self {
+        self.config.trim_text = trim;
+        self
+    }
+
+    /// Set whether to include debug output
+    pub fn debug_output(mut self, debug: bool) -> Self {
+        self.config.debug_output = debug;
+        self
+    }
+
+    /// Set the output granularity
+    pub fn granularity(mut self, granularity: Granularity) -> Self {
+        self.config.granularity = granularity;
+        self
+    }
+
+    /// Reorder the output into the given canon's book order
+    pub fn canonical_order(mut self, canon: Canon) -> Self {
+        self.config.canonical_order = Some(canon);
+        self
+    }
+
+    /// Reorder the output into a custom book order, overriding any canonical order
+    pub fn book_order(mut self, order: Vec<String>) -> Self {
+        self.config.book_order = order;
+        self
+    }
+
+    /// Append an optional column to each row
+    pub fn column(mut self, column: Column) -> Self {
+        self.config.columns.push(column);
+        self
+    }
+
+    /// Set how verse bridges are written
+    pub fn bridge_mode(mut self, mode: BridgeMode) -> Self {
+        self.config.bridge_mode = mode;
+        self
+    }
+
+    /// Set the output format
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Set how chapter titles are written
+    pub fn title_mode(mut self, mode: TitleMode) -> Self {
+        self.config.title_mode = mode;
+        self
+    }
+
+    /// Set how acrostic headings are written
+    pub fn acrostic_mode(mut self, mode: AcrosticMode) -> Self {
+        self.config.acrostic_mode = mode;
+        self
+    }
+
+    /// Set how Selah interjections are written
+    pub fn selah_mode(mut self, mode: SelahMode) -> Self {
+        self.config.selah_mode = mode;
+        self
+    }
+
+    /// Set how translator-supplied words are written
+    pub fn added_words(mut self, mode: AddedWords) -> Self {
+        self.config.added_words = mode;
+        self
+    }
+
+    /// Set how the divine name is written
+    pub fn divine_name(mut self, mode: DivineName) -> Self {
+        self.config.divine_name = mode;
+        self
+    }
+
+    /// Set how character styles are written
+    pub fn char_styles(mut self, mode: CharStyles) -> Self {
+        self.config.char_styles = mode;
+        self
+    }
+
+    /// Set how quoted text is marked
+    pub fn quotation_mode(mut self, mode: QuotationMode) -> Self {
+        self.config.quotation_mode = mode;
+        self
+    }
+
+    /// Set the separator written between table cells
+    pub fn table_separator(mut self, separator: impl Into<String>) -> Self {
+        self.config.table_separator = separator.into();
+        self
+    }
+
+    /// Set what discretionary line breaks become
+    pub fn opt_break(mut self, mode: OptBreak) -> Self {
+        self.config.opt_break = mode;
+        self
+    }
+
+    /// Set whether trimming keeps non-breaking and other Unicode spaces
+    pub fn preserve_unicode_spaces(mut self, preserve: bool) -> Self {
+        self.config.preserve_unicode_spaces = preserve;
+        self
+    }
+
+    /// Set what happens to introductions and peripheral material
+    pub fn intro_mode(mut self, mode: IntroMode) -> Self {
+        self.config.intro_mode = mode;
+        self
+    }
+
+    /// Leave a marker such as `[{n}]` or `†` in the verse text where each footnote occurred
+    pub fn footnote_marker(mut self, marker: impl Into<String>) -> Self {
+        self.config.footnote_marker = Some(marker.into());
+        self
+    }
+
+    /// Set whether the section is the full breadcrumb of nested headings
+    pub fn section_breadcrumb(mut self, breadcrumb: bool) -> Self {
+        self.config.section_breadcrumb = breadcrumb;
+        self
+    }
+
+    /// Write a token such as `/` or `¶` into the verse text for each stanza break
+    pub fn stanza_break(mut self, token: impl Into<String>) -> Self {
+        self.config.stanza_break = Some(token.into());
+        self
+    }
+
+    /// Set where spaces go when text fragments are joined
+    pub fn join_rules(mut self, rules: JoinRules) -> Self {
+        self.config.join_rules = rules;
+        self
+    }
+
+    /// Set whether the output starts with a UTF-8 byte order mark
+    pub fn byte_order_mark(mut self, bom: bool) -> Self {
+        self.config.byte_order_mark = bom;
+        self
+    }
+
+    /// Set the number of threads the books of the document are read on
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Set how many rows are written between flushes of the output
+    pub fn flush_every_n_rows(mut self, rows: usize) -> Self {
+        self.config.flush_every_n_rows = Some(rows);
+        self
+    }
+
+    /// Set whether rows are formatted on a second thread while the parser reads on
+    pub fn pipeline(mut self, pipeline: bool) -> Self {
+        self.config.pipeline = pipeline;
+        self
+    }
+
+    /// Set the largest verse, or row of verses, held in memory, in bytes of text
+    pub fn max_verse_size(mut self, bytes: usize) -> Self {
+        self.config.max_verse_size = Some(bytes);
+        self
+    }
+
+    /// Set whether malformed XML is skipped with a warning instead of failing the conversion
+    pub fn recover(mut self, recover: bool) -> Self {
+        self.config.recover = recover;
+        self
+    }
+
+    /// Set what happens to verses whose reference was already read
+    pub fn duplicate_verses(mut self, mode: DuplicateVerses) -> Self {
+        self.config.duplicate_verses = mode;
+        self
+    }
+
+    /// Set what happens to control characters in the text
+    pub fn control_chars(mut self, mode: ControlChars) -> Self {
+        self.config.control_chars = mode;
+        self
+    }
+
+    /// Set what happens to bidirectional control characters
+    pub fn bidi(mut self, mode: Bidi) -> Self {
+        self.config.bidi = mode;
+        self
+    }
+
+    /// Set the terminator written after each output record
+    pub fn line_ending(mut self, ending: LineEnding) -> Self {
+        self.config.line_ending = ending;
+        self
+    }
+
+    /// Set whether book identifiers are normalized to standard USFM codes
+    pub fn normalize_book_codes(mut self, normalize: bool) -> Self {
+        self.config.normalize_book_codes = normalize;
+        self
+    }
+
+    /// Set the names written in the book column instead of book codes
+    pub fn book_names(mut self, names: BookNames) -> Self {
+        self.config.book_names = Some(names);
+        self
+    }
+
+    /// Set the abbreviations written in the book column for books without a name
+    pub fn book_abbreviations(mut self, abbreviations: BookAbbreviations) -> Self {
+        self.config.book_abbreviations = abbreviations;
+        self
+    }
+
+    /// Set the mapping applied to every verse reference
+    pub fn versification_map(mut self, map: VersificationMap) -> Self {
+        self.config.versification_map = Some(map);
+        self
+    }
+
+    /// Set the ranges of verses to write, e.g. from [`reference::parse`]
+    pub fn ranges(mut self, ranges: Vec<reference::ReferenceRange>) -> Self {
+        self.config.ranges = ranges;
+        self
+    }
+
+    /// Set how the reference of each row is written
+    pub fn reference_format(mut self, format: ReferenceFormat) -> Self {
+        self.config.reference_format = format;
+        self
+    }
+
+    /// Set how the `tokens` column counts tokens
+    pub fn token_counter(mut self, counter: TokenCounter) -> Self {
+        self.config.token_counter = counter;
+        self
+    }
+
+    /// Build the configuration
+    pub fn build(self) -> UsfxConfig {
+        self.config
+    }
+}
+
+#[derive(Debug)]
+pub enum ParserError {
+    FileError(std::io::Error),
+    XmlError(quick_xml::Error),
+    ParseError(String),
+}
+
+/// A problem in the input that did not stop the conversion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Byte offset in the input where the problem was found
+    pub position: u64,
+    /// What was wrong
+    pub message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "byte {}: {}", self.position, self.message)
+    }
+}
+
+impl From<std::io::Error> for ParserError {
+    fn from(e: std::io::Error) -> Self {
+        Self::FileError(e)
+    }
+}
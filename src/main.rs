@@ -1,308 +1,1298 @@
-//! USFX to TSV Converter
-//!
-//! This crate provides functionality to convert USFX (Unified Scripture Format XML) files to TSV format.
-//! USFX files can be found at <https://ebible.org/> (e.g., <https://ebible.org/find/show.php?id=engnet>)
-//!
-//! # Example
-//! ```no_run
-//! use usfx_to_tsv::UsfxParser;
-//! use std::fs::File;
-//!
-//! let config = UsfxConfig::default();
-//! let output = Box::new(File::create("output.tsv").unwrap());
-//! let mut parser = UsfxParser::new("input.xml", output, config).unwrap();
-//! parser.parse().unwrap();
-//! ```
-
-// Source of clippy linters: <https://github.com/EmbarkStudios/rust-ecosystem/blob/main/lints.rs>
-// Clippy configuration is now in clippy.toml
-
-use quick_xml::events::Event;
-use quick_xml::reader::Reader;
-use std::io::BufReader;
-use std::io::Write;
+//! Command line entry point for the USFX to TSV converter
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
 use std::path::Path;
-use std::str;
-
-/// Configuration options for the USFX parser
-#[derive(Debug, Clone)]
-pub struct UsfxConfig {
-    /// Buffer size for XML parsing (default: 1024)
-    pub buffer_size: usize,
-    /// Whether to trim whitespace from text (default: true)
-    pub trim_text: bool,
-    /// Whether to include debug output (default: false)
-    pub debug_output: bool,
-}
-
-impl Default for UsfxConfig {
-    fn default() -> Self {
-        Self {
-            buffer_size: 1024,
-            trim_text: true,
-            debug_output: false,
+use usfx_to_tsv::align::{Unaligned, align_by_reference, write_parallel};
+use usfx_to_tsv::diff::VerseChange;
+use usfx_to_tsv::graph::{CrossReferenceGraph, GraphFormat};
+use usfx_to_tsv::interlinear::{self, write_interlinear};
+use usfx_to_tsv::versification::Versification;
+use usfx_to_tsv::words::{Concordance, Frequencies, Statistics, Tokenizer, parse_ngram_sizes};
+use usfx_to_tsv::{
+    BookNames, BufferSize, Column, IntroMode, OutputFormat, ParserError, UsfxConfig,
+    UsfxConfigBuilder, UsfxParser, Verse, VersificationMap,
+};
+use usfx_to_tsv::{canon, reference};
+
+const USAGE: &str = "Usage: usfx_to_tsv [--granularity verse|chapter|book|section] [--canonical-order protestant|catholic|orthodox|<file>] [--columns <list>] [--format tsv|json] [--titles column|verse0] [--acrostics column|rows] [--selah inline|bracketed|column] [--added plain|omit|brackets|italics] [--divine-name plain|uppercase|markers] [--styles plain|markers] [--quotations plain|markers] [--table-separator <text>] [--optbreak none|space|soft-hyphen] [--keep-unicode-spaces] [--intro <file.tsv>] [--intro-rows] [--metadata <file.json>] [--books <file.tsv>] [--manifest <file.tsv|file.json>] [--show-license] [--footnote-marker <text>] [--section-breadcrumb] [--stanza-break <token>] [--join <boundary=rule,...>] [--bom] [--flush-every <rows>] [--line-buffered] [--threads <n> | --pipeline] [--buffer-size auto|<bytes>] [--max-verse-size <bytes>] [--recover] [--validate] [--mmap] [--missing-verses kjv|<file>] [--duplicates keep|drop|merge] [--control-chars strip|escape|keep] [--bidi preserve|strip|mark] [--line-ending lf|crlf] [--keep-book-codes] [--book-names <file.json|file.tsv>] [--book-abbreviations paratext|osis|sbl] [--remap <from:to>|<file>] [--range <references>] [--reference columns|single|osis|numeric] [--bridges range|expand] [--token-counter estimate|words|characters] [-o <file|s3://bucket/key|gs://bucket/key|postgres://...?table=<name>|redis://...|kafka://<brokers>/<topic>|nats://.../<subject>>] <usfx_input.xml>
+       usfx_to_tsv check-versification [--scheme kjv|eng|<file>] <usfx_input.xml>
+       usfx_to_tsv freq [--lowercase] [--strip-punctuation] [--ngrams <n>|<from..to>] [--per-book] <usfx_input.xml>
+       usfx_to_tsv parallel [-o <pairs.tsv>] [--unaligned skip|empty|error] <source.xml> <target.xml>
+       usfx_to_tsv diff <old.xml> <new.xml>
+       usfx_to_tsv stats [--per-chapter] [--format tsv|json] [--lowercase] [--strip-punctuation] <usfx_input.xml>
+       usfx_to_tsv concordance [--min-count <n>] [--format tsv|json] [--lowercase] [--strip-punctuation] <usfx_input.xml>
+       usfx_to_tsv xref-graph [-o <graph.dot|graph.graphml>] [--format dot|graphml] <usfx_input.xml>
+       usfx_to_tsv interlinear [-o <words.tsv>] <usfx_input.xml>
+       usfx_to_tsv index <usfx_input.xml> <index_dir>
+       usfx_to_tsv duckdb [--table-per-translation] <bible.duckdb> <usfx_input.xml>...
+       usfx_to_tsv fetch [-o <dir>] [--sha256 <hex>] [--convert] <translation_id>
+       usfx_to_tsv serve [--port <n>] [--host <address>] [--data <dir>]
+       usfx_to_tsv grpc [--port <n>] [--host <address>]";
+
+/// Parsed command line arguments
+#[derive(Debug)]
+struct Cli {
+    input_path: String,
+    output_path: Option<String>,
+    intro_path: Option<String>,
+    metadata_path: Option<String>,
+    books_path: Option<String>,
+    manifest_path: Option<String>,
+    validate: bool,
+    mmap: bool,
+    show_license: bool,
+    missing_verses: Option<Versification>,
+    config: UsfxConfig,
+}
+
+impl Cli {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut builder = UsfxConfigBuilder::new().debug_output(true);
+        let mut input_path = None;
+        let mut output_path = None;
+        let mut intro_path = None;
+        let mut metadata_path = None;
+        let mut books_path = None;
+        let mut manifest_path = None;
+        let mut validate = false;
+        let mut mmap = false;
+        let mut show_license = false;
+        let mut missing_verses = None;
+        let mut line_buffered = false;
+        let mut threads = 1;
+        let mut pipeline = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--granularity" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.granularity(value.parse()?);
+                }
+                "--canonical-order" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = if Path::new(value).is_file() {
+                        builder.book_order(canon::load_order(value)?)
+                    } else {
+                        builder.canonical_order(value.parse()?)
+                    };
+                }
+                "--token-counter" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.token_counter(value.parse()?);
+                }
+                "--bridges" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.bridge_mode(value.parse()?);
+                }
+                "--titles" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.title_mode(value.parse()?);
+                }
+                "--acrostics" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.acrostic_mode(value.parse()?);
+                }
+                "--selah" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.selah_mode(value.parse()?);
+                }
+                "--added" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.added_words(value.parse()?);
+                }
+                "--divine-name" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.divine_name(value.parse()?);
+                }
+                "--styles" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.char_styles(value.parse()?);
+                }
+                "--quotations" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.quotation_mode(value.parse()?);
+                }
+                "--table-separator" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.table_separator(value.as_str());
+                }
+                "--optbreak" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.opt_break(value.parse()?);
+                }
+                "--keep-unicode-spaces" => builder = builder.preserve_unicode_spaces(true),
+                "-o" | "--output" => output_path = Some(args.next().ok_or_else(usage)?.clone()),
+                "--intro" => {
+                    intro_path = Some(args.next().ok_or_else(usage)?.clone());
+                    builder = builder.intro_mode(IntroMode::Export);
+                }
+                "--intro-rows" => builder = builder.intro_mode(IntroMode::VerseZero),
+                "--metadata" => {
+                    metadata_path = Some(args.next().ok_or_else(usage)?.clone());
+                }
+                "--show-license" => show_license = true,
+                "--books" => books_path = Some(args.next().ok_or_else(usage)?.clone()),
+                "--manifest" => manifest_path = Some(args.next().ok_or_else(usage)?.clone()),
+                "--footnote-marker" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.footnote_marker(value.as_str());
+                }
+                "--section-breadcrumb" => builder = builder.section_breadcrumb(true),
+                "--bom" => builder = builder.byte_order_mark(true),
+                "--threads" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    threads = value.parse().map_err(|e| {
+                        ParserError::ParseError(format!("Invalid thread count: {value} ({e})"))
+                    })?;
+                }
+                "--flush-every" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    let rows = value.parse().map_err(|e| {
+                        ParserError::ParseError(format!("Invalid row count: {value} ({e})"))
+                    })?;
+                    builder = builder.flush_every_n_rows(rows);
+                }
+                "--line-buffered" => line_buffered = true,
+                "--pipeline" => pipeline = true,
+                "--buffer-size" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.buffer_size(value.parse::<BufferSize>()?);
+                }
+                "--max-verse-size" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    let bytes = value.parse().map_err(|e| {
+                        ParserError::ParseError(format!("Invalid verse size: {value} ({e})"))
+                    })?;
+                    builder = builder.max_verse_size(bytes);
+                }
+                "--recover" => builder = builder.recover(true),
+                "--validate" => validate = true,
+                "--mmap" => mmap = true,
+                "--keep-book-codes" => builder = builder.normalize_book_codes(false),
+                "--book-names" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.book_names(BookNames::load(value)?);
+                }
+                "--book-abbreviations" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.book_abbreviations(value.parse()?);
+                }
+                "--remap" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    let map = if Path::new(value).is_file() {
+                        VersificationMap::load(value)?
+                    } else if let Some((from, to)) = value.rsplit_once(':')
+                        && Path::new(from).is_file()
+                    {
+                        VersificationMap::from_versification(&Versification::load(from)?, to)?
+                    } else {
+                        value.parse()?
+                    };
+                    builder = builder.versification_map(map);
+                }
+                "--reference" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.reference_format(value.parse()?);
+                }
+                "--range" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.ranges(reference::parse(value)?);
+                }
+                "--line-ending" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.line_ending(value.parse()?);
+                }
+                "--bidi" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.bidi(value.parse()?);
+                }
+                "--control-chars" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.control_chars(value.parse()?);
+                }
+                "--duplicates" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.duplicate_verses(value.parse()?);
+                }
+                "--missing-verses" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    missing_verses = Some(versification(value)?);
+                }
+                "--stanza-break" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.stanza_break(value.as_str());
+                }
+                "--join" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.join_rules(value.parse()?);
+                }
+                "--format" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    builder = builder.format(value.parse()?);
+                }
+                "--columns" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    for column in value.split(',') {
+                        builder = builder.column(column.parse()?);
+                    }
+                }
+                _ if arg.starts_with("--") || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
         }
+
+        if line_buffered {
+            // Rows read on several threads are only written once the whole document is read
+            builder = builder.flush_every_n_rows(1);
+            threads = 1;
+        }
+        if pipeline && threads > 1 {
+            // Books read on several threads are all read before the first row is written, so
+            // there is no reading left for the formatting to overlap
+            return Err(ParserError::ParseError(
+                "--pipeline formats rows while the document is read on one thread; it cannot be used with --threads above 1".to_string(),
+            ));
+        }
+        builder = builder.threads(threads).pipeline(pipeline);
+
+        let input_path = input_path.ok_or_else(usage)?;
+        // A compressed document keeps its `.xml` before the compression's extension
+        let document_path = [".gz", ".zst", ".xz"]
+            .iter()
+            .find_map(|extension| input_path.strip_suffix(extension))
+            .unwrap_or(&input_path);
+        if !is_url(&input_path) && !document_path.ends_with(".xml") && !input_path.ends_with(".zip")
+        {
+            return Err(ParserError::ParseError(
+                "Input file must be an XML file or a zip archive".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            input_path,
+            output_path,
+            intro_path,
+            metadata_path,
+            books_path,
+            manifest_path,
+            validate,
+            mmap,
+            show_license,
+            missing_verses,
+            config: builder.build(),
+        })
     }
 }
 
-/// Builder for `UsfxConfig`
-#[derive(Debug, Default)]
-pub struct UsfxConfigBuilder {
-    config: UsfxConfig,
+/// Parsed arguments of the `check-versification` subcommand
+#[derive(Debug)]
+struct CheckVersification {
+    input_path: String,
+    scheme: Versification,
 }
 
-impl UsfxConfigBuilder {
-    /// Create a new builder with default settings
-    pub fn new() -> Self {
-        Self {
-            config: UsfxConfig::default(),
+impl CheckVersification {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut input_path = None;
+        let mut scheme = Versification::kjv();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scheme" => scheme = versification(args.next().ok_or_else(usage)?)?,
+                _ if arg.starts_with("--") || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
         }
+
+        Ok(Self {
+            input_path: input_path.ok_or_else(usage)?,
+            scheme,
+        })
     }
 
-    /// Set the buffer size
-    pub fn buffer_size(mut self, size: usize) -> Self {
-        self.config.buffer_size = size;
-        self
+    /// Print the differences between the input's verse structure and the scheme, one per line
+    fn run(&self) -> Result<(), ParserError> {
+        let output = Box::new(std::io::sink());
+        let mut parser = open(&self.input_path, output, UsfxConfig::default())?;
+        let result = parser.parse();
+        for warning in parser.warnings() {
+            eprintln!("warning: {}: {warning}", self.input_path);
+        }
+        result?;
+        for difference in parser.versification_differences(&self.scheme) {
+            println!("{difference}");
+        }
+        Ok(())
     }
+}
+
+/// Parsed arguments of the `freq` subcommand
+#[derive(Debug)]
+struct Freq {
+    input_path: String,
+    tokenizer: Tokenizer,
+    ngrams: RangeInclusive<usize>,
+    per_book: bool,
+}
+
+impl Freq {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut input_path = None;
+        let mut tokenizer = Tokenizer::default();
+        let mut ngrams = 1..=1;
+        let mut per_book = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--lowercase" => tokenizer.lowercase = true,
+                "--strip-punctuation" => tokenizer.strip_punctuation = true,
+                "--ngrams" => ngrams = parse_ngram_sizes(args.next().ok_or_else(usage)?)?,
+                "--per-book" => per_book = true,
+                _ if arg.starts_with("--") || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
+        }
 
-    /// Set whether to trim text
-    pub fn trim_text(mut self, trim: bool) -> Self {
-        self.config.trim_text = trim;
-        self
+        Ok(Self {
+            input_path: input_path.ok_or_else(usage)?,
+            tokenizer,
+            ngrams,
+            per_book,
+        })
     }
 
-    /// Set whether to include debug output
-    pub fn debug_output(mut self, debug: bool) -> Self {
-        self.config.debug_output = debug;
-        self
+    /// Print the count of each token or n-gram of the verse text as TSV, counting as the verses
+    /// are read
+    fn run(&self) -> Result<(), ParserError> {
+        let mut frequencies = Frequencies::new(self.tokenizer)
+            .ngrams(self.ngrams.clone())
+            .per_book(self.per_book);
+        read_verses(&self.input_path, UsfxConfig::default(), |verse| {
+            frequencies.add(verse);
+            Ok(())
+        })?;
+        frequencies.write_tsv(std::io::stdout().lock())
     }
+}
+
+/// Parsed arguments of the `stats` subcommand
+#[derive(Debug)]
+struct Stats {
+    input_path: String,
+    tokenizer: Tokenizer,
+    per_chapter: bool,
+    format: OutputFormat,
+}
+
+impl Stats {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut input_path = None;
+        let mut tokenizer = Tokenizer::default();
+        let mut per_chapter = false;
+        let mut format = OutputFormat::Tsv;
 
-    /// Build the configuration
-    pub fn build(self) -> UsfxConfig {
-        self.config
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--lowercase" => tokenizer.lowercase = true,
+                "--strip-punctuation" => tokenizer.strip_punctuation = true,
+                "--per-chapter" => per_chapter = true,
+                "--format" => format = args.next().ok_or_else(usage)?.parse()?,
+                _ if arg.starts_with("--") || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
+        }
+
+        Ok(Self {
+            input_path: input_path.ok_or_else(usage)?,
+            tokenizer,
+            per_chapter,
+            format,
+        })
+    }
+
+    /// Print the statistics of each book or chapter, gathered as the verses are read
+    fn run(&self) -> Result<(), ParserError> {
+        let mut statistics = Statistics::new(self.tokenizer).per_chapter(self.per_chapter);
+        read_verses(&self.input_path, UsfxConfig::default(), |verse| {
+            statistics.add(verse);
+            Ok(())
+        })?;
+        statistics.write(std::io::stdout().lock(), self.format)
     }
 }
 
+/// Parsed arguments of the `concordance` subcommand
 #[derive(Debug)]
-pub enum ParserError {
-    FileError(std::io::Error),
-    XmlError(quick_xml::Error),
-    ParseError(String),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-enum ParserState {
-    Book,
-    Initial,
-    InVerse,
-    InWord,
-    InSection,
-    InFootnote,
-    InCrossReference,
-    VerseEnd,
-}
-
-/// Main parser for USFX files
-pub struct UsfxParser {
-    reader: Reader<BufReader<std::fs::File>>,
-    state: ParserState,
-    buffer: Vec<u8>,
-    output: Box<dyn Write>,
-    config: UsfxConfig,
+struct ConcordanceCli {
+    input_path: String,
+    tokenizer: Tokenizer,
+    min_count: usize,
+    format: OutputFormat,
 }
 
-impl UsfxParser {
-    /// Create a new USFX parser
-    ///
-    /// # Arguments
-    /// * `input_path` - Path to the input USFX file
-    /// * `output` - Writer for the output TSV
-    /// * `config` - Configuration options for the parser
-    ///
-    /// # Returns
-    /// * `Result<Self, ParserError>` - The parser instance or an error
-    pub fn new<P: AsRef<Path>>(
-        input_path: P,
-        output: Box<dyn Write>,
-        config: UsfxConfig,
-    ) -> Result<Self, ParserError> {
-        let reader = Reader::from_file(input_path).map_err(|e| {
-            ParserError::FileError(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                e.to_string(),
-            ))
+impl ConcordanceCli {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut input_path = None;
+        let mut tokenizer = Tokenizer::default();
+        let mut min_count = 1;
+        let mut format = OutputFormat::Tsv;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--lowercase" => tokenizer.lowercase = true,
+                "--strip-punctuation" => tokenizer.strip_punctuation = true,
+                "--min-count" => {
+                    let value = args.next().ok_or_else(usage)?;
+                    min_count = value.parse().map_err(|e| {
+                        ParserError::ParseError(format!("Invalid minimum count: {value} ({e})"))
+                    })?;
+                }
+                "--format" => format = args.next().ok_or_else(usage)?.parse()?,
+                _ if arg.starts_with("--") || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
+        }
+
+        Ok(Self {
+            input_path: input_path.ok_or_else(usage)?,
+            tokenizer,
+            min_count,
+            format,
+        })
+    }
+
+    /// Print the references of each token occurring at least the minimum number of times
+    fn run(&self) -> Result<(), ParserError> {
+        let mut concordance = Concordance::new(self.tokenizer);
+        read_verses(&self.input_path, UsfxConfig::default(), |verse| {
+            concordance.add(verse);
+            Ok(())
         })?;
+        concordance.write(std::io::stdout().lock(), self.format, self.min_count)
+    }
+}
+
+/// Parsed arguments of the `xref-graph` subcommand
+#[derive(Debug)]
+struct XrefGraph {
+    input_path: String,
+    output_path: Option<String>,
+    format: GraphFormat,
+}
+
+impl XrefGraph {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut input_path = None;
+        let mut output_path: Option<String> = None;
+        let mut format = None;
 
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => output_path = Some(args.next().ok_or_else(usage)?.clone()),
+                "--format" => format = Some(args.next().ok_or_else(usage)?.parse()?),
+                _ if arg.starts_with('-') || input_path.is_some() => return Err(usage()),
+                _ => input_path = Some(arg.clone()),
+            }
+        }
+
+        // Without --format, an output file ending in .graphml selects GraphML
+        let format = format.unwrap_or_else(|| match &output_path {
+            Some(path) if path.ends_with(".graphml") => GraphFormat::GraphMl,
+            _ => GraphFormat::Dot,
+        });
         Ok(Self {
-            reader,
-            state: ParserState::Initial,
-            buffer: Vec::with_capacity(config.buffer_size),
-            output,
-            config,
+            input_path: input_path.ok_or_else(usage)?,
+            output_path,
+            format,
         })
     }
 
-    /// Parse the USFX file and convert it to TSV format
-    ///
-    /// # Returns
-    /// * `Result<(), ParserError>` - Success or error
-    pub fn parse(&mut self) -> Result<(), ParserError> {
-        let mut in_content = false;
-        let mut last_state = ParserState::Initial;
-
-        loop {
-            match self.reader.read_event_into(&mut self.buffer) {
-                Err(e) => return Err(ParserError::XmlError(e)),
-
-                Ok(Event::Start(e)) => {
-                    match e.name().as_ref() {
-                        b"book" => self.state = ParserState::Book,
-                        b"ve" => {
-                            self.state = ParserState::VerseEnd;
-                            in_content = false;
-                        },
-                        b"w" => {
-                            self.state = ParserState::InWord;
-                            in_content = true;
-                        },
-                        b"v" => {
-                            in_content = true;
-                            self.state = ParserState::InVerse;
-                        }
-                        b"s" => {
-                            self.state = ParserState::InSection;
-                            in_content = false;
-                        }
-                        b"f" => self.state = ParserState::InFootnote,
-                        b"x" => self.state = ParserState::InCrossReference,
-                        _ => (),
-                    }
-                }
+    /// Write the graph of the cross references between verses
+    fn run(&self) -> Result<(), ParserError> {
+        let mut graph = CrossReferenceGraph::default();
+        read_verses(&self.input_path, UsfxConfig::default(), |verse| {
+            graph.add(verse);
+            Ok(())
+        })?;
+        match &self.output_path {
+            Some(path) => graph.write(BufWriter::new(File::create(path)?), self.format),
+            None => graph.write(std::io::stdout().lock(), self.format),
+        }
+    }
+}
 
-                Ok(Event::Text(e)) => {
-                    if in_content
-                        && self.state != ParserState::InFootnote
-                        && self.state != ParserState::InCrossReference
-                        && self.state != ParserState::InSection
-                        && self.state != ParserState::Book
-                    {
-                        let text = e
-                            .unescape()
-                            .map_err(|e| {
-                                ParserError::ParseError(format!("Failed to unescape text: {}", e))
-                            })?
-                            .into_owned();
-
-                        let text = if self.config.trim_text {
-                            text.trim()
-                        } else {
-                            &text
-                        };
-                        // write!(self.output, "[{:?}]", self.state).map_err(|e| ParserError::ParseError(e.to_string()))?;
-
-                        match self.state {
-                            ParserState::InVerse => match text {
-                                "\n" => write!(self.output, "^")
-                                    .map_err(|e| ParserError::ParseError(e.to_string()))?,
-                                _ => {
-                                    if in_content {
-                                        write!(self.output, "{}", text)
-                                        .map_err(|e| ParserError::ParseError(e.to_string()))?;
-                                    }
-                                },
-                            },
-                            ParserState::InWord => {
-if in_content {
-                                    match last_state {
-                                        ParserState::Initial => write!(self.output, "{}", text)
-                                            .map_err(|e| ParserError::ParseError(e.to_string()))?,
-                                        ParserState::InWord =>
-                                        {
-                                            write!(self.output, "{}", text)
-                                            .map_err(|e| ParserError::ParseError(e.to_string()))?;
-                                        }
-                                        _ => write!(self.output, " {}", text)
-                                            .map_err(|e| ParserError::ParseError(e.to_string()))?,
-                                    }
-                                }
-                            }
-                            _ => {
-                                // write!(self.output, "{}", text).map_err(|e| ParserError::ParseError(e.to_string()))?;
-                            }
-                        }
-                    }
-                    last_state = self.state.clone();
-                }
-
-                Ok(Event::End(e)) => match e.name().as_ref() {
-                    b"v"|b"w" => self.state = ParserState::InVerse,
-                    b"f"|b"s"|b"ve"|b"x" => self.state = ParserState::Initial,
-                    _ => (),
-                },
-
-                Ok(Event::Empty(e)) => {
-                    if e.name() == quick_xml::name::QName(b"ve") {
-                        self.state = ParserState::Initial;
-                        writeln!(self.output)
-                            .map_err(|e| ParserError::ParseError(e.to_string()))?;
-                    } else if e.name() == quick_xml::name::QName(b"v") {
-                        for attr in e.attributes() {
-                            let attr = attr.map_err(|e| ParserError::ParseError(e.to_string()))?;
-                            let key = str::from_utf8(attr.key.as_ref())
-                                .map_err(|e| ParserError::ParseError(e.to_string()))?;
-
-                            if key == "bcv" {
-                                let value = str::from_utf8(attr.value.as_ref())
-                                    .map_err(|e| ParserError::ParseError(e.to_string()))?;
-
-                                let parts: Vec<&str> = value.split('.').collect();
-                                if parts.len() == 3 {
-                                    write!(
-                                        self.output,
-                                        "{}\t{}\t{}\t",
-                                        parts[0], parts[1], parts[2]
-                                    )
-                                    .map_err(|e| ParserError::ParseError(e.to_string()))?;
-                                    in_content = true;
-                                }
-                            }
-                        }
-                    }
-                }
+/// Parsed arguments of the `parallel` subcommand
+#[derive(Debug)]
+struct Parallel {
+    source_path: String,
+    target_path: String,
+    output_path: Option<String>,
+    unaligned: Unaligned,
+}
+
+impl Parallel {
+    fn parse(args: &[String]) -> Result<Self, ParserError> {
+        let usage = || ParserError::ParseError(USAGE.to_string());
+        let mut paths = Vec::new();
+        let mut output_path = None;
+        let mut unaligned = Unaligned::Skip;
 
-                Ok(Event::Eof) => break,
-                _ => (),
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => output_path = Some(args.next().ok_or_else(usage)?.clone()),
+                "--unaligned" => unaligned = args.next().ok_or_else(usage)?.parse()?,
+                _ if arg.starts_with('-') || paths.len() == 2 => return Err(usage()),
+                _ => paths.push(arg.clone()),
             }
-            self.buffer.clear();
         }
-        Ok(())
+
+        let [source_path, target_path] = <[String; 2]>::try_from(paths).map_err(|_e| usage())?;
+        Ok(Self {
+            source_path,
+            target_path,
+            output_path,
+            unaligned,
+        })
+    }
+
+    /// Write the text of each verse of the source next to the same verse of the target
+    fn run(&self) -> Result<(), ParserError> {
+        let parser = |path: &str| open(path, Box::new(std::io::sink()), UsfxConfig::default());
+        let mut source = parser(&self.source_path)?;
+        let mut target = parser(&self.target_path)?;
+        let rows = align_by_reference([&mut source, &mut target], self.unaligned);
+        let result = match &self.output_path {
+            Some(path) => write_parallel(rows, BufWriter::new(File::create(path)?)),
+            None => write_parallel(rows, std::io::stdout().lock()),
+        };
+        for (path, parser) in [(&self.source_path, &source), (&self.target_path, &target)] {
+            for warning in parser.warnings() {
+                eprintln!("warning: {path}: {warning}");
+            }
+        }
+        result.map(|_| ())
+    }
+}
+
+/// Print the verses that differ between two revisions of a translation, with their word-level
+/// changes, one per line
+fn diff(args: &[String]) -> Result<(), ParserError> {
+    let [old_path, new_path] = args else {
+        return Err(ParserError::ParseError(USAGE.to_string()));
+    };
+    let parser = |path: &str| open(path, Box::new(std::io::sink()), UsfxConfig::default());
+    let mut old = parser(old_path)?;
+    let mut new = parser(new_path)?;
+    let mut output = std::io::stdout().lock();
+    let result = align_by_reference([&mut old, &mut new], Unaligned::Empty).try_for_each(|row| {
+        if let Some(change) = VerseChange::from_row(&row?) {
+            writeln!(output, "{change}")?;
+        }
+        Ok::<_, ParserError>(())
+    });
+    for (path, parser) in [(old_path, &old), (new_path, &new)] {
+        for warning in parser.warnings() {
+            eprintln!("warning: {path}: {warning}");
+        }
+    }
+    result
+}
+
+/// Parse a file without writing any output, passing each verse to `visit` as it is read and
+/// reporting warnings to standard error; stops at the first error `visit` returns
+fn read_verses(
+    input_path: &str,
+    config: UsfxConfig,
+    mut visit: impl FnMut(&Verse) -> Result<(), ParserError>,
+) -> Result<(), ParserError> {
+    let output = Box::new(std::io::sink());
+    let mut parser = open(input_path, output, config)?;
+    let result = loop {
+        match parser.next_verse() {
+            Ok(Some(verse)) => {
+                if let Err(error) = visit(&verse) {
+                    break Err(error);
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+    for warning in parser.warnings() {
+        eprintln!("warning: {input_path}: {warning}");
+    }
+    result
+}
+
+/// Write the tagged words of each verse as interlinear rows of surface form, lemma, Strong's
+/// number, morphology and gloss
+fn interlinear(args: &[String]) -> Result<(), ParserError> {
+    let usage = || ParserError::ParseError(USAGE.to_string());
+    let (input_path, output_path) = match args {
+        [input_path] => (input_path, None),
+        [flag, output_path, input_path] if flag == "-o" || flag == "--output" => {
+            (input_path, Some(output_path))
+        }
+        _ => return Err(usage()),
+    };
+    let mut writer: Box<dyn Write> = match output_path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    writeln!(writer, "{}", interlinear::HEADER)?;
+    let config = UsfxConfigBuilder::new().column(Column::Words).build();
+    read_verses(input_path, config, |verse| {
+        write_interlinear(verse, &mut writer).map(|_| ())
+    })?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// A built-in versification by name, or one loaded from a `.vrs` or `.json` file
+fn versification(value: &str) -> Result<Versification, ParserError> {
+    if Path::new(value).is_file() {
+        Versification::load(value)
+    } else {
+        value.parse()
     }
 }
 
 fn main() -> Result<(), ParserError> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        Err(ParserError::ParseError(
-            "Usage: usfx_to_tsv <usfx_input.xml>".to_string(),
-        ))
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .split_first()
+        .map(|(command, args)| (command.as_str(), args))
+    {
+        Some(("check-versification", args)) => return CheckVersification::parse(args)?.run(),
+        Some(("freq", args)) => return Freq::parse(args)?.run(),
+        Some(("parallel", args)) => return Parallel::parse(args)?.run(),
+        Some(("diff", args)) => return diff(args),
+        Some(("stats", args)) => return Stats::parse(args)?.run(),
+        Some(("concordance", args)) => return ConcordanceCli::parse(args)?.run(),
+        Some(("xref-graph", args)) => return XrefGraph::parse(args)?.run(),
+        Some(("interlinear", args)) => return interlinear(args),
+        Some(("index", args)) => return index(args),
+        Some(("duckdb", args)) => return duckdb(args),
+        Some(("fetch", args)) => return fetch(args),
+        Some(("serve", args)) => return serve(args),
+        Some(("grpc", args)) => return grpc(args),
+        _ => (),
+    }
+    let cli = Cli::parse(&args)?;
+    if cli.validate {
+        validate(&cli.input_path)?;
+    }
+    if let Some(url) = &cli.output_path
+        && is_postgres(url)
+    {
+        return load_postgres(&cli.input_path, url, cli.config);
+    }
+    if let Some(url) = &cli.output_path
+        && url.starts_with("redis://")
+    {
+        return load_redis(&cli.input_path, url, cli.config);
+    }
+    if let Some(url) = &cli.output_path
+        && url.starts_with("kafka://")
+    {
+        return publish_kafka(&cli.input_path, url, cli.config);
+    }
+    if let Some(url) = &cli.output_path
+        && url.starts_with("nats://")
+    {
+        return publish_nats(&cli.input_path, url, cli.config);
+    }
+    let mut upload = None;
+    let output: Box<dyn Write> = match &cli.output_path {
+        None => Box::new(std::io::stdout()),
+        Some(path) if is_object(path) => {
+            let (writer, finish) = object_output(path)?;
+            upload = Some(finish);
+            writer
+        }
+        Some(path) => Box::new(File::create(path)?),
+    };
+    let mut parser = if cli.mmap {
+        mapped(&cli.input_path, output, cli.config)?
     } else {
-        if !args[1].ends_with(".xml") {
-            return Err(ParserError::ParseError(
-                "Input file must be an XML file".to_string(),
-            ));
+        open(&cli.input_path, output, cli.config)?
+    };
+    let result = parser.parse();
+    for warning in parser.warnings() {
+        eprintln!("warning: {}: {warning}", cli.input_path);
+    }
+    match result {
+        // The program reading the output stopped early, as `head` does, which isn't an error
+        Err(ParserError::FileError(error))
+            if cli.output_path.is_none() && error.kind() == std::io::ErrorKind::BrokenPipe => {}
+        result => {
+            result?;
+        }
+    }
+    if let Some(upload) = upload {
+        upload()?;
+    }
+    if cli.show_license {
+        let metadata = parser.metadata();
+        for statement in metadata.copyright.iter().chain(&metadata.license) {
+            eprintln!("license: {}: {statement}", cli.input_path);
+        }
+    }
+    if let Some(versification) = &cli.missing_verses {
+        for missing in parser.missing_verses(versification) {
+            eprintln!("missing: {}: {missing}", cli.input_path);
+        }
+    }
+    if let Some(path) = &cli.intro_path {
+        parser.write_intro(File::create(path)?)?;
+    }
+    if let Some(path) = &cli.metadata_path {
+        parser.write_metadata(File::create(path)?)?;
+    }
+    if let Some(path) = &cli.books_path {
+        parser.write_books(File::create(path)?)?;
+    }
+    if let Some(path) = &cli.manifest_path {
+        let format = if path.ends_with(".json") {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Tsv
+        };
+        parser.write_manifest(File::create(path)?, format)?;
+    }
+    Ok(())
+}
+
+/// Build a full-text search index of the verses of a file
+#[cfg(feature = "index")]
+fn index(args: &[String]) -> Result<(), ParserError> {
+    let [input_path, index_path] = args else {
+        return Err(ParserError::ParseError(USAGE.to_string()));
+    };
+    let mut index = usfx_to_tsv::index::VerseIndex::create(index_path)?;
+    read_verses(input_path, UsfxConfig::default(), |verse| index.add(verse))?;
+    let verses = index.commit()?;
+    eprintln!("Indexed {verses} verses in {index_path}");
+    Ok(())
+}
+
+#[cfg(not(feature = "index"))]
+fn index(_args: &[String]) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "index requires building with the index feature".to_string(),
+    ))
+}
+
+/// Name of the translation in an input file, URL or object: its file name without extensions
+/// or eBible's `_usfx` suffix (`engkjv` for `xml/engkjv_usfx.xml.gz`)
+#[cfg(any(feature = "duckdb", feature = "redis"))]
+fn translation_name(input: &str) -> &str {
+    let file_name = input.rsplit(['/', '\\']).next().unwrap_or(input);
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    stem.strip_suffix("_usfx").unwrap_or(stem)
+}
+
+/// Load the verses of files into a `DuckDB` database, one translation per file
+#[cfg(feature = "duckdb")]
+fn duckdb(args: &[String]) -> Result<(), ParserError> {
+    use usfx_to_tsv::duckdb::{Layout, VerseDatabase};
+
+    let (layout, args) = match args {
+        [flag, rest @ ..] if flag == "--table-per-translation" => (Layout::PerTranslation, rest),
+        _ => (Layout::Combined, args),
+    };
+    let [database_path, input_paths @ ..] = args else {
+        return Err(ParserError::ParseError(USAGE.to_string()));
+    };
+    if input_paths.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        return Err(ParserError::ParseError(USAGE.to_string()));
+    }
+    let mut database = VerseDatabase::open(database_path, layout)?;
+    for input_path in input_paths {
+        let translation = translation_name(input_path);
+        let mut parser = open(input_path, Box::new(std::io::sink()), UsfxConfig::default())?;
+        let result = database.load(&mut parser, translation);
+        for warning in parser.warnings() {
+            eprintln!("warning: {input_path}: {warning}");
+        }
+        let rows = result?;
+        eprintln!("Loaded {rows} verses of {translation} into {database_path}");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "duckdb"))]
+fn duckdb(_args: &[String]) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "duckdb requires building with the duckdb feature".to_string(),
+    ))
+}
+
+/// Download the USFX bundle of a translation from eBible.org, and optionally convert it
+#[cfg(feature = "fetch")]
+fn fetch(args: &[String]) -> Result<(), ParserError> {
+    use usfx_to_tsv::ebible::{self, SCRIPTURES_URL};
+
+    let usage = || ParserError::ParseError(USAGE.to_string());
+    let mut directory = std::path::PathBuf::from(".");
+    let mut sha256 = None;
+    let mut convert = false;
+    let mut translation = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => directory = std::path::PathBuf::from(args.next().ok_or_else(usage)?),
+            "--sha256" => sha256 = Some(args.next().ok_or_else(usage)?.as_str()),
+            "--convert" => convert = true,
+            _ if arg.starts_with('-') || translation.is_some() => return Err(usage()),
+            _ => translation = Some(arg.as_str()),
+        }
+    }
+    let translation = translation.ok_or_else(usage)?;
+    let bundle = ebible::fetch(SCRIPTURES_URL, translation, sha256)?;
+    let path = bundle.save(&directory)?;
+    eprintln!(
+        "Saved {} ({} verses, SHA-256 {})",
+        path.display(),
+        bundle.verses,
+        bundle.sha256
+    );
+    if convert {
+        let tsv_path = directory.join(format!("{translation}.tsv"));
+        let output = Box::new(File::create(&tsv_path)?);
+        let mut parser = bundle.parser(output, UsfxConfig::default());
+        let result = parser.parse();
+        for warning in parser.warnings() {
+            eprintln!("warning: {}: {warning}", path.display());
+        }
+        result?;
+        eprintln!("Converted {} to {}", path.display(), tsv_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn fetch(_args: &[String]) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "fetch requires building with the fetch feature".to_string(),
+    ))
+}
+
+/// Run the HTTP conversion service
+#[cfg(feature = "server")]
+fn serve(args: &[String]) -> Result<(), ParserError> {
+    let usage = || ParserError::ParseError(USAGE.to_string());
+    let mut port = 8080u16;
+    let mut host = "127.0.0.1".to_string();
+    let mut data_dir = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = args.next().ok_or_else(usage)?;
+                port = value
+                    .parse()
+                    .map_err(|e| ParserError::ParseError(format!("Invalid port: {value} ({e})")))?;
+            }
+            "--host" => host.clone_from(args.next().ok_or_else(usage)?),
+            "--data" => data_dir = Some(std::path::PathBuf::from(args.next().ok_or_else(usage)?)),
+            _ => return Err(usage()),
+        }
+    }
+    let service = usfx_to_tsv::server::ConversionService::new(data_dir)?;
+    let address = format!("{host}:{port}");
+    eprintln!(
+        "Serving {} translations on http://{address}",
+        service.translations().len()
+    );
+    service.serve(&address)
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_args: &[String]) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "serve requires building with the server feature".to_string(),
+    ))
+}
+
+/// Run the gRPC conversion service
+#[cfg(feature = "grpc")]
+fn grpc(args: &[String]) -> Result<(), ParserError> {
+    let usage = || ParserError::ParseError(USAGE.to_string());
+    let mut port = 50051u16;
+    let mut host = "127.0.0.1".to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = args.next().ok_or_else(usage)?;
+                port = value
+                    .parse()
+                    .map_err(|e| ParserError::ParseError(format!("Invalid port: {value} ({e})")))?;
+            }
+            "--host" => host.clone_from(args.next().ok_or_else(usage)?),
+            _ => return Err(usage()),
         }
-        let input_path = &args[1];
-        let config = UsfxConfigBuilder::new().debug_output(true).build();
-        let output = Box::new(std::io::stdout());
-        let mut parser = UsfxParser::new(input_path, output, config)?;
-        parser.parse()
     }
+    let address = format!("{host}:{port}");
+    eprintln!("Serving gRPC on {address}");
+    usfx_to_tsv::grpc::serve(&address)
+}
+
+#[cfg(not(feature = "grpc"))]
+fn grpc(_args: &[String]) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "grpc requires building with the grpc feature".to_string(),
+    ))
+}
+
+/// Whether an input is an HTTP(S) URL rather than a file
+fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Whether an input or output is an object in S3 or Cloud Storage rather than a file
+fn is_object(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("gs://")
+}
+
+/// A parser reading a file, a URL or an object
+fn open(
+    input: &str,
+    output: Box<dyn Write>,
+    config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    if is_url(input) {
+        fetched(input, output, config)
+    } else if is_object(input) {
+        object_input(input, output, config)
+    } else {
+        UsfxParser::new(input, output, config)
+    }
+}
+
+/// A parser reading the response to an HTTP(S) request
+#[cfg(feature = "http")]
+fn fetched(
+    url: &str,
+    output: Box<dyn Write>,
+    config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    UsfxParser::from_url(url, output, config)
+}
+
+#[cfg(not(feature = "http"))]
+fn fetched(
+    _url: &str,
+    _output: Box<dyn Write>,
+    _config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    Err(ParserError::ParseError(
+        "URL input requires building with the http feature".to_string(),
+    ))
+}
+
+/// Uploads an object once the rows have been written to it
+type Upload = Box<dyn FnOnce() -> Result<(), ParserError>>;
+
+/// A parser reading an object in S3 or Cloud Storage
+#[cfg(feature = "s3")]
+fn object_input(
+    uri: &str,
+    output: Box<dyn Write>,
+    config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    UsfxParser::from_object(&uri.parse()?, output, config)
+}
+
+/// A writer holding the rows for an object in S3 or Cloud Storage, and the upload writing them
+#[cfg(feature = "s3")]
+fn object_output(uri: &str) -> Result<(Box<dyn Write>, Upload), ParserError> {
+    let writer = usfx_to_tsv::storage::ObjectWriter::new(uri.parse()?);
+    Ok((Box::new(writer.clone()), Box::new(move || writer.upload())))
+}
+
+#[cfg(not(feature = "s3"))]
+fn object_input(
+    _uri: &str,
+    _output: Box<dyn Write>,
+    _config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    Err(ParserError::ParseError(
+        "s3:// and gs:// input requires building with the s3 feature".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "s3"))]
+fn object_output(_uri: &str) -> Result<(Box<dyn Write>, Upload), ParserError> {
+    Err(ParserError::ParseError(
+        "s3:// and gs:// output requires building with the s3 feature".to_string(),
+    ))
+}
+
+/// Whether an output is a Postgres database rather than a file
+fn is_postgres(output: &str) -> bool {
+    output.starts_with("postgres://") || output.starts_with("postgresql://")
+}
+
+/// Copy the verses of a file into a Postgres table
+#[cfg(feature = "postgres")]
+fn load_postgres(input_path: &str, url: &str, config: UsfxConfig) -> Result<(), ParserError> {
+    let destination: usfx_to_tsv::postgres::Destination = url.parse()?;
+    let mut parser = open(input_path, Box::new(std::io::sink()), config)?;
+    let result = usfx_to_tsv::postgres::copy_verses(&mut parser, &destination);
+    for warning in parser.warnings() {
+        eprintln!("warning: {input_path}: {warning}");
+    }
+    let rows = result?;
+    eprintln!("Copied {rows} verses into {}", destination.table);
+    Ok(())
+}
+
+#[cfg(not(feature = "postgres"))]
+fn load_postgres(_input_path: &str, _url: &str, _config: UsfxConfig) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "postgres:// output requires building with the postgres feature".to_string(),
+    ))
+}
+
+/// Write the verses of a file to Redis, under keys starting with the translation
+#[cfg(feature = "redis")]
+fn load_redis(input_path: &str, url: &str, config: UsfxConfig) -> Result<(), ParserError> {
+    let destination: usfx_to_tsv::redis::Destination = url.parse()?;
+    let translation = destination
+        .translation
+        .as_deref()
+        .unwrap_or_else(|| translation_name(input_path));
+    let mut parser = open(input_path, Box::new(std::io::sink()), config)?;
+    let result = usfx_to_tsv::redis::write_verses(&mut parser, &destination, translation);
+    for warning in parser.warnings() {
+        eprintln!("warning: {input_path}: {warning}");
+    }
+    let verses = result?;
+    eprintln!(
+        "Wrote {verses} verses of {translation} to {}",
+        destination.address
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "redis"))]
+fn load_redis(_input_path: &str, _url: &str, _config: UsfxConfig) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "redis:// output requires building with the redis feature".to_string(),
+    ))
+}
+
+/// Publish the verses of a file to a Kafka topic
+#[cfg(feature = "kafka")]
+fn publish_kafka(input_path: &str, url: &str, config: UsfxConfig) -> Result<(), ParserError> {
+    let destination: usfx_to_tsv::kafka::Destination = url.parse()?;
+    let mut parser = open(input_path, Box::new(std::io::sink()), config)?;
+    let result = usfx_to_tsv::kafka::publish_verses(&mut parser, &destination);
+    for warning in parser.warnings() {
+        eprintln!("warning: {input_path}: {warning}");
+    }
+    let messages = result?;
+    eprintln!("Published {messages} verses to {}", destination.topic);
+    Ok(())
+}
+
+#[cfg(not(feature = "kafka"))]
+fn publish_kafka(_input_path: &str, _url: &str, _config: UsfxConfig) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "kafka:// output requires building with the kafka feature".to_string(),
+    ))
+}
+
+/// Publish the verses of a file to NATS subjects
+#[cfg(feature = "nats")]
+fn publish_nats(input_path: &str, url: &str, config: UsfxConfig) -> Result<(), ParserError> {
+    let destination: usfx_to_tsv::nats::Destination = url.parse()?;
+    let mut parser = open(input_path, Box::new(std::io::sink()), config)?;
+    let result = usfx_to_tsv::nats::publish_verses(&mut parser, &destination);
+    for warning in parser.warnings() {
+        eprintln!("warning: {input_path}: {warning}");
+    }
+    let messages = result?;
+    eprintln!("Published {messages} verses to {}.>", destination.subject);
+    Ok(())
+}
+
+#[cfg(not(feature = "nats"))]
+fn publish_nats(_input_path: &str, _url: &str, _config: UsfxConfig) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "nats:// output requires building with the nats feature".to_string(),
+    ))
+}
+
+/// A parser reading a memory-mapped file
+#[cfg(feature = "mmap")]
+fn mapped(
+    path: &str,
+    output: Box<dyn Write>,
+    config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    UsfxParser::mapped(path, output, config)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn mapped(
+    _path: &str,
+    _output: Box<dyn Write>,
+    _config: UsfxConfig,
+) -> Result<UsfxParser, ParserError> {
+    Err(ParserError::ParseError(
+        "--mmap requires building with the mmap feature".to_string(),
+    ))
+}
+
+/// Report schema violations in the input to standard error
+#[cfg(feature = "validate")]
+fn validate(path: &str) -> Result<(), ParserError> {
+    for violation in usfx_to_tsv::validate::validate_file(path)? {
+        eprintln!("invalid: {path}: {violation}");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "validate"))]
+fn validate(_path: &str) -> Result<(), ParserError> {
+    Err(ParserError::ParseError(
+        "--validate requires building with the validate feature".to_string(),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use usfx_to_tsv::{Canon, Column, Granularity};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_granularity_argument() {
+        let cli = Cli::parse(&args(&["--granularity", "book", "bible.xml"])).unwrap();
+        assert_eq!(cli.input_path, "bible.xml");
+        assert_eq!(cli.config.granularity, Granularity::Book);
+        assert_eq!(cli.config.canonical_order, None);
+    }
+
+    #[test]
+    fn test_canonical_order_argument() {
+        let cli = Cli::parse(&args(&["bible.xml", "--canonical-order", "orthodox"])).unwrap();
+        assert_eq!(cli.config.canonical_order, Some(Canon::Orthodox));
+    }
+
+    #[test]
+    fn test_columns_argument() {
+        let cli = Cli::parse(&args(&["--columns", "raw", "bible.xml"])).unwrap();
+        assert_eq!(cli.config.columns, vec![Column::Raw]);
+        assert!(Cli::parse(&args(&["--columns", "raw,colour", "bible.xml"])).is_err());
+    }
+
+    #[test]
+    fn test_check_versification_arguments() {
+        let check = CheckVersification::parse(&args(&["bible.xml", "--scheme", "eng"])).unwrap();
+        assert_eq!(check.input_path, "bible.xml");
+        assert_eq!(check.scheme, Versification::kjv());
+        assert!(CheckVersification::parse(&args(&["--scheme", "lxx", "bible.xml"])).is_err());
+        assert!(CheckVersification::parse(&args(&[])).is_err());
+    }
+
+    #[test]
+    fn test_freq_arguments() {
+        let freq = Freq::parse(&args(&["--lowercase", "bible.xml"])).unwrap();
+        assert_eq!(freq.input_path, "bible.xml");
+        assert!(freq.tokenizer.lowercase && !freq.tokenizer.strip_punctuation);
+        assert!(Freq::parse(&args(&["--stem", "bible.xml"])).is_err());
+        let freq = Freq::parse(&args(&["bible.xml", "--ngrams", "2..4", "--per-book"])).unwrap();
+        assert_eq!(freq.ngrams, 2..=4);
+        assert!(freq.per_book);
+    }
+
+    #[test]
+    fn test_concordance_arguments() {
+        let cli = ConcordanceCli::parse(&args(&[
+            "--min-count",
+            "3",
+            "--format",
+            "json",
+            "bible.xml",
+        ]))
+        .unwrap();
+        assert_eq!((cli.min_count, cli.format), (3, OutputFormat::Json));
+        assert!(ConcordanceCli::parse(&args(&["--min-count", "many", "bible.xml"])).is_err());
+    }
+
+    #[test]
+    fn test_parallel_arguments() {
+        let parallel = Parallel::parse(&args(&[
+            "en.xml",
+            "fr.xml",
+            "-o",
+            "pairs.tsv",
+            "--unaligned",
+            "empty",
+        ]))
+        .unwrap();
+        assert_eq!(
+            (parallel.source_path.as_str(), parallel.target_path.as_str()),
+            ("en.xml", "fr.xml")
+        );
+        assert_eq!(parallel.output_path.as_deref(), Some("pairs.tsv"));
+        assert_eq!(parallel.unaligned, Unaligned::Empty);
+        assert!(Parallel::parse(&args(&["en.xml"])).is_err());
+        assert!(Parallel::parse(&args(&["en.xml", "fr.xml", "de.xml"])).is_err());
+    }
+
+    #[test]
+    fn test_line_buffered_argument() {
+        let cli = Cli::parse(&args(&["--threads", "4", "--line-buffered", "bible.xml"])).unwrap();
+        assert_eq!(cli.config.flush_every_n_rows, Some(1));
+        assert_eq!(cli.config.threads, 1);
+    }
+
+    #[test]
+    fn test_pipeline_argument() {
+        let cli = Cli::parse(&args(&["--pipeline", "bible.xml"])).unwrap();
+        assert!(cli.config.pipeline);
+        assert!(matches!(
+            Cli::parse(&args(&["--threads", "4", "--pipeline", "bible.xml"])),
+            Err(ParserError::ParseError(message)) if message.contains("--threads")
+        ));
+        let cli = Cli::parse(&args(&[
+            "--threads",
+            "4",
+            "--line-buffered",
+            "--pipeline",
+            "bible.xml",
+        ]))
+        .unwrap();
+        assert_eq!(cli.config.threads, 1);
+    }
+
     #[test]
-    fn test_basic_parsing() {
-        todo!()
+    fn test_rejects_bad_arguments() {
+        assert!(Cli::parse(&args(&[])).is_err());
+        assert!(Cli::parse(&args(&["bible.txt"])).is_err());
+        assert!(Cli::parse(&args(&["--granularity", "page", "bible.xml"])).is_err());
     }
 }
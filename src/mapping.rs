@@ -0,0 +1,496 @@
+//! Remapping of verse references between versification schemes
+//!
+//! Each built-in scheme is described by how its references map onto the KJV scheme, so any two
+//! of them can be mapped through it. A mapping is a list of stages, each a set of rules such as
+//! `MAL 3:19-24 = MAL 4:1-6`, applied in turn; a reference no rule of a stage covers passes
+//! through that stage unchanged.
+
+use crate::versification::Versification;
+use crate::{ParserError, Verse};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Chapters and verses of the Hebrew (original) text that differ from the KJV, outside the
+/// Psalms
+const ORIGINAL: &str = "
+GEN 32:1 = GEN 31:55
+GEN 32:2-33 = GEN 32:1-32
+EXO 7:26-29 = EXO 8:1-4
+EXO 8:1-28 = EXO 8:5-32
+EXO 21:37 = EXO 22:1
+EXO 22:1-30 = EXO 22:2-31
+LEV 5:20-26 = LEV 6:1-7
+LEV 6:1-23 = LEV 6:8-30
+NUM 17:1-15 = NUM 16:36-50
+NUM 17:16-28 = NUM 17:1-13
+NUM 30:1 = NUM 29:40
+NUM 30:2-17 = NUM 30:1-16
+DEU 13:1 = DEU 12:32
+DEU 13:2-19 = DEU 13:1-18
+DEU 23:1 = DEU 22:30
+DEU 23:2-26 = DEU 23:1-25
+DEU 28:69 = DEU 29:1
+DEU 29:1-28 = DEU 29:2-29
+1SA 21:1 = 1SA 20:42
+1SA 21:2-16 = 1SA 21:1-15
+1SA 24:1 = 1SA 23:29
+1SA 24:2-23 = 1SA 24:1-22
+2SA 19:1 = 2SA 18:33
+2SA 19:2-44 = 2SA 19:1-43
+1KI 5:1-14 = 1KI 4:21-34
+1KI 5:15-32 = 1KI 5:1-18
+2KI 12:1 = 2KI 11:21
+2KI 12:2-22 = 2KI 12:1-21
+1CH 5:27-41 = 1CH 6:1-15
+1CH 6:1-66 = 1CH 6:16-81
+2CH 1:18 = 2CH 2:1
+2CH 2:1-17 = 2CH 2:2-18
+2CH 13:23 = 2CH 14:1
+2CH 14:1-14 = 2CH 14:2-15
+NEH 3:33-38 = NEH 4:1-6
+NEH 4:1-17 = NEH 4:7-23
+NEH 10:1 = NEH 9:38
+NEH 10:2-40 = NEH 10:1-39
+JOB 40:25-32 = JOB 41:1-8
+JOB 41:1-26 = JOB 41:9-34
+ECC 4:17 = ECC 5:1
+ECC 5:1-19 = ECC 5:2-20
+SNG 7:1 = SNG 6:13
+SNG 7:2-14 = SNG 7:1-13
+ISA 8:23 = ISA 9:1
+ISA 9:1-20 = ISA 9:2-21
+JER 8:23 = JER 9:1
+JER 9:1-25 = JER 9:2-26
+EZK 21:1-5 = EZK 20:45-49
+EZK 21:6-37 = EZK 21:1-32
+DAN 3:31-33 = DAN 4:1-3
+DAN 4:1-34 = DAN 4:4-37
+DAN 6:1 = DAN 5:31
+DAN 6:2-29 = DAN 6:1-28
+HOS 2:1-2 = HOS 1:10-11
+HOS 2:3-25 = HOS 2:1-23
+HOS 12:1 = HOS 11:12
+HOS 12:2-15 = HOS 12:1-14
+HOS 14:1 = HOS 13:16
+HOS 14:2-10 = HOS 14:1-9
+JOL 3:1-5 = JOL 2:28-32
+JOL 4:1-21 = JOL 3:1-21
+JON 2:1 = JON 1:17
+JON 2:2-11 = JON 2:1-10
+MIC 4:14 = MIC 5:1
+MIC 5:1-14 = MIC 5:2-15
+NAM 2:1 = NAM 1:15
+NAM 2:2-14 = NAM 2:1-13
+ZEC 2:1-4 = ZEC 1:18-21
+ZEC 2:5-17 = ZEC 2:1-13
+MAL 3:19-24 = MAL 4:1-6
+";
+
+/// Psalms whose Hebrew text numbers the title as the first verse, so that each remaining verse
+/// is numbered one higher than in the KJV
+const PSALM_TITLES: &[u32] = &[
+    3, 4, 5, 6, 7, 8, 9, 12, 18, 19, 20, 21, 22, 30, 31, 34, 36, 38, 39, 40, 41, 42, 44, 45, 46,
+    47, 48, 49, 53, 55, 56, 57, 58, 59, 61, 62, 63, 64, 65, 67, 68, 69, 70, 75, 76, 77, 80, 81, 83,
+    84, 85, 88, 89, 92, 102, 108, 140, 142,
+];
+
+/// Psalms whose Hebrew text numbers the title as the first two verses
+const PSALM_LONG_TITLES: &[u32] = &[51, 52, 54, 60];
+
+/// Psalms of the Greek (and Latin) Psalter that are split or joined in the Hebrew; verses
+/// within each psalm are numbered as in the Hebrew
+const GREEK_PSALMS: &str = "
+PSA 9:22-39 = PSA 10:1-18
+PSA 113:1-8 = PSA 114:1-8
+PSA 113:9-26 = PSA 115:1-18
+PSA 114:1-9 = PSA 116:1-9
+PSA 115:1-10 = PSA 116:10-19
+PSA 146:1-11 = PSA 147:1-11
+PSA 147:1-9 = PSA 147:12-20
+";
+
+/// Runs of whole psalms the Greek Psalter numbers one lower than the Hebrew, as the psalms it
+/// splits and joins leave them
+const GREEK_PSALM_SHIFTS: &[(u32, u32)] = &[(10, 112), (116, 145)];
+
+/// A verse reference with numeric chapter and verse
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Position {
+    book: String,
+    chapter: u32,
+    verse: u32,
+}
+
+/// Verses `first..=last` of a chapter mapped onto verses starting at `target`; a range mapped
+/// onto a single verse collapses into that verse
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    book: String,
+    chapter: u32,
+    first: u32,
+    last: u32,
+    target: Position,
+    target_last: u32,
+}
+
+impl Rule {
+    fn map(&self, position: &Position) -> Option<Position> {
+        if position.book != self.book
+            || position.chapter != self.chapter
+            || !(self.first..=self.last).contains(&position.verse)
+        {
+            return None;
+        }
+        let verse = if self.target_last == self.target.verse {
+            self.target.verse
+        } else {
+            self.target.verse + (position.verse - self.first)
+        };
+        Some(Position {
+            verse,
+            ..self.target.clone()
+        })
+    }
+
+    fn inverse(&self) -> Self {
+        Self {
+            book: self.target.book.clone(),
+            chapter: self.target.chapter,
+            first: self.target.verse,
+            last: self.target_last,
+            target: Position {
+                book: self.book.clone(),
+                chapter: self.chapter,
+                verse: self.first,
+            },
+            // A collapsed range maps back onto its first verse
+            target_last: if self.target_last == self.target.verse {
+                self.first
+            } else {
+                self.last
+            },
+        }
+    }
+}
+
+/// A mapping of verse references from one versification scheme onto another
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersificationMap {
+    stages: Vec<Vec<Rule>>,
+}
+
+impl VersificationMap {
+    /// The mapping from the built-in scheme `from` onto the built-in scheme `to`
+    ///
+    /// The schemes are `kjv` (or `eng`), `original` (the Hebrew text) and `lxx` or `vulgate`,
+    /// whose built-in tables cover the numbering of the Psalter only.
+    pub fn between(from: &str, to: &str) -> Result<Self, ParserError> {
+        let mut stages = to_kjv(from)?;
+        stages.extend(to_kjv(to)?.iter().rev().map(|stage| invert(stage)));
+        Ok(Self { stages })
+    }
+
+    /// The mapping from a loaded versification onto the built-in scheme `to`, through the
+    /// original versification that its mapping lines target
+    pub fn from_versification(
+        versification: &Versification,
+        to: &str,
+    ) -> Result<Self, ParserError> {
+        Ok(versification
+            .mapping()
+            .clone()
+            .then(Self::between("original", to)?))
+    }
+
+    /// This mapping followed by `next`
+    #[must_use]
+    pub fn then(mut self, next: Self) -> Self {
+        self.stages.extend(next.stages);
+        self
+    }
+
+    /// Load a mapping from a file of rules; see [`VersificationMap::parse`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ParserError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a mapping from lines such as `MAL 3:19-24 = MAL 4:1-6`, each mapping a verse or
+    /// range of one chapter onto a verse or range of the same length
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, ParserError> {
+        Ok(Self {
+            stages: vec![parse_rules(text)?],
+        })
+    }
+
+    /// Map a reference, returning the book, chapter and verse in the target scheme
+    pub fn map(&self, book: &str, chapter: u32, verse: u32) -> (String, u32, u32) {
+        let mut position = Position {
+            book: book.to_string(),
+            chapter,
+            verse,
+        };
+        for stage in &self.stages {
+            if let Some(mapped) = stage.iter().find_map(|rule| rule.map(&position)) {
+                position = mapped;
+            }
+        }
+        (position.book, position.chapter, position.verse)
+    }
+
+    /// The mapping in the opposite direction
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        Self {
+            stages: self
+                .stages
+                .iter()
+                .rev()
+                .map(|stage| invert(stage))
+                .collect(),
+        }
+    }
+
+    /// Remap the reference of a verse in place; a bridge whose end falls in a different
+    /// chapter from its start after mapping keeps only its start
+    pub fn apply(&self, verse: &mut Verse) {
+        let (Ok(source_chapter), Ok(number)) = (verse.chapter.parse(), verse.verse.parse()) else {
+            return;
+        };
+        let (book, chapter, number) = self.map(&verse.book, source_chapter, number);
+        if let Some(end) = verse.verse_end.as_deref().and_then(|end| end.parse().ok()) {
+            let (end_book, end_chapter, end) = self.map(&verse.book, source_chapter, end);
+            verse.verse_end = (end_book == book && end_chapter == chapter && end > number)
+                .then(|| end.to_string());
+        }
+        verse.book = book;
+        verse.chapter = chapter.to_string();
+        verse.verse = number.to_string();
+    }
+}
+
+impl FromStr for VersificationMap {
+    type Err = ParserError;
+
+    /// Parse `from:to`, e.g. `original:kjv`, naming two built-in schemes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once(':').ok_or_else(|| {
+            ParserError::ParseError(format!(
+                "Invalid versification mapping: {s} (expected from:to, e.g. original:kjv)"
+            ))
+        })?;
+        Self::between(from, to)
+    }
+}
+
+/// The stages mapping a built-in scheme onto the KJV scheme
+fn to_kjv(scheme: &str) -> Result<Vec<Vec<Rule>>, ParserError> {
+    match scheme {
+        "kjv" | "eng" => Ok(Vec::new()),
+        "original" => {
+            let mut rules = parse_rules(ORIGINAL)?;
+            rules.extend(psalm_title_rules());
+            Ok(vec![rules])
+        }
+        "lxx" | "vulgate" => {
+            let mut rules = parse_rules(GREEK_PSALMS)?;
+            rules.extend(greek_psalm_shift_rules());
+            Ok(vec![rules, psalm_title_rules()])
+        }
+        _ => Err(ParserError::ParseError(format!(
+            "Unknown versification scheme: {scheme} (expected kjv, eng, original, lxx or vulgate)"
+        ))),
+    }
+}
+
+/// Rules mapping Hebrew psalm titles onto verse 0 and the verses after them onto the KJV
+/// numbering
+fn psalm_title_rules() -> Vec<Rule> {
+    let kjv = Versification::kjv();
+    let rule = |first, last, verse, target_last| Rule {
+        book: "PSA".to_string(),
+        chapter: 0,
+        first,
+        last,
+        target: Position {
+            book: "PSA".to_string(),
+            chapter: 0,
+            verse,
+        },
+        target_last,
+    };
+    let mut rules = Vec::new();
+    let titles = PSALM_TITLES.iter().map(|&psalm| (psalm, 1));
+    let long_titles = PSALM_LONG_TITLES.iter().map(|&psalm| (psalm, 2));
+    for (psalm, offset) in titles.chain(long_titles) {
+        let verses = kjv.verses("PSA", psalm).unwrap_or_default();
+        for mut rule in [
+            rule(1, offset, 0, 0),
+            rule(offset + 1, offset + verses, 1, verses),
+        ] {
+            rule.chapter = psalm;
+            rule.target.chapter = psalm;
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+/// Rules mapping each psalm of [`GREEK_PSALM_SHIFTS`], title and all, onto the next psalm
+fn greek_psalm_shift_rules() -> Vec<Rule> {
+    let psalm = |chapter| Position {
+        book: "PSA".to_string(),
+        chapter,
+        verse: 0,
+    };
+    GREEK_PSALM_SHIFTS
+        .iter()
+        .flat_map(|&(first, last)| first..=last)
+        .map(|chapter| Rule {
+            book: "PSA".to_string(),
+            chapter,
+            first: 0,
+            last: u32::MAX,
+            target: psalm(chapter + 1),
+            target_last: u32::MAX,
+        })
+        .collect()
+}
+
+fn invert(stage: &[Rule]) -> Vec<Rule> {
+    stage.iter().map(Rule::inverse).collect()
+}
+
+fn parse_rules(text: &str) -> Result<Vec<Rule>, ParserError> {
+    let mut rules = Vec::new();
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: &str| {
+            ParserError::ParseError(format!(
+                "Invalid versification mapping line {number}: {line} ({reason})"
+            ))
+        };
+        let (source, target) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected source = target"))?;
+        let (source, last) =
+            parse_range(source).ok_or_else(|| invalid("expected BOOK chapter:verse[-verse]"))?;
+        let (target, target_last) =
+            parse_range(target).ok_or_else(|| invalid("expected BOOK chapter:verse[-verse]"))?;
+        if target_last != target.verse && target_last - target.verse != last - source.verse {
+            return Err(invalid("ranges differ in length"));
+        }
+        rules.push(Rule {
+            book: source.book,
+            chapter: source.chapter,
+            first: source.verse,
+            last,
+            target,
+            target_last,
+        });
+    }
+    Ok(rules)
+}
+
+/// Parse `BOOK chapter:verse[-verse]` into its first verse and last verse number
+fn parse_range(text: &str) -> Option<(Position, u32)> {
+    let (book, numbers) = text.trim().split_once(' ')?;
+    let (chapter, verses) = numbers.trim().split_once(':')?;
+    let (first, last) = verses.split_once('-').unwrap_or((verses, verses));
+    let first: u32 = first.trim().parse().ok()?;
+    let last: u32 = last.trim().parse().ok()?;
+    if last < first {
+        return None;
+    }
+    let book = crate::canon::usfm_code(book).unwrap_or(book);
+    Some((
+        Position {
+            book: book.to_string(),
+            chapter: chapter.trim().parse().ok()?,
+            verse: first,
+        },
+        last,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(map: &VersificationMap, book: &str, chapter: u32, verse: u32) -> String {
+        let (book, chapter, verse) = map.map(book, chapter, verse);
+        format!("{book} {chapter}:{verse}")
+    }
+
+    #[test]
+    fn test_original_to_kjv() {
+        let map: VersificationMap = "original:kjv".parse().unwrap();
+        assert_eq!(mapped(&map, "MAL", 3, 19), "MAL 4:1");
+        assert_eq!(mapped(&map, "MAL", 3, 18), "MAL 3:18");
+        assert_eq!(mapped(&map, "JOL", 4, 21), "JOL 3:21");
+        assert_eq!(mapped(&map, "PSA", 51, 1), "PSA 51:0");
+        assert_eq!(mapped(&map, "PSA", 51, 2), "PSA 51:0");
+        assert_eq!(mapped(&map, "PSA", 51, 21), "PSA 51:19");
+        assert_eq!(mapped(&map, "PSA", 23, 1), "PSA 23:1");
+
+        let back = map.inverse();
+        assert_eq!(mapped(&back, "MAL", 4, 6), "MAL 3:24");
+        assert_eq!(mapped(&back, "PSA", 51, 19), "PSA 51:21");
+        assert_eq!(map, VersificationMap::between("original", "kjv").unwrap());
+        assert_eq!(back, VersificationMap::between("kjv", "original").unwrap());
+    }
+
+    #[test]
+    fn test_greek_psalter() {
+        let map: VersificationMap = "vulgate:kjv".parse().unwrap();
+        assert_eq!(mapped(&map, "PSA", 9, 22), "PSA 10:1");
+        assert_eq!(mapped(&map, "PSA", 9, 2), "PSA 9:1");
+        assert_eq!(mapped(&map, "PSA", 113, 9), "PSA 115:1");
+        assert_eq!(mapped(&map, "PSA", 147, 1), "PSA 147:12");
+        let map: VersificationMap = "lxx:kjv".parse().unwrap();
+        assert_eq!(mapped(&map, "PSA", 22, 1), "PSA 23:1");
+        assert_eq!(mapped(&map, "PSA", 50, 1), "PSA 51:0");
+        assert_eq!(mapped(&map, "PSA", 50, 3), "PSA 51:1");
+        assert_eq!(mapped(&map, "PSA", 145, 10), "PSA 146:10");
+        assert_eq!(mapped(&map, "PSA", 148, 1), "PSA 148:1");
+        assert_eq!(mapped(&map.inverse(), "PSA", 23, 1), "PSA 22:1");
+        assert_eq!(mapped(&map.inverse(), "PSA", 51, 1), "PSA 50:3");
+        let map: VersificationMap = "lxx:original".parse().unwrap();
+        assert_eq!(mapped(&map, "PSA", 9, 22), "PSA 10:1");
+        assert_eq!(mapped(&map, "PSA", 9, 2), "PSA 9:2");
+        assert_eq!(mapped(&map, "PSA", 112, 10), "PSA 113:10");
+        assert_eq!(mapped(&map, "PSA", 116, 2), "PSA 117:2");
+    }
+
+    #[test]
+    fn test_mapping_file() {
+        let map = VersificationMap::parse("# Malachi\nMal 3:19-24 = MAL 4:1-6\n").unwrap();
+        let mut verse = Verse {
+            book: "MAL".to_string(),
+            chapter: "3".to_string(),
+            verse: "20".to_string(),
+            verse_end: Some("21".to_string()),
+            ..Verse::default()
+        };
+        map.apply(&mut verse);
+        assert_eq!((verse.chapter.as_str(), verse.verse.as_str()), ("4", "2"));
+        assert_eq!(verse.verse_end.as_deref(), Some("3"));
+        assert!(VersificationMap::parse("MAL 3:19-24 = MAL 4:1-5").is_err());
+        assert!(VersificationMap::parse("MAL 3 = MAL 4").is_err());
+        assert!("original:masoretic".parse::<VersificationMap>().is_err());
+    }
+
+    #[test]
+    fn test_loaded_versification() {
+        let versification =
+            Versification::from_vrs("MAL 1:14 2:17 3:18 4:6\nMAL 4:1-6 = MAL 3:19-24\n").unwrap();
+        let map = VersificationMap::from_versification(&versification, "kjv").unwrap();
+        assert_eq!(mapped(&map, "MAL", 4, 2), "MAL 4:2");
+        let map = VersificationMap::from_versification(&versification, "original").unwrap();
+        assert_eq!(mapped(&map, "MAL", 4, 2), "MAL 3:20");
+    }
+}
@@ -0,0 +1,243 @@
+//! Publishing verses to NATS, one message per verse
+//!
+//! Each verse is published to a subject ending in its reference, `<subject>.JHN.11.35` (with
+//! the part of a split verse as in `1a`), so subscribers can pick out books or chapters with
+//! wildcards (`verses.JHN.>`), and carries the verse as a row of JSON output. The client
+//! speaks the NATS text protocol over a plain TCP connection.
+
+use crate::{ParserError, UsfxParser};
+use serde_json::json;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+
+/// Messages published before waiting for the server to confirm it has processed them
+const BATCH: usize = 1000;
+
+/// Subject prefix used when the URL names none
+const DEFAULT_SUBJECT: &str = "verses";
+
+/// A NATS server and subject prefix to publish verses to, written as a URL:
+/// `nats://[user:password@|token@]host[:port][/subject]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    /// Host and port of the server
+    pub address: String,
+    /// User name and password to authenticate with, if any
+    pub credentials: Option<(String, String)>,
+    /// Token to authenticate with, if any
+    pub token: Option<String>,
+    /// Subject prefix the references are appended to
+    pub subject: String,
+}
+
+impl FromStr for Destination {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ParserError::ParseError(format!(
+                "Invalid NATS URL: {s} (expected nats://[user:password@|token@]host[:port][/subject])"
+            ))
+        };
+        let rest = s.strip_prefix("nats://").ok_or_else(invalid)?;
+        let (authority, subject) = rest.split_once('/').unwrap_or((rest, DEFAULT_SUBJECT));
+        let (user_info, host) = match authority.rsplit_once('@') {
+            Some((user_info, host)) => (Some(user_info), host),
+            None => (None, authority),
+        };
+        let (credentials, token) = match user_info.map(|user_info| user_info.split_once(':')) {
+            Some(Some((user, password))) => (Some((user.to_string(), password.to_string())), None),
+            Some(None) => (None, user_info.map(str::to_string)),
+            None => (None, None),
+        };
+        let subject = subject.trim_end_matches('.');
+        if host.is_empty()
+            || subject.is_empty()
+            || subject.contains(['*', '>', ' '])
+            || subject.split('.').any(str::is_empty)
+        {
+            return Err(invalid());
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:4222")
+        };
+        Ok(Self {
+            address,
+            credentials,
+            token,
+            subject: subject.to_string(),
+        })
+    }
+}
+
+/// A connection publishing messages
+struct Connection {
+    writer: BufWriter<TcpStream>,
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    fn open(destination: &Destination) -> Result<Self, ParserError> {
+        let stream = TcpStream::connect(&destination.address)?;
+        let mut connection = Self {
+            writer: BufWriter::new(stream.try_clone()?),
+            reader: BufReader::new(stream),
+        };
+        let info = connection.line()?;
+        if !info.starts_with("INFO ") {
+            return Err(ParserError::ParseError(format!(
+                "Not a NATS server: {}",
+                destination.address
+            )));
+        }
+        let mut options = json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "usfx_to_tsv",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some((user, password)) = &destination.credentials {
+            options["user"] = json!(user);
+            options["pass"] = json!(password);
+        }
+        if let Some(token) = &destination.token {
+            options["auth_token"] = json!(token);
+        }
+        write!(connection.writer, "CONNECT {options}\r\n")?;
+        Ok(connection)
+    }
+
+    fn line(&mut self) -> Result<String, ParserError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ParserError::ParseError(
+                "NATS server closed the connection".to_string(),
+            ));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    fn publish(&mut self, subject: &str, payload: &[u8]) -> Result<(), ParserError> {
+        write!(self.writer, "PUB {subject} {}\r\n", payload.len())?;
+        self.writer.write_all(payload)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+
+    /// Wait until the server has processed everything sent, failing on an error it reports
+    fn sync(&mut self) -> Result<(), ParserError> {
+        self.writer.write_all(b"PING\r\n")?;
+        self.writer.flush()?;
+        loop {
+            let line = self.line()?;
+            match line
+                .split_once(' ')
+                .map_or(line.as_str(), |(operation, _)| operation)
+            {
+                "PONG" => return Ok(()),
+                "PING" => {
+                    self.writer.write_all(b"PONG\r\n")?;
+                    self.writer.flush()?;
+                }
+                "-ERR" => return Err(ParserError::ParseError(format!("NATS error: {line}"))),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Publish the parser's remaining verses under the destination subject, one message per verse
+/// whatever the configured granularity, returning the number of messages published
+pub fn publish_verses(
+    parser: &mut UsfxParser,
+    destination: &Destination,
+) -> Result<usize, ParserError> {
+    let mut connection = Connection::open(destination)?;
+    connection.sync()?;
+    let mut published = 0;
+    while let Some(verse) = parser.next_verse()? {
+        let subject = format!(
+            "{}.{}.{}.{}{}",
+            destination.subject,
+            verse.book,
+            verse.chapter,
+            verse.verse,
+            verse.subverse.as_deref().unwrap_or("")
+        );
+        let payload = serde_json::to_vec(&verse)
+            .map_err(|error| ParserError::ParseError(format!("Cannot serialize verse: {error}")))?;
+        connection.publish(&subject, &payload)?;
+        published += 1;
+        if published % BATCH == 0 {
+            connection.sync()?;
+        }
+    }
+    connection.sync()?;
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsfxConfig;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_publish_verses() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        // A server answering pings, recording the operations and payloads it receives
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let operation = line.trim_end().to_string();
+                if operation == "PING" {
+                    stream.write_all(b"PONG\r\n").unwrap();
+                } else if let Some(length) = operation.strip_prefix("PUB ") {
+                    let length: usize = length.rsplit(' ').next().unwrap().parse().unwrap();
+                    let mut payload = vec![0; length + 2];
+                    reader.read_exact(&mut payload).unwrap();
+                    received.push(String::from_utf8(payload).unwrap());
+                }
+                received.push(operation);
+                line.clear();
+            }
+            received
+        });
+
+        let destination: Destination = format!("nats://s3cret@{address}/bible.kjv")
+            .parse()
+            .unwrap();
+        let input = std::io::Cursor::new(XML.as_bytes().to_vec());
+        let mut parser =
+            UsfxParser::from_reader(input, Box::new(std::io::sink()), UsfxConfig::default());
+        assert_eq!(publish_verses(&mut parser, &destination).unwrap(), 2);
+
+        let received = server.join().unwrap();
+        assert!(
+            received[0].starts_with("CONNECT {")
+                && received[0].contains(r#""auth_token":"s3cret""#)
+        );
+        assert_eq!(received[1], "PING");
+        assert!(received[2].contains(r#""text":"Jesus wept.""#));
+        assert!(received[3].starts_with("PUB bible.kjv.JHN.11.35 "));
+        assert_eq!(received.last().unwrap(), "PING");
+        assert!("nats://localhost/verses.*".parse::<Destination>().is_err());
+        let destination: Destination = "nats://reader:pw@localhost".parse().unwrap();
+        assert_eq!(destination.address, "localhost:4222");
+        assert_eq!(destination.subject, "verses");
+    }
+}
@@ -0,0 +1,98 @@
+//! Node.js native addon, built with napi-rs
+//!
+//! ```js
+//! const { convert, VerseReader } = require("usfx-to-tsv");
+//!
+//! const tsv = convert(usfx, { granularity: "chapter" });
+//! const reader = VerseReader.open("engkjv_usfx.xml");
+//! reader.forEach((verse) => console.log(verse.book, verse.chapter, verse.verse));
+//! ```
+
+use crate::options::{ConvertOptions, convert_document, error_message};
+use crate::{ParserError, UsfxParser};
+use napi::bindgen_prelude::Function;
+use napi_derive::napi;
+use serde_json::Value;
+
+fn js_error(error: ParserError) -> napi::Error {
+    napi::Error::from_reason(error_message(error))
+}
+
+fn options(options: Option<Value>) -> napi::Result<ConvertOptions> {
+    options.map_or_else(
+        || Ok(ConvertOptions::default()),
+        |options| {
+            serde_json::from_value(options)
+                .map_err(|error| napi::Error::from_reason(format!("Invalid options: {error}")))
+        },
+    )
+}
+
+/// Convert a USFX document to TSV (or JSON Lines with `{ format: "json" }`); `options` are
+/// the command line options in camel case, as for the WebAssembly build
+#[napi]
+pub fn convert(usfx: String, options: Option<Value>) -> napi::Result<String> {
+    let config = self::options(options)?.config().map_err(js_error)?;
+    convert_document(&usfx, config).map_err(js_error)
+}
+
+/// Reader handing out the verses of a document one at a time, as objects shaped like the rows
+/// of JSON output, so large files are never held in JavaScript memory whole
+#[napi]
+pub struct VerseReader {
+    parser: UsfxParser,
+}
+
+#[napi]
+impl VerseReader {
+    /// A reader of a document given as a string
+    #[napi(constructor)]
+    pub fn new(usfx: String, options: Option<Value>) -> napi::Result<Self> {
+        let config = self::options(options)?.config().map_err(js_error)?;
+        let input = std::io::Cursor::new(usfx.into_bytes());
+        Ok(Self {
+            parser: UsfxParser::from_reader(input, Box::new(std::io::sink()), config),
+        })
+    }
+
+    /// A reader of a file, read as the verses are asked for
+    #[napi(factory)]
+    pub fn open(path: String, options: Option<Value>) -> napi::Result<Self> {
+        let config = self::options(options)?.config().map_err(js_error)?;
+        Ok(Self {
+            parser: UsfxParser::new(path, Box::new(std::io::sink()), config).map_err(js_error)?,
+        })
+    }
+
+    /// The next verse, or `null` after the last one
+    #[napi(js_name = "next")]
+    pub fn next_verse(&mut self) -> napi::Result<Option<Value>> {
+        let Some(verse) = self.parser.next_verse().map_err(js_error)? else {
+            return Ok(None);
+        };
+        serde_json::to_value(verse)
+            .map(Some)
+            .map_err(|error| napi::Error::from_reason(error.to_string()))
+    }
+
+    /// Call `callback` with each remaining verse in turn, returning the number of verses
+    #[napi]
+    pub fn for_each(&mut self, callback: Function<'_, Value, ()>) -> napi::Result<u32> {
+        let mut verses = 0;
+        while let Some(verse) = self.next_verse()? {
+            callback.call(verse)?;
+            verses += 1;
+        }
+        Ok(verses)
+    }
+
+    /// Warnings about the document read so far, as `byte <offset>: <message>`
+    #[napi(getter)]
+    pub fn warnings(&self) -> Vec<String> {
+        self.parser
+            .warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+}
@@ -0,0 +1,250 @@
+//! Conversion options given as a single serializable value, for the JavaScript bindings
+
+use crate::{IntroMode, ParserError};
+use crate::{UsfxConfig, UsfxConfigBuilder, UsfxParser, canon, reference};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Options of a conversion, named as the command line options in camel case (`divineName` for
+/// `--divine-name`) and taking the same values; all are optional, and unknown names are
+/// rejected rather than ignored so that a misspelled option is noticed
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct ConvertOptions {
+    pub format: Option<String>,
+    pub granularity: Option<String>,
+    pub columns: Vec<String>,
+    pub canonical_order: Option<String>,
+    pub book_order: Option<Vec<String>>,
+    pub reference: Option<String>,
+    pub range: Option<String>,
+    pub book_abbreviations: Option<String>,
+    pub keep_book_codes: bool,
+    pub remap: Option<String>,
+    pub bridges: Option<String>,
+    pub titles: Option<String>,
+    pub acrostics: Option<String>,
+    pub selah: Option<String>,
+    pub added: Option<String>,
+    pub divine_name: Option<String>,
+    pub styles: Option<String>,
+    pub quotations: Option<String>,
+    pub table_separator: Option<String>,
+    pub optbreak: Option<String>,
+    pub keep_unicode_spaces: bool,
+    pub intro_rows: bool,
+    pub footnote_marker: Option<String>,
+    pub section_breadcrumb: bool,
+    pub stanza_break: Option<String>,
+    pub join: Option<String>,
+    pub duplicates: Option<String>,
+    pub control_chars: Option<String>,
+    pub bidi: Option<String>,
+    pub line_ending: Option<String>,
+    pub token_counter: Option<String>,
+    pub bom: bool,
+    pub recover: bool,
+}
+
+impl ConvertOptions {
+    /// Options from name and value pairs, as in a query string: `columns` and `bookOrder`
+    /// take comma-separated lists and flags take `true` or `false`
+    pub fn from_pairs<'a>(
+        pairs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, ParserError> {
+        let mut map = Map::new();
+        for (key, value) in pairs {
+            let value = match (key, value) {
+                ("columns" | "bookOrder", _) => {
+                    Value::Array(value.split(',').map(|item| item.into()).collect())
+                }
+                (_, "true") => Value::Bool(true),
+                (_, "false") => Value::Bool(false),
+                _ => Value::String(value.to_string()),
+            };
+            map.insert(key.to_string(), value);
+        }
+        serde_json::from_value(Value::Object(map))
+            .map_err(|error| ParserError::ParseError(format!("Invalid options: {error}")))
+    }
+
+    /// The parser configuration for these options
+    pub fn config(&self) -> Result<UsfxConfig, ParserError> {
+        let mut builder = UsfxConfigBuilder::new();
+        if let Some(value) = &self.format {
+            builder = builder.format(value.parse()?);
+        }
+        if let Some(value) = &self.granularity {
+            builder = builder.granularity(value.parse()?);
+        }
+        for column in &self.columns {
+            builder = builder.column(column.parse()?);
+        }
+        if let Some(value) = &self.canonical_order {
+            builder = builder.canonical_order(value.parse()?);
+        }
+        if let Some(books) = &self.book_order {
+            builder = builder.book_order(canon::parse_order(&books.join(","))?);
+        }
+        if let Some(value) = &self.reference {
+            builder = builder.reference_format(value.parse()?);
+        }
+        if let Some(value) = &self.range {
+            builder = builder.ranges(reference::parse(value)?);
+        }
+        if let Some(value) = &self.book_abbreviations {
+            builder = builder.book_abbreviations(value.parse()?);
+        }
+        if self.keep_book_codes {
+            builder = builder.normalize_book_codes(false);
+        }
+        if let Some(value) = &self.remap {
+            builder = builder.versification_map(value.parse()?);
+        }
+        if let Some(value) = &self.bridges {
+            builder = builder.bridge_mode(value.parse()?);
+        }
+        if let Some(value) = &self.titles {
+            builder = builder.title_mode(value.parse()?);
+        }
+        if let Some(value) = &self.acrostics {
+            builder = builder.acrostic_mode(value.parse()?);
+        }
+        if let Some(value) = &self.selah {
+            builder = builder.selah_mode(value.parse()?);
+        }
+        if let Some(value) = &self.added {
+            builder = builder.added_words(value.parse()?);
+        }
+        if let Some(value) = &self.divine_name {
+            builder = builder.divine_name(value.parse()?);
+        }
+        if let Some(value) = &self.styles {
+            builder = builder.char_styles(value.parse()?);
+        }
+        if let Some(value) = &self.quotations {
+            builder = builder.quotation_mode(value.parse()?);
+        }
+        if let Some(value) = &self.table_separator {
+            builder = builder.table_separator(value.as_str());
+        }
+        if let Some(value) = &self.optbreak {
+            builder = builder.opt_break(value.parse()?);
+        }
+        if self.keep_unicode_spaces {
+            builder = builder.preserve_unicode_spaces(true);
+        }
+        if self.intro_rows {
+            builder = builder.intro_mode(IntroMode::VerseZero);
+        }
+        if let Some(value) = &self.footnote_marker {
+            builder = builder.footnote_marker(value.as_str());
+        }
+        if self.section_breadcrumb {
+            builder = builder.section_breadcrumb(true);
+        }
+        if let Some(value) = &self.stanza_break {
+            builder = builder.stanza_break(value.as_str());
+        }
+        if let Some(value) = &self.join {
+            builder = builder.join_rules(value.parse()?);
+        }
+        if let Some(value) = &self.duplicates {
+            builder = builder.duplicate_verses(value.parse()?);
+        }
+        if let Some(value) = &self.control_chars {
+            builder = builder.control_chars(value.parse()?);
+        }
+        if let Some(value) = &self.bidi {
+            builder = builder.bidi(value.parse()?);
+        }
+        if let Some(value) = &self.line_ending {
+            builder = builder.line_ending(value.parse()?);
+        }
+        if let Some(value) = &self.token_counter {
+            builder = builder.token_counter(value.parse()?);
+        }
+        Ok(builder
+            .byte_order_mark(self.bom)
+            .recover(self.recover)
+            .build())
+    }
+}
+
+/// Output collected in memory, shared with the parser writing it
+#[derive(Clone, Default)]
+struct Collected(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Collected {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert a USFX document to rows with a configuration
+pub fn convert_document(usfx: &str, config: UsfxConfig) -> Result<String, ParserError> {
+    let output = Collected::default();
+    let input = std::io::Cursor::new(usfx.as_bytes().to_vec());
+    UsfxParser::from_reader(input, Box::new(output.clone()), config).parse()?;
+    String::from_utf8(output.0.take())
+        .map_err(|error| ParserError::ParseError(format!("Output is not UTF-8: {error}")))
+}
+
+/// Message of an error, as thrown to JavaScript or sent in an HTTP or gRPC response
+#[cfg(any(
+    feature = "wasm",
+    feature = "node",
+    feature = "server",
+    feature = "grpc"
+))]
+pub(crate) fn error_message(error: ParserError) -> String {
+    match error {
+        ParserError::FileError(error) => error.to_string(),
+        ParserError::XmlError(error) => error.to_string(),
+        ParserError::ParseError(message) => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_options() {
+        const XML: &str =
+            r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/></p></book></usfx>"#;
+
+        let options: ConvertOptions =
+            serde_json::from_str(r#"{"granularity": "chapter", "columns": ["checksum"]}"#).unwrap();
+        let tsv = convert_document(XML, options.config().unwrap()).unwrap();
+        assert!(tsv.starts_with("JHN\t11\tJesus wept.\t"));
+        assert_eq!(
+            convert_document(XML, ConvertOptions::default().config().unwrap()).unwrap(),
+            "JHN\t11\t35\tJesus wept.\n"
+        );
+        let options = ConvertOptions {
+            format: Some("yaml".to_string()),
+            ..ConvertOptions::default()
+        };
+        assert!(options.config().is_err());
+        assert!(ConvertOptions::from_pairs([("bom", "yes")]).is_err());
+        let misspelled = ConvertOptions::from_pairs([("granularty", "chapter")]);
+        assert!(
+            matches!(misspelled, Err(ParserError::ParseError(message)) if message.contains("granularty"))
+        );
+        assert!(serde_json::from_str::<ConvertOptions>(r#"{"divine_name": "uppercase"}"#).is_err());
+        let options =
+            ConvertOptions::from_pairs([("columns", "checksum,raw"), ("introRows", "true")])
+                .unwrap();
+        assert_eq!(options.columns, ["checksum", "raw"]);
+        assert!(options.intro_rows);
+    }
+}
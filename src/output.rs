@@ -0,0 +1,603 @@
+use crate::books;
+use crate::canon;
+use crate::encoding::UTF_8_BOM;
+use crate::versification::MAX_BRIDGE_VERSES;
+use crate::{
+    BookNames, BridgeMode, Column, Granularity, LineEnding, OutputFormat, ParserError,
+    ReferenceFormat, TokenCounter, UsfxConfig, Verse,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::Write;
+
+/// Writes verses as TSV or JSON rows, combining them according to the configured granularity
+pub struct RowWriter<W: Write> {
+    writer: W,
+    granularity: Granularity,
+    columns: Vec<Column>,
+    bridge_mode: BridgeMode,
+    format: OutputFormat,
+    line_ending: LineEnding,
+    book_names: Option<BookNames>,
+    reference_format: ReferenceFormat,
+    token_counter: TokenCounter,
+    pending: Option<Verse>,
+    bom_pending: bool,
+    flush_every: Option<usize>,
+    max_row_size: Option<usize>,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter<W> {
+    /// Create a new row writer using the granularity and columns of `config`
+    pub fn new(writer: W, config: &UsfxConfig) -> Self {
+        Self {
+            writer,
+            granularity: config.granularity,
+            columns: config.columns.clone(),
+            bridge_mode: config.bridge_mode,
+            format: config.format,
+            line_ending: config.line_ending,
+            book_names: books::labels(config),
+            reference_format: config.reference_format,
+            token_counter: config.token_counter.clone(),
+            pending: None,
+            bom_pending: config.byte_order_mark,
+            flush_every: config.flush_every_n_rows.filter(|&rows| rows > 0),
+            max_row_size: config.max_verse_size,
+            rows: 0,
+        }
+    }
+
+    /// Write a verse, buffering it if it belongs to a larger row
+    pub fn write_verse(&mut self, verse: &Verse) -> Result<(), ParserError> {
+        if self.granularity == Granularity::Verse {
+            if self.bridge_mode == BridgeMode::Expand
+                && let Some(verses) = verse.bridged_verses()
+            {
+                // The parser warns about a bridge longer than any chapter
+                let first = *verses.start();
+                let last = (*verses.end()).min(first.saturating_add(MAX_BRIDGE_VERSES - 1));
+                for number in first..=last {
+                    self.write_row(&Verse {
+                        verse: number.to_string(),
+                        verse_end: None,
+                        ..verse.clone()
+                    })?;
+                }
+                return Ok(());
+            }
+            return self.write_row(verse);
+        }
+
+        if let Some(pending) = self.pending.as_mut()
+            && self.granularity.same_row(pending, verse)
+        {
+            pending.append(verse);
+            if let Some(limit) = self.max_row_size
+                && pending.text.len() > limit
+            {
+                return Err(ParserError::ParseError(format!(
+                    "Row from {} {}:{} is larger than the limit of {limit} bytes",
+                    pending.book, pending.chapter, pending.verse
+                )));
+            }
+            return Ok(());
+        }
+
+        if let Some(pending) = self.pending.replace(verse.clone()) {
+            self.write_row(&pending)?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered row and flush the underlying writer
+    pub fn finish(&mut self) -> Result<(), ParserError> {
+        if let Some(pending) = self.pending.take() {
+            self.write_row(&pending)?;
+        }
+        self.flush()
+    }
+
+    /// Flush the rows written so far, leaving any row still being combined buffered
+    pub fn flush(&mut self) -> Result<(), ParserError> {
+        self.write_bom()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Mutable access to the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consume the row writer, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write the byte order mark, if one is wanted and has not been written yet
+    fn write_bom(&mut self) -> Result<(), ParserError> {
+        if std::mem::take(&mut self.bom_pending) {
+            self.writer.write_all(UTF_8_BOM)?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, verse: &Verse) -> Result<(), ParserError> {
+        self.write_bom()?;
+        let book = self
+            .book_names
+            .as_ref()
+            .and_then(|names| names.get(&verse.book))
+            .unwrap_or(&verse.book);
+        // Without a subverse column the part letter stays attached to the verse number
+        let number = match &verse.subverse {
+            Some(part) if !self.columns.contains(&Column::Subverse) => {
+                Cow::Owned(format!("{}{}", verse.verse, part))
+            }
+            _ => Cow::Borrowed(verse.verse.as_str()),
+        };
+        let reference = self.reference(verse, book, &number);
+        if self.format == OutputFormat::Json {
+            let named;
+            let verse = if book == verse.book {
+                verse
+            } else {
+                named = Verse {
+                    book: book.to_string(),
+                    ..verse.clone()
+                };
+                &named
+            };
+            let row = JsonRow {
+                reference: reference.as_deref(),
+                tokens: self
+                    .columns
+                    .contains(&Column::Tokens)
+                    .then(|| self.token_counter.count(&verse.text)),
+                verse,
+            };
+            serde_json::to_writer(&mut self.writer, &row)
+                .map_err(|e| ParserError::ParseError(e.to_string()))?;
+            self.end_record()?;
+            return Ok(());
+        }
+
+        let (book, chapter, number) = (one_line(book), one_line(&verse.chapter), one_line(&number));
+        let text = one_line(&verse.text);
+        if let Some(reference) = reference.as_deref().map(one_line) {
+            match self.granularity {
+                Granularity::Section => write!(
+                    self.writer,
+                    "{}\t{}\t{}",
+                    reference,
+                    one_line(verse.section.as_deref().unwrap_or_default()),
+                    text
+                )?,
+                _ => write!(self.writer, "{reference}\t{text}")?,
+            }
+        } else {
+            match self.granularity {
+                Granularity::Verse => {
+                    write!(self.writer, "{book}\t{chapter}\t{number}\t{text}")?;
+                }
+                Granularity::Chapter => {
+                    write!(self.writer, "{}\t{}\t{}", book, chapter, text)?;
+                }
+                Granularity::Book => write!(self.writer, "{}\t{}", book, text)?,
+                Granularity::Section => write!(
+                    self.writer,
+                    "{}\t{}\t{}\t{}\t{}",
+                    book,
+                    chapter,
+                    number,
+                    one_line(verse.section.as_deref().unwrap_or_default()),
+                    text
+                )?,
+            }
+        }
+        for column in &self.columns {
+            let value = column_value(*column, verse, &self.token_counter);
+            write!(self.writer, "\t{value}")?;
+        }
+        self.end_record()
+    }
+
+    /// The reference of a row as a single field, unless references are written as columns
+    fn reference(&self, verse: &Verse, book: &str, number: &str) -> Option<String> {
+        let (chapter, number) = match self.granularity {
+            Granularity::Verse | Granularity::Section => {
+                (Some(verse.chapter.as_str()), Some(number))
+            }
+            Granularity::Chapter => (Some(verse.chapter.as_str()), None),
+            Granularity::Book => (None, None),
+        };
+        match self.reference_format {
+            ReferenceFormat::Columns => None,
+            ReferenceFormat::Single => Some(match (chapter, number) {
+                (Some(chapter), Some(number)) => format!("{book} {chapter}:{number}"),
+                (Some(chapter), None) => format!("{book} {chapter}"),
+                _ => book.to_string(),
+            }),
+            ReferenceFormat::Osis => {
+                let id = canon::osis_id(&verse.book).unwrap_or(&verse.book);
+                let verse = number.map(|_| verse.verse.as_str());
+                let parts: Vec<&str> = [Some(id), chapter, verse].into_iter().flatten().collect();
+                Some(parts.join("."))
+            }
+            ReferenceFormat::Numeric => {
+                let numeric = |part: Option<&str>| {
+                    part.and_then(|part| {
+                        part.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0u32)
+                };
+                // A subverse part keeps its letter, so that each part has a key of its own; the
+                // verse number carries it unless there is a subverse column
+                let part = number.map_or("", |number| {
+                    let letters = number.trim_start_matches(|c: char| c.is_ascii_digit());
+                    verse
+                        .subverse
+                        .as_deref()
+                        .filter(|_| letters.is_empty())
+                        .unwrap_or(letters)
+                });
+                Some(format!(
+                    "{:03}{:03}{:03}{part}",
+                    canon::book_number(&verse.book).unwrap_or(0),
+                    numeric(chapter),
+                    numeric(number)
+                ))
+            }
+        }
+    }
+
+    fn end_record(&mut self) -> Result<(), ParserError> {
+        self.writer
+            .write_all(self.line_ending.as_str().as_bytes())?;
+        self.rows += 1;
+        if let Some(rows) = self.flush_every
+            && self.rows.is_multiple_of(rows)
+        {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// A verse serialized with the fields computed for its row: its reference as a single field
+/// and its token count, when wanted
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<usize>,
+    #[serde(flatten)]
+    verse: &'a Verse,
+}
+
+/// The TSV field for an optional column
+fn column_value<'a>(column: Column, verse: &'a Verse, counter: &TokenCounter) -> Cow<'a, str> {
+    match column {
+        Column::Raw => escape_field(verse.raw.as_deref().unwrap_or_default()),
+        Column::Checksum => Cow::Owned(verse.checksum()),
+        Column::Tokens => Cow::Owned(counter.count(&verse.text).to_string()),
+        Column::VerseEnd => Cow::Borrowed(verse.verse_end.as_deref().unwrap_or_default()),
+        Column::AltVerse => escape_field(verse.alt_verse.as_deref().unwrap_or_default()),
+        Column::PublishedVerse => {
+            escape_field(verse.published_verse.as_deref().unwrap_or_default())
+        }
+        Column::Subverse => Cow::Borrowed(verse.subverse.as_deref().unwrap_or_default()),
+        Column::MajorSection => escape_field(verse.major_section.as_deref().unwrap_or_default()),
+        Column::Parallel if verse.parallel_targets.is_empty() => {
+            escape_field(verse.parallel.as_deref().unwrap_or_default())
+        }
+        Column::Parallel => {
+            let targets: Vec<String> = verse
+                .parallel_targets
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            Cow::Owned(targets.join("; "))
+        }
+        Column::Speaker => escape_field(verse.speaker.as_deref().unwrap_or_default()),
+        Column::ChapterLabel => escape_field(verse.chapter_label.as_deref().unwrap_or_default()),
+        Column::AltChapter => escape_field(verse.alt_chapter.as_deref().unwrap_or_default()),
+        Column::Acrostic => escape_field(verse.acrostic.as_deref().unwrap_or_default()),
+        Column::Title => escape_field(verse.title.as_deref().unwrap_or_default()),
+        Column::Selah => escape_field(verse.selah.as_deref().unwrap_or_default()),
+        Column::Footnotes => {
+            let notes: Vec<String> = verse.footnotes.iter().map(ToString::to_string).collect();
+            Cow::Owned(escape_field(&notes.join(" | ")).into_owned())
+        }
+        Column::Paragraph => Cow::Borrowed(verse.paragraph.as_deref().unwrap_or_default()),
+        Column::Poetry => {
+            let levels: Vec<String> = verse
+                .poetry
+                .iter()
+                .map(|line| line.level.to_string())
+                .collect();
+            Cow::Owned(levels.join(","))
+        }
+        Column::Quotations => Cow::Owned(escape_field(&verse.quotations.join(" | ")).into_owned()),
+        Column::Words => {
+            let words: Vec<String> = verse.words.iter().map(ToString::to_string).collect();
+            Cow::Owned(escape_field(&words.join("; ")).into_owned())
+        }
+        Column::Figures => {
+            let figures: Vec<String> = verse.figures.iter().map(ToString::to_string).collect();
+            Cow::Owned(escape_field(&figures.join(" | ")).into_owned())
+        }
+        Column::CrossReferences => {
+            let notes: Vec<String> = verse
+                .cross_references
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            Cow::Owned(escape_field(&notes.join(" | ")).into_owned())
+        }
+    }
+}
+
+/// Replace line breaks and tabs inside a field with spaces, so only record terminators end a
+/// line and only separators end a field
+fn one_line(field: &str) -> Cow<'_, str> {
+    if !field.contains(['\r', '\n', '\t']) {
+        return Cow::Borrowed(field);
+    }
+    Cow::Owned(field.replace("\r\n", " ").replace(['\r', '\n', '\t'], " "))
+}
+
+/// Escape backslashes, tabs and line breaks so a field cannot break the TSV row structure
+pub(crate) fn escape_field(field: &str) -> Cow<'_, str> {
+    if !field.contains(['\\', '\t', '\n', '\r']) {
+        return Cow::Borrowed(field);
+    }
+    let mut escaped = String::with_capacity(field.len() + 8);
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+impl Granularity {
+    /// Whether two consecutive verses belong to the same output row
+    fn same_row(self, a: &Verse, b: &Verse) -> bool {
+        match self {
+            Self::Verse => false,
+            Self::Chapter => a.book == b.book && a.chapter == b.chapter,
+            Self::Book => a.book == b.book,
+            Self::Section => a.book == b.book && a.section_number == b.section_number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsfxConfigBuilder;
+
+    fn verse(book: &str, chapter: &str, verse: &str, text: &str) -> Verse {
+        Verse {
+            book: book.to_string(),
+            chapter: chapter.to_string(),
+            verse: verse.to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        }
+    }
+
+    fn sample() -> Vec<Verse> {
+        let creation = Some("The Creation".to_string());
+        vec![
+            Verse {
+                section: creation.clone(),
+                section_number: 1,
+                ..verse("GEN", "1", "1", "In the beginning")
+            },
+            Verse {
+                section: creation,
+                section_number: 1,
+                ..verse("GEN", "1", "2", "The earth")
+            },
+            Verse {
+                section: Some("The Sabbath".to_string()),
+                section_number: 2,
+                ..verse("GEN", "2", "1", "The heavens")
+            },
+            verse("EXO", "1", "1", "Now these"),
+        ]
+    }
+
+    fn write_all(granularity: Granularity) -> String {
+        let config = UsfxConfigBuilder::new().granularity(granularity).build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        for v in sample() {
+            writer.write_verse(&v).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_verse_rows() {
+        assert_eq!(
+            write_all(Granularity::Verse),
+            "GEN\t1\t1\tIn the beginning\nGEN\t1\t2\tThe earth\nGEN\t2\t1\tThe heavens\nEXO\t1\t1\tNow these\n"
+        );
+    }
+
+    #[test]
+    fn test_chapter_rows() {
+        assert_eq!(
+            write_all(Granularity::Chapter),
+            "GEN\t1\tIn the beginning The earth\nGEN\t2\tThe heavens\nEXO\t1\tNow these\n"
+        );
+    }
+
+    #[test]
+    fn test_book_rows() {
+        assert_eq!(
+            write_all(Granularity::Book),
+            "GEN\tIn the beginning The earth The heavens\nEXO\tNow these\n"
+        );
+    }
+
+    #[test]
+    fn test_section_rows() {
+        assert_eq!(
+            write_all(Granularity::Section),
+            "GEN\t1\t1\tThe Creation\tIn the beginning The earth\n\
+             GEN\t2\t1\tThe Sabbath\tThe heavens\n\
+             EXO\t1\t1\t\tNow these\n"
+        );
+    }
+
+    #[test]
+    fn test_raw_column() {
+        let config = UsfxConfigBuilder::new().column(Column::Raw).build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        let verse = Verse {
+            raw: Some("In <w s=\"H7225\">the\tbeginning</w>\n".to_string()),
+            ..verse("GEN", "1", "1", "In the beginning")
+        };
+        writer.write_verse(&verse).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "GEN\t1\t1\tIn the beginning\tIn <w s=\"H7225\">the\\tbeginning</w>\\n\n"
+        );
+    }
+
+    #[test]
+    fn test_subverse_column() {
+        let verse = Verse {
+            subverse: Some("b".to_string()),
+            ..verse("EST", "1", "1", "In the days")
+        };
+
+        let mut writer = RowWriter::new(Vec::new(), &UsfxConfig::default());
+        writer.write_verse(&verse).unwrap();
+        assert_eq!(writer.into_inner(), b"EST\t1\t1b\tIn the days\n");
+
+        let config = UsfxConfigBuilder::new().column(Column::Subverse).build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        writer.write_verse(&verse).unwrap();
+        assert_eq!(writer.into_inner(), b"EST\t1\t1\tIn the days\tb\n");
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let config = UsfxConfigBuilder::new()
+            .line_ending(LineEnding::CrLf)
+            .build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        writer
+            .write_verse(&verse("GEN", "1", "1", "In the\r\nbeginning\nGod"))
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"GEN\t1\t1\tIn the beginning God\r\n");
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let config = UsfxConfigBuilder::new().byte_order_mark(true).build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        writer.write_verse(&verse("GEN", "1", "1", "In")).unwrap();
+        writer.write_verse(&verse("GEN", "1", "2", "The")).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"\xef\xbb\xbfGEN\t1\t1\tIn\nGEN\t1\t2\tThe\n"
+        );
+    }
+
+    #[test]
+    fn test_book_names() {
+        let mut names = BookNames::default();
+        names.insert("GEN", "Genèse");
+        let config = UsfxConfigBuilder::new().book_names(names).build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        writer
+            .write_verse(&verse("GEN", "1", "1", "Au commencement"))
+            .unwrap();
+        writer
+            .write_verse(&verse("EXO", "1", "1", "Voici"))
+            .unwrap();
+        writer.finish().unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(output, "Genèse\t1\t1\tAu commencement\nEXO\t1\t1\tVoici\n");
+    }
+
+    #[test]
+    fn test_reference_formats() {
+        let write = |format, granularity, json| {
+            let config = UsfxConfigBuilder::new()
+                .reference_format(format)
+                .granularity(granularity)
+                .format(if json {
+                    OutputFormat::Json
+                } else {
+                    OutputFormat::Tsv
+                })
+                .build();
+            let mut writer = RowWriter::new(Vec::new(), &config);
+            writer
+                .write_verse(&verse("MAT", "5", "3", "Blessed"))
+                .unwrap();
+            writer.finish().unwrap();
+            String::from_utf8(writer.into_inner()).unwrap()
+        };
+        assert_eq!(
+            write(ReferenceFormat::Single, Granularity::Verse, false),
+            "MAT 5:3\tBlessed\n"
+        );
+        assert_eq!(
+            write(ReferenceFormat::Osis, Granularity::Verse, false),
+            "Matt.5.3\tBlessed\n"
+        );
+        assert_eq!(
+            write(ReferenceFormat::Osis, Granularity::Chapter, false),
+            "Matt.5\tBlessed\n"
+        );
+        assert_eq!(
+            write(ReferenceFormat::Numeric, Granularity::Verse, false),
+            "040005003\tBlessed\n"
+        );
+
+        // Books numbered past 99 keep the width, and subverse parts are told apart
+        let config = UsfxConfigBuilder::new()
+            .reference_format(ReferenceFormat::Numeric)
+            .build();
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        writer
+            .write_verse(&verse("DAG", "3", "24", "Azariah"))
+            .unwrap();
+        for part in ["a", "b"] {
+            writer
+                .write_verse(&Verse {
+                    subverse: Some(part.to_string()),
+                    ..verse("EST", "1", "1", "In the days")
+                })
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "112003024\tAzariah\n017001001a\tIn the days\n017001001b\tIn the days\n"
+        );
+        assert!(
+            write(ReferenceFormat::Single, Granularity::Verse, true)
+                .starts_with("{\"reference\":\"MAT 5:3\",\"book\":\"MAT\"")
+        );
+    }
+}
@@ -0,0 +1,3571 @@
+use crate::output::{RowWriter, escape_field};
+use crate::reference::{self, Reference, ReferenceRange};
+use crate::versification::{Coverage, Difference, MAX_BRIDGE_VERSES, MissingVerse, Versification};
+use crate::{
+    AcrosticMode, AddedWords, BookTitles, BufferSize, CharStyles, Column, ControlChars,
+    CrossReference, DivineName, DuplicateVerses, Figure, Footnote, IntroMode, IntroParagraph,
+    Metadata, OptBreak, OutputFormat, ParseSummary, ParserError, PoetryLine, QuotationMode, Remark,
+    SelahMode, TitleMode, UsfxConfig, Verse, Warning, Word,
+};
+use crate::{bidi, books, canon, compression, encoding};
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::reader::Reader;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str;
+
+mod parallel;
+mod pipeline;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ParserState {
+    Book,
+    Initial,
+    InVerse,
+    InWord,
+    InSection,
+    InMajorSection,
+    InMajorRange,
+    InParallel,
+    InSpeaker,
+    InTitle,
+    InAcrostic,
+    InChapterLabel,
+    InAltChapter,
+    InAltVerse,
+    InPublishedVerse,
+    InMetadata,
+    InFootnote,
+    InCrossReference,
+    InFigure,
+    InIntro,
+    VerseEnd,
+}
+
+/// Phrases that mark a front matter paragraph as a copyright or license statement
+const LICENSE_PHRASES: &[&str] = &[
+    "©",
+    "copyright",
+    "licen",
+    "public domain",
+    "creative commons",
+    "all rights reserved",
+    "permission",
+];
+
+/// Number of warnings kept; a document with a problem in every verse, such as translations
+/// concatenated into one, would otherwise fill memory with them
+const MAX_WARNINGS: usize = 1000;
+
+/// Paragraph styles of introductions and peripheral material
+const INTRO_STYLES: &[&str] = &[
+    "imt", "imt1", "imt2", "imt3", "imt4", "imte", "imte1", "imte2", "is", "is1", "is2", "is3",
+    "ip", "ipi", "im", "imi", "ipq", "imq", "ipr", "iq", "iq1", "iq2", "iq3", "ib", "ili", "ili1",
+    "ili2", "iot", "io", "io1", "io2", "io3", "io4", "iex", "ie", "periph",
+];
+
+/// The part of a footnote or cross reference that text is currently being added to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NotePart {
+    Reference,
+    Keyword,
+    Quotation,
+    Text,
+}
+
+/// Verse counts of one book in a manifest
+#[derive(Serialize)]
+struct BookCounts<'a> {
+    book: &'a str,
+    chapters: &'a BTreeMap<u32, usize>,
+}
+
+/// The document metadata element whose text is being read
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MetadataField {
+    LanguageCode,
+    Id,
+    Remark,
+    Header,
+    /// Table of contents entry of the given level
+    Toc(u8),
+}
+
+/// The part of a figure that text is currently being added to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FigurePart {
+    Caption,
+    File,
+    Reference,
+    /// Description, size, location and copyright, which are not kept
+    Other,
+}
+
+/// An inline character span open in the current verse
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Span {
+    /// Selah (`<qs>`)
+    Selah,
+    /// Words supplied by the translators (`<add>`)
+    Added,
+    /// The divine name (`<nd>`)
+    DivineName,
+    /// Italics (`<it>`)
+    Italic,
+    /// Bold (`<bd>`)
+    Bold,
+    /// Bold italics (`<bdit>`)
+    BoldItalic,
+    /// Emphasis (`<em>`)
+    Emphasis,
+    /// Small capitals (`<sc>`)
+    SmallCaps,
+    /// Quoted text (`<qt>`)
+    Quotation,
+}
+
+impl Span {
+    /// The span opened by a character style element, if `name` is one
+    fn char_style(name: &[u8]) -> Option<Self> {
+        match name {
+            b"it" => Some(Self::Italic),
+            b"bd" => Some(Self::Bold),
+            b"bdit" => Some(Self::BoldItalic),
+            b"em" => Some(Self::Emphasis),
+            b"sc" => Some(Self::SmallCaps),
+            _ => None,
+        }
+    }
+}
+
+/// Main parser for USFX files
+pub struct UsfxParser {
+    reader: Reader<Box<dyn BufRead>>,
+    state: ParserState,
+    states: Vec<ParserState>,
+    in_content: bool,
+    current: Option<Verse>,
+    section: Option<String>,
+    sections: Vec<(u8, String)>,
+    section_number: usize,
+    section_level: u8,
+    major_section: Option<String>,
+    parallel: Option<String>,
+    parallel_targets: Vec<ReferenceRange>,
+    speaker: Option<String>,
+    title: Option<String>,
+    acrostic: Option<String>,
+    book_chapter_label: Option<String>,
+    chapter_label: Option<String>,
+    alt_chapter: Option<String>,
+    book_id: String,
+    chapter_id: String,
+    poetry_level: Option<u8>,
+    paragraph: Option<String>,
+    heading: String,
+    spans: Vec<Span>,
+    space_pending: bool,
+    space_required: bool,
+    tag_boundary: bool,
+    stanza_break_pending: bool,
+    table_cells: usize,
+    footnote: Option<Footnote>,
+    cross_reference: Option<CrossReference>,
+    note_part: NotePart,
+    figures: Vec<Figure>,
+    figure_part: FigurePart,
+    word: Option<Word>,
+    /// Reusable buffer for text rewritten before it is appended, such as uppercased names
+    scratch: String,
+    intro: Vec<IntroParagraph>,
+    metadata: Metadata,
+    metadata_field: MetadataField,
+    books: Vec<BookTitles>,
+    front_matter: String,
+    intro_depth: usize,
+    intro_note_depth: Option<usize>,
+    skipping: bool,
+    warnings: Vec<Warning>,
+    omitted_warnings: usize,
+    coverage: Coverage,
+    unknown_books: Vec<String>,
+    held: Option<Verse>,
+    intro_row: Option<Verse>,
+    acrostic_row: Option<Verse>,
+    error_position: Option<u64>,
+    buffer: Vec<u8>,
+    output: RowWriter<BufWriter<Box<dyn Write>>>,
+    config: UsfxConfig,
+}
+
+impl UsfxParser {
+    /// Create a new USFX parser
+    ///
+    /// # Arguments
+    /// * `input_path` - Path to the input USFX file
+    /// * `output` - Writer for the output TSV
+    /// * `config` - Configuration options for the parser
+    ///
+    /// # Returns
+    /// * `Result<Self, ParserError>` - The parser instance or an error
+    pub fn new<P: AsRef<Path>>(
+        input_path: P,
+        output: Box<dyn Write>,
+        mut config: UsfxConfig,
+    ) -> Result<Self, ParserError> {
+        let file = File::open(input_path)?;
+        let size = config.buffer_size.bytes(Some(file.metadata()?.len()));
+        config.buffer_size = BufferSize::Bytes(size);
+        // Reads are never smaller than the standard library's default
+        let input = BufReader::with_capacity(size.max(8 * 1024), file);
+        Ok(Self::from_reader(input, output, config))
+    }
+
+    /// Create a new USFX parser reading a memory-mapped file
+    ///
+    /// The document is read in place instead of being copied through a read buffer, which
+    /// speeds up converting large files. The file must not be changed while it is being read.
+    #[cfg(feature = "mmap")]
+    #[allow(unsafe_code)]
+    pub fn mapped<P: AsRef<Path>>(
+        input_path: P,
+        output: Box<dyn Write>,
+        mut config: UsfxConfig,
+    ) -> Result<Self, ParserError> {
+        let file = File::open(input_path)?;
+        // SAFETY: the mapping is only read, and the caller keeps the file unchanged meanwhile
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        let size = config.buffer_size.bytes(Some(map.len() as u64));
+        config.buffer_size = BufferSize::Bytes(size);
+        Ok(Self::from_reader(std::io::Cursor::new(map), output, config))
+    }
+
+    /// Create a new USFX parser reading a document from an HTTP(S) URL
+    ///
+    /// The response body is parsed as it arrives rather than downloaded first. A response that
+    /// is not successful, such as `404 Not Found`, is an error.
+    #[cfg(feature = "http")]
+    pub fn from_url(
+        url: &str,
+        output: Box<dyn Write>,
+        config: UsfxConfig,
+    ) -> Result<Self, ParserError> {
+        let response = ureq::get(url)
+            .call()
+            .map_err(|error| ParserError::ParseError(format!("Cannot fetch {url}: {error}")))?;
+        let body = response.into_body().into_reader();
+        Ok(Self::from_reader(BufReader::new(body), output, config))
+    }
+
+    /// Create a new USFX parser reading an object in S3 or Cloud Storage
+    ///
+    /// The object is parsed as it arrives rather than downloaded first; see
+    /// [`crate::storage`] for the credentials used.
+    #[cfg(feature = "s3")]
+    pub fn from_object(
+        uri: &crate::storage::ObjectUri,
+        output: Box<dyn Write>,
+        config: UsfxConfig,
+    ) -> Result<Self, ParserError> {
+        Ok(Self::from_reader(crate::storage::get(uri)?, output, config))
+    }
+
+    /// Create a new USFX parser reading from any buffered source
+    ///
+    /// Documents compressed with gzip, zstd or xz are decompressed and the USFX document in a
+    /// zip archive is found (with the `compression` feature), a leading byte order mark is
+    /// skipped, and documents in UTF-16 or another declared encoding are transcoded to UTF-8 as
+    /// they are read.
+    ///
+    /// # Arguments
+    /// * `input` - Reader for the USFX document
+    /// * `output` - Writer for the output TSV
+    /// * `config` - Configuration options for the parser
+    pub fn from_reader<R: BufRead + 'static>(
+        input: R,
+        output: Box<dyn Write>,
+        config: UsfxConfig,
+    ) -> Self {
+        Self {
+            reader: Reader::from_reader(encoding::decoded(compression::decompressed(input))),
+            state: ParserState::Initial,
+            states: Vec::new(),
+            in_content: false,
+            current: None,
+            section: None,
+            sections: Vec::new(),
+            section_number: 0,
+            section_level: 1,
+            major_section: None,
+            parallel: None,
+            parallel_targets: Vec::new(),
+            speaker: None,
+            title: None,
+            acrostic: None,
+            book_chapter_label: None,
+            chapter_label: None,
+            alt_chapter: None,
+            book_id: String::new(),
+            chapter_id: String::new(),
+            poetry_level: None,
+            paragraph: None,
+            heading: String::new(),
+            spans: Vec::new(),
+            space_pending: false,
+            space_required: false,
+            tag_boundary: false,
+            stanza_break_pending: false,
+            table_cells: 0,
+            footnote: None,
+            cross_reference: None,
+            note_part: NotePart::Text,
+            figures: Vec::new(),
+            figure_part: FigurePart::Other,
+            word: None,
+            scratch: String::new(),
+            intro: Vec::new(),
+            metadata: Metadata::default(),
+            metadata_field: MetadataField::Remark,
+            books: Vec::new(),
+            front_matter: String::new(),
+            intro_depth: 0,
+            intro_note_depth: None,
+            skipping: false,
+            warnings: Vec::new(),
+            omitted_warnings: 0,
+            coverage: Coverage::default(),
+            unknown_books: Vec::new(),
+            held: None,
+            intro_row: None,
+            acrostic_row: None,
+            error_position: None,
+            buffer: Vec::with_capacity(config.buffer_size.bytes(None)),
+            output: RowWriter::new(BufWriter::new(output), &config),
+            config,
+        }
+    }
+
+    /// Parse the USFX file and convert it to TSV format
+    ///
+    /// The output is buffered, and flushed at the end whether or not the conversion succeeds, so
+    /// the rows converted before an error are not lost.
+    ///
+    /// # Returns
+    /// * `Result<ParseSummary, ParserError>` - What was found in the document, or an error
+    pub fn parse(&mut self) -> Result<ParseSummary, ParserError> {
+        if let Err(error) = self.write_rows() {
+            // Best effort: the conversion error matters more than a failure to flush
+            let _ = self.output.flush();
+            return Err(error);
+        }
+        self.output.finish()?;
+        Ok(self.summary())
+    }
+
+    /// Convert every verse, writing the rows to the output
+    fn write_rows(&mut self) -> Result<(), ParserError> {
+        if self.config.threads > 1 {
+            let mut verses = self.read_books_in_parallel()?;
+            if self.config.canonical_order.is_some() || !self.config.book_order.is_empty() {
+                verses.sort_by_key(|verse| self.book_position(&verse.book));
+            }
+            for verse in &verses {
+                self.output.write_verse(verse)?;
+            }
+        } else if self.config.canonical_order.is_some() || !self.config.book_order.is_empty() {
+            let mut verses = Vec::new();
+            while let Some(verse) = self.next_verse()? {
+                verses.push(verse);
+            }
+            // Stable sort keeps source order within a book and for books outside the canon
+            verses.sort_by_key(|verse| self.book_position(&verse.book));
+            for verse in &verses {
+                self.output.write_verse(verse)?;
+            }
+        } else if self.config.pipeline {
+            self.write_rows_pipelined()?;
+        } else {
+            while let Some(verse) = self.next_verse()? {
+                self.output.write_verse(&verse)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Position of a book in the custom book order or, without one, the canonical order
+    fn book_position(&self, book: &str) -> usize {
+        let position = if self.config.book_order.is_empty() {
+            self.config
+                .canonical_order
+                .and_then(|canon| canon.position(book))
+        } else {
+            self.config.book_order.iter().position(|code| code == book)
+        };
+        position.unwrap_or(usize::MAX)
+    }
+
+    /// What was found in the document read so far
+    pub fn summary(&self) -> ParseSummary {
+        ParseSummary {
+            books: self.books.clone(),
+        }
+    }
+
+    /// Read events until the next complete verse
+    ///
+    /// # Returns
+    /// * `Result<Option<Verse>, ParserError>` - The next verse, or `None` at end of input
+    pub fn next_verse(&mut self) -> Result<Option<Verse>, ParserError> {
+        let merge = self.config.duplicate_verses == DuplicateVerses::Merge;
+        while let Some(verse) = self.read_next_verse()? {
+            if let Some(verses) = verse.bridged_verses()
+                && verses.end() - verses.start() >= MAX_BRIDGE_VERSES
+            {
+                self.warn(format!(
+                    "Bridge {} {}:{}-{} is longer than any chapter; only its first {MAX_BRIDGE_VERSES} verses are counted or expanded",
+                    verse.book,
+                    verse.chapter,
+                    verses.start(),
+                    verses.end()
+                ));
+            }
+            let acrostic = self.is_acrostic_row(&verse);
+            let duplicate = !acrostic && self.coverage.contains(&verse);
+            if !acrostic {
+                self.coverage.add(&verse);
+            }
+            if duplicate {
+                self.warn(duplicate_warning(&verse));
+                match self.config.duplicate_verses {
+                    DuplicateVerses::Drop => continue,
+                    DuplicateVerses::Merge => {
+                        if let Some(held) = self.held.as_mut()
+                            && same_reference(held, &verse)
+                        {
+                            held.append(&verse);
+                            continue;
+                        }
+                    }
+                    DuplicateVerses::Keep => (),
+                }
+            }
+            // Merging needs the verse before a duplicate, so each verse is held back until the next
+            if !merge {
+                return Ok(Some(verse));
+            }
+            if let Some(held) = self.held.replace(verse) {
+                return Ok(Some(held));
+            }
+        }
+        Ok(self.held.take())
+    }
+
+    /// Read the next verse from the document, recovering from errors if configured to
+    fn read_next_verse(&mut self) -> Result<Option<Verse>, ParserError> {
+        loop {
+            let mut buffer = std::mem::take(&mut self.buffer);
+            let verse = self.read_verse(&mut buffer);
+            self.buffer = buffer;
+            match verse {
+                Err(error) if self.config.recover => self.recover(error)?,
+                Ok(Some(mut verse)) => {
+                    bidi::apply(self.config.bidi, &mut verse);
+                    if let Some(map) = &self.config.versification_map {
+                        map.apply(&mut verse);
+                    }
+                    if !self.in_ranges(&verse) {
+                        continue;
+                    }
+                    return Ok(Some(verse));
+                }
+                verse => return verse,
+            }
+        }
+    }
+
+    /// Whether a verse lies within the configured ranges, if there are any
+    fn in_ranges(&self, verse: &Verse) -> bool {
+        if self.config.ranges.is_empty() {
+            return true;
+        }
+        let (Ok(chapter), Ok(number)) = (verse.chapter.parse(), verse.verse.parse()) else {
+            return false;
+        };
+        let reference = Reference {
+            book: verse.book.clone(),
+            chapter,
+            verse: Some(number),
+        };
+        self.config
+            .ranges
+            .iter()
+            .any(|range| range.contains(&reference))
+    }
+
+    /// Verses of the books read so far that `versification` expects but the text lacks
+    pub fn missing_verses(&self, versification: &Versification) -> Vec<MissingVerse> {
+        self.coverage.missing(versification)
+    }
+
+    /// Differences between the verse structure of the books read so far and `versification`:
+    /// missing, extra and bridged verses
+    pub fn versification_differences(&self, versification: &Versification) -> Vec<Difference> {
+        self.coverage.differences(versification)
+    }
+
+    /// Number of verses in each chapter of each book read so far, in document order, for
+    /// checking one release of a text against another
+    pub fn verse_counts(&self) -> Vec<(String, BTreeMap<u32, usize>)> {
+        let mut books: Vec<&str> = Vec::new();
+        for titles in &self.books {
+            if !books.contains(&titles.code.as_str()) {
+                books.push(&titles.code);
+            }
+        }
+        books
+            .into_iter()
+            .map(|book| (book.to_string(), self.coverage.verse_counts(book)))
+            .filter(|(_, chapters)| !chapters.is_empty())
+            .collect()
+    }
+
+    /// Write the verse counts read so far as a manifest: TSV rows of book, chapter and verse
+    /// count, or with `OutputFormat::Json` an array of `{"book", "chapters"}` objects mapping
+    /// chapter numbers to verse counts
+    pub fn write_manifest<W: Write>(
+        &self,
+        mut writer: W,
+        format: OutputFormat,
+    ) -> Result<(), ParserError> {
+        let counts = self.verse_counts();
+        let line_ending = self.config.line_ending.as_str();
+        match format {
+            OutputFormat::Tsv => {
+                for (book, chapters) in &counts {
+                    for (chapter, verses) in chapters {
+                        write!(writer, "{book}\t{chapter}\t{verses}{line_ending}")?;
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                let books: Vec<BookCounts<'_>> = counts
+                    .iter()
+                    .map(|(book, chapters)| BookCounts { book, chapters })
+                    .collect();
+                serde_json::to_writer_pretty(&mut writer, &books)
+                    .map_err(|e| ParserError::ParseError(e.to_string()))?;
+                writer.write_all(line_ending.as_bytes())?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Problems found in the input so far, such as duplicate verses or, in recovery mode,
+    /// malformed XML that was skipped
+    ///
+    /// Only the first thousand are kept, followed by a note that the rest were left out.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Number of warnings left out of [`UsfxParser::warnings`]
+    pub fn omitted_warnings(&self) -> usize {
+        self.omitted_warnings
+    }
+
+    /// Unescape a text event, replacing tabs with spaces so that they cannot split TSV fields
+    /// and dealing with any control characters in it as configured
+    fn read_text<'a>(&mut self, e: &'a BytesText<'_>) -> Result<Cow<'a, str>, ParserError> {
+        let mut text = unescape(e)?;
+        if text.contains('\t') {
+            self.warn(format!("Replaced tabs with spaces in {}", self.location()));
+            text = Cow::Owned(text.replace('\t', " "));
+        }
+        let mut found: Vec<char> = text.chars().filter(|&c| is_control(c)).collect();
+        if found.is_empty() {
+            return Ok(text);
+        }
+        found.sort_unstable();
+        found.dedup();
+        let found: Vec<String> = found
+            .iter()
+            .map(|c| format!("U+{:04X}", u32::from(*c)))
+            .collect();
+        let location = self.location();
+        let (action, text) = match self.config.control_chars {
+            ControlChars::Strip => ("Removed", Cow::Owned(text.replace(is_control, ""))),
+            ControlChars::Escape => (
+                "Escaped",
+                Cow::Owned(
+                    text.chars()
+                        .map(|c| match c {
+                            _ if is_control(c) => c.escape_unicode().to_string(),
+                            _ => c.to_string(),
+                        })
+                        .collect(),
+                ),
+            ),
+            ControlChars::Keep => ("Kept", text),
+        };
+        self.warn(format!(
+            "{action} control characters {} in {location}",
+            found.join(", ")
+        ));
+        Ok(text)
+    }
+
+    /// The verse being read, or the book and chapter outside verses, for warnings
+    fn location(&self) -> String {
+        let location = match &self.current {
+            Some(verse) => format!("{} {}:{}", verse.book, verse.chapter, verse.verse),
+            None => format!("{} {}", self.book_id, self.chapter_id),
+        };
+        location.trim().to_string()
+    }
+
+    /// The standard USFM code for a book identifier, if normalizing them
+    fn book_code(&mut self, id: &str) -> String {
+        if !self.config.normalize_book_codes || id.is_empty() {
+            return id.to_string();
+        }
+        if let Some(code) = canon::usfm_code(id) {
+            return code.to_string();
+        }
+        if let Some(index) = self.unknown_books.iter().position(|book| book == id) {
+            return synthetic_book_code(index);
+        }
+        let code = synthetic_book_code(self.unknown_books.len());
+        self.unknown_books.push(id.to_string());
+        self.warn(format!("Unknown book code {id} (written as {code})"));
+        code
+    }
+
+    /// Record a warning at the current position in the input
+    fn warn(&mut self, message: String) {
+        self.record(Warning {
+            position: self.reader.buffer_position(),
+            message,
+        });
+    }
+
+    /// Keep a warning, unless the most that are kept have been already
+    fn record(&mut self, warning: Warning) {
+        if self.warnings.len() < MAX_WARNINGS {
+            self.warnings.push(warning);
+            return;
+        }
+        if self.omitted_warnings == 0 {
+            self.warnings.push(Warning {
+                message: format!("More than {MAX_WARNINGS} warnings; the rest are left out"),
+                ..warning
+            });
+        }
+        self.omitted_warnings += 1;
+    }
+
+    /// Fail if the XML event just read, `event` bytes long, or the verse being read is larger
+    /// than the configured limit
+    fn check_size(&mut self, event: usize) -> Result<(), ParserError> {
+        let Some(limit) = self.config.max_verse_size else {
+            return Ok(());
+        };
+        if event > limit {
+            return Err(ParserError::ParseError(format!(
+                "XML event before byte {} is larger than the limit of {limit} bytes",
+                self.reader.buffer_position()
+            )));
+        }
+        if let Some(verse) = &self.current
+            && verse.text.len() + verse.raw.as_ref().map_or(0, String::len) > limit
+        {
+            let message = format!(
+                "Verse {} {}:{} is larger than the limit of {limit} bytes",
+                verse.book, verse.chapter, verse.verse
+            );
+            // Dropped, so that recovering from the error does not write it out
+            self.current = None;
+            return Err(ParserError::ParseError(message));
+        }
+        Ok(())
+    }
+
+    /// Record a recoverable error as a warning and skip to the next verse boundary
+    fn recover(&mut self, error: ParserError) -> Result<(), ParserError> {
+        let (position, message) = match &error {
+            ParserError::FileError(_) | ParserError::XmlError(quick_xml::Error::Io(_)) => {
+                return Err(error);
+            }
+            ParserError::XmlError(e) => (self.reader.error_position(), e.to_string()),
+            ParserError::ParseError(message) => (self.reader.buffer_position(), message.clone()),
+        };
+        // An error that does not move the reader forward would be hit again forever
+        if self.error_position.replace(position) == Some(position) {
+            return Err(error);
+        }
+        self.record(Warning {
+            position,
+            message: format!("{message} (skipped to the next verse boundary)"),
+        });
+        self.skipping = true;
+        self.states.clear();
+        self.spans.clear();
+        self.footnote = None;
+        self.cross_reference = None;
+        self.word = None;
+        self.resume();
+        Ok(())
+    }
+
+    fn read_verse(&mut self, buffer: &mut Vec<u8>) -> Result<Option<Verse>, ParserError> {
+        loop {
+            buffer.clear();
+            let event = self.reader.read_event_into(buffer);
+            let size = event.as_ref().map_or(0, |event| event.len());
+            self.check_size(size)?;
+            if let Ok(event) = &event
+                && let Some(raw) = self.current.as_mut().and_then(|v| v.raw.as_mut())
+            {
+                append_raw(raw, event);
+            }
+            if matches!(event, Ok(Event::Start(_) | Event::End(_) | Event::Empty(_))) {
+                self.tag_boundary = true;
+            }
+            if self.skipping {
+                match &event {
+                    Ok(Event::Start(e) | Event::Empty(e)) if is_boundary(e.name().as_ref()) => {}
+                    Ok(Event::End(e)) if is_boundary(e.name().as_ref()) => {}
+                    Ok(Event::Eof) | Err(_) => {}
+                    Ok(_) => continue,
+                }
+                self.skipping = false;
+            }
+
+            match event {
+                Err(e) => return Err(ParserError::XmlError(e)),
+
+                Ok(Event::Start(e)) if self.state == ParserState::InIntro => {
+                    self.intro_depth += 1;
+                    if matches!(e.name().as_ref(), b"f" | b"x") && self.intro_note_depth.is_none() {
+                        self.intro_note_depth = Some(self.intro_depth);
+                    }
+                }
+
+                Ok(Event::End(_)) if self.state == ParserState::InIntro => {
+                    if self.intro_depth == 0 {
+                        self.leave();
+                        self.finish_front_matter();
+                    } else {
+                        if self.intro_note_depth == Some(self.intro_depth) {
+                            self.intro_note_depth = None;
+                        }
+                        self.intro_depth -= 1;
+                    }
+                }
+
+                Ok(Event::Start(e)) => match e.name().as_ref() {
+                    b"book" => {
+                        self.state = ParserState::Book;
+                        self.states.clear();
+                        let id = attribute(&e, b"id")?.unwrap_or_default();
+                        self.book_id = self.book_code(&id);
+                        self.books.push(BookTitles {
+                            code: self.book_id.clone(),
+                            ..BookTitles::default()
+                        });
+                        self.chapter_id.clear();
+                        self.section = None;
+                        self.sections.clear();
+                        self.section_number = 0;
+                        self.major_section = None;
+                        self.parallel = None;
+                        self.parallel_targets.clear();
+                        self.speaker = None;
+                        self.title = None;
+                        self.book_chapter_label = None;
+                        self.chapter_label = None;
+                        self.alt_chapter = None;
+                    }
+                    b"c" => {
+                        // A new chapter ends a verse whose `<ve/>` is missing
+                        self.start_chapter(&e)?;
+                        if let Some(verse) = self.end_verse() {
+                            return Ok(Some(verse));
+                        }
+                    }
+                    b"ve" => {
+                        self.state = ParserState::VerseEnd;
+                        self.in_content = false;
+                    }
+                    b"w" => {
+                        // Words inside notes and headings belong to them, not to the verse
+                        let state = match self.state {
+                            ParserState::Initial | ParserState::InVerse | ParserState::InWord => {
+                                ParserState::InWord
+                            }
+                            state => state,
+                        };
+                        self.enter(state);
+                        self.in_content = true;
+                        self.start_word(&e)?;
+                    }
+                    b"wg" | b"wh" | b"wa" | b"wl" => self.start_word(&e)?,
+                    b"v" => {
+                        self.in_content = true;
+                        self.state = ParserState::InVerse;
+                    }
+                    b"s" | b"s1" | b"s2" | b"s3" | b"s4" => {
+                        self.section_level = section_level(&e)?;
+                        self.start_heading(ParserState::InSection);
+                    }
+                    b"ms" | b"ms1" | b"ms2" | b"ms3" => {
+                        self.start_heading(ParserState::InMajorSection);
+                    }
+                    b"mr" => self.start_heading(ParserState::InMajorRange),
+                    b"r" => self.start_heading(ParserState::InParallel),
+                    b"sp" => self.start_heading(ParserState::InSpeaker),
+                    b"d" => self.start_heading(ParserState::InTitle),
+                    b"qa" => self.start_heading(ParserState::InAcrostic),
+                    b"cl" | b"cp" => self.start_heading(ParserState::InChapterLabel),
+                    b"ca" => self.start_heading(ParserState::InAltChapter),
+                    b"va" => self.start_heading(ParserState::InAltVerse),
+                    b"vp" => self.start_heading(ParserState::InPublishedVerse),
+                    b"usfx" => self.read_root_attributes(&e)?,
+                    b"languageCode" => self.start_metadata(MetadataField::LanguageCode),
+                    b"id" => self.start_metadata(MetadataField::Id),
+                    b"rem" => self.start_metadata(MetadataField::Remark),
+                    b"h" => self.start_metadata(MetadataField::Header),
+                    b"toc" => {
+                        let level = attribute(&e, b"level")?
+                            .and_then(|level| level.parse().ok())
+                            .unwrap_or(1);
+                        self.start_metadata(MetadataField::Toc(level));
+                    }
+                    // A note inside another note is read as part of the outer one
+                    b"f" | b"x" if self.in_note() => self.enter(self.state),
+                    b"f" => {
+                        let caller = attribute(&e, b"caller")?.unwrap_or_default();
+                        self.mark_footnote(&caller);
+                        self.enter(ParserState::InFootnote);
+                        self.footnote = Some(Footnote {
+                            caller,
+                            ..Footnote::default()
+                        });
+                        self.note_part = NotePart::Text;
+                    }
+                    b"x" => {
+                        self.enter(ParserState::InCrossReference);
+                        self.cross_reference = Some(CrossReference {
+                            caller: attribute(&e, b"caller")?.unwrap_or_default(),
+                            ..CrossReference::default()
+                        });
+                        self.note_part = NotePart::Text;
+                    }
+                    b"tr" => {
+                        self.table_cells = 0;
+                        self.space_required = true;
+                    }
+                    b"th" | b"thr" | b"tc" | b"tcr" => self.start_table_cell(),
+                    b"qs" => self.open_span(Span::Selah),
+                    b"add" => self.open_span(Span::Added),
+                    b"nd" => self.open_span(Span::DivineName),
+                    b"qt" => self.open_span(Span::Quotation),
+                    b"fig" => {
+                        self.enter(ParserState::InFigure);
+                        self.figures.push(Figure {
+                            file: attribute(&e, b"src")?.unwrap_or_default(),
+                            ..Figure::default()
+                        });
+                        self.figure_part = FigurePart::Other;
+                    }
+                    b"caption" => self.figure_part = FigurePart::Caption,
+                    b"catalog" => self.figure_part = FigurePart::File,
+                    b"reference" => self.figure_part = FigurePart::Reference,
+                    b"description" | b"size" | b"location" | b"copyright" => {
+                        self.figure_part = FigurePart::Other;
+                    }
+                    b"fr" | b"xo" => self.note_part = NotePart::Reference,
+                    b"fk" | b"xk" => self.note_part = NotePart::Keyword,
+                    b"fq" | b"fqa" | b"xq" => self.note_part = NotePart::Quotation,
+                    b"ft" | b"xt" => self.note_part = NotePart::Text,
+                    b"ref" => {
+                        // Explicit targets, e.g. <ref tgt="JHN.1.1">John 1:1</ref>
+                        if let Some(target) = attribute(&e, b"tgt")?
+                            .as_deref()
+                            .and_then(reference::parse_bcv)
+                            && let Some(cross_reference) = self.cross_reference.as_mut()
+                        {
+                            cross_reference.targets.push(target);
+                        }
+                    }
+                    name if let Some(span) = Span::char_style(name) => self.open_span(span),
+                    _ if let Some(style) = intro_style(&e)? => self.start_intro(style),
+                    _ => {
+                        if let Some(style) = paragraph_style(&e)? {
+                            // List items are content paragraphs like any other
+                            self.in_content = true;
+                            self.front_matter.clear();
+                            self.paragraph = Some(style);
+                        }
+                        if let Some(level) = poetry_level(&e)? {
+                            self.start_poetry_line(level);
+                        }
+                    }
+                },
+
+                Ok(Event::Text(e)) => {
+                    if self.state == ParserState::InIntro {
+                        if self.intro_note_depth.is_none() {
+                            let text = self.read_text(&e)?;
+                            push_words(&mut self.front_matter, &text);
+                            if self.config.intro_mode == IntroMode::Export
+                                && let Some(paragraph) = self.intro.last_mut()
+                            {
+                                push_words(&mut paragraph.text, &text);
+                            }
+                        }
+                    } else if self.state == ParserState::Book && self.chapter_id.is_empty() {
+                        // Front matter before the first chapter, e.g. of a `FRT` book
+                        let text = self.read_text(&e)?;
+                        push_words(&mut self.front_matter, &text);
+                    } else if matches!(
+                        self.state,
+                        ParserState::InSection
+                            | ParserState::InMajorSection
+                            | ParserState::InMajorRange
+                            | ParserState::InParallel
+                            | ParserState::InSpeaker
+                            | ParserState::InTitle
+                            | ParserState::InAcrostic
+                            | ParserState::InChapterLabel
+                            | ParserState::InAltChapter
+                            | ParserState::InAltVerse
+                            | ParserState::InPublishedVerse
+                            | ParserState::InMetadata
+                    ) {
+                        let text = self.read_text(&e)?;
+                        self.push_heading(&text);
+                    } else if self.state == ParserState::InFigure {
+                        let text = self.read_text(&e)?;
+                        self.push_figure(&text);
+                    } else if matches!(
+                        self.state,
+                        ParserState::InFootnote | ParserState::InCrossReference
+                    ) {
+                        let text = self.read_text(&e)?;
+                        self.push_note(&text);
+                    } else if self.in_content
+                        && self.state != ParserState::InFootnote
+                        && self.state != ParserState::InCrossReference
+                        && self.state != ParserState::InSection
+                        && self.state != ParserState::Book
+                    {
+                        let text = self.read_text(&e)?;
+
+                        // Whitespace trimmed from the edges still separates the text from its neighbours
+                        let (text, trailing_space) = if self.config.trim_text {
+                            let trimmable = self.trimmable();
+                            if text.starts_with(trimmable) {
+                                self.space_pending = true;
+                            }
+                            (text.trim_matches(trimmable), text.ends_with(trimmable))
+                        } else {
+                            (text.as_ref(), false)
+                        };
+
+                        match self.state {
+                            ParserState::InVerse => match text {
+                                "\n" => self.push_text("^"),
+                                _ => self.push_text(text),
+                            },
+                            ParserState::InWord => self.push_text(text),
+                            _ => (),
+                        }
+                        if trailing_space {
+                            self.space_pending = true;
+                        }
+                    }
+                }
+
+                Ok(Event::End(e)) => match e.name().as_ref() {
+                    b"v" => self.state = ParserState::InVerse,
+                    b"w" => {
+                        self.leave();
+                        self.finish_word();
+                    }
+                    b"wg" | b"wh" | b"wa" | b"wl" => self.finish_word(),
+                    b"c" | b"book" => {
+                        if let Some(verse) = self.end_verse() {
+                            return Ok(Some(verse));
+                        }
+                    }
+                    b"s" | b"s1" | b"s2" | b"s3" | b"s4" => {
+                        self.leave();
+                        if !self.heading.is_empty() {
+                            let heading = std::mem::take(&mut self.heading);
+                            self.push_section(heading);
+                            self.parallel = None;
+                            self.parallel_targets.clear();
+                        }
+                    }
+                    b"r" => {
+                        self.leave();
+                        let parallel = std::mem::take(&mut self.heading);
+                        let context = Reference {
+                            book: self.book_id.clone(),
+                            chapter: self.chapter_id.parse().unwrap_or(1),
+                            verse: None,
+                        };
+                        self.parallel_targets = reference::parse_list(
+                            parallel.trim_matches(['(', ')']),
+                            Some(&context),
+                        );
+                        self.parallel = Some(parallel);
+                    }
+                    b"languageCode" | b"id" | b"rem" | b"h" | b"toc"
+                        if self.state == ParserState::InMetadata =>
+                    {
+                        self.leave();
+                        let text = std::mem::take(&mut self.heading);
+                        self.finish_metadata(text);
+                    }
+                    b"ms" | b"ms1" | b"ms2" | b"ms3" => {
+                        self.leave();
+                        if !self.heading.is_empty() {
+                            self.major_section = Some(std::mem::take(&mut self.heading));
+                        }
+                    }
+                    b"mr" => {
+                        self.leave();
+                        let range = std::mem::take(&mut self.heading);
+                        if let Some(major_section) = self.major_section.as_mut() {
+                            push_words(major_section, &range);
+                        } else if !range.is_empty() {
+                            self.major_section = Some(range);
+                        }
+                    }
+                    b"sp" => {
+                        self.leave();
+                        if !self.heading.is_empty() {
+                            self.speaker = Some(std::mem::take(&mut self.heading));
+                        }
+                    }
+                    b"d" => {
+                        // The title may sit inside the chapter's first verse
+                        self.leave();
+                        let title = std::mem::take(&mut self.heading);
+                        if !title.is_empty()
+                            && let Some(verse) = self.finish_title(title)
+                        {
+                            return Ok(Some(verse));
+                        }
+                    }
+                    b"f" => {
+                        // Text after the note continues the verse
+                        self.leave();
+                        if !self.in_note()
+                            && let (Some(footnote), Some(verse)) =
+                                (self.footnote.take(), self.current.as_mut())
+                        {
+                            verse.footnotes.push(footnote);
+                        }
+                    }
+                    b"x" => {
+                        self.leave();
+                        if !self.in_note()
+                            && let Some(cross_reference) = self.cross_reference.take()
+                        {
+                            self.finish_cross_reference(cross_reference);
+                        }
+                    }
+                    b"qa" => {
+                        self.leave();
+                        let acrostic = std::mem::take(&mut self.heading);
+                        match self.config.acrostic_mode {
+                            AcrosticMode::Column => self.acrostic = Some(acrostic),
+                            AcrosticMode::Rows => {
+                                // The row is numbered as the verse under the heading, so it
+                                // waits for that verse to start; the verse before it ends here
+                                self.acrostic_row = Some(Verse {
+                                    book: self.book_id.clone(),
+                                    chapter: self.chapter_id.clone(),
+                                    text: acrostic.clone(),
+                                    acrostic: Some(acrostic),
+                                    section: self.section.clone(),
+                                    section_number: self.section_number,
+                                    ..Verse::default()
+                                });
+                                if let Some(verse) = self.end_verse() {
+                                    return Ok(Some(verse));
+                                }
+                            }
+                        }
+                    }
+                    b"cl" | b"cp" => {
+                        self.leave();
+                        let label = std::mem::take(&mut self.heading);
+                        if self.chapter_id.is_empty() {
+                            // Before the first chapter, `<cl>` labels every chapter of the book
+                            self.book_chapter_label = Some(label);
+                        } else {
+                            self.chapter_label = Some(label);
+                        }
+                    }
+                    b"ca" => {
+                        self.leave();
+                        self.alt_chapter = Some(std::mem::take(&mut self.heading));
+                    }
+                    b"va" | b"vp" => {
+                        let number = Some(std::mem::take(&mut self.heading));
+                        if let Some(verse) = self.current.as_mut() {
+                            if self.state == ParserState::InAltVerse {
+                                verse.alt_verse = number;
+                            } else {
+                                verse.published_verse = number;
+                            }
+                        }
+                        self.leave();
+                    }
+                    b"fig" => {
+                        self.leave();
+                        if let Some(verse) = self.current.as_mut() {
+                            verse.figures.append(&mut self.figures);
+                        }
+                    }
+                    b"caption" | b"catalog" | b"reference" => self.figure_part = FigurePart::Other,
+                    b"fr" | b"fk" | b"fq" | b"fqa" | b"xo" | b"xk" | b"xq" => {
+                        self.note_part = NotePart::Text;
+                    }
+                    b"ve" => self.state = ParserState::Initial,
+                    b"qs" => self.close_span(Span::Selah),
+                    b"add" => self.close_span(Span::Added),
+                    b"nd" => self.close_span(Span::DivineName),
+                    b"qt" => self.close_span(Span::Quotation),
+                    name if let Some(span) = Span::char_style(name) => self.close_span(span),
+                    b"p" | b"q" | b"q1" | b"q2" | b"q3" | b"q4" => {
+                        self.poetry_level = None;
+                        self.paragraph = None;
+                        self.finish_front_matter();
+                    }
+                    b"li" | b"li1" | b"li2" | b"li3" | b"li4" => self.paragraph = None,
+                    _ => (),
+                },
+
+                Ok(Event::Empty(e)) => {
+                    if e.name() == quick_xml::name::QName(b"ve") {
+                        self.state = ParserState::Initial;
+                        if let Some(verse) = self.current.take() {
+                            return Ok(Some(verse));
+                        }
+                    } else if e.name() == quick_xml::name::QName(b"v") {
+                        // The next verse ends a verse whose `<ve/>` is missing
+                        let previous = self.end_verse();
+                        self.start_verse(&e)?;
+                        if previous.is_some() {
+                            return Ok(previous);
+                        }
+                        if let Some(verse) = self.current.as_ref()
+                            && let Some(mut row) = self.acrostic_row.take()
+                        {
+                            row.chapter.clone_from(&verse.chapter);
+                            row.verse.clone_from(&verse.verse);
+                            return Ok(Some(row));
+                        }
+                    } else if e.name() == quick_xml::name::QName(b"b") {
+                        // A stanza break always separates words, with or without a token
+                        self.space_required = true;
+                        self.stanza_break_pending = true;
+                        if self.current.is_some() {
+                            self.push_stanza_break();
+                        }
+                    } else if e.name() == quick_xml::name::QName(b"optbreak") {
+                        match self.config.opt_break {
+                            OptBreak::Nothing => (),
+                            OptBreak::Space => self.space_required = true,
+                            OptBreak::SoftHyphen => self.push_text("\u{ad}"),
+                        }
+                    } else if e.name() == quick_xml::name::QName(b"c") {
+                        self.start_chapter(&e)?;
+                        if let Some(verse) = self.end_verse() {
+                            return Ok(Some(verse));
+                        }
+                    }
+                }
+
+                Ok(Event::Eof) => {
+                    return Ok(self.end_verse().or_else(|| self.acrostic_row.take()));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Enter a nested element's state, remembering the state to return to when it ends
+    fn enter(&mut self, state: ParserState) {
+        let previous = std::mem::replace(&mut self.state, state);
+        self.states.push(previous);
+    }
+
+    /// Return to the state from before the innermost nested element
+    fn leave(&mut self) {
+        match self.states.pop() {
+            Some(state) => self.state = state,
+            None => self.resume(),
+        }
+    }
+
+    /// Whether a footnote or cross reference is being read
+    fn in_note(&self) -> bool {
+        matches!(
+            self.state,
+            ParserState::InFootnote | ParserState::InCrossReference
+        )
+    }
+
+    /// Enter a heading or label, whose text is collected apart from the verse
+    fn start_heading(&mut self, state: ParserState) {
+        self.enter(state);
+        self.heading.clear();
+    }
+
+    /// Replace the headings at or below the current level with a new heading
+    fn push_section(&mut self, heading: String) {
+        let level = self.section_level;
+        self.sections.retain(|(open, _)| *open < level);
+        self.section_number += 1;
+        self.section = Some(if self.config.section_breadcrumb {
+            let mut breadcrumb = String::new();
+            for (_, open) in &self.sections {
+                breadcrumb.push_str(open);
+                breadcrumb.push_str(" > ");
+            }
+            breadcrumb.push_str(&heading);
+            breadcrumb
+        } else {
+            heading.clone()
+        });
+        self.sections.push((level, heading));
+    }
+
+    /// Enter an introduction paragraph, which is kept out of the verses
+    fn start_intro(&mut self, style: String) {
+        self.enter(ParserState::InIntro);
+        self.front_matter.clear();
+        self.intro_depth = 0;
+        self.intro_note_depth = None;
+        if self.config.intro_mode == IntroMode::Export {
+            self.intro.push(IntroParagraph {
+                book: self.book_id.clone(),
+                style,
+                text: String::new(),
+            });
+        }
+    }
+
+    /// Introduction paragraphs read so far, when exporting them
+    pub fn intro(&self) -> &[IntroParagraph] {
+        &self.intro
+    }
+
+    /// Metadata of the document read so far: its language, root attributes, book
+    /// identification lines, remarks and copyright statements
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Write the metadata read so far as a JSON object
+    pub fn write_metadata<W: Write>(&self, mut writer: W) -> Result<(), ParserError> {
+        serde_json::to_writer_pretty(&mut writer, &self.metadata)
+            .map_err(|e| ParserError::ParseError(e.to_string()))?;
+        writer.write_all(self.config.line_ending.as_str().as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Record the attributes of the root `<usfx>` element
+    fn read_root_attributes(&mut self, e: &BytesStart<'_>) -> Result<(), ParserError> {
+        for attr in e.attributes() {
+            let attr = attr.map_err(|e| ParserError::ParseError(e.to_string()))?;
+            let value = attr
+                .unescape_value()
+                .map_err(|e| ParserError::ParseError(e.to_string()))?;
+            self.metadata.attributes.insert(
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                value.into_owned(),
+            );
+        }
+        Ok(())
+    }
+
+    fn start_metadata(&mut self, field: MetadataField) {
+        self.metadata_field = field;
+        self.start_heading(ParserState::InMetadata);
+    }
+
+    /// Store the text of a metadata element, noting any copyright it states
+    fn finish_metadata(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if matches!(
+            self.metadata_field,
+            MetadataField::Id | MetadataField::Remark
+        ) && (text.contains('©') || text.to_lowercase().contains("copyright"))
+            && !self.metadata.copyright.contains(&text)
+        {
+            self.metadata.copyright.push(text.clone());
+        }
+        match self.metadata_field {
+            MetadataField::LanguageCode => self.metadata.language_code = Some(text),
+            MetadataField::Id => {
+                self.metadata.ids.insert(self.book_id.clone(), text);
+            }
+            MetadataField::Remark => self.metadata.remarks.push(Remark {
+                book: (!self.book_id.is_empty()).then(|| self.book_id.clone()),
+                text,
+            }),
+            MetadataField::Header | MetadataField::Toc(_) => {
+                let Some(titles) = self.books.last_mut() else {
+                    return;
+                };
+                let name = match self.metadata_field {
+                    MetadataField::Toc(1) => &mut titles.long_name,
+                    MetadataField::Toc(2) => &mut titles.short_name,
+                    MetadataField::Toc(3) => &mut titles.abbreviation,
+                    MetadataField::Header => &mut titles.header,
+                    _ => return,
+                };
+                name.get_or_insert(text);
+            }
+        }
+    }
+
+    /// Keep the front matter paragraph just read if it states a copyright or license, and add
+    /// it to the verse 0 row if introductions are written as rows
+    fn finish_front_matter(&mut self) {
+        let paragraph = std::mem::take(&mut self.front_matter);
+        let lowercase = paragraph.to_lowercase();
+        if LICENSE_PHRASES
+            .iter()
+            .any(|phrase| lowercase.contains(phrase))
+            && !self.metadata.license.contains(&paragraph)
+        {
+            self.metadata.license.push(paragraph.clone());
+        }
+        if self.config.intro_mode != IntroMode::VerseZero || paragraph.is_empty() {
+            return;
+        }
+        let chapter = match self.chapter_id.as_str() {
+            "" => "0",
+            chapter => chapter,
+        };
+        match self.intro_row.as_mut() {
+            Some(row) if row.book == self.book_id && row.chapter == chapter => {
+                push_words(&mut row.text, &paragraph);
+            }
+            _ => {
+                self.intro_row = Some(Verse {
+                    book: self.book_id.clone(),
+                    chapter: chapter.to_string(),
+                    verse: "0".to_string(),
+                    text: paragraph,
+                    ..Verse::default()
+                });
+            }
+        }
+    }
+
+    /// Write the names of each book read so far as TSV rows: code, short name, long name and
+    /// abbreviation, with the running header standing in for a missing short name
+    pub fn write_books<W: Write>(&self, mut writer: W) -> Result<(), ParserError> {
+        if self.config.byte_order_mark {
+            writer.write_all(encoding::UTF_8_BOM)?;
+        }
+        for titles in &self.books {
+            write!(
+                writer,
+                "{}\t{}\t{}\t{}{}",
+                titles.code,
+                escape_field(
+                    titles
+                        .short_name
+                        .as_deref()
+                        .or(titles.header.as_deref())
+                        .unwrap_or_default()
+                ),
+                escape_field(titles.long_name.as_deref().unwrap_or_default()),
+                escape_field(titles.abbreviation.as_deref().unwrap_or_default()),
+                self.config.line_ending.as_str()
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the introduction paragraphs read so far as TSV rows: book, style, text
+    pub fn write_intro<W: Write>(&self, mut writer: W) -> Result<(), ParserError> {
+        if self.config.byte_order_mark {
+            writer.write_all(encoding::UTF_8_BOM)?;
+        }
+        let labels = books::labels(&self.config);
+        for paragraph in self
+            .intro
+            .iter()
+            .filter(|paragraph| !paragraph.text.is_empty())
+        {
+            let book = labels
+                .as_ref()
+                .and_then(|names| names.get(&paragraph.book))
+                .unwrap_or(&paragraph.book);
+            write!(
+                writer,
+                "{}\t{}\t{}{}",
+                book,
+                paragraph.style,
+                escape_field(&paragraph.text),
+                self.config.line_ending.as_str()
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Return to the verse after material inside it, or to the space between verses
+    fn resume(&mut self) {
+        self.state = match self.current {
+            Some(_) => ParserState::InVerse,
+            None => ParserState::Initial,
+        };
+    }
+
+    /// Whether a verse is an acrostic heading written as a row of its own, which shares the
+    /// number of the verse under it and so is never a duplicate
+    fn is_acrostic_row(&self, verse: &Verse) -> bool {
+        self.config.acrostic_mode == AcrosticMode::Rows && verse.acrostic.is_some()
+    }
+
+    /// Finish the verse being read, if any, as when a boundary implies its missing `<ve/>`;
+    /// without one, a pending verse 0 row of introduction ends at the boundary instead
+    fn end_verse(&mut self) -> Option<Verse> {
+        let Some(verse) = self.current.take() else {
+            return self.intro_row.take();
+        };
+        self.state = ParserState::Initial;
+        Some(verse)
+    }
+
+    /// Enter a chapter from a `<c>` milestone or element
+    fn start_chapter(&mut self, e: &BytesStart<'_>) -> Result<(), ParserError> {
+        self.chapter_id = attribute(e, b"id")?.unwrap_or_default();
+        self.title = None;
+        self.acrostic = None;
+        self.chapter_label = None;
+        self.alt_chapter = None;
+        Ok(())
+    }
+
+    /// Start a new verse from a `<v>` milestone
+    fn start_verse(&mut self, e: &BytesStart<'_>) -> Result<(), ParserError> {
+        let mut bcv = None;
+        let mut id = None;
+        for attr in e.attributes() {
+            let attr = attr.map_err(|e| ParserError::ParseError(e.to_string()))?;
+            // Attribute values are borrowed from the event, as verse numbers are never escaped
+            let value = match attr.value {
+                Cow::Borrowed(value) => Cow::Borrowed(
+                    str::from_utf8(value).map_err(|e| ParserError::ParseError(e.to_string()))?,
+                ),
+                Cow::Owned(value) => Cow::Owned(
+                    String::from_utf8(value).map_err(|e| ParserError::ParseError(e.to_string()))?,
+                ),
+            };
+            match attr.key.as_ref() {
+                b"bcv" => bcv = Some(value),
+                b"id" => id = Some(value),
+                _ => (),
+            }
+        }
+
+        // Without `bcv`, the reference comes from the enclosing book and chapter; single-chapter
+        // books may have no `<c>` at all
+        let chapter = match self.chapter_id.as_str() {
+            "" if canon::is_single_chapter(&self.book_id) => "1",
+            chapter => chapter,
+        };
+        let bcv = match (bcv, id.as_deref()) {
+            (Some(bcv), _) => bcv,
+            (None, Some(id)) if !self.book_id.is_empty() && !chapter.is_empty() => {
+                Cow::Owned(format!("{}.{chapter}.{id}", self.book_id))
+            }
+            _ => return Ok(()),
+        };
+        // Bridges appear as `PRO.30.17-18`, `PRO.30.17-PRO.30.18` or only in `id="17-18"`
+        let (start, end) = split_bridge(&bcv);
+        let mut parts: Vec<&str> = start.split('.').collect();
+        // Single-chapter books may leave the chapter out, as in `JUD.3`
+        if parts.len() == 2 && canon::is_single_chapter(&self.book_code(parts[0])) {
+            parts.insert(1, "1");
+        }
+        // Verse parts appear as `EST.1.1a` or `EST.1.1.a`
+        let (verse, subverse) = match parts[..] {
+            [_, _, verse] => split_subverse(verse),
+            [_, _, verse, part] if part.chars().all(char::is_alphabetic) => (verse, Some(part)),
+            _ => return Ok(()),
+        };
+        let verse_end = end.or_else(|| id.as_deref().and_then(|id| split_bridge(id).1));
+
+        let book = self.book_code(parts[0]);
+        self.current = Some(Verse {
+            book,
+            chapter: parts[1].to_string(),
+            verse: verse.to_string(),
+            subverse: subverse.map(str::to_string),
+            verse_end: verse_end.map(str::to_string),
+            section: self.section.clone(),
+            section_number: self.section_number,
+            major_section: self.major_section.clone(),
+            parallel: self.parallel.clone(),
+            parallel_targets: self.parallel_targets.clone(),
+            speaker: self.speaker.clone(),
+            paragraph: self.paragraph.clone(),
+            chapter_label: self.chapter_label.clone().or_else(|| {
+                self.book_chapter_label
+                    .as_ref()
+                    .map(|label| format!("{label} {}", self.chapter_id))
+            }),
+            alt_chapter: self.alt_chapter.clone(),
+            acrostic: self.acrostic.clone(),
+            title: self.title.clone(),
+            raw: self.config.columns.contains(&Column::Raw).then(String::new),
+            figures: std::mem::take(&mut self.figures),
+            poetry: self
+                .poetry_level
+                .map(|level| PoetryLine {
+                    level,
+                    text: String::new(),
+                })
+                .into_iter()
+                .collect(),
+            ..Verse::default()
+        });
+        self.state = ParserState::InVerse;
+        self.in_content = true;
+        self.space_pending = false;
+        self.space_required = false;
+        self.push_stanza_break();
+        Ok(())
+    }
+
+    /// Write a pending stanza break into the current verse, as a token set apart by spaces
+    fn push_stanza_break(&mut self) {
+        if !std::mem::take(&mut self.stanza_break_pending) {
+            return;
+        }
+        if let Some(token) = self.config.stanza_break.clone() {
+            self.space_required = true;
+            self.push_text(&token);
+            self.space_required = true;
+        }
+    }
+
+    /// Append text to the heading or label currently being read
+    fn push_heading(&mut self, text: &str) {
+        push_words(&mut self.heading, text);
+    }
+
+    /// Append text to the current part of the footnote or cross reference being read
+    fn push_note(&mut self, text: &str) {
+        let part = if let Some(footnote) = self.footnote.as_mut() {
+            push_words(&mut footnote.content, text);
+            match self.note_part {
+                NotePart::Reference => &mut footnote.reference,
+                NotePart::Keyword => &mut footnote.keyword,
+                NotePart::Quotation => &mut footnote.quotation,
+                NotePart::Text => &mut footnote.text,
+            }
+        } else if let Some(cross_reference) = self.cross_reference.as_mut() {
+            match self.note_part {
+                NotePart::Reference => &mut cross_reference.origin,
+                NotePart::Keyword => &mut cross_reference.keyword,
+                NotePart::Quotation => &mut cross_reference.quotation,
+                NotePart::Text => &mut cross_reference.text,
+            }
+        } else {
+            return;
+        };
+        push_words(part, text);
+    }
+
+    /// Append text to the current part of the figure being read
+    fn push_figure(&mut self, text: &str) {
+        let Some(figure) = self.figures.last_mut() else {
+            return;
+        };
+        match self.figure_part {
+            FigurePart::Caption => push_words(&mut figure.caption, text),
+            FigurePart::File if figure.file.is_empty() => figure.file = text.trim().to_string(),
+            FigurePart::Reference => push_words(&mut figure.reference, text),
+            FigurePart::File | FigurePart::Other => (),
+        }
+    }
+
+    /// Resolve a cross reference's targets and attach it to the current verse
+    fn finish_cross_reference(&mut self, mut cross_reference: CrossReference) {
+        let Some(verse) = self.current.as_mut() else {
+            return;
+        };
+        if cross_reference.targets.is_empty() {
+            // Bare verse numbers ("v. 3") refer to the verse's own chapter
+            let context = Reference {
+                book: verse.book.clone(),
+                chapter: verse.chapter.parse().unwrap_or(1),
+                verse: None,
+            };
+            cross_reference.targets = reference::parse_list(&cross_reference.text, Some(&context));
+        }
+        verse.cross_references.push(cross_reference);
+    }
+
+    /// Whitespace removed from the edges of text when trimming
+    fn trimmable(&self) -> fn(char) -> bool {
+        if self.config.preserve_unicode_spaces {
+            |c| c.is_ascii_whitespace()
+        } else {
+            char::is_whitespace
+        }
+    }
+
+    /// Append text to the verse currently being read, or to the span capturing it
+    fn push_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.spans.contains(&Span::Added) && self.config.added_words == AddedWords::Omit {
+            return;
+        }
+        let text = if self.spans.contains(&Span::DivineName)
+            && self.config.divine_name == DivineName::Uppercase
+        {
+            self.scratch.clear();
+            self.scratch
+                .extend(text.chars().flat_map(char::to_uppercase));
+            self.scratch.as_str()
+        } else {
+            text
+        };
+        let source_space = std::mem::take(&mut self.space_pending);
+        let required = std::mem::take(&mut self.space_required);
+        let tag_boundary = std::mem::take(&mut self.tag_boundary);
+        let Some(verse) = self.current.as_mut() else {
+            return;
+        };
+        if self.spans.contains(&Span::Selah) && self.config.selah_mode == SelahMode::Column {
+            push_words(verse.selah.get_or_insert_default(), text);
+            return;
+        }
+        let space = match (verse.text.chars().next_back(), text.chars().next()) {
+            (Some(before), Some(after)) if required => {
+                !before.is_whitespace() && !after.is_whitespace()
+            }
+            (Some(before), Some(after)) => {
+                self.config
+                    .join_rules
+                    .space_between(before, after, source_space, tag_boundary)
+            }
+            _ => false,
+        };
+        if space {
+            verse.text.push(' ');
+            if let Some(line) = verse.poetry.last_mut()
+                && !line.text.is_empty()
+            {
+                line.text.push(' ');
+            }
+        }
+        verse.text.push_str(text);
+        if let Some(line) = verse.poetry.last_mut() {
+            line.text.push_str(text);
+        }
+        if let Some(word) = self.word.as_mut() {
+            word.text.push_str(text);
+        }
+        if self.spans.contains(&Span::Quotation) {
+            if verse.quotations.is_empty() {
+                verse.quotations.push(String::new());
+            }
+            if let Some(quotation) = verse.quotations.last_mut() {
+                if space && !quotation.is_empty() {
+                    quotation.push(' ');
+                }
+                quotation.push_str(text);
+            }
+        }
+    }
+
+    /// Start recording a marked word and its attributes, if words are part of the output
+    fn start_word(&mut self, e: &BytesStart<'_>) -> Result<(), ParserError> {
+        if !self.config.columns.contains(&Column::Words) && self.config.format != OutputFormat::Json
+        {
+            return Ok(());
+        }
+        let mut word = Word::default();
+        for attr in e.attributes() {
+            let attr = attr.map_err(|e| ParserError::ParseError(e.to_string()))?;
+            let value = attr
+                .unescape_value()
+                .map_err(|e| ParserError::ParseError(e.to_string()))?;
+            word.attributes.insert(
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                value.into_owned(),
+            );
+        }
+        self.word = Some(word);
+        Ok(())
+    }
+
+    /// Attach the word being recorded to the current verse
+    fn finish_word(&mut self) {
+        if let (Some(word), Some(verse)) = (self.word.take(), self.current.as_mut()) {
+            verse.words.push(word);
+        }
+    }
+
+    /// Separate a table cell from the previous cell of its row
+    fn start_table_cell(&mut self) {
+        self.table_cells += 1;
+        if self.table_cells > 1
+            && let Some(verse) = self.current.as_mut()
+            && !verse.text.is_empty()
+        {
+            self.space_pending = false;
+            self.space_required = false;
+            let separator = self.config.table_separator.clone();
+            self.push_text(&separator);
+        }
+    }
+
+    /// Open an inline span, writing its opening marker if it has one
+    fn open_span(&mut self, span: Span) {
+        if let Some((open, _)) = self.span_markers(span) {
+            self.push_text(open);
+        }
+        if span == Span::Quotation
+            && let Some(verse) = self.current.as_mut()
+        {
+            verse.quotations.push(String::new());
+        }
+        self.spans.push(span);
+    }
+
+    /// Close the innermost span of this kind, writing its closing marker if it has one
+    fn close_span(&mut self, span: Span) {
+        let Some(index) = self.spans.iter().rposition(|open| *open == span) else {
+            return;
+        };
+        self.spans.remove(index);
+        if let Some((_, close)) = self.span_markers(span) {
+            // A closing marker hugs the span's text, whatever whitespace followed it
+            self.push_attached(close);
+        }
+    }
+
+    /// Append text directly after the preceding text, keeping any pending space for what follows
+    fn push_attached(&mut self, text: &str) {
+        let space = std::mem::take(&mut self.space_pending);
+        let required = std::mem::take(&mut self.space_required);
+        let tag_boundary = std::mem::take(&mut self.tag_boundary);
+        self.push_text(text);
+        self.space_pending = space;
+        self.space_required = required;
+        self.tag_boundary = tag_boundary;
+    }
+
+    /// Leave the configured footnote marker at the current position in the verse text
+    fn mark_footnote(&mut self, caller: &str) {
+        let (Some(marker), Some(verse)) = (&self.config.footnote_marker, &self.current) else {
+            return;
+        };
+        let marker = marker
+            .replace("{n}", &(verse.footnotes.len() + 1).to_string())
+            .replace("{caller}", caller);
+        self.push_attached(&marker);
+    }
+
+    /// Opening and closing markers written around a span's text, if any
+    fn span_markers(&self, span: Span) -> Option<(&'static str, &'static str)> {
+        match span {
+            Span::Selah => (self.config.selah_mode == SelahMode::Bracketed).then_some(("[", "]")),
+            Span::Added => match self.config.added_words {
+                AddedWords::Plain | AddedWords::Omit => None,
+                AddedWords::Brackets => Some(("[", "]")),
+                AddedWords::Italics => Some(("*", "*")),
+            },
+            Span::DivineName => {
+                (self.config.divine_name == DivineName::Markers).then_some(("{", "}"))
+            }
+            Span::Quotation => {
+                (self.config.quotation_mode == QuotationMode::Markers).then_some(("<<", ">>"))
+            }
+            _ if self.config.char_styles == CharStyles::Plain => None,
+            Span::Italic => Some(("*", "*")),
+            Span::Bold => Some(("**", "**")),
+            Span::BoldItalic => Some(("***", "***")),
+            Span::Emphasis => Some(("_", "_")),
+            Span::SmallCaps => Some(("{", "}")),
+        }
+    }
+
+    /// Record a chapter title (`<d>`), returning it as a verse 0 row if configured
+    fn finish_title(&mut self, title: String) -> Option<Verse> {
+        match self.config.title_mode {
+            TitleMode::Column => {
+                if let Some(verse) = self.current.as_mut() {
+                    verse.title = Some(title.clone());
+                }
+                self.title = Some(title);
+                None
+            }
+            TitleMode::VerseZero => Some(Verse {
+                book: self.book_id.clone(),
+                chapter: self.chapter_id.clone(),
+                verse: "0".to_string(),
+                text: title,
+                section: self.section.clone(),
+                section_number: self.section_number,
+                ..Verse::default()
+            }),
+        }
+    }
+
+    /// Enter a poetry paragraph, starting a new line in the current verse
+    fn start_poetry_line(&mut self, level: u8) {
+        self.poetry_level = Some(level);
+        if let Some(verse) = self.current.as_mut() {
+            verse.poetry.push(PoetryLine {
+                level,
+                text: String::new(),
+            });
+        }
+    }
+}
+
+impl Iterator for UsfxParser {
+    type Item = Result<Verse, ParserError>;
+
+    /// The next verse, as from [`UsfxParser::next_verse`]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_verse().transpose()
+    }
+}
+
+/// Append trimmed text to `target`, separated from any previous text by a space
+fn push_words(target: &mut String, text: &str) {
+    let text = text.trim();
+    if !target.is_empty() && !text.is_empty() {
+        target.push(' ');
+    }
+    target.push_str(text);
+}
+
+/// Style of a content paragraph: `<p>` with its `sfm` or `style`, `q1`..`q4` (also `<q level>`)
+/// or a list item `li1`..`li4` (`<li>` counts as `li1`)
+fn paragraph_style(e: &BytesStart<'_>) -> Result<Option<String>, ParserError> {
+    Ok(match e.name().as_ref() {
+        b"p" => Some(
+            attribute(e, b"sfm")?
+                .or(attribute(e, b"style")?)
+                .unwrap_or_else(|| "p".to_string()),
+        ),
+        b"q" => {
+            let level = attribute(e, b"level")?.unwrap_or_else(|| "1".to_string());
+            Some(format!("q{level}"))
+        }
+        b"li" => Some("li1".to_string()),
+        name @ (b"q1" | b"q2" | b"q3" | b"q4" | b"li1" | b"li2" | b"li3" | b"li4") => {
+            Some(String::from_utf8_lossy(name).into_owned())
+        }
+        _ => None,
+    })
+}
+
+/// Poetry level of a `q`, `q1`..`q4` element, or of a paragraph styled as one
+fn poetry_level(e: &BytesStart<'_>) -> Result<Option<u8>, ParserError> {
+    let style = match e.name().as_ref() {
+        b"q" => {
+            let level = attribute(e, b"level")?.and_then(|level| level.parse().ok());
+            return Ok(Some(level.unwrap_or(1)));
+        }
+        b"p" => match attribute(e, b"sfm")?.or(attribute(e, b"style")?) {
+            Some(style) => style,
+            None => return Ok(None),
+        },
+        name => String::from_utf8_lossy(name).into_owned(),
+    };
+    Ok(match style.as_str() {
+        "q" => Some(1),
+        style => style.strip_prefix('q').and_then(|level| level.parse().ok()),
+    })
+}
+
+/// Style of an introduction paragraph, given as its element name or a `<p>` style
+fn intro_style(e: &BytesStart<'_>) -> Result<Option<String>, ParserError> {
+    let style = match e.name().as_ref() {
+        b"p" => match attribute(e, b"sfm")?.or(attribute(e, b"style")?) {
+            Some(style) => style,
+            None => return Ok(None),
+        },
+        name => String::from_utf8_lossy(name).into_owned(),
+    };
+    Ok(INTRO_STYLES.contains(&style.as_str()).then_some(style))
+}
+
+/// Level of a section heading: `<s level="2">`, `<s sfm="s2">` or `<s2>`, defaulting to 1
+fn section_level(e: &BytesStart<'_>) -> Result<u8, ParserError> {
+    let level = match e.name().as_ref() {
+        b"s" => match attribute(e, b"level")? {
+            Some(level) => level.parse().ok(),
+            None => attribute(e, b"sfm")?
+                .or(attribute(e, b"style")?)
+                .and_then(|style| style.strip_prefix('s')?.parse().ok()),
+        },
+        name => str::from_utf8(&name[1..])
+            .ok()
+            .and_then(|level| level.parse().ok()),
+    };
+    Ok(level.unwrap_or(1))
+}
+
+/// Read an attribute's value from an element, if present
+fn attribute(e: &BytesStart<'_>, name: &[u8]) -> Result<Option<String>, ParserError> {
+    let attr = e
+        .try_get_attribute(name)
+        .map_err(|e| ParserError::ParseError(e.to_string()))?;
+    attr.map(|attr| {
+        attr.unescape_value()
+            .map(|value| value.into_owned())
+            .map_err(|e| ParserError::ParseError(e.to_string()))
+    })
+    .transpose()
+}
+
+/// Split a verse reference into its first verse and, for a bridge, the number of its last verse
+fn split_bridge(reference: &str) -> (&str, Option<&str>) {
+    match reference.split_once('-') {
+        Some((start, end)) => (start, end.rsplit('.').next()),
+        None => (reference, None),
+    }
+}
+
+/// Code standing in for the unknown book found `index`th: the USFM extra book codes `XXA`
+/// through `XXG`, then `X08`, `X09` and so on
+fn synthetic_book_code(index: usize) -> String {
+    match u8::try_from(index) {
+        Ok(index) if index < 7 => format!("XX{}", char::from(b'A' + index)),
+        _ => format!("X{:02}", index + 1),
+    }
+}
+
+/// Split a verse number such as `1a` into its number and part letter
+fn split_subverse(verse: &str) -> (&str, Option<&str>) {
+    match verse.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) if i > 0 && verse[i..].chars().all(char::is_alphabetic) => {
+            (&verse[..i], Some(&verse[i..]))
+        }
+        _ => (verse, None),
+    }
+}
+
+/// Append an event to a verse's raw markup, reproducing the source bytes
+fn append_raw(raw: &mut String, event: &Event<'_>) {
+    let (open, close, bytes): (&str, &str, &[u8]) = match event {
+        // Verse, chapter and book boundaries are not part of any verse's markup
+        Event::Start(e) | Event::Empty(e) if is_boundary(e.name().as_ref()) => return,
+        Event::End(e) if is_boundary(e.name().as_ref()) => return,
+        Event::Start(e) => ("<", ">", e),
+        Event::End(e) => ("</", ">", e),
+        Event::Empty(e) => ("<", "/>", e),
+        Event::Text(e) => ("", "", e),
+        Event::CData(e) => ("<![CDATA[", "]]>", e),
+        Event::Comment(e) => ("<!--", "-->", e),
+        _ => return,
+    };
+    raw.push_str(open);
+    raw.push_str(&String::from_utf8_lossy(bytes));
+    raw.push_str(close);
+}
+
+/// Whether a character is a control character other than tab and line breaks, or a
+/// noncharacter, none of which belong in the output
+fn is_control(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\t' | '\n' | '\r')) || matches!(c, '\u{fffe}' | '\u{ffff}')
+}
+
+/// Warning about a verse whose reference has been read already
+fn duplicate_warning(verse: &Verse) -> String {
+    format!(
+        "Duplicate verse {} {}:{}{}",
+        verse.book,
+        verse.chapter,
+        verse.verse,
+        verse.subverse.as_deref().unwrap_or_default()
+    )
+}
+
+/// Whether two verses have the same book, chapter, verse number and subverse part
+fn same_reference(a: &Verse, b: &Verse) -> bool {
+    a.book == b.book && a.chapter == b.chapter && a.verse == b.verse && a.subverse == b.subverse
+}
+
+/// Whether an element marks a verse, chapter or book boundary
+fn is_boundary(name: &[u8]) -> bool {
+    matches!(name, b"v" | b"ve" | b"c" | b"book")
+}
+
+/// Unescape the XML entities in a text event, borrowing the text unless it has any
+fn unescape<'a>(e: &'a BytesText<'_>) -> Result<Cow<'a, str>, ParserError> {
+    e.unescape()
+        .map_err(|e| ParserError::ParseError(format!("Failed to unescape text: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BridgeMode, Canon, Granularity, OutputFormat, ReferenceFormat, TokenCounter,
+        UsfxConfigBuilder,
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    const SAMPLE: &str = r#"<usfx><book id="GEN"><c id="1"/><s>The Creation</s>
+<p><v id="1" bcv="GEN.1.1"/>In the beginning <w s="H430">God</w><ve/>
+<v id="2" bcv="GEN.1.2"/>The earth was formless.<ve/></p>
+<c id="2"/><p><v id="1" bcv="GEN.2.1"/>The heavens were finished.<ve/></p></book>
+<book id="EXO"><c id="1"/><p><v id="1" bcv="EXO.1.1"/>Now these are the names.<ve/></p></book></usfx>"#;
+
+    fn parser(xml: &'static str, config: UsfxConfig) -> UsfxParser {
+        UsfxParser::from_reader(xml.as_bytes(), Box::new(std::io::sink()), config)
+    }
+
+    /// Writer whose contents stay readable after the parser takes ownership of it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn convert(xml: &'static str, config: UsfxConfig) -> String {
+        let buffer = SharedBuffer::default();
+        let mut parser = UsfxParser::from_reader(xml.as_bytes(), Box::new(buffer.clone()), config);
+        parser.parse().unwrap();
+        String::from_utf8(buffer.0.take()).unwrap()
+    }
+
+    #[test]
+    fn test_basic_parsing() {
+        let mut parser = parser(SAMPLE, UsfxConfig::default());
+        let mut verses = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            verses.push(verse);
+        }
+
+        assert_eq!(verses.len(), 4);
+        assert_eq!(
+            verses[0],
+            Verse {
+                book: "GEN".to_string(),
+                chapter: "1".to_string(),
+                verse: "1".to_string(),
+                text: "In the beginning God".to_string(),
+                paragraph: Some("p".to_string()),
+                section: Some("The Creation".to_string()),
+                section_number: 1,
+                ..Verse::default()
+            }
+        );
+        assert_eq!(verses[3].book, "EXO");
+        assert_eq!(verses[3].text, "Now these are the names.");
+        assert_eq!(verses[3].section, None);
+    }
+
+    #[test]
+    fn test_raw_markup() {
+        let config = UsfxConfigBuilder::new().column(Column::Raw).build();
+        let mut parser = parser(SAMPLE, config);
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(
+            verse.raw.as_deref(),
+            Some(r#"In the beginning <w s="H430">God</w>"#)
+        );
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.raw.as_deref(), Some("The earth was formless."));
+    }
+
+    #[test]
+    fn test_verse_bridges() {
+        const XML: &str = r#"<usfx><book id="PRO"><p><v id="17-18" bcv="PRO.30.17"/>An eye<ve/>
+<v bcv="PRO.30.19-20"/>The way<ve/><v bcv="PRO.30.21-PRO.30.22"/>For three<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut bridges = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            bridges.push((verse.verse.clone(), verse.verse_end.clone()));
+            assert!(verse.bridged_verses().is_some());
+        }
+        assert_eq!(
+            bridges,
+            [
+                ("17".to_string(), Some("18".to_string())),
+                ("19".to_string(), Some("20".to_string())),
+                ("21".to_string(), Some("22".to_string())),
+            ]
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .bridge_mode(BridgeMode::Expand)
+            .build();
+        assert_eq!(
+            convert(XML, config).lines().take(3).collect::<Vec<_>>(),
+            [
+                "PRO\t30\t17\tAn eye",
+                "PRO\t30\t18\tAn eye",
+                "PRO\t30\t19\tThe way"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_implausible_bridge() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.1-300000000"/>In the beginning<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        assert_eq!(
+            parser.warnings()[0].message,
+            "Bridge GEN 1:1-300000000 is longer than any chapter; only its first 176 verses are counted or expanded"
+        );
+        assert_eq!(
+            parser.verse_counts(),
+            [("GEN".to_string(), BTreeMap::from([(1, 176)]))]
+        );
+        assert_eq!(
+            parser
+                .versification_differences(&Versification::kjv())
+                .iter()
+                .filter(|difference| matches!(difference, Difference::Extra { .. }))
+                .count(),
+            176 - 31
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .bridge_mode(BridgeMode::Expand)
+            .build();
+        let output = convert(XML, config);
+        assert_eq!(output.lines().count(), 176);
+        assert_eq!(output.lines().last(), Some("GEN\t1\t176\tIn the beginning"));
+    }
+
+    #[test]
+    fn test_subverses() {
+        const XML: &str = r#"<usfx><book id="EST"><p><v bcv="EST.1.1a"/>In the days<ve/>
+<v bcv="EST.1.1.b"/>of Ahasuerus<ve/><v bcv="EST.1.2"/>In those days<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut verses = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            verses.push((verse.verse, verse.subverse));
+        }
+        assert_eq!(
+            verses,
+            [
+                ("1".to_string(), Some("a".to_string())),
+                ("1".to_string(), Some("b".to_string())),
+                ("2".to_string(), None),
+            ]
+        );
+
+        // Parts of a verse are not duplicates of each other, but a repeated part is
+        const PARTS: &str = r#"<usfx><book id="EST"><c id="1"/><p><v bcv="EST.1.1a"/>First part.<ve/>
+<v bcv="EST.1.1b"/>Second part.<ve/><v bcv="EST.1.1b"/>Again.<ve/></p></book></usfx>"#;
+        for (duplicates, expected) in [
+            (
+                DuplicateVerses::Keep,
+                "EST\t1\t1a\tFirst part.\nEST\t1\t1b\tSecond part.\nEST\t1\t1b\tAgain.\n",
+            ),
+            (
+                DuplicateVerses::Drop,
+                "EST\t1\t1a\tFirst part.\nEST\t1\t1b\tSecond part.\n",
+            ),
+            (
+                DuplicateVerses::Merge,
+                "EST\t1\t1a\tFirst part.\nEST\t1\t1b\tSecond part. Again.\n",
+            ),
+        ] {
+            let config = UsfxConfigBuilder::new()
+                .duplicate_verses(duplicates)
+                .build();
+            let mut parts = self::parser(PARTS, config.clone());
+            parts.parse().unwrap();
+            let warnings: Vec<&str> = parts
+                .warnings()
+                .iter()
+                .map(|warning| warning.message.as_str())
+                .collect();
+            assert_eq!(warnings, ["Duplicate verse EST 1:1b"], "{duplicates:?}");
+            assert_eq!(convert(PARTS, config), expected, "{duplicates:?}");
+        }
+
+        // Nor when the parts are in separate books read in parallel
+        const BOOKS: &str = r#"<usfx><book id="EST"><c id="1"/><p><v bcv="EST.1.1a"/>First part.<ve/></p></book>
+<book id="EST"><c id="1"/><p><v bcv="EST.1.1b"/>Second part.<ve/></p></book></usfx>"#;
+        let config = UsfxConfigBuilder::new()
+            .duplicate_verses(DuplicateVerses::Drop)
+            .threads(2)
+            .build();
+        let mut parts = self::parser(BOOKS, config.clone());
+        parts.parse().unwrap();
+        assert!(parts.warnings().is_empty());
+        assert_eq!(
+            convert(BOOKS, config),
+            "EST\t1\t1a\tFirst part.\nEST\t1\t1b\tSecond part.\n"
+        );
+    }
+
+    #[test]
+    fn test_speaker_labels() {
+        const XML: &str = r#"<usfx><book id="SNG"><c id="1"/><sp>Beloved</sp>
+<p><v bcv="SNG.1.2"/>Let him kiss me<ve/><v bcv="SNG.1.3"/>Your oils<ve/></p>
+<sp>Lover</sp><p><v bcv="SNG.1.8"/>If you do not know<ve/></p></book>
+<book id="JOB"><p><v bcv="JOB.1.1"/>There was a man<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut speakers = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            assert!(!verse.text.contains("Beloved") && !verse.text.contains("Lover"));
+            speakers.push(verse.speaker);
+        }
+        assert_eq!(
+            speakers,
+            [
+                Some("Beloved".to_string()),
+                Some("Beloved".to_string()),
+                Some("Lover".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        const XML: &str = r#"<usfx><book id="GEN"><p><v bcv="GEN.1.1"/>In the beginning<f caller="+"><fr>1:1 </fr><fk>beginning</fk><ft>Or </ft><fq>when God began</fq></f><ve/>
+<v bcv="GEN.1.2"/>The earth<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "In the beginning");
+        assert_eq!(
+            verse.footnotes,
+            [Footnote {
+                caller: "+".to_string(),
+                reference: "1:1".to_string(),
+                keyword: "beginning".to_string(),
+                quotation: "when God began".to_string(),
+                text: "Or".to_string(),
+                content: "1:1 beginning Or when God began".to_string(),
+            }]
+        );
+        assert!(parser.next_verse().unwrap().unwrap().footnotes.is_empty());
+
+        // A quotation within the text stays where it was in the footnotes column
+        const NESTED: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<f caller="+"><fr>1:1 </fr><ft>Or <fq>when</fq> began</ft></f><ve/></p></book></usfx>"#;
+        let config = UsfxConfigBuilder::new().column(Column::Footnotes).build();
+        assert_eq!(
+            convert(NESTED, config),
+            "GEN\t1\t1\tIn the beginning\t1:1 Or when began\n"
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        const XML: &str = r#"<usfx><book id="GEN"><p><v bcv="GEN.1.1"/>In the beginning<f caller="+"><fr>1:1</fr><ft>Or when</ft></f><ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().format(OutputFormat::Json).build();
+        assert_eq!(
+            convert(XML, config).trim_end(),
+            r#"{"book":"GEN","chapter":"1","verse":"1","text":"In the beginning","paragraph":"p","footnotes":[{"caller":"+","reference":"1:1","text":"Or when"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        const XML: &str = r#"<usfx><book id="MAT"><p><v bcv="MAT.1.1"/>Matthew<ve/></p></book>
+<book id="TOB"><p><v bcv="TOB.1.1"/>Tobit<ve/></p></book>
+<book id="GEN"><p><v bcv="GEN.1.1"/>Genesis one<ve/><v bcv="GEN.1.2"/>Genesis two<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new()
+            .canonical_order(Canon::Protestant)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tGenesis one\nGEN\t1\t2\tGenesis two\nMAT\t1\t1\tMatthew\nTOB\t1\t1\tTobit\n"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .canonical_order(Canon::Catholic)
+            .build();
+        assert!(
+            convert(XML, config).starts_with("GEN\t1\t1\tGenesis one\nGEN\t1\t2\tGenesis two\nTOB")
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .canonical_order(Canon::Protestant)
+            .book_order(vec!["TOB".to_string(), "MAT".to_string()])
+            .build();
+        assert!(convert(XML, config).starts_with("TOB\t1\t1\tTobit\nMAT\t1\t1\tMatthew\nGEN"));
+    }
+
+    #[test]
+    fn test_cross_references() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.1"/>In the beginning<x caller="-"><xo>1:1 </xo><xt>John 1:1-3; Heb 11:3; v. 2</xt></x> God<ve/>
+<v bcv="GEN.1.2"/>The earth<x caller="-"><xo>1:2</xo><xt><ref tgt="JER.4.23">Jer 4:23</ref></xt></x><ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "In the beginning God");
+        let cross_reference = &verse.cross_references[0];
+        assert_eq!(cross_reference.origin, "1:1");
+        assert_eq!(cross_reference.text, "John 1:1-3; Heb 11:3; v. 2");
+        assert_eq!(cross_reference.to_string(), "JHN.1.1-3; HEB.11.3; GEN.1.2");
+
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.cross_references[0].to_string(), "JER.4.23");
+    }
+
+    #[test]
+    fn test_poetry_levels() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="1"/><q level="1"><v bcv="PSA.1.1"/>Blessed is the man</q>
+<q level="2">who doesn't walk in the counsel of the wicked,</q><q2>nor stand on the path of sinners;<ve/></q2>
+<p><v bcv="PSA.1.2"/>but his delight<ve/></p><p sfm="q3"><v bcv="PSA.1.3"/>He will be like a tree<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        let lines: Vec<(u8, &str)> = verse
+            .poetry
+            .iter()
+            .map(|line| (line.level, line.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (1, "Blessed is the man"),
+                (2, "who doesn't walk in the counsel of the wicked,"),
+                (2, "nor stand on the path of sinners;"),
+            ]
+        );
+        assert!(parser.next_verse().unwrap().unwrap().poetry.is_empty());
+        assert_eq!(parser.next_verse().unwrap().unwrap().poetry[0].level, 3);
+    }
+
+    #[test]
+    fn test_psalm_titles() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="3"/><d>A Psalm by David, when he fled from Absalom his son.</d>
+<q><v bcv="PSA.3.1"/>Yahweh, how my adversaries have increased!<ve/></q>
+<q><v bcv="PSA.3.2"/>Many there are<ve/></q><c id="4"/><q><v bcv="PSA.4.1"/><d>For the Chief Musician</d>Answer me<ve/></q></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut titles = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            assert!(!verse.text.contains("Psalm") && !verse.text.contains("Musician"));
+            titles.push(verse.title);
+        }
+        let david = Some("A Psalm by David, when he fled from Absalom his son.".to_string());
+        assert_eq!(
+            titles,
+            [
+                david.clone(),
+                david,
+                Some("For the Chief Musician".to_string())
+            ]
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .title_mode(TitleMode::VerseZero)
+            .build();
+        let rows = convert(XML, config);
+        let rows: Vec<&str> = rows.lines().collect();
+        assert_eq!(
+            rows,
+            [
+                "PSA\t3\t0\tA Psalm by David, when he fled from Absalom his son.",
+                "PSA\t3\t1\tYahweh, how my adversaries have increased!",
+                "PSA\t3\t2\tMany there are",
+                "PSA\t4\t0\tFor the Chief Musician",
+                "PSA\t4\t1\tAnswer me",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selah() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="3"/><q><v bcv="PSA.3.2"/>There is no help for him in God.</q>
+<qs>Selah.</qs><ve/><v bcv="PSA.3.3"/>But you, Yahweh<ve/></book></usfx>"#;
+
+        let verses = |mode| {
+            let config = UsfxConfigBuilder::new().selah_mode(mode).build();
+            let mut parser = parser(XML, config);
+            let verse = parser.next_verse().unwrap().unwrap();
+            assert!(parser.next_verse().unwrap().unwrap().selah.is_none());
+            (verse.text, verse.selah)
+        };
+        assert_eq!(
+            verses(SelahMode::Inline),
+            ("There is no help for him in God. Selah.".to_string(), None)
+        );
+        assert_eq!(
+            verses(SelahMode::Bracketed),
+            (
+                "There is no help for him in God. [Selah.]".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            verses(SelahMode::Column),
+            (
+                "There is no help for him in God.".to_string(),
+                Some("Selah.".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn test_added_words() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.2"/>And the earth was without form, and void; and darkness <add>was</add> upon the face of the deep.<ve/></p></book></usfx>"#;
+
+        let text = |mode| {
+            let config = UsfxConfigBuilder::new().added_words(mode).build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert!(text(AddedWords::Plain).ends_with("darkness was upon the face of the deep."));
+        assert!(text(AddedWords::Omit).ends_with("darkness upon the face of the deep."));
+        assert!(text(AddedWords::Brackets).ends_with("darkness [was] upon the face of the deep."));
+        assert!(text(AddedWords::Italics).ends_with("darkness *was* upon the face of the deep."));
+    }
+
+    #[test]
+    fn test_divine_name() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="23"/><q><v bcv="PSA.23.1"/>The <nd>Lord</nd>'s my shepherd<ve/></q></book></usfx>"#;
+
+        let text = |mode| {
+            let config = UsfxConfigBuilder::new().divine_name(mode).build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert_eq!(text(DivineName::Plain), "The Lord's my shepherd");
+        assert_eq!(text(DivineName::Uppercase), "The LORD's my shepherd");
+        assert_eq!(text(DivineName::Markers), "The {Lord}'s my shepherd");
+    }
+
+    #[test]
+    fn test_char_styles() {
+        const XML: &str = r#"<usfx><book id="MRK"><c id="1"/><p><v bcv="MRK.1.1"/>The <bd>beginning</bd> of the <it>Good News</it> of <sc>Jesus</sc>, <em>the</em> Christ<ve/></p></book></usfx>"#;
+
+        let text = |mode| {
+            let config = UsfxConfigBuilder::new().char_styles(mode).build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert_eq!(
+            text(CharStyles::Plain),
+            "The beginning of the Good News of Jesus, the Christ"
+        );
+        assert_eq!(
+            text(CharStyles::Markers),
+            "The **beginning** of the *Good News* of {Jesus}, _the_ Christ"
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        const XML: &str = r#"<usfx><book id="EZR"><c id="2"/><table>
+<tr><tc><v bcv="EZR.2.3"/>The children of Parosh,</tc><tcr>two thousand one hundred seventy-two.<ve/></tcr></tr>
+<tr><tc><v bcv="EZR.2.4"/>The children of Shephatiah,</tc><tcr>three hundred seventy-two.</tcr></tr>
+<tr><tc>The children of Arah,</tc><tcr>seven hundred seventy-five.<ve/></tcr></tr></table></book></usfx>"#;
+
+        let mut rows = parser(XML, UsfxConfig::default());
+        assert_eq!(
+            rows.next_verse().unwrap().unwrap().text,
+            "The children of Parosh, | two thousand one hundred seventy-two."
+        );
+        assert_eq!(
+            rows.next_verse().unwrap().unwrap().text,
+            "The children of Shephatiah, | three hundred seventy-two. The children of Arah, | seven hundred seventy-five."
+        );
+
+        let config = UsfxConfigBuilder::new().table_separator(": ").build();
+        assert_eq!(
+            parser(XML, config).next_verse().unwrap().unwrap().text,
+            "The children of Parosh,: two thousand one hundred seventy-two."
+        );
+    }
+
+    #[test]
+    fn test_figures() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="6"/><p><v bcv="GEN.6.14"/>Make a ship of gopher wood.<ve/></p>
+<fig><description>Noah's ark</description><catalog>ark.jpg</catalog><size>col</size><caption>The Ark</caption><reference>6.14</reference></fig>
+<p><v bcv="GEN.6.15"/>This is how you shall make it.<fig src="plan.png"><caption>Plan of the ark</caption></fig> The length<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert!(verse.figures.is_empty());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "This is how you shall make it. The length");
+        assert_eq!(
+            verse.figures,
+            [
+                Figure {
+                    caption: "The Ark".to_string(),
+                    file: "ark.jpg".to_string(),
+                    reference: "6.14".to_string(),
+                },
+                Figure {
+                    caption: "Plan of the ark".to_string(),
+                    file: "plan.png".to_string(),
+                    reference: String::new(),
+                },
+            ]
+        );
+        assert_eq!(verse.figures[0].to_string(), "The Ark (ark.jpg)");
+    }
+
+    #[test]
+    fn test_optbreak() {
+        const XML: &str = r#"<usfx><book id="JOB"><c id="1"/><p><v bcv="JOB.1.1"/>Uz/<optbreak/>Edom<ve/></p></book></usfx>"#;
+
+        let text = |mode| {
+            let config = UsfxConfigBuilder::new().opt_break(mode).build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert_eq!(text(OptBreak::Nothing), "Uz/Edom");
+        assert_eq!(text(OptBreak::Space), "Uz/ Edom");
+        assert_eq!(text(OptBreak::SoftHyphen), "Uz/\u{ad}Edom");
+    }
+
+    #[test]
+    fn test_unicode_spaces() {
+        const XML: &str = "<usfx><book id=\"JHN\"><c id=\"11\"/><p><v bcv=\"JHN.11.35\"/>J\u{e9}sus pleura\u{a0}<w>!</w><ve/></p></book></usfx>";
+
+        let text = |preserve| {
+            let config = UsfxConfigBuilder::new()
+                .preserve_unicode_spaces(preserve)
+                .build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert_eq!(text(false), "J\u{e9}sus pleura !");
+        assert_eq!(text(true), "J\u{e9}sus pleura\u{a0}!");
+    }
+
+    #[test]
+    fn test_verses_without_bcv() {
+        const XML: &str = r#"<usfx><book id="RUT"><c id="1"/><p><v id="1"/>In the days<ve/><v id="2-3"/>The name<ve/></p>
+<c id="2"/><p><v id="1"/>Naomi had a relative<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut references = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            references.push(format!("{}.{}.{}", verse.book, verse.chapter, verse.verse));
+            if verse.verse == "2" {
+                assert_eq!(verse.verse_end.as_deref(), Some("3"));
+            }
+        }
+        assert_eq!(references, ["RUT.1.1", "RUT.1.2", "RUT.2.1"]);
+    }
+
+    #[test]
+    fn test_implicit_verse_ends() {
+        const XML: &str = r#"<usfx><book id="OBA"><c id="1"/><p><v bcv="OBA.1.1"/>The vision of Obadiah.
+<v bcv="OBA.1.2"/>Behold, I have made you small</p></book>
+<book id="JON"><c id="1"/><p><v bcv="JON.1.1"/>Now the word<c id="2"/><v bcv="JON.2.1"/>Jonah prayed</p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().column(Column::Raw).build();
+        let mut parser = parser(XML, config);
+        let mut verses = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            verses.push((verse.verse, verse.text, verse.raw.unwrap()));
+        }
+        let expected = [
+            ("1", "The vision of Obadiah.", "The vision of Obadiah.\n"),
+            (
+                "2",
+                "Behold, I have made you small",
+                "Behold, I have made you small</p>",
+            ),
+            ("1", "Now the word", "Now the word"),
+            ("1", "Jonah prayed", "Jonah prayed</p>"),
+        ];
+        assert_eq!(verses.len(), expected.len());
+        for ((verse, text, raw), (number, expected_text, expected_raw)) in
+            verses.iter().zip(expected)
+        {
+            assert_eq!(
+                (verse.as_str(), text.as_str(), raw.as_str()),
+                (number, expected_text, expected_raw)
+            );
+        }
+    }
+
+    #[test]
+    fn test_introductions() {
+        const XML: &str = r#"<usfx><book id="MRK"><h>Mark</h><imt>The Gospel According to Mark</imt>
+<ip>Mark tells the <it>good news</it><f caller="+"><ft>A note</ft></f> of Jesus.</ip><p sfm="io1">Preparation 1:1-13</p>
+<c id="1"/><p><v bcv="MRK.1.1"/>The beginning<ve/></p></book></usfx>"#;
+
+        let mut skipping = parser(XML, UsfxConfig::default());
+        let verse = skipping.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "The beginning");
+        assert!(skipping.intro().is_empty());
+
+        let config = UsfxConfigBuilder::new()
+            .intro_mode(IntroMode::Export)
+            .build();
+        let mut exporting = parser(XML, config);
+        assert_eq!(
+            exporting.next_verse().unwrap().unwrap().text,
+            "The beginning"
+        );
+        let mut intro = Vec::new();
+        exporting.write_intro(&mut intro).unwrap();
+        assert_eq!(
+            String::from_utf8(intro).unwrap(),
+            "MRK\timt\tThe Gospel According to Mark\n\
+             MRK\tip\tMark tells the good news of Jesus.\n\
+             MRK\tio1\tPreparation 1:1-13\n"
+        );
+    }
+
+    #[test]
+    fn test_chapter_labels() {
+        const XML: &str = r#"<usfx><book id="PSA"><cl>Psalm</cl><c id="9"/><ca>9</ca><q><v bcv="PSA.9.1"/>I will give thanks<ve/></q>
+<c id="10"/><cl>Psalm 10 (9b)</cl><ca>9</ca><q><v bcv="PSA.10.1"/>Why do you stand far off<ve/></q>
+<c id="11"/><q><v bcv="PSA.11.1"/>In Yahweh<ve/></q></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut labels = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            assert!(!verse.text.contains("Psalm") && !verse.text.contains('9'));
+            labels.push((verse.chapter_label.unwrap(), verse.alt_chapter));
+        }
+        assert_eq!(
+            labels,
+            [
+                ("Psalm 9".to_string(), Some("9".to_string())),
+                ("Psalm 10 (9b)".to_string(), Some("9".to_string())),
+                ("Psalm 11".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alternate_verse_numbers() {
+        const XML: &str = r#"<usfx><book id="MAL"><c id="4"/><p><v bcv="MAL.4.1"/><va>3:19</va>For behold, the day comes<ve/>
+<v bcv="MAL.4.2"/><vp>2a</vp>But to you<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new()
+            .column(Column::AltVerse)
+            .column(Column::PublishedVerse)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "MAL\t4\t1\tFor behold, the day comes\t3:19\t\nMAL\t4\t2\tBut to you\t\t2a\n"
+        );
+    }
+
+    #[test]
+    fn test_word_attributes() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.1"/>In the beginning <w s="H430" l="elohim">God</w> created the <wl gloss="sky">heavens</wl><ve/></p></book></usfx>"#;
+
+        assert!(
+            parser(XML, UsfxConfig::default())
+                .next_verse()
+                .unwrap()
+                .unwrap()
+                .words
+                .is_empty()
+        );
+
+        let config = UsfxConfigBuilder::new().column(Column::Words).build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tIn the beginning God created the heavens\tGod|l=elohim|s=H430; heavens|gloss=sky\n"
+        );
+    }
+
+    #[test]
+    fn test_footnote_markers() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.1"/>In the beginning<f caller="+"><ft>Or when</ft></f> God created the heavens<f caller="a"><ft>Or sky</ft></f>.<ve/></p></book></usfx>"#;
+
+        let text = |marker| {
+            let config = UsfxConfigBuilder::new().footnote_marker(marker).build();
+            parser(XML, config).next_verse().unwrap().unwrap().text
+        };
+        assert_eq!(
+            text("[{n}]"),
+            "In the beginning[1] God created the heavens[2]."
+        );
+        assert_eq!(
+            text("\u{2020}{caller}"),
+            "In the beginning\u{2020}+ God created the heavens\u{2020}a."
+        );
+    }
+
+    #[test]
+    fn test_nested_notes() {
+        const XML: &str = r#"<usfx><book id="MAT"><c id="1"/><p><v bcv="MAT.1.1"/>The book of the <it>genealogy<f caller="+"><ft>Or <w s="G1078">origin</w></ft><f caller="-"><ft>inner</ft></f><ft> note</ft></f></it> of Jesus<ve/>
+<v bcv="MAT.1.2"/>Abraham <s>Heading</s>became the father<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "The book of the genealogy of Jesus");
+        assert_eq!(verse.footnotes.len(), 1);
+        assert_eq!(verse.footnotes[0].text, "Or origin inner note");
+
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "Abraham became the father");
+    }
+
+    #[test]
+    fn test_major_sections() {
+        const XML: &str = r#"<usfx><book id="PSA"><ms>BOOK ONE</ms><mr>(Psalms 1-41)</mr><c id="1"/><s>The two ways</s>
+<q><v bcv="PSA.1.1"/>Blessed is the man<ve/></q><ms>BOOK TWO</ms><c id="42"/><q><v bcv="PSA.42.1"/>As the deer pants<ve/></q></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.text, "Blessed is the man");
+        assert_eq!(
+            verse.major_section.as_deref(),
+            Some("BOOK ONE (Psalms 1-41)")
+        );
+        assert_eq!(verse.section.as_deref(), Some("The two ways"));
+        let verse = parser.next_verse().unwrap().unwrap();
+        assert_eq!(verse.major_section.as_deref(), Some("BOOK TWO"));
+    }
+
+    #[test]
+    fn test_section_breadcrumbs() {
+        const XML: &str = r#"<usfx><book id="MAT"><c id="5"/><s>Sermon on the Mount</s><s level="2">The Beatitudes</s>
+<p><v bcv="MAT.5.3"/>Blessed are the poor<ve/></p><s2>Salt and Light</s2><p><v bcv="MAT.5.13"/>You are the salt<ve/></p>
+<s>Another sermon</s><p><v bcv="MAT.5.17"/>Don't think<ve/></p></book></usfx>"#;
+
+        let sections = |breadcrumb| {
+            let config = UsfxConfigBuilder::new()
+                .section_breadcrumb(breadcrumb)
+                .build();
+            let mut parser = parser(XML, config);
+            let mut sections = Vec::new();
+            while let Some(verse) = parser.next_verse().unwrap() {
+                sections.push(verse.section.unwrap());
+            }
+            sections
+        };
+        assert_eq!(
+            sections(false),
+            ["The Beatitudes", "Salt and Light", "Another sermon"]
+        );
+        assert_eq!(
+            sections(true),
+            [
+                "Sermon on the Mount > The Beatitudes",
+                "Sermon on the Mount > Salt and Light",
+                "Another sermon"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_section_headings() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="3"/><s>A Psalm of David</s><q><v bcv="PSA.3.1"/>Lord, how many<ve/></q>
+<c id="4"/><s>A Psalm of David</s><q><v bcv="PSA.4.1"/>Answer me<ve/></q><q><v bcv="PSA.4.2"/>How long<ve/></q></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new()
+            .granularity(Granularity::Section)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "PSA\t3\t1\tA Psalm of David\tLord, how many\n\
+             PSA\t4\t1\tA Psalm of David\tAnswer me How long\n"
+        );
+    }
+
+    #[test]
+    fn test_parallel_passages() {
+        const XML: &str = r#"<usfx><book id="MAT"><c id="5"/><s>The Beatitudes</s><r>(Luke 6:20-23)</r>
+<p><v bcv="MAT.5.3"/>Blessed are the poor<ve/></p><s>Salt and Light</s><p><v bcv="MAT.5.13"/>You are the salt<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().column(Column::Parallel).build();
+        assert_eq!(
+            convert(XML, config),
+            "MAT\t5\t3\tBlessed are the poor\tLUK.6.20-23\nMAT\t5\t13\tYou are the salt\t\n"
+        );
+    }
+
+    #[test]
+    fn test_acrostics() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="119"/><qa>ALEPH</qa><q><v bcv="PSA.119.1"/>Blessed are those<ve/></q>
+<qa>BET</qa><q><v bcv="PSA.119.9"/>How can a young man<ve/></q><c id="120"/><q><v bcv="PSA.120.1"/>In my distress<ve/></q></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut acrostics = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            assert!(!verse.text.contains("ALEPH") && !verse.text.contains("BET"));
+            acrostics.push(verse.acrostic);
+        }
+        assert_eq!(
+            acrostics,
+            [Some("ALEPH".to_string()), Some("BET".to_string()), None]
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .acrostic_mode(AcrosticMode::Rows)
+            .build();
+        assert!(
+            convert(XML, config).starts_with(
+                "PSA\t119\t1\tALEPH\nPSA\t119\t1\tBlessed are those\nPSA\t119\t9\tBET\n"
+            )
+        );
+
+        // Without `<ve/>`, the verse before a heading still comes before the heading's row,
+        // which is not taken for a duplicate of the verse under it
+        const UNENDED: &str = r#"<usfx><book id="PSA"><c id="119"/><qa>ALEPH</qa><q><v bcv="PSA.119.1"/>Blessed are those</q>
+<q><v bcv="PSA.119.8"/>I will keep your statutes</q><qa>BET</qa><q><v bcv="PSA.119.9"/>How can a young man</q></book></usfx>"#;
+        let config = UsfxConfigBuilder::new()
+            .acrostic_mode(AcrosticMode::Rows)
+            .duplicate_verses(DuplicateVerses::Drop)
+            .reference_format(ReferenceFormat::Numeric)
+            .column(Column::Acrostic)
+            .build();
+        assert_eq!(
+            convert(UNENDED, config),
+            "019119001\tALEPH\tALEPH\n\
+             019119001\tBlessed are those\t\n\
+             019119008\tI will keep your statutes\t\n\
+             019119009\tBET\tBET\n\
+             019119009\tHow can a young man\t\n"
+        );
+    }
+
+    #[test]
+    fn test_stanza_breaks() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="1"/><q><v bcv="PSA.1.5"/>Therefore the wicked shall not stand<ve/></q><b/>
+<q><v bcv="PSA.1.6"/>For Yahweh knows the way<b/>of the righteous<ve/></q></book></usfx>"#;
+
+        let dropped = convert(XML, UsfxConfigBuilder::new().build());
+        assert!(dropped.ends_with("\tFor Yahweh knows the way of the righteous\n"));
+
+        let config = UsfxConfigBuilder::new()
+            .stanza_break("/")
+            .granularity(Granularity::Chapter)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "PSA\t1\tTherefore the wicked shall not stand / For Yahweh knows the way / of the righteous\n"
+        );
+    }
+
+    #[test]
+    fn test_list_paragraphs() {
+        const XML: &str = r#"<usfx><book id="1CH"><c id="3"/><p><v bcv="1CH.3.1"/>Now these were the sons of David<ve/></p>
+<li><v bcv="1CH.3.2"/>the third, Absalom<ve/></li><li2><v bcv="1CH.3.3"/>the fifth, Shephatiah<ve/></li2>
+<p sfm="li1"><v bcv="1CH.3.4"/>six were born to him<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().column(Column::Paragraph).build();
+        assert_eq!(
+            convert(XML, config),
+            "1CH\t3\t1\tNow these were the sons of David\tp\n\
+             1CH\t3\t2\tthe third, Absalom\tli1\n\
+             1CH\t3\t3\tthe fifth, Shephatiah\tli2\n\
+             1CH\t3\t4\tsix were born to him\tli1\n"
+        );
+    }
+
+    #[test]
+    fn test_quoted_text() {
+        const XML: &str = r#"<usfx><book id="MAT"><c id="4"/><p><v bcv="MAT.4.4"/>But he answered, "It is written, <qt>'Man shall not live by bread alone,</qt><ve/>
+<v bcv="MAT.4.5"/><qt>but by every word.'</qt>"<ve/><v bcv="MAT.4.6"/>Then the devil<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new()
+            .quotation_mode(QuotationMode::Markers)
+            .column(Column::Quotations)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "MAT\t4\t4\tBut he answered, \"It is written, <<'Man shall not live by bread alone,>>\t'Man shall not live by bread alone,\n\
+             MAT\t4\t5\t<<but by every word.'>>\"\tbut by every word.'\n\
+             MAT\t4\t6\tThen the devil\t\n"
+        );
+    }
+
+    #[test]
+    fn test_join_rules() {
+        const XML: &str = r#"<usfx><book id="ROM"><c id="1"/><p><v bcv="ROM.1.1"/><w s="G3972">Paul</w><w s="G1401">a servant</w> of <w s="G2424">Jesus</w> ,<w s="G2822">called</w><ve/></p></book></usfx>"#;
+
+        assert_eq!(
+            convert(XML, UsfxConfig::default()),
+            "ROM\t1\t1\tPaula servant of Jesus ,called\n"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .join_rules(
+                "word-word=tag-boundary,word-punctuation=never,punctuation-word=always"
+                    .parse()
+                    .unwrap(),
+            )
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "ROM\t1\t1\tPaul a servant of Jesus, called\n"
+        );
+    }
+
+    #[test]
+    fn test_utf16_input() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-16"?><usfx><book id="JHN"><c id="11"/><p><v bcv="JHN.11.35"/>Jésus pleura.<ve/></p></book></usfx>"#;
+        let bytes: Vec<u8> = [0xfeff]
+            .into_iter()
+            .chain(xml.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut parser = UsfxParser::from_reader(
+            std::io::Cursor::new(bytes),
+            Box::new(std::io::sink()),
+            UsfxConfig::default(),
+        );
+        assert_eq!(parser.next_verse().unwrap().unwrap().text, "Jésus pleura.");
+    }
+
+    #[test]
+    fn test_recover_from_malformed_xml() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v bcv="GEN.1.1"/>In the <w>beginning</q> God<ve/>
+<v bcv="GEN.1.2"/>The earth &amp; sky<ve/><v bcv="GEN.1.3"/>Light & dark<ve/><v bcv="GEN.1.4"/>It was good.<ve/></p></book></usfx>"#;
+
+        assert!(parser(XML, UsfxConfig::default()).parse().is_err());
+
+        let mut parser = parser(XML, UsfxConfigBuilder::new().recover(true).build());
+        let mut verses = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            verses.push((verse.verse, verse.text));
+        }
+        assert_eq!(
+            verses,
+            [
+                ("1".to_string(), "In the beginning".to_string()),
+                ("2".to_string(), "The earth & sky".to_string()),
+                ("3".to_string(), String::new()),
+                ("4".to_string(), "It was good.".to_string()),
+            ]
+        );
+        assert_eq!(parser.warnings().len(), 2);
+    }
+
+    #[test]
+    fn test_missing_verses() {
+        const XML: &str = r#"<usfx><book id="3JN"><c id="1"/><p><v id="1"/>The elder<ve/><v id="2-13"/>Beloved<f caller="+"><fr>1:13 </fr><ft>Verse 14 is part of verse 13 in some texts.</ft></f><ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        let missing = parser.missing_verses(&Versification::kjv());
+        assert_eq!(missing.len(), 1);
+        assert_eq!(
+            missing[0].to_string(),
+            "3JN 1:14 absent (footnoted omission)"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_verses() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<ve/><v id="1"/>God created<ve/><v id="2"/>The earth<ve/><v id="1"/>Again<ve/></p></book></usfx>"#;
+
+        let mut keep = parser(XML, UsfxConfig::default());
+        keep.parse().unwrap();
+        assert_eq!(keep.warnings().len(), 2);
+        assert_eq!(keep.warnings()[0].message, "Duplicate verse GEN 1:1");
+        assert_eq!(
+            convert(XML, UsfxConfig::default()),
+            "GEN\t1\t1\tIn the beginning\nGEN\t1\t1\tGod created\nGEN\t1\t2\tThe earth\nGEN\t1\t1\tAgain\n"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .duplicate_verses(DuplicateVerses::Drop)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tIn the beginning\nGEN\t1\t2\tThe earth\n"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .duplicate_verses(DuplicateVerses::Merge)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tIn the beginning God created\nGEN\t1\t2\tThe earth\nGEN\t1\t1\tAgain\n"
+        );
+    }
+
+    #[test]
+    fn test_control_characters() {
+        const XML: &str = "<usfx><book id=\"GEN\"><c id=\"1\"/><p><v id=\"1\"/>In the\u{1b} begin&#x7;ning<ve/></p></book></usfx>";
+
+        let mut strip = parser(XML, UsfxConfig::default());
+        assert_eq!(
+            strip.next_verse().unwrap().unwrap().text,
+            "In the beginning"
+        );
+        assert_eq!(
+            strip.warnings()[0].message,
+            "Removed control characters U+0007, U+001B in GEN 1:1"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .control_chars(ControlChars::Escape)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tIn the\\u{1b} begin\\u{7}ning\n"
+        );
+    }
+
+    #[test]
+    fn test_tabs() {
+        const XML: &str = "<usfx><book id=\"GEN\"><h>Gen\tesis</h><c id=\"1\"/><s>Crea\ttion</s><p><v id=\"1\"/>In the\tbeginning<ve/></p></book></usfx>";
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        let warnings: Vec<&str> = parser
+            .warnings()
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "Replaced tabs with spaces in GEN",
+                "Replaced tabs with spaces in GEN 1",
+                "Replaced tabs with spaces in GEN 1:1"
+            ]
+        );
+        let config = UsfxConfigBuilder::new()
+            .granularity(Granularity::Section)
+            .control_chars(ControlChars::Escape)
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "GEN\t1\t1\tCrea tion\tIn the beginning\n"
+        );
+
+        // Names from elsewhere, such as a book names file, cannot split fields either
+        let mut verse = Verse {
+            book: "GEN".to_string(),
+            chapter: "1".to_string(),
+            verse: "1".to_string(),
+            text: "In the beginning".to_string(),
+            ..Verse::default()
+        };
+        verse.chapter.push('\t');
+        let mut output = Vec::new();
+        let mut writer = RowWriter::new(&mut output, &UsfxConfig::default());
+        writer.write_verse(&verse).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(output, b"GEN\t1 \t1\tIn the beginning\n");
+    }
+
+    #[test]
+    fn test_bidi_marks() {
+        const XML: &str = r#"<usfx><book id="PSA"><c id="23"/><s>מזמור (ראה 1:2)</s><p><v id="1"/>יהוה רעי<f caller="+"><fr>23:1 </fr><ft>ראה 80:2</ft></f><ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().bidi(crate::Bidi::Mark).build();
+        let verse = parser(XML, config).next_verse().unwrap().unwrap();
+        assert_eq!(
+            verse.section.as_deref(),
+            Some("מזמור (ראה \u{200e}1:2\u{200e})")
+        );
+        assert_eq!(verse.footnotes[0].text, "ראה \u{200e}80:2\u{200e}");
+        assert_eq!(verse.text, "יהוה רעי");
+    }
+
+    #[test]
+    fn test_book_code_normalization() {
+        const XML: &str = r#"<usfx><book id="Gen"><c id="1"/><p><v id="1"/>In the beginning<ve/></p></book>
+<book id="1Sam"><c id="1"/><p><v bcv="1Sam.1.1"/>There was a man<ve/></p></book>
+<book id="XYZ"><c id="1"/><p><v id="1"/>Appendix<ve/><v bcv="XYZ.1.2"/>More<ve/></p></book>
+<book id="Notes.Extra"><c id="1"/><p><v id="1"/>Notes<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut books = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            books.push(verse.book);
+        }
+        assert_eq!(books, ["GEN", "1SA", "XXA", "XXA", "XXB"]);
+        assert_eq!(parser.warnings().len(), 2);
+        assert_eq!(
+            parser.warnings()[0].message,
+            "Unknown book code XYZ (written as XXA)"
+        );
+        assert_eq!(synthetic_book_code(7), "X08");
+
+        let config = UsfxConfigBuilder::new().normalize_book_codes(false).build();
+        assert!(convert(XML, config).starts_with("Gen\t1\t1\t"));
+    }
+
+    #[test]
+    fn test_metadata() {
+        const XML: &str = r#"<usfx xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="usfx.xsd"><languageCode>eng</languageCode><rem>Converted from USFM</rem>
+<book id="GEN"><id id="GEN">World English Bible © 2020 eBible.org</id><rem>Draft</rem><c id="1"/><p><v id="1"/>In the beginning<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        let metadata = parser.metadata();
+        assert_eq!(metadata.language_code.as_deref(), Some("eng"));
+        assert_eq!(
+            metadata.attributes["xsi:noNamespaceSchemaLocation"],
+            "usfx.xsd"
+        );
+        assert_eq!(metadata.ids["GEN"], "World English Bible © 2020 eBible.org");
+        assert_eq!(metadata.remarks.len(), 2);
+        assert_eq!(metadata.remarks[0].book, None);
+        assert_eq!(metadata.remarks[1].book.as_deref(), Some("GEN"));
+        assert_eq!(
+            metadata.copyright,
+            ["World English Bible © 2020 eBible.org"]
+        );
+        assert_eq!(
+            convert(XML, UsfxConfig::default()),
+            "GEN\t1\t1\tIn the beginning\n"
+        );
+    }
+
+    #[test]
+    fn test_book_titles() {
+        const XML: &str = r#"<usfx><book id="GEN"><h>Genesis</h><toc level="1">The First Book of Moses, Commonly Called Genesis</toc><toc level="2">Genesis</toc><toc level="3">Gen</toc><c id="1"/><p><v id="1"/>In the beginning<ve/></p></book>
+<book id="EXO"><h>Exodus</h><c id="1"/><p><v id="1"/>Now these are the names<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let summary = parser.parse().unwrap();
+        assert_eq!(summary.books.len(), 2);
+        assert_eq!(summary.books[0].code, "GEN");
+        assert_eq!(summary.books[0].abbreviation.as_deref(), Some("Gen"));
+        assert_eq!(summary.books[1].short_name, None);
+
+        let mut books = Vec::new();
+        parser.write_books(&mut books).unwrap();
+        assert_eq!(
+            String::from_utf8(books).unwrap(),
+            "GEN\tGenesis\tThe First Book of Moses, Commonly Called Genesis\tGen\nEXO\tExodus\t\t\n"
+        );
+        assert!(convert(XML, UsfxConfig::default()).starts_with("GEN\t1\t1\tIn the beginning\n"));
+    }
+
+    #[test]
+    fn test_license_paragraphs() {
+        const XML: &str = r#"<usfx><book id="FRT"><id id="FRT">Front matter</id><p sfm="mt">The Holy Bible</p><p>Copyright © 2020 <bd>Example</bd> Society.</p><p sfm="ip">This translation is licensed under a Creative Commons Attribution-ShareAlike 4.0 license.</p><p>Preface</p></book>
+<book id="GEN"><c id="1"/><p><v id="1"/>All rights reserved to no one<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        assert_eq!(
+            parser.metadata().license,
+            [
+                "Copyright © 2020 Example Society.",
+                "This translation is licensed under a Creative Commons Attribution-ShareAlike 4.0 license."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        const XML: &str = r#"<usfx><book id="JUD"><c id="1"/><p><v id="1"/>Jude<ve/><v id="2"/>Mercy<ve/></p></book>
+<book id="3JN"><c id="1"/><p><v id="1"/>The elder<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        parser.parse().unwrap();
+        let mut tsv = Vec::new();
+        parser.write_manifest(&mut tsv, OutputFormat::Tsv).unwrap();
+        assert_eq!(String::from_utf8(tsv).unwrap(), "JUD\t1\t2\n3JN\t1\t1\n");
+        let mut json = Vec::new();
+        parser
+            .write_manifest(&mut json, OutputFormat::Json)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[1]["book"], "3JN");
+        assert_eq!(json[0]["chapters"]["1"], 2);
+    }
+
+    #[test]
+    fn test_single_chapter_books() {
+        const XML: &str = r#"<usfx><book id="OBA"><p><v id="1"/>The vision of Obadiah<ve/><v id="2"/>Behold<ve/></p></book>
+<book id="JUD"><p><v bcv="JUD.3"/>Beloved<ve/><v bcv="JUD.4-JUD.5"/>For there are<ve/></p></book>
+<book id="RUT"><p><v id="1"/>Dropped without a chapter<ve/><v bcv="RUT.2"/>Dropped too<ve/></p></book></usfx>"#;
+
+        let mut parser = parser(XML, UsfxConfig::default());
+        let mut references = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            references.push(format!("{}.{}.{}", verse.book, verse.chapter, verse.verse));
+            if verse.verse == "4" {
+                assert_eq!(verse.verse_end.as_deref(), Some("5"));
+            }
+        }
+        assert_eq!(references, ["OBA.1.1", "OBA.1.2", "JUD.1.3", "JUD.1.4"]);
+    }
+
+    #[test]
+    fn test_intro_rows() {
+        const XML: &str = r#"<usfx><book id="MRK"><imt>The Gospel According to Mark</imt>
+<ip>Mark tells the good news<f caller="+"><ft>A note</ft></f> of Jesus.</ip>
+<c id="1"/><ip>John prepares the way.</ip><p><v bcv="MRK.1.1"/>The beginning<ve/></p>
+<c id="2"/><p><v bcv="MRK.2.1"/>After some days<ve/></p></book>
+<book id="BAK"><p>Glossary</p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new()
+            .intro_mode(IntroMode::VerseZero)
+            .build();
+        let mut parser = parser(XML, config);
+        let mut rows = Vec::new();
+        while let Some(verse) = parser.next_verse().unwrap() {
+            rows.push(format!(
+                "{} {}:{} {}",
+                verse.book, verse.chapter, verse.verse, verse.text
+            ));
+        }
+        assert_eq!(
+            rows,
+            [
+                "MRK 0:0 The Gospel According to Mark Mark tells the good news of Jesus.",
+                "MRK 1:0 John prepares the way.",
+                "MRK 1:1 The beginning",
+                "MRK 2:1 After some days",
+                "BAK 0:0 Glossary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_counts() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        let config = UsfxConfigBuilder::new().column(Column::Tokens).build();
+        assert_eq!(
+            convert(XML, config),
+            "JHN\t11\t35\tJesus wept.\t4\nJHN\t11\t36\tThen said the Jews, Behold how he loved him!\t13\n"
+        );
+
+        let config = UsfxConfigBuilder::new()
+            .granularity(Granularity::Chapter)
+            .column(Column::Tokens)
+            .token_counter(TokenCounter::Custom(Arc::new(|text| text.len())))
+            .build();
+        assert_eq!(
+            convert(XML, config),
+            "JHN\t11\tJesus wept. Then said the Jews, Behold how he loved him!\t56\n"
+        );
+        assert!("bpe".parse::<TokenCounter>().is_err());
+    }
+
+    #[test]
+    fn test_max_verse_size() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<ve/><v id="2"/>And the earth <w>was</w> without form, <w>and</w> void<ve/><v id="3"/>And God said<ve/></p></book></usfx>"#;
+
+        let limit = |bytes| UsfxConfigBuilder::new().max_verse_size(bytes);
+        assert!(parser(XML, limit(40).build()).parse().is_ok());
+        let error = parser(XML, limit(20).build()).parse();
+        assert!(
+            matches!(error, Err(ParserError::ParseError(message)) if message == "Verse GEN 1:2 is larger than the limit of 20 bytes")
+        );
+        let error = parser(XML, limit(10).build()).parse();
+        assert!(
+            matches!(error, Err(ParserError::ParseError(message)) if message.starts_with("XML event before byte"))
+        );
+        let mut recovered = parser(XML, limit(20).recover(true).build());
+        recovered.parse().unwrap();
+        assert_eq!(recovered.warnings().len(), 1);
+        // A chapter row combines its verses
+        let rows = limit(40).granularity(Granularity::Chapter).build();
+        let error = parser(XML, rows).parse();
+        assert!(
+            matches!(error, Err(ParserError::ParseError(message)) if message == "Row from GEN 1:1 is larger than the limit of 40 bytes")
+        );
+    }
+
+    #[test]
+    fn test_warnings_limit() {
+        let verses = "<v id=\"1\"/>In the beginning<ve/>".repeat(MAX_WARNINGS + 3);
+        let xml = format!(r#"<usfx><book id="GEN"><c id="1"/><p>{verses}</p></book></usfx>"#);
+        let mut parser = parser(xml.leak(), UsfxConfig::default());
+        parser.parse().unwrap();
+        assert_eq!(parser.warnings().len(), MAX_WARNINGS + 1);
+        assert_eq!(
+            parser.warnings()[MAX_WARNINGS].message,
+            "More than 1000 warnings; the rest are left out"
+        );
+        assert_eq!(parser.omitted_warnings(), 2);
+    }
+
+    #[test]
+    fn test_output_flushing() {
+        /// Writer counting the flushes that reach it
+        struct Flushes(Rc<RefCell<usize>>);
+
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                *self.0.borrow_mut() += 1;
+                Ok(())
+            }
+        }
+
+        let flushes = Rc::new(RefCell::new(0));
+        let config = UsfxConfigBuilder::new().flush_every_n_rows(2).build();
+        let mut parser = UsfxParser::from_reader(
+            SAMPLE.as_bytes(),
+            Box::new(Flushes(flushes.clone())),
+            config,
+        );
+        parser.parse().unwrap();
+        // After the second and fourth rows, and at the end
+        assert_eq!(*flushes.borrow(), 3);
+
+        const BROKEN: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<ve/><v id="2"/>The earth</q></p></book></usfx>"#;
+        let buffer = SharedBuffer::default();
+        let mut parser = UsfxParser::from_reader(
+            BROKEN.as_bytes(),
+            Box::new(buffer.clone()),
+            UsfxConfig::default(),
+        );
+        assert!(parser.parse().is_err());
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            "GEN\t1\t1\tIn the beginning\n"
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_input() {
+        let path =
+            std::env::temp_dir().join(format!("usfx_to_tsv_mapped_{}.xml", std::process::id()));
+        std::fs::write(&path, SAMPLE).unwrap();
+        let buffer = SharedBuffer::default();
+        let mut parser =
+            UsfxParser::mapped(&path, Box::new(buffer.clone()), UsfxConfig::default()).unwrap();
+        parser.parse().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            convert(SAMPLE, UsfxConfig::default())
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_url_input() {
+        use std::io::{BufRead, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            for status in ["200 OK", "404 Not Found"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while request.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let body = if status == "200 OK" { SAMPLE } else { "" };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let url = format!("http://{address}/engkjv_usfx.xml");
+        let buffer = SharedBuffer::default();
+        let mut parser =
+            UsfxParser::from_url(&url, Box::new(buffer.clone()), UsfxConfig::default()).unwrap();
+        parser.parse().unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            convert(SAMPLE, UsfxConfig::default())
+        );
+        let missing = UsfxParser::from_url(&url, Box::new(std::io::sink()), UsfxConfig::default());
+        assert!(missing.is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_parallel_books() {
+        const XML: &str = r#"<?xml version="1.0"?>
+<usfx xmlns:ns0="http://ebible.org/usfx.xsd"><languageCode>eng</languageCode>
+<book id="RUT"><id id="RUT">Ruth</id><h>Ruth</h><c id="1"/><p><v id="1"/>In the days<ve/><v id="1"/>Again<ve/></p></book>
+<book id="ABC"><c id="1"/><p><v id="1"/>Unknown<ve/></p></book>
+<book id="GEN"><h>Genesis</h><c id="1"/><p><v id="1"/>In the beginning<ve/></p></book>
+<book id="DEF"><c id="1"/><p><v id="1"/>Also unknown<ve/></p></book>
+</usfx>"#;
+
+        let sequential = UsfxConfigBuilder::new()
+            .canonical_order(Canon::Protestant)
+            .build();
+        let parallel = UsfxConfigBuilder::new()
+            .canonical_order(Canon::Protestant)
+            .threads(3)
+            .build();
+        assert_eq!(
+            convert(XML, parallel.clone()),
+            convert(XML, sequential.clone())
+        );
+
+        let buffer = SharedBuffer::default();
+        let mut parser =
+            UsfxParser::from_reader(XML.as_bytes(), Box::new(buffer.clone()), parallel);
+        let summary = parser.parse().unwrap();
+        let mut expected =
+            UsfxParser::from_reader(XML.as_bytes(), Box::new(std::io::sink()), sequential);
+        assert_eq!(summary, expected.parse().unwrap());
+        assert_eq!(parser.warnings(), expected.warnings());
+        assert_eq!(parser.metadata(), expected.metadata());
+    }
+
+    #[test]
+    fn test_parallel_duplicates() {
+        const XML: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<ve/></p>
+<!-- <book id="EXO"> follows --><p><v id="2"/>The earth<![CDATA[ <book ]]><ve/></p></book>
+<book id="GEN"><c id="1"/><p><v id="1"/>Again<ve/><v id="1"/>Once more<ve/><v id="3"/>Light<ve/></p></book></usfx>"#;
+
+        for duplicates in [
+            DuplicateVerses::Keep,
+            DuplicateVerses::Drop,
+            DuplicateVerses::Merge,
+        ] {
+            let serial = UsfxConfigBuilder::new()
+                .duplicate_verses(duplicates)
+                .build();
+            let threaded = UsfxConfig {
+                threads: 2,
+                ..serial.clone()
+            };
+            assert_eq!(
+                convert(XML, threaded.clone()),
+                convert(XML, serial.clone()),
+                "{duplicates:?}"
+            );
+
+            let mut expected = parser(XML, serial);
+            expected.parse().unwrap();
+            let mut parser = parser(XML, threaded);
+            parser.parse().unwrap();
+            assert_eq!(parser.warnings().len(), 2);
+            // Merged verses are handed back late, so only their warnings' messages match
+            if duplicates == DuplicateVerses::Merge {
+                let messages = |parser: &UsfxParser| {
+                    parser
+                        .warnings()
+                        .iter()
+                        .map(|warning| warning.message.clone())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(messages(&parser), messages(&expected));
+            } else {
+                assert_eq!(parser.warnings(), expected.warnings());
+            }
+        }
+        assert_eq!(
+            convert(
+                XML,
+                UsfxConfigBuilder::new()
+                    .duplicate_verses(DuplicateVerses::Drop)
+                    .threads(2)
+                    .build()
+            ),
+            "GEN\t1\t1\tIn the beginning\nGEN\t1\t2\tThe earth\nGEN\t1\t3\tLight\n"
+        );
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        assert_eq!("auto".parse::<BufferSize>().unwrap(), BufferSize::Auto);
+        assert_eq!(
+            "4096".parse::<BufferSize>().unwrap(),
+            BufferSize::Bytes(4096)
+        );
+        assert!("large".parse::<BufferSize>().is_err());
+        assert_eq!(BufferSize::Auto.bytes(None), 8 * 1024);
+        assert_eq!(BufferSize::Auto.bytes(Some(1000)), 4 * 1024);
+        assert_eq!(BufferSize::Auto.bytes(Some(20_000_000)), 19_531);
+        assert_eq!(BufferSize::Auto.bytes(Some(500_000_000)), 64 * 1024);
+        assert_eq!(BufferSize::Bytes(16).bytes(Some(500_000_000)), 16);
+
+        // Any size reads the same document, the buffer growing as needed
+        let path = std::env::temp_dir().join(format!("usfx_buffer_{}.xml", std::process::id()));
+        std::fs::write(&path, SAMPLE).unwrap();
+        for size in [BufferSize::Auto, BufferSize::Bytes(1)] {
+            let buffer = SharedBuffer::default();
+            let config = UsfxConfigBuilder::new().buffer_size(size).build();
+            let mut parser = UsfxParser::new(&path, Box::new(buffer.clone()), config).unwrap();
+            parser.parse().unwrap();
+            assert_eq!(
+                String::from_utf8(buffer.0.take()).unwrap(),
+                convert(SAMPLE, UsfxConfig::default())
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let configs = [
+            UsfxConfig::default(),
+            UsfxConfigBuilder::new()
+                .granularity(Granularity::Chapter)
+                .build(),
+            UsfxConfigBuilder::new()
+                .format(OutputFormat::Json)
+                .byte_order_mark(true)
+                .flush_every_n_rows(1)
+                .build(),
+        ];
+        for config in configs {
+            let pipelined = UsfxConfig {
+                pipeline: true,
+                ..config.clone()
+            };
+            assert_eq!(convert(SAMPLE, pipelined), convert(SAMPLE, config));
+        }
+
+        // Rows converted before an error are written, but not a row still being combined
+        const BROKEN: &str = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the beginning<ve/></p><c id="2"/><p><v id="1"/>Thus<ve/></q></p></book></usfx>"#;
+        let buffer = SharedBuffer::default();
+        let config = UsfxConfigBuilder::new()
+            .granularity(Granularity::Chapter)
+            .pipeline(true)
+            .build();
+        let mut parser =
+            UsfxParser::from_reader(BROKEN.as_bytes(), Box::new(buffer.clone()), config);
+        assert!(parser.parse().is_err());
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            "GEN\t1\tIn the beginning\n"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Reading the books of a document on several threads
+
+use super::{UsfxParser, attribute, duplicate_warning, same_reference};
+use crate::versification::Coverage;
+use crate::{
+    BookTitles, DuplicateVerses, IntroParagraph, Metadata, ParserError, UsfxConfig, Verse, Warning,
+    canon,
+};
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Root element wrapped around each book so it can be read as a document of its own
+const ROOT_START: &[u8] = b"<usfx>";
+const ROOT_END: &[u8] = b"</usfx>";
+
+/// What reading one book found
+struct BookResult {
+    /// Each verse, with the position in the document where it was read
+    verses: Vec<(u64, Verse)>,
+    warnings: Vec<Warning>,
+    books: Vec<BookTitles>,
+    intro: Vec<IntroParagraph>,
+    metadata: Metadata,
+}
+
+impl UsfxParser {
+    /// Read the rest of the document, parsing its books on the configured number of threads,
+    /// and return its verses in document order
+    ///
+    /// The document is split at each `<book>` start tag. What comes before the first book, such
+    /// as the root element and the language code, is read by this parser, and each book by a
+    /// parser of its own with the same configuration. Their warnings (with positions in the
+    /// whole document), metadata, book names, introductions and verse coverage are then
+    /// gathered here as if this parser had read the whole document. A verse that repeats one
+    /// from an earlier book is only found then, so it is warned about and kept, dropped or
+    /// merged here.
+    ///
+    /// Positions of those warnings are where the verse was handed back by its book's parser,
+    /// which with [`DuplicateVerses::Merge`] is after the verse that follows it.
+    pub(super) fn read_books_in_parallel(&mut self) -> Result<Vec<Verse>, ParserError> {
+        let mut document = Vec::new();
+        self.reader.get_mut().read_to_end(&mut document)?;
+        let offsets = book_offsets(&document);
+        let first = offsets.first().copied().unwrap_or(document.len());
+        let end = document
+            .windows(ROOT_END.len())
+            .rposition(|window| window == ROOT_END)
+            .filter(|&end| end >= first)
+            .unwrap_or(document.len());
+
+        // Unknown book codes are numbered in document order, so each book's parser starts with
+        // those of the books before it
+        let mut unknown_books: Vec<String> = Vec::new();
+        let mut seeds = Vec::new();
+        for &offset in &offsets {
+            seeds.push(unknown_books.clone());
+            let id = book_id(&document[offset..])?;
+            if self.config.normalize_book_codes
+                && !id.is_empty()
+                && canon::usfm_code(&id).is_none()
+                && !unknown_books.contains(&id)
+            {
+                unknown_books.push(id);
+            }
+        }
+
+        let mut prefix = document[..first].to_vec();
+        if !offsets.is_empty() {
+            prefix.extend_from_slice(ROOT_END);
+        }
+        self.reader = Reader::from_reader(Box::new(Cursor::new(prefix)));
+        let mut verses = Vec::new();
+        while let Some(verse) = self.next_verse()? {
+            verses.push(verse);
+        }
+
+        let ranges: Vec<(usize, usize)> = offsets
+            .iter()
+            .zip(offsets.iter().skip(1).chain([&end]))
+            .map(|(&start, &end)| (start, end))
+            .collect();
+        let next = AtomicUsize::new(0);
+        let threads = self.config.threads.min(ranges.len()).max(1);
+        let mut results: Vec<(usize, Result<BookResult, ParserError>)> =
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut results = Vec::new();
+                            loop {
+                                let index = next.fetch_add(1, Ordering::Relaxed);
+                                let Some(&(start, end)) = ranges.get(index) else {
+                                    break results;
+                                };
+                                let result = read_book(
+                                    &document[start..end],
+                                    start,
+                                    &self.config,
+                                    &seeds[index],
+                                );
+                                results.push((index, result));
+                            }
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("book parser thread panicked"))
+                    .collect()
+            });
+        results.sort_by_key(|(index, _)| *index);
+
+        for (_, result) in results {
+            let book = result?;
+            let mut warnings = book.warnings;
+            // The book's own parser has dealt with verses repeated within the book, so only
+            // the first of each is checked against the books before it
+            let mut seen = Coverage::default();
+            let duplicates: Vec<bool> = book
+                .verses
+                .iter()
+                .map(|(_, verse)| {
+                    if self.is_acrostic_row(verse) {
+                        return false;
+                    }
+                    let repeated = seen.contains(verse);
+                    seen.add(verse);
+                    !repeated && self.coverage.contains(verse)
+                })
+                .collect();
+            for (_, verse) in &book.verses {
+                if self.is_acrostic_row(verse) {
+                    continue;
+                }
+                self.coverage.add(verse);
+            }
+            for ((position, verse), duplicate) in book.verses.into_iter().zip(duplicates) {
+                if duplicate {
+                    warnings.push(Warning {
+                        position,
+                        message: duplicate_warning(&verse),
+                    });
+                    match self.config.duplicate_verses {
+                        DuplicateVerses::Drop => continue,
+                        DuplicateVerses::Merge => {
+                            if let Some(previous) = verses.last_mut()
+                                && same_reference(previous, &verse)
+                            {
+                                previous.append(&verse);
+                                continue;
+                            }
+                        }
+                        DuplicateVerses::Keep => (),
+                    }
+                }
+                verses.push(verse);
+            }
+            // Stable, so warnings raised while a verse was read stay ahead of its duplicate one
+            warnings.sort_by_key(|warning| warning.position);
+            for warning in warnings {
+                self.record(warning);
+            }
+            self.books.extend(book.books);
+            self.intro.extend(book.intro);
+            let metadata = &mut self.metadata;
+            metadata.language_code = metadata
+                .language_code
+                .take()
+                .or(book.metadata.language_code);
+            metadata.ids.extend(book.metadata.ids);
+            metadata.remarks.extend(book.metadata.remarks);
+            metadata.copyright.extend(book.metadata.copyright);
+            metadata.license.extend(book.metadata.license);
+        }
+        self.unknown_books = unknown_books;
+        Ok(verses)
+    }
+}
+
+/// Read one book, starting at `offset` in the document, with its own parser
+fn read_book(
+    book: &[u8],
+    offset: usize,
+    config: &UsfxConfig,
+    unknown_books: &[String],
+) -> Result<BookResult, ParserError> {
+    let document = [ROOT_START, book, ROOT_END].concat();
+    let mut parser = UsfxParser::from_reader(
+        Cursor::new(document),
+        Box::new(std::io::sink()),
+        config.clone(),
+    );
+    parser.unknown_books = unknown_books.to_vec();
+    let in_document =
+        |position: u64| position.saturating_sub(ROOT_START.len() as u64) + offset as u64;
+    let mut verses = Vec::new();
+    while let Some(verse) = parser.next_verse()? {
+        verses.push((in_document(parser.reader.buffer_position()), verse));
+    }
+    let warnings = parser
+        .warnings
+        .into_iter()
+        .map(|warning| Warning {
+            position: in_document(warning.position),
+            ..warning
+        })
+        .collect();
+    Ok(BookResult {
+        verses,
+        warnings,
+        books: parser.books,
+        intro: parser.intro,
+        metadata: parser.metadata,
+    })
+}
+
+/// Markup whose content is not parsed, so may hold `<book` as text, and how it ends
+const UNPARSED: [(&[u8], &[u8]); 3] = [(b"<!--", b"-->"), (b"<![CDATA[", b"]]>"), (b"<?", b"?>")];
+
+/// Byte offsets of the `<book>` start tags in a document, outside comments, CDATA sections and
+/// processing instructions
+fn book_offsets(document: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut position = 0;
+    while let Some(found) = document[position..].iter().position(|&byte| byte == b'<') {
+        let start = position + found;
+        let rest = &document[start..];
+        position = start + 1;
+        if let Some((open, close)) = UNPARSED.iter().find(|(open, _)| rest.starts_with(open)) {
+            position = rest[open.len()..]
+                .windows(close.len())
+                .position(|window| window == *close)
+                .map_or(document.len(), |end| start + open.len() + end + close.len());
+        } else if rest.starts_with(b"<book")
+            && matches!(rest.get(5), Some(b' ' | b'\t' | b'\r' | b'\n' | b'>'))
+        {
+            offsets.push(start);
+        }
+    }
+    offsets
+}
+
+/// The `id` of the `<book>` start tag at the start of `bytes`
+fn book_id(bytes: &[u8]) -> Result<String, ParserError> {
+    let mut reader = Reader::from_reader(bytes);
+    match reader.read_event() {
+        Ok(Event::Start(e) | Event::Empty(e)) => Ok(attribute(&e, b"id")?.unwrap_or_default()),
+        Ok(_) => Ok(String::new()),
+        Err(e) => Err(ParserError::XmlError(e)),
+    }
+}
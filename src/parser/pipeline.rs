@@ -0,0 +1,144 @@
+//! Formatting rows on a second thread while the document is read
+
+use super::UsfxParser;
+use crate::output::RowWriter;
+use crate::{ParserError, Verse};
+use std::io::Write;
+use std::sync::mpsc;
+
+/// Verses handed to the formatter at a time, unless rows are flushed as they are written
+const BATCH_SIZE: usize = 64;
+
+/// Batches read ahead of the formatter before the parser waits for it
+const PIPELINE_DEPTH: usize = 4;
+
+/// Verses for the formatter, and whether they are the last of the document
+struct Batch {
+    verses: Vec<Verse>,
+    end: bool,
+}
+
+/// Rows formatted since they were last handed back, and whether the row writer asked for them
+/// to be flushed
+#[derive(Default)]
+struct Formatted {
+    bytes: Vec<u8>,
+    flush: bool,
+}
+
+impl Write for Formatted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush = true;
+        Ok(())
+    }
+}
+
+impl Formatted {
+    /// Take the rows formatted so far, if there are any or a flush is due
+    fn take(&mut self) -> Option<Self> {
+        (!self.bytes.is_empty() || self.flush).then(|| std::mem::take(self))
+    }
+}
+
+impl UsfxParser {
+    /// Convert every verse, formatting the rows on a second thread
+    ///
+    /// Verses go to the formatter in batches over a bounded channel, so the parser is never
+    /// more than [`PIPELINE_DEPTH`] batches ahead of it. The formatted rows come back to this
+    /// thread to be written, as neither the input nor the output can be sent to another
+    /// thread. They are collected before each verse is read, so they only ever hold the rows
+    /// of the verses in flight. With `flush_every_n_rows`, each verse is a batch of its own so
+    /// that rows are written as promptly as without the pipeline.
+    pub(super) fn write_rows_pipelined(&mut self) -> Result<(), ParserError> {
+        // The byte order mark goes ahead of the rows, rather than in the formatter's output
+        self.output.flush()?;
+        let mut config = self.config.clone();
+        config.byte_order_mark = false;
+        let batch_size = if config.flush_every_n_rows.is_some() {
+            1
+        } else {
+            BATCH_SIZE
+        };
+        let (batches, received) = mpsc::sync_channel::<Batch>(PIPELINE_DEPTH);
+        let (formatted, rows) = mpsc::channel::<Formatted>();
+
+        std::thread::scope(|scope| {
+            let formatter = scope.spawn(move || -> Result<(), ParserError> {
+                let mut writer = RowWriter::new(Formatted::default(), &config);
+                while let Ok(batch) = received.recv() {
+                    for verse in &batch.verses {
+                        writer.write_verse(verse)?;
+                    }
+                    // If the parser failed, a row still being combined is dropped, as it
+                    // would be without the pipeline
+                    if batch.end {
+                        writer.finish()?;
+                    }
+                    // Nobody is left to write the rows if the parser has stopped
+                    if let Some(rows) = writer.get_mut().take()
+                        && formatted.send(rows).is_err()
+                    {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+
+            let mut parsed = Ok(());
+            let mut written;
+            let mut verses = Vec::with_capacity(batch_size);
+            loop {
+                written = rows
+                    .try_iter()
+                    .try_for_each(|rows| self.write_formatted(rows));
+                if written.is_err() {
+                    break;
+                }
+                let end = match self.next_verse() {
+                    Ok(Some(verse)) => {
+                        verses.push(verse);
+                        false
+                    }
+                    Ok(None) => true,
+                    Err(error) => {
+                        parsed = Err(error);
+                        true
+                    }
+                };
+                if verses.len() == batch_size || end {
+                    let batch = Batch {
+                        verses: std::mem::replace(&mut verses, Vec::with_capacity(batch_size)),
+                        end: parsed.is_ok() && end,
+                    };
+                    // The formatter only stops early on an error, which it returns below
+                    if batches.send(batch).is_err() || end {
+                        break;
+                    }
+                }
+            }
+            drop(batches);
+            // Rows formatted before an error are written, as without the pipeline
+            if written.is_ok() {
+                written = rows.iter().try_for_each(|rows| self.write_formatted(rows));
+            }
+            drop(rows);
+            let formatted = formatter.join().expect("formatter thread panicked");
+            parsed.and(formatted).and(written)
+        })
+    }
+
+    /// Write rows that came back from the formatter
+    fn write_formatted(&mut self, rows: Formatted) -> Result<(), ParserError> {
+        let output = self.output.get_mut();
+        output.write_all(&rows.bytes)?;
+        if rows.flush {
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
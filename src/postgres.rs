@@ -0,0 +1,150 @@
+//! Loading verses into Postgres with the binary `COPY` protocol
+//!
+//! Verses are copied into a table with the columns of the `polars` and `arrow` features'
+//! output, created if it does not exist: `book` and `text` (`text not null`), `chapter`,
+//! `verse` and `verse_end` (`integer`) and `subverse`, `paragraph` and `section` (`text`). The
+//! copy runs in a transaction, so a document that fails to parse leaves the table as it was.
+
+use crate::{ParserError, UsfxParser};
+use postgres::binary_copy::BinaryCopyInWriter;
+use postgres::types::Type;
+use postgres::{Client, NoTls};
+use std::str::FromStr;
+
+/// Table verses are copied into when the URL names none
+const DEFAULT_TABLE: &str = "verses";
+
+const COLUMNS: &str = "book, chapter, verse, verse_end, subverse, text, paragraph, section";
+
+const TYPES: [Type; 8] = [
+    Type::TEXT,
+    Type::INT4,
+    Type::INT4,
+    Type::INT4,
+    Type::TEXT,
+    Type::TEXT,
+    Type::TEXT,
+    Type::TEXT,
+];
+
+fn postgres_error(error: postgres::Error) -> ParserError {
+    ParserError::ParseError(format!("PostgreSQL error: {error}"))
+}
+
+/// A database and table to copy verses into, written as a connection URL with the table as
+/// a `table` parameter: `postgres://user@host/bible?table=kjv_verses`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    /// Connection URL, without the `table` parameter
+    pub url: String,
+    /// Table name, optionally qualified by a schema (`public.verses`)
+    pub table: String,
+}
+
+impl FromStr for Destination {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with("postgres://") && !s.starts_with("postgresql://") {
+            return Err(ParserError::ParseError(format!(
+                "Not a PostgreSQL URL: {s} (expected postgres://...)"
+            )));
+        }
+        let (url, query) = s.split_once('?').unwrap_or((s, ""));
+        let mut table = DEFAULT_TABLE.to_string();
+        let mut parameters = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            match pair.strip_prefix("table=") {
+                Some(name) => name.clone_into(&mut table),
+                None => parameters.push(pair),
+            }
+        }
+        if table.split('.').any(str::is_empty) {
+            return Err(ParserError::ParseError(format!(
+                "Invalid table name: {table}"
+            )));
+        }
+        let url = if parameters.is_empty() {
+            url.to_string()
+        } else {
+            format!("{url}?{}", parameters.join("&"))
+        };
+        Ok(Self { url, table })
+    }
+}
+
+impl Destination {
+    /// The table name as an SQL identifier, each part quoted
+    fn quoted_table(&self) -> String {
+        self.table
+            .split('.')
+            .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Copy the parser's remaining verses into the destination table, one row per verse whatever
+/// the configured granularity, returning the number of rows copied
+pub fn copy_verses(parser: &mut UsfxParser, destination: &Destination) -> Result<u64, ParserError> {
+    let table = destination.quoted_table();
+    let mut client = Client::connect(&destination.url, NoTls).map_err(postgres_error)?;
+    let mut transaction = client.transaction().map_err(postgres_error)?;
+    transaction
+        .batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (book text NOT NULL, chapter integer, \
+             verse integer, verse_end integer, subverse text, text text NOT NULL, \
+             paragraph text, section text)"
+        ))
+        .map_err(postgres_error)?;
+    let sink = transaction
+        .copy_in(&format!(
+            "COPY {table} ({COLUMNS}) FROM STDIN (FORMAT binary)"
+        ))
+        .map_err(postgres_error)?;
+    let mut writer = BinaryCopyInWriter::new(sink, &TYPES);
+    while let Some(verse) = parser.next_verse()? {
+        let number = |value: &str| value.parse::<i32>().ok();
+        writer
+            .write(&[
+                &verse.book,
+                &number(&verse.chapter),
+                &number(&verse.verse),
+                &verse.verse_end.as_deref().and_then(number),
+                &verse.subverse,
+                &verse.text,
+                &verse.paragraph,
+                &verse.section,
+            ])
+            .map_err(postgres_error)?;
+    }
+    let rows = writer.finish().map_err(postgres_error)?;
+    transaction.commit().map_err(postgres_error)?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let destination: Destination =
+            "postgres://reader@localhost/bible?sslmode=disable&table=public.kjv"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            destination.url,
+            "postgres://reader@localhost/bible?sslmode=disable"
+        );
+        assert_eq!(destination.quoted_table(), r#""public"."kjv""#);
+        let destination: Destination = "postgresql://localhost/bible".parse().unwrap();
+        assert_eq!(destination.table, "verses");
+        assert!(
+            "postgres://localhost/bible?table=kjv."
+                .parse::<Destination>()
+                .is_err()
+        );
+        assert!("mysql://localhost/bible".parse::<Destination>().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Loading verses into Redis, for applications serving verse lookups from memory
+//!
+//! Verses are written with pipelined `SET` (or `HSET`) commands over a plain TCP connection
+//! speaking the Redis protocol (RESP), so any Redis-compatible store such as Valkey or
+//! `KeyDB` can be loaded.
+
+use crate::{ParserError, UsfxParser, Verse};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+
+/// Commands sent before waiting for their replies
+const PIPELINE: usize = 1000;
+
+/// How verses are laid out in keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyLayout {
+    /// A string key per verse, `<translation>:<book>:<chapter>:<verse>` (default)
+    #[default]
+    Verse,
+    /// A hash per chapter, `<translation>:<book>:<chapter>`, with a field per verse
+    Chapter,
+}
+
+impl FromStr for KeyLayout {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verse" => Ok(Self::Verse),
+            "chapter" => Ok(Self::Chapter),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown key layout: {s} (expected verse or chapter)"
+            ))),
+        }
+    }
+}
+
+/// A Redis server to write verses to, written as a URL:
+/// `redis://[[user]:password@]host[:port][/database][?translation=kjv&layout=chapter]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    /// Host and port of the server
+    pub address: String,
+    /// User name and password to authenticate with, if any
+    pub credentials: Option<(Option<String>, String)>,
+    /// Database number to select, if not the default 0
+    pub database: Option<u32>,
+    /// Translation name starting each key, if given in the URL
+    pub translation: Option<String>,
+    /// Layout of the keys
+    pub layout: KeyLayout,
+}
+
+impl FromStr for Destination {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ParserError::ParseError(format!(
+                "Invalid Redis URL: {s} (expected redis://[[user]:password@]host[:port][/database])"
+            ))
+        };
+        let rest = s.strip_prefix("redis://").ok_or_else(invalid)?;
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (credentials, host) = match authority.rsplit_once('@') {
+            Some((user_info, host)) => {
+                let (user, password) = user_info.split_once(':').ok_or_else(invalid)?;
+                let user = (!user.is_empty()).then(|| user.to_string());
+                (Some((user, password.to_string())), host)
+            }
+            None => (None, authority),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let address = if host.contains(':') && !host.ends_with(']') {
+            host.to_string()
+        } else {
+            format!("{host}:6379")
+        };
+        let database = match path {
+            "" => None,
+            number => Some(number.parse().ok().ok_or_else(invalid)?),
+        };
+        let mut translation = None;
+        let mut layout = KeyLayout::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            match pair.split_once('=') {
+                Some(("translation", name)) => translation = Some(name.to_string()),
+                Some(("layout", value)) => layout = value.parse()?,
+                _ => {
+                    return Err(ParserError::ParseError(format!(
+                        "Unknown Redis URL parameter: {pair} (expected translation or layout)"
+                    )));
+                }
+            }
+        }
+        Ok(Self {
+            address,
+            credentials,
+            database,
+            translation,
+            layout,
+        })
+    }
+}
+
+/// A connection sending pipelined commands
+struct Connection {
+    writer: BufWriter<TcpStream>,
+    reader: BufReader<TcpStream>,
+    pending: usize,
+}
+
+impl Connection {
+    fn open(address: &str) -> Result<Self, ParserError> {
+        let stream = TcpStream::connect(address)?;
+        Ok(Self {
+            writer: BufWriter::new(stream.try_clone()?),
+            reader: BufReader::new(stream),
+            pending: 0,
+        })
+    }
+
+    /// Queue a command, waiting for the replies to those queued once the pipeline is full
+    fn send(&mut self, args: &[&[u8]]) -> Result<(), ParserError> {
+        write!(self.writer, "*{}\r\n", args.len())?;
+        for arg in args {
+            write!(self.writer, "${}\r\n", arg.len())?;
+            self.writer.write_all(arg)?;
+            self.writer.write_all(b"\r\n")?;
+        }
+        self.pending += 1;
+        if self.pending == PIPELINE {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the replies to every queued command, failing on the first error reply
+    fn sync(&mut self) -> Result<(), ParserError> {
+        self.writer.flush()?;
+        while self.pending > 0 {
+            self.reply()?;
+            self.pending -= 1;
+        }
+        Ok(())
+    }
+
+    /// Read a reply, skipping its value
+    fn reply(&mut self) -> Result<(), ParserError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ParserError::ParseError(
+                "Redis closed the connection".to_string(),
+            ));
+        }
+        let line = line.trim_end();
+        let malformed = || ParserError::ParseError(format!("Malformed Redis reply: {line}"));
+        match line.split_at_checked(1) {
+            Some(("+" | ":", _)) => Ok(()),
+            Some(("-", message)) => Err(ParserError::ParseError(format!("Redis error: {message}"))),
+            Some(("$", length)) => {
+                let length: i64 = length.parse().ok().ok_or_else(malformed)?;
+                if let Ok(length) = u64::try_from(length) {
+                    std::io::copy(
+                        &mut (&mut self.reader).take(length + 2),
+                        &mut std::io::sink(),
+                    )?;
+                }
+                Ok(())
+            }
+            Some(("*", count)) => {
+                let count: i64 = count.parse().ok().ok_or_else(malformed)?;
+                for _ in 0..count.max(0) {
+                    self.reply()?;
+                }
+                Ok(())
+            }
+            _ => Err(malformed()),
+        }
+    }
+}
+
+/// Verse number of a key or hash field, with the verse part if the verse is split (`1a`)
+fn verse_number(verse: &Verse) -> String {
+    format!("{}{}", verse.verse, verse.subverse.as_deref().unwrap_or(""))
+}
+
+/// Write the parser's remaining verse texts under keys starting with `translation`, one per
+/// verse whatever the configured granularity, returning the number of verses written
+pub fn write_verses(
+    parser: &mut UsfxParser,
+    destination: &Destination,
+    translation: &str,
+) -> Result<usize, ParserError> {
+    let mut connection = Connection::open(&destination.address)?;
+    if let Some((user, password)) = &destination.credentials {
+        match user {
+            Some(user) => connection.send(&[b"AUTH", user.as_bytes(), password.as_bytes()])?,
+            None => connection.send(&[b"AUTH", password.as_bytes()])?,
+        }
+    }
+    if let Some(database) = destination.database {
+        connection.send(&[b"SELECT", database.to_string().as_bytes()])?;
+    }
+    connection.sync()?;
+    let mut verses = 0;
+    while let Some(verse) = parser.next_verse()? {
+        let chapter_key = format!("{translation}:{}:{}", verse.book, verse.chapter);
+        match destination.layout {
+            KeyLayout::Verse => {
+                let key = format!("{chapter_key}:{}", verse_number(&verse));
+                connection.send(&[b"SET", key.as_bytes(), verse.text.as_bytes()])?;
+            }
+            KeyLayout::Chapter => {
+                let field = verse_number(&verse);
+                connection.send(&[
+                    b"HSET",
+                    chapter_key.as_bytes(),
+                    field.as_bytes(),
+                    verse.text.as_bytes(),
+                ])?;
+            }
+        }
+        verses += 1;
+    }
+    connection.sync()?;
+    Ok(verses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsfxConfig;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_write_verses() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        // A server answering every command, recording the commands of each connection
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut connections = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut commands = Vec::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let count: usize = line.trim_end()[1..].parse().unwrap();
+                    let mut command = Vec::new();
+                    for _ in 0..count * 2 {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        command.push(line.trim_end().to_string());
+                    }
+                    let command: Vec<_> = command.into_iter().skip(1).step_by(2).collect();
+                    let reply = if command[0] == "HSET" {
+                        ":1\r\n"
+                    } else {
+                        "+OK\r\n"
+                    };
+                    stream.write_all(reply.as_bytes()).unwrap();
+                    commands.push(command.join(" "));
+                    line.clear();
+                }
+                connections.push(commands);
+            }
+            connections
+        });
+
+        let parser = || {
+            let input = std::io::Cursor::new(XML.as_bytes().to_vec());
+            UsfxParser::from_reader(input, Box::new(std::io::sink()), UsfxConfig::default())
+        };
+        let destination: Destination = format!("redis://:secret@{address}/2").parse().unwrap();
+        assert_eq!(write_verses(&mut parser(), &destination, "kjv").unwrap(), 2);
+        let destination: Destination = format!("redis://{address}?translation=web&layout=chapter")
+            .parse()
+            .unwrap();
+        assert_eq!(destination.translation.as_deref(), Some("web"));
+        write_verses(&mut parser(), &destination, "web").unwrap();
+
+        let connections = server.join().unwrap();
+        assert_eq!(
+            connections[0],
+            [
+                "AUTH secret",
+                "SELECT 2",
+                "SET kjv:JHN:11:35 Jesus wept.",
+                "SET kjv:JHN:11:36 Then said the Jews, Behold how he loved him!",
+            ]
+        );
+        assert_eq!(connections[1][0], "HSET web:JHN:11 35 Jesus wept.");
+        assert!(
+            "redis://localhost?layout=book"
+                .parse::<Destination>()
+                .is_err()
+        );
+        assert!("redis://localhost/x".parse::<Destination>().is_err());
+    }
+}
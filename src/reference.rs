@@ -0,0 +1,607 @@
+//! Scripture references and parsing of human-readable reference lists
+
+use crate::ParserError;
+use crate::canon;
+use crate::versification::Versification;
+use serde::Serialize;
+use std::fmt;
+
+/// A chapter or verse in a book, identified by USFM book code
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Reference {
+    /// USFM book code (e.g. `JHN`)
+    pub book: String,
+    /// Chapter number
+    pub chapter: u32,
+    /// Verse number, or `None` for the whole chapter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verse: Option<u32>,
+}
+
+impl fmt::Display for Reference {
+    /// Writes the reference in USFX `bcv` style, e.g. `JHN.3.16`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.book, self.chapter)?;
+        if let Some(verse) = self.verse {
+            write!(f, ".{verse}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An inclusive span of references; a single reference has `start == end`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ReferenceRange {
+    /// First chapter or verse of the range
+    pub start: Reference,
+    /// Last chapter or verse of the range
+    pub end: Reference,
+}
+
+impl ReferenceRange {
+    /// A range covering a single reference
+    pub fn single(reference: Reference) -> Self {
+        Self {
+            end: reference.clone(),
+            start: reference,
+        }
+    }
+
+    /// Whether the range covers a reference; a chapter at either end of the range covers all
+    /// of its verses
+    pub fn contains(&self, reference: &Reference) -> bool {
+        if self.start.book == self.end.book && reference.book != self.start.book {
+            return false;
+        }
+        let book = |book: &str| canon::USFM_BOOKS.iter().position(|code| *code == book);
+        let key = |r: &Reference, default| (book(&r.book), r.chapter, r.verse.unwrap_or(default));
+        let position = key(reference, 0);
+        key(&self.start, 0) <= position && position <= key(&self.end, u32::MAX)
+    }
+
+    /// Every verse of the range, in order, with the chapters and verses of each book taken
+    /// from a versification; books it does not cover are skipped
+    pub fn verses(&self, versification: &Versification) -> Vec<Reference> {
+        let book = |book: &str| canon::USFM_BOOKS.iter().position(|code| *code == book);
+        let (Some(first), Some(last)) = (book(&self.start.book), book(&self.end.book)) else {
+            return Vec::new();
+        };
+        let mut verses = Vec::new();
+        for book in canon::USFM_BOOKS.get(first..=last).unwrap_or_default() {
+            for chapter in 1..=versification.chapters(book).unwrap_or(0) {
+                for verse in 1..=versification.verses(book, chapter).unwrap_or(0) {
+                    let reference = Reference {
+                        book: book.to_string(),
+                        chapter,
+                        verse: Some(verse),
+                    };
+                    if self.contains(&reference) {
+                        verses.push(reference);
+                    }
+                }
+            }
+        }
+        verses
+    }
+}
+
+/// Every verse reference from `start` to `end`, given in `bcv` style (`GEN.1.1`, or `GEN.2`
+/// for a whole chapter), under a versification, e.g. for building reading plans
+pub fn expand_range(
+    start: &str,
+    end: &str,
+    versification: &Versification,
+) -> Result<Vec<Reference>, ParserError> {
+    let reference = |text: &str| {
+        let invalid = || ParserError::ParseError(format!("Invalid reference: {text}"));
+        let range = parse_bcv(text).filter(|range| range.start == range.end);
+        let mut reference = range.ok_or_else(invalid)?.start;
+        reference.book = canon::usfm_code(&reference.book)
+            .ok_or_else(invalid)?
+            .to_string();
+        Ok::<_, ParserError>(reference)
+    };
+    let range = ReferenceRange {
+        start: reference(start)?,
+        end: reference(end)?,
+    };
+    Ok(range.verses(versification))
+}
+
+impl fmt::Display for ReferenceRange {
+    /// Writes the range compactly, e.g. `JHN.3.16`, `JHN.3.16-18` or `JHN.3.16-4.2`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start)?;
+        if self.end == self.start {
+            return Ok(());
+        }
+        if self.end.book != self.start.book {
+            return write!(f, "-{}", self.end);
+        }
+        match self.end.verse {
+            Some(verse) if self.end.chapter == self.start.chapter => write!(f, "-{verse}"),
+            Some(verse) => write!(f, "-{}.{verse}", self.end.chapter),
+            None => write!(f, "-{}", self.end.chapter),
+        }
+    }
+}
+
+/// English book names and common abbreviations, keyed by USFM code.
+/// Names are matched case-insensitively, ignoring spaces and periods.
+const BOOK_NAMES: &[(&str, &[&str])] = &[
+    ("GEN", &["genesis", "gen", "ge", "gn"]),
+    ("EXO", &["exodus", "exod", "exo", "ex"]),
+    ("LEV", &["leviticus", "lev", "le", "lv"]),
+    ("NUM", &["numbers", "num", "nu", "nm", "nb"]),
+    ("DEU", &["deuteronomy", "deut", "deu", "dt"]),
+    ("JOS", &["joshua", "josh", "jos", "jsh"]),
+    ("JDG", &["judges", "judg", "jdg", "jg", "jdgs"]),
+    ("RUT", &["ruth", "rut", "ru", "rth"]),
+    ("1SA", &["1samuel", "1sam", "1sa", "1sm", "1s"]),
+    ("2SA", &["2samuel", "2sam", "2sa", "2sm", "2s"]),
+    ("1KI", &["1kings", "1kgs", "1ki", "1kg", "1k"]),
+    ("2KI", &["2kings", "2kgs", "2ki", "2kg", "2k"]),
+    ("1CH", &["1chronicles", "1chron", "1chr", "1ch"]),
+    ("2CH", &["2chronicles", "2chron", "2chr", "2ch"]),
+    ("EZR", &["ezra", "ezr"]),
+    ("NEH", &["nehemiah", "neh", "ne"]),
+    ("EST", &["esther", "esth", "est", "es"]),
+    ("JOB", &["job", "jb"]),
+    ("PSA", &["psalms", "psalm", "pss", "psa", "ps", "pslm"]),
+    ("PRO", &["proverbs", "prov", "pro", "prv", "pr"]),
+    (
+        "ECC",
+        &["ecclesiastes", "eccles", "eccl", "ecc", "ec", "qoh"],
+    ),
+    (
+        "SNG",
+        &[
+            "songofsongs",
+            "songofsolomon",
+            "song",
+            "sng",
+            "sos",
+            "ss",
+            "cant",
+        ],
+    ),
+    ("ISA", &["isaiah", "isa", "is"]),
+    ("JER", &["jeremiah", "jer", "je", "jr"]),
+    ("LAM", &["lamentations", "lam", "la"]),
+    ("EZK", &["ezekiel", "ezek", "eze", "ezk"]),
+    ("DAN", &["daniel", "dan", "da", "dn"]),
+    ("HOS", &["hosea", "hos", "ho"]),
+    ("JOL", &["joel", "jol", "joe", "jl"]),
+    ("AMO", &["amos", "amo", "am"]),
+    ("OBA", &["obadiah", "obad", "oba", "ob"]),
+    ("JON", &["jonah", "jon", "jnh"]),
+    ("MIC", &["micah", "mic", "mi"]),
+    ("NAM", &["nahum", "nah", "nam", "na"]),
+    ("HAB", &["habakkuk", "hab", "hb"]),
+    ("ZEP", &["zephaniah", "zeph", "zep", "zp"]),
+    ("HAG", &["haggai", "hag", "hg"]),
+    ("ZEC", &["zechariah", "zech", "zec", "zc"]),
+    ("MAL", &["malachi", "mal", "ml"]),
+    ("MAT", &["matthew", "matt", "mat", "mt"]),
+    ("MRK", &["mark", "mrk", "mar", "mk", "mr"]),
+    ("LUK", &["luke", "luk", "lk"]),
+    ("JHN", &["john", "jhn", "joh", "jn"]),
+    ("ACT", &["acts", "act", "ac"]),
+    ("ROM", &["romans", "rom", "ro", "rm"]),
+    ("1CO", &["1corinthians", "1cor", "1co"]),
+    ("2CO", &["2corinthians", "2cor", "2co"]),
+    ("GAL", &["galatians", "gal", "ga"]),
+    ("EPH", &["ephesians", "eph", "ep"]),
+    ("PHP", &["philippians", "phil", "php", "pp"]),
+    ("COL", &["colossians", "col"]),
+    ("1TH", &["1thessalonians", "1thess", "1thes", "1th"]),
+    ("2TH", &["2thessalonians", "2thess", "2thes", "2th"]),
+    ("1TI", &["1timothy", "1tim", "1ti", "1tm"]),
+    ("2TI", &["2timothy", "2tim", "2ti", "2tm"]),
+    ("TIT", &["titus", "tit", "ti"]),
+    ("PHM", &["philemon", "philem", "phlm", "phm"]),
+    ("HEB", &["hebrews", "heb"]),
+    ("JAS", &["james", "jas", "jam", "jm"]),
+    ("1PE", &["1peter", "1pet", "1pe", "1pt", "1p"]),
+    ("2PE", &["2peter", "2pet", "2pe", "2pt", "2p"]),
+    ("1JN", &["1john", "1jn", "1jhn", "1joh", "1jo"]),
+    ("2JN", &["2john", "2jn", "2jhn", "2joh", "2jo"]),
+    ("3JN", &["3john", "3jn", "3jhn", "3joh", "3jo"]),
+    ("JUD", &["jude", "jud", "jd"]),
+    ("REV", &["revelation", "rev", "re", "rv", "apoc"]),
+    ("TOB", &["tobit", "tob", "tb"]),
+    ("JDT", &["judith", "jdt", "jth"]),
+    (
+        "ESG",
+        &[
+            "esthergreek",
+            "greekesther",
+            "additionstoesther",
+            "restofesther",
+            "addesth",
+            "esthgr",
+            "esg",
+        ],
+    ),
+    ("WIS", &["wisdomofsolomon", "wisdom", "wis", "ws"]),
+    ("SIR", &["sirach", "ecclesiasticus", "ecclus", "sir"]),
+    ("BAR", &["baruch", "bar"]),
+    (
+        "LJE",
+        &[
+            "letterofjeremiah",
+            "epistleofjeremiah",
+            "epjer",
+            "letjer",
+            "lje",
+        ],
+    ),
+    (
+        "S3Y",
+        &[
+            "songofthethree",
+            "songofthree",
+            "songofthethreeyoungmen",
+            "songofthreeyouths",
+            "prayerofazariah",
+            "prazar",
+            "s3y",
+        ],
+    ),
+    ("SUS", &["susanna", "sus"]),
+    ("BEL", &["belandthedragon", "bel"]),
+    ("1MA", &["1maccabees", "1macc", "1mac", "1ma"]),
+    ("2MA", &["2maccabees", "2macc", "2mac", "2ma"]),
+    ("3MA", &["3maccabees", "3macc", "3mac", "3ma"]),
+    ("4MA", &["4maccabees", "4macc", "4mac", "4ma"]),
+    ("1ES", &["1esdras", "1esd", "1es"]),
+    ("2ES", &["2esdras", "2esd", "2es"]),
+    (
+        "MAN",
+        &["prayerofmanasseh", "prayerofmanasses", "prman", "man"],
+    ),
+    ("PS2", &["psalm151", "ps151", "ps2"]),
+    ("DAG", &["danielgreek", "greekdaniel", "dangr", "dag"]),
+];
+
+/// Look up the USFM code for an English book name, abbreviation or USFM code
+pub(crate) fn book_code(name: &str) -> Option<&'static str> {
+    let key = normalize_book_name(name);
+    BOOK_NAMES
+        .iter()
+        .find(|(code, names)| code.eq_ignore_ascii_case(&key) || names.contains(&key.as_str()))
+        .map(|(code, _)| *code)
+}
+
+/// Lowercase a book name, drop spaces and periods, and turn a leading ordinal into a digit
+fn normalize_book_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let mut words = name.split([' ', '.']).filter(|word| !word.is_empty());
+    let Some(first) = words.next() else {
+        return String::new();
+    };
+    let first = match first {
+        "i" | "first" | "1st" => "1",
+        "ii" | "second" | "2nd" => "2",
+        "iii" | "third" | "3rd" => "3",
+        "iv" | "fourth" | "4th" => "4",
+        _ => first,
+    };
+    std::iter::once(first).chain(words).collect()
+}
+
+/// Parse a USFX-style reference such as `JHN.1.1`, `JHN.1.1-3` or `JHN.1.1-JHN.1.3`
+pub(crate) fn parse_bcv(text: &str) -> Option<ReferenceRange> {
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (start, Some(end)),
+        None => (text, None),
+    };
+    let start = match start.trim().split('.').collect::<Vec<_>>()[..] {
+        [book, chapter] if !book.is_empty() => Reference {
+            book: book.to_string(),
+            chapter: number(chapter)?,
+            verse: None,
+        },
+        [book, chapter, verse] if !book.is_empty() => Reference {
+            book: book.to_string(),
+            chapter: number(chapter)?,
+            verse: Some(number(verse)?),
+        },
+        _ => return None,
+    };
+    let Some(end) = end else {
+        return Some(ReferenceRange::single(start));
+    };
+    let parts: Vec<&str> = end.trim().split('.').collect();
+    let end = match parts[..] {
+        [verse] if start.verse.is_some() => Reference {
+            verse: Some(number(verse)?),
+            ..start.clone()
+        },
+        [chapter] => Reference {
+            chapter: number(chapter)?,
+            verse: None,
+            ..start.clone()
+        },
+        [chapter, verse] => Reference {
+            chapter: number(chapter)?,
+            verse: Some(number(verse)?),
+            ..start.clone()
+        },
+        [book, chapter, verse] => Reference {
+            book: book.to_string(),
+            chapter: number(chapter)?,
+            verse: Some(number(verse)?),
+        },
+        _ => return None,
+    };
+    Some(ReferenceRange { start, end })
+}
+
+/// Parse a list of references such as `1 Jn 3:16-18`, `Gen 1; Ex 20:1-17` or `JHN.3.16`
+///
+/// Book names may be English names, common abbreviations (including numbered books written
+/// as `1 Kings`, `I Kings` or `First Kings`) or USFM codes. A segment without a book name
+/// continues the previous segment's book. Unlike the lenient parsing of note text, any
+/// segment that cannot be understood is an error.
+pub fn parse(text: &str) -> Result<Vec<ReferenceRange>, ParserError> {
+    let invalid =
+        |segment: &str| ParserError::ParseError(format!("Invalid reference: {}", segment.trim()));
+    let mut ranges = Vec::new();
+    let mut book: Option<String> = None;
+    for segment in text.split(';').filter(|segment| !segment.trim().is_empty()) {
+        if let Some(mut range) = parse_bcv(segment)
+            && let Some(start) = canon::usfm_code(&range.start.book)
+            && let Some(end) = canon::usfm_code(&range.end.book)
+        {
+            range.start.book = start.to_string();
+            range.end.book = end.to_string();
+            book = Some(end.to_string());
+            ranges.push(range);
+            continue;
+        }
+        let (prefix, spec) = split_segment(segment).ok_or_else(|| invalid(segment))?;
+        match prefix {
+            Prefix::Book(code) => book = Some(code.to_string()),
+            Prefix::None if book.is_some() => (),
+            _ => return Err(invalid(segment)),
+        }
+        let book = book.as_deref().unwrap_or_default();
+        ranges.extend(parse_spec(book, None, spec).ok_or_else(|| invalid(segment))?);
+    }
+    if ranges.is_empty() {
+        return Err(invalid(text));
+    }
+    Ok(ranges)
+}
+
+/// What precedes the chapter/verse part of a segment
+enum Prefix {
+    /// No book name: continue with the previous book
+    None,
+    /// A book name
+    Book(&'static str),
+    /// `v.`, `vv.` and the like: verses of the context chapter
+    Verses,
+}
+
+/// Leniently parse a list of references such as `John 1:1-3, 14; Heb 11:3`.
+///
+/// A segment without a book name continues the previous segment's book, starting from
+/// the `context` book; `v. 3` style segments refer to the `context` chapter.
+/// Segments that cannot be understood are skipped.
+pub(crate) fn parse_list(text: &str, context: Option<&Reference>) -> Vec<ReferenceRange> {
+    let mut ranges = Vec::new();
+    let mut book = context.map(|context| context.book.clone());
+    for segment in text.split(';') {
+        let Some((prefix, spec)) = split_segment(segment) else {
+            continue;
+        };
+        let chapter = match prefix {
+            Prefix::None => None,
+            Prefix::Book(code) => {
+                book = Some(code.to_string());
+                None
+            }
+            Prefix::Verses => match context {
+                Some(context) => {
+                    book = Some(context.book.clone());
+                    Some(context.chapter)
+                }
+                None => continue,
+            },
+        };
+        if let Some(book) = &book {
+            ranges.extend(parse_spec(book, chapter, spec).unwrap_or_default());
+        }
+    }
+    ranges
+}
+
+/// Split a segment into its prefix and its chapter/verse part
+fn split_segment(segment: &str) -> Option<(Prefix, &str)> {
+    let segment = segment.trim().trim_end_matches('.');
+    // Try each word that starts with a digit as the start of the chapter/verse part
+    let candidates = segment.char_indices().filter(|&(i, c)| {
+        c.is_ascii_digit() && (i == 0 || segment[..i].ends_with(char::is_whitespace))
+    });
+    for (i, _) in candidates {
+        let (name, spec) = segment.split_at(i);
+        let prefix = match name.trim().trim_end_matches('.').to_lowercase().as_str() {
+            "" => Prefix::None,
+            "v" | "vs" | "ver" | "verse" | "verses" | "vv" => Prefix::Verses,
+            name => match book_code(name) {
+                Some(code) => Prefix::Book(code),
+                None => continue,
+            },
+        };
+        // Only part letters such as the `a` in `5a` may follow; longer words mean the
+        // digit belongs to a book name such as `1 Kings`
+        if spec
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || " :.,-–—".contains(c))
+            && spec
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .all(|word| word.len() <= 1)
+        {
+            return Some((prefix, spec));
+        }
+    }
+    None
+}
+
+/// Parse the chapter/verse part of a segment, e.g. `1:1-3, 14` or `23`
+fn parse_spec(book: &str, mut chapter: Option<u32>, spec: &str) -> Option<Vec<ReferenceRange>> {
+    let reference = |chapter, verse| Reference {
+        book: book.to_string(),
+        chapter,
+        verse,
+    };
+    let mut ranges = Vec::new();
+    // Once a chapter is known, bare numbers are verses in that chapter
+    for item in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (first, last) = match item.split_once(['-', '–', '—']) {
+            Some((first, last)) => (first.trim(), Some(last.trim())),
+            None => (item, None),
+        };
+
+        let start = match first.split_once([':', '.']) {
+            Some((c, v)) => {
+                let c = number(c)?;
+                chapter = Some(c);
+                reference(c, Some(number(v)?))
+            }
+            None => match chapter {
+                Some(c) => reference(c, Some(number(first)?)),
+                None => reference(number(first)?, None),
+            },
+        };
+
+        let end = match last {
+            None => start.clone(),
+            Some(last) => match last.split_once([':', '.']) {
+                Some((c, v)) => {
+                    let c = number(c)?;
+                    chapter = Some(c);
+                    reference(c, Some(number(v)?))
+                }
+                None if start.verse.is_some() => reference(start.chapter, Some(number(last)?)),
+                None => reference(number(last)?, None),
+            },
+        };
+        ranges.push(ReferenceRange { start, end });
+    }
+    (!ranges.is_empty()).then_some(ranges)
+}
+
+/// Parse a chapter or verse number, ignoring part letters such as the `a` in `5a`
+fn number(text: &str) -> Option<u32> {
+    let digits = text
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(text: &str, context: Option<(&str, u32)>) -> Vec<String> {
+        let context = context.map(|(book, chapter)| Reference {
+            book: book.to_string(),
+            chapter,
+            verse: None,
+        });
+        parse_list(text, context.as_ref())
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_book_code() {
+        assert_eq!(book_code("John"), Some("JHN"));
+        assert_eq!(book_code("1 Jn"), Some("1JN"));
+        assert_eq!(book_code("I Kings"), Some("1KI"));
+        assert_eq!(book_code("Song of Solomon"), Some("SNG"));
+        assert_eq!(book_code("Ps."), Some("PSA"));
+        assert_eq!(book_code("jhn"), Some("JHN"));
+        assert_eq!(book_code("Hezekiah"), None);
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            list("John 1:1-3, 14; Heb 11:3", None),
+            ["JHN.1.1-3", "JHN.1.14", "HEB.11.3"]
+        );
+        assert_eq!(list("Is 42:5; 45:18", None), ["ISA.42.5", "ISA.45.18"]);
+        assert_eq!(
+            list("Ps 23; 1 Kings 3:4–4:2.", None),
+            ["PSA.23", "1KI.3.4-4.2"]
+        );
+        assert_eq!(list("ver. 3", Some(("GEN", 2))), ["GEN.2.3"]);
+        assert_eq!(list("ver. 3", None), Vec::<String>::new());
+        assert_eq!(list("1:3, 5a", Some(("GEN", 2))), ["GEN.1.3", "GEN.1.5"]);
+        assert_eq!(list("See the notes", None), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_parse_bcv() {
+        let parsed = |text| parse_bcv(text).map(|range| range.to_string());
+        assert_eq!(parsed("JHN.1.1").as_deref(), Some("JHN.1.1"));
+        assert_eq!(parsed("JHN.1.1-3").as_deref(), Some("JHN.1.1-3"));
+        assert_eq!(parsed("JHN.1.1-JHN.2.3").as_deref(), Some("JHN.1.1-2.3"));
+        assert_eq!(parsed("PSA.23").as_deref(), Some("PSA.23"));
+        assert_eq!(parsed("JHN"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = |text| {
+            parse(text).map(|ranges| ranges.iter().map(ToString::to_string).collect::<Vec<_>>())
+        };
+        assert_eq!(parsed("1 Jn 3:16-18").unwrap(), ["1JN.3.16-18"]);
+        assert_eq!(
+            parsed("Gen 1; Ex 20:1-17; 21").unwrap(),
+            ["GEN.1", "EXO.20.1-17", "EXO.21"]
+        );
+        assert_eq!(parsed("jhn.3.16").unwrap(), ["JHN.3.16"]);
+        assert!(parsed("3:16").is_err());
+        assert!(parsed("Hezekiah 3:16").is_err());
+        assert!(parsed("").is_err());
+
+        let range = &parse("Ps 23; MAL.4.1-MAT.1.5").unwrap()[1];
+        let reference = |book: &str, chapter, verse| Reference {
+            book: book.to_string(),
+            chapter,
+            verse: Some(verse),
+        };
+        assert!(range.contains(&reference("MAL", 4, 6)));
+        assert!(range.contains(&reference("MAT", 1, 5)));
+        assert!(!range.contains(&reference("MAT", 1, 6)));
+        assert!(!range.contains(&reference("MAL", 3, 18)));
+    }
+
+    #[test]
+    fn test_expand_range() {
+        let kjv = Versification::kjv();
+        let verses = expand_range("GEN.1.1", "GEN.2.3", &kjv).unwrap();
+        assert_eq!(verses.len(), 34);
+        assert_eq!(verses[31].to_string(), "GEN.2.1");
+        assert_eq!(verses[33].to_string(), "GEN.2.3");
+
+        let verses = expand_range("Obad.1.21", "jon.1", &kjv).unwrap();
+        let verses: Vec<String> = verses.iter().map(ToString::to_string).collect();
+        assert_eq!(verses.first().unwrap(), "OBA.1.21");
+        assert_eq!(verses.last().unwrap(), "JON.1.17");
+        assert_eq!(verses.len(), 18);
+        assert!(expand_range("GEN.2.3", "GEN.1.1", &kjv).unwrap().is_empty());
+        assert!(expand_range("GEN.1.1-3", "GEN.2.3", &kjv).is_err());
+        assert!(expand_range("Genesis 1:1", "GEN.2.3", &kjv).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+//! HTTP conversion service, serving converted documents and verse lookups
+//!
+//! | Request | Response |
+//! |---|---|
+//! | `POST /convert?<options>` with a USFX body | The converted rows |
+//! | `PUT /translations/<name>` with a USFX body | Stores the translation for later requests |
+//! | `GET /translations` | The stored translations, one name per line |
+//! | `GET /translations/<name>?<options>` | The translation's converted rows |
+//! | `GET /verse/<name>/<references>?<options>` | The rows of the verses referenced |
+//!
+//! Options are the command line options as query parameters in camel case, as for the
+//! JavaScript bindings: `?format=json&divineName=uppercase&columns=checksum,raw`. Verse lookups
+//! take references as `--range` does (`JHN.3.16-18`) and default to JSON.
+
+use crate::options::{ConvertOptions, convert_document, error_message};
+use crate::reference::{self, Reference};
+use crate::{ParserError, RowWriter, UsfxParser, Verse};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Largest request body accepted
+const MAX_BODY: u64 = 64 * 1024 * 1024;
+
+/// A response to a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// HTTP status code
+    pub status: u16,
+    /// Value of the `Content-Type` header
+    pub content_type: &'static str,
+    /// Body of the response
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, text: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: text.into().into_bytes(),
+        }
+    }
+
+    fn rows(rows: impl Into<Vec<u8>>, json: bool) -> Self {
+        Self {
+            status: 200,
+            content_type: if json {
+                "application/x-ndjson"
+            } else {
+                "text/tab-separated-values; charset=utf-8"
+            },
+            body: rows.into(),
+        }
+    }
+
+    fn error(error: ParserError) -> Self {
+        Self::text(400, error_message(error))
+    }
+}
+
+/// A translation stored by the service
+struct Translation {
+    /// The USFX document, converted anew for each download
+    usfx: String,
+    /// Its verses, for lookups
+    verses: Vec<Verse>,
+}
+
+/// Conversion service holding the translations uploaded to it
+///
+/// With a data directory, uploads are saved there as `<name>.xml` and loaded again when the
+/// service starts.
+pub struct ConversionService {
+    translations: RwLock<BTreeMap<String, Translation>>,
+    data_dir: Option<PathBuf>,
+}
+
+impl ConversionService {
+    /// A service storing translations in memory, and in `data_dir` if given, loading the
+    /// `.xml` files already there
+    pub fn new(data_dir: Option<PathBuf>) -> Result<Self, ParserError> {
+        let mut translations = BTreeMap::new();
+        if let Some(dir) = &data_dir {
+            std::fs::create_dir_all(dir)?;
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                let name = path.file_stem().and_then(|name| name.to_str());
+                if let Some(name) = name.filter(|name| valid_name(name))
+                    && path.extension().is_some_and(|extension| extension == "xml")
+                {
+                    let usfx = std::fs::read_to_string(&path)?;
+                    translations.insert(name.to_string(), Translation::read(usfx)?);
+                }
+            }
+        }
+        Ok(Self {
+            translations: RwLock::new(translations),
+            data_dir,
+        })
+    }
+
+    /// Names of the stored translations, in order
+    pub fn translations(&self) -> Vec<String> {
+        self.read().keys().cloned().collect()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Translation>> {
+        self.translations
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Answer a request for `url` (path and query) with a body
+    pub fn handle(&self, method: &str, url: &str, body: &[u8]) -> Response {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let result = match (method, segments.as_slice()) {
+            ("POST", ["convert"]) => Self::convert(body, query),
+            ("GET", ["translations"]) => Ok(Response::text(200, self.translations().join("\n"))),
+            ("GET", ["translations", name]) => self.download(name, query),
+            ("PUT", ["translations", name]) => self.upload(name, body),
+            ("GET", ["verse", name, references]) => self.lookup(name, references, query),
+            _ => Ok(Response::text(
+                404,
+                format!("No such resource: {method} {path}"),
+            )),
+        };
+        result.unwrap_or_else(Response::error)
+    }
+
+    fn convert(body: &[u8], query: &str) -> Result<Response, ParserError> {
+        let options = options(query)?;
+        let usfx = std::str::from_utf8(body)
+            .map_err(|error| ParserError::ParseError(format!("Body is not UTF-8: {error}")))?;
+        let json = options.format.as_deref() == Some("json");
+        Ok(Response::rows(
+            convert_document(usfx, options.config()?)?,
+            json,
+        ))
+    }
+
+    fn download(&self, name: &str, query: &str) -> Result<Response, ParserError> {
+        let options = options(query)?;
+        let config = options.config()?;
+        let translations = self.read();
+        let Some(translation) = translations.get(name) else {
+            return Ok(Response::text(404, format!("No such translation: {name}")));
+        };
+        let json = options.format.as_deref() == Some("json");
+        Ok(Response::rows(
+            convert_document(&translation.usfx, config)?,
+            json,
+        ))
+    }
+
+    fn upload(&self, name: &str, body: &[u8]) -> Result<Response, ParserError> {
+        if !valid_name(name) {
+            return Ok(Response::text(
+                400,
+                format!("Invalid translation name: {name} (expected letters, digits, - and _)"),
+            ));
+        }
+        let usfx = String::from_utf8(body.to_vec())
+            .map_err(|error| ParserError::ParseError(format!("Body is not UTF-8: {error}")))?;
+        let translation = Translation::read(usfx)?;
+        if let Some(dir) = &self.data_dir {
+            std::fs::write(dir.join(format!("{name}.xml")), &translation.usfx)?;
+        }
+        let verses = translation.verses.len();
+        self.translations
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(name.to_string(), translation);
+        Ok(Response::text(
+            201,
+            format!("Stored {verses} verses of {name}"),
+        ))
+    }
+
+    fn lookup(&self, name: &str, references: &str, query: &str) -> Result<Response, ParserError> {
+        let mut options = options(query)?;
+        let json = options.format.get_or_insert_with(|| "json".to_string()) == "json";
+        let config = options.config()?;
+        let ranges = reference::parse(references)?;
+        let translations = self.read();
+        let Some(translation) = translations.get(name) else {
+            return Ok(Response::text(404, format!("No such translation: {name}")));
+        };
+        let mut writer = RowWriter::new(Vec::new(), &config);
+        let mut found = false;
+        for verse in &translation.verses {
+            let (Ok(chapter), Ok(number)) = (verse.chapter.parse(), verse.verse.parse()) else {
+                continue;
+            };
+            let reference = Reference {
+                book: verse.book.clone(),
+                chapter,
+                verse: Some(number),
+            };
+            if ranges.iter().any(|range| range.contains(&reference)) {
+                writer.write_verse(verse)?;
+                found = true;
+            }
+        }
+        writer.finish()?;
+        if !found {
+            return Ok(Response::text(
+                404,
+                format!("No verses of {name} at {references}"),
+            ));
+        }
+        Ok(Response::rows(writer.into_inner(), json))
+    }
+
+    /// Answer requests on `address` (such as `127.0.0.1:8080`) until the process ends, on one
+    /// thread per core
+    pub fn serve(&self, address: &str) -> Result<(), ParserError> {
+        let server = tiny_http::Server::http(address).map_err(|error| {
+            ParserError::ParseError(format!("Cannot listen on {address}: {error}"))
+        })?;
+        let workers = std::thread::available_parallelism().map_or(1, usize::from);
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    for request in server.incoming_requests() {
+                        if let Err(error) = self.respond(request) {
+                            eprintln!("error: {error}");
+                        }
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    fn respond(&self, mut request: tiny_http::Request) -> std::io::Result<()> {
+        let mut body = Vec::new();
+        request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_end(&mut body)?;
+        let response = if body.len() as u64 > MAX_BODY {
+            Response::text(413, format!("Body larger than {MAX_BODY} bytes"))
+        } else {
+            self.handle(request.method().as_str(), request.url(), &body)
+        };
+        let content_type = tiny_http::Header::from_bytes("Content-Type", response.content_type)
+            .expect("valid header");
+        request.respond(
+            tiny_http::Response::from_data(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type),
+        )
+    }
+}
+
+impl Translation {
+    fn read(usfx: String) -> Result<Self, ParserError> {
+        let input = std::io::Cursor::new(usfx.clone().into_bytes());
+        let parser = UsfxParser::from_reader(
+            input,
+            Box::new(std::io::sink()),
+            ConvertOptions::default().config()?,
+        );
+        let verses = parser.collect::<Result<_, _>>()?;
+        Ok(Self { usfx, verses })
+    }
+}
+
+/// Whether a translation name is safe to use as a file name
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// Conversion options from a query string; `columns` and `bookOrder` take comma-separated
+/// lists and flags take `true` or `false`
+fn options(query: &str) -> Result<ConvertOptions, ParserError> {
+    let pairs: Vec<_> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, "true"));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    ConvertOptions::from_pairs(
+        pairs
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    )
+}
+
+/// Decode `%XX` escapes and `+` for spaces in a URL component
+fn percent_decode(text: &str) -> String {
+    let hex = |byte: Option<&u8>| byte.and_then(|&byte| char::from(byte).to_digit(16));
+    let mut bytes = text.as_bytes().iter();
+    let mut decoded = Vec::with_capacity(text.len());
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' if let (Some(high), Some(low)) =
+                (hex(bytes.as_slice().first()), hex(bytes.as_slice().get(1))) =>
+            {
+                decoded.push((high * 16 + low) as u8);
+                bytes.nth(1);
+            }
+            byte => decoded.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KJV: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+    fn text(response: &Response) -> &str {
+        std::str::from_utf8(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_conversion_service() {
+        let service = ConversionService::new(None).unwrap();
+        let converted = service.handle("POST", "/convert?granularity=chapter", KJV.as_bytes());
+        assert_eq!(converted.status, 200);
+        assert!(text(&converted).starts_with("JHN\t11\tJesus wept. Then said"));
+        assert_eq!(
+            service.handle("POST", "/convert?format=yaml", b"").status,
+            400
+        );
+
+        let stored = service.handle("PUT", "/translations/kjv", KJV.as_bytes());
+        assert_eq!(
+            (stored.status, text(&stored)),
+            (201, "Stored 2 verses of kjv")
+        );
+        assert_eq!(service.handle("PUT", "/translations/..", b"").status, 400);
+        assert_eq!(text(&service.handle("GET", "/translations", b"")), "kjv");
+        let download = service.handle("GET", "/translations/kjv?columns=checksum", b"");
+        assert_eq!(text(&download).lines().count(), 2);
+
+        let verse = service.handle("GET", "/verse/kjv/JHN.11.35?divineName=uppercase", b"");
+        assert_eq!(verse.content_type, "application/x-ndjson");
+        assert!(text(&verse).contains(r#""text":"Jesus wept.""#));
+        let verse = service.handle("GET", "/verse/kjv/John%2011%3A35?format=tsv", b"");
+        assert_eq!(text(&verse), "JHN\t11\t35\tJesus wept.\n");
+        assert_eq!(service.handle("GET", "/verse/kjv/GEN.1.1", b"").status, 404);
+        assert_eq!(
+            service.handle("GET", "/verse/web/JHN.11.35", b"").status,
+            404
+        );
+        assert_eq!(
+            service.handle("DELETE", "/translations/kjv", b"").status,
+            404
+        );
+    }
+
+    #[test]
+    fn test_data_directory() {
+        let dir = std::env::temp_dir().join(format!("usfx_to_tsv_serve_{}", std::process::id()));
+        let service = ConversionService::new(Some(dir.clone())).unwrap();
+        service.handle("PUT", "/translations/kjv", KJV.as_bytes());
+        let reloaded = ConversionService::new(Some(dir.clone())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(reloaded.translations(), ["kjv"]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Converting documents into async writers
+//!
+//! [`convert`] parses and formats on a blocking thread and hands the output to the async side
+//! in chunks over a bounded channel. Each chunk is written, awaiting the writer until it is
+//! ready, before the next is taken, so a slow sink such as a network connection pauses the
+//! parser once `capacity` chunks are waiting rather than letting the output pile up in memory.
+//!
+//! ```no_run
+//! # async fn run(socket: impl tokio::io::AsyncWrite + Unpin) -> Result<(), usfx_to_tsv::ParserError> {
+//! use std::io::BufReader;
+//! use usfx_to_tsv::UsfxConfig;
+//! use usfx_to_tsv::stream::{DEFAULT_CAPACITY, convert};
+//!
+//! let input = BufReader::new(std::fs::File::open("engkjv_usfx.xml")?);
+//! convert(input, socket, UsfxConfig::default(), DEFAULT_CAPACITY).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ParseSummary, ParserError, UsfxConfig, UsfxParser};
+use std::io::{self, BufRead, Write};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Chunks of output held between the parser and the writer by default; the parser writes
+/// through an 8 KiB buffer, so this is 128 KiB
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// Output of the parser, sent to the async side a chunk at a time
+struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Blocks while the channel is full; a closed channel means the writer has failed
+        if self.0.blocking_send(buf.to_vec()).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the output writer has stopped",
+            ));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert a USFX document from `input` into `writer`, holding at most `capacity` chunks of
+/// output (at least one) between them
+///
+/// Must be called within a Tokio runtime, which runs the parser on its blocking thread pool.
+/// The output is the same as [`UsfxParser::parse`] writes for `config`. If the writer fails,
+/// the parser stops and the writer's error is returned.
+pub async fn convert<R, W>(
+    input: R,
+    mut writer: W,
+    config: UsfxConfig,
+    capacity: usize,
+) -> Result<ParseSummary, ParserError>
+where
+    R: BufRead + Send + 'static,
+    W: AsyncWrite + Unpin,
+{
+    let (sender, mut receiver) = mpsc::channel(capacity.max(1));
+    let parsing = tokio::task::spawn_blocking(move || {
+        UsfxParser::from_reader(input, Box::new(ChannelWriter(sender)), config).parse()
+    });
+    let mut written = Ok(());
+    while let Some(chunk) = receiver.recv().await {
+        written = writer.write_all(&chunk).await;
+        if written.is_err() {
+            break;
+        }
+    }
+    // Closing the channel stops a parser still writing
+    drop(receiver);
+    let summary = parsing
+        .await
+        .map_err(|error| ParserError::ParseError(format!("Parser failed: {error}")))?;
+    written?;
+    writer.flush().await?;
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::convert_document;
+
+    #[test]
+    fn test_async_convert() {
+        const XML: &str = r#"<usfx><book id="JHN"><c id="11"/><p><v id="35"/>Jesus wept.<ve/><v id="36"/>Then said the Jews, Behold how he loved him!<ve/></p></book></usfx>"#;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut output = Vec::new();
+            let config = crate::UsfxConfigBuilder::new()
+                .flush_every_n_rows(1)
+                .build();
+            convert(XML.as_bytes(), &mut output, config.clone(), 1)
+                .await
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                convert_document(XML, config).unwrap()
+            );
+
+            // A writer whose reader has gone away fails the conversion instead of hanging it
+            let (writer, reader) = tokio::io::duplex(16);
+            drop(reader);
+            let result = convert(XML.as_bytes(), writer, UsfxConfig::default(), 1).await;
+            assert!(matches!(result, Err(ParserError::FileError(_))));
+        });
+    }
+}
@@ -0,0 +1,154 @@
+//! Checks of element nesting and required attributes against the USFX schema rules
+//!
+//! The check is a separate pass over the document that needs no external validator. It covers
+//! the structure the converter relies on rather than the whole schema.
+
+use crate::{ParserError, Warning};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Elements that hold note content, inside which no verse, chapter or other note may start
+const NOTES: &[&[u8]] = &[b"f", b"x", b"fe"];
+
+/// Table cell elements, which belong inside a table row
+const CELLS: &[&[u8]] = &[b"th", b"thr", b"tc", b"tcr"];
+
+/// Validate the USFX file at `path`, returning the violations found
+pub fn validate_file<P: AsRef<Path>>(path: P) -> Result<Vec<Warning>, ParserError> {
+    validate(BufReader::new(File::open(path)?))
+}
+
+/// Validate a USFX document, returning the violations found in document order
+///
+/// Malformed XML ends the check with a final violation describing the error.
+pub fn validate<R: BufRead>(input: R) -> Result<Vec<Warning>, ParserError> {
+    let mut reader = Reader::from_reader(input);
+    let mut buffer = Vec::new();
+    let mut open: Vec<Vec<u8>> = Vec::new();
+    let mut violations = Vec::new();
+
+    loop {
+        buffer.clear();
+        let position = reader.buffer_position();
+        match reader.read_event_into(&mut buffer) {
+            Err(quick_xml::Error::Io(e)) => {
+                return Err(ParserError::XmlError(quick_xml::Error::Io(e)));
+            }
+            Err(e) => {
+                violations.push(Warning {
+                    position: reader.error_position(),
+                    message: e.to_string(),
+                });
+                break;
+            }
+            Ok(Event::Start(e)) => {
+                check_element(&e, &open, position, &mut violations);
+                open.push(e.name().as_ref().to_vec());
+            }
+            Ok(Event::Empty(e)) => check_element(&e, &open, position, &mut violations),
+            Ok(Event::End(_)) => {
+                open.pop();
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => (),
+        }
+    }
+    Ok(violations)
+}
+
+/// Check where an element starts and which attributes it has
+fn check_element(
+    e: &BytesStart<'_>,
+    open: &[Vec<u8>],
+    position: u64,
+    violations: &mut Vec<Warning>,
+) {
+    let name = e.name();
+    let name = name.as_ref();
+    let display = String::from_utf8_lossy(name);
+    let parent = open.last().map(Vec::as_slice);
+    let inside = |names: &[&[u8]]| open.iter().any(|o| names.contains(&o.as_slice()));
+    let mut violation = |message: String| violations.push(Warning { position, message });
+
+    match name {
+        b"usfx" if parent.is_some() => violation("<usfx> must be the root element".to_string()),
+        _ if open.is_empty() && name != b"usfx" => {
+            violation(format!("Root element is <{display}>, expected <usfx>"));
+        }
+        b"book" if parent != Some(b"usfx") => {
+            violation("<book> must be a child of <usfx>".to_string());
+        }
+        b"c" | b"v" | b"ve" if !inside(&[b"book"]) => {
+            violation(format!("<{display}> must be inside a <book>"));
+        }
+        b"c" | b"v" | b"ve" | b"f" | b"x" | b"fe" if inside(NOTES) => {
+            violation(format!("<{display}> must not be inside a note"));
+        }
+        b"c" | b"v" if inside(&[b"w"]) => {
+            violation(format!("<{display}> must not be inside a <w>"));
+        }
+        b"w" if inside(&[b"w"]) => violation("<w> must not be inside another <w>".to_string()),
+        b"tr" if parent != Some(b"table") => {
+            violation("<tr> must be a child of <table>".to_string());
+        }
+        _ if CELLS.contains(&name) && parent != Some(b"tr") => {
+            violation(format!("<{display}> must be a child of <tr>"));
+        }
+        _ => (),
+    }
+
+    if matches!(name, b"book" | b"c" | b"v") && !has_attribute(e, b"id") {
+        violation(format!("<{display}> is missing its id attribute"));
+    }
+}
+
+fn has_attribute(e: &BytesStart<'_>, name: &[u8]) -> bool {
+    e.attributes()
+        .flatten()
+        .any(|attr| attr.key.as_ref() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(xml: &str) -> Vec<String> {
+        validate(xml.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|w| w.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_document() {
+        let xml = r#"<usfx><book id="GEN"><c id="1"/><p><v id="1"/>In the <w s="H7225">beginning</w><f caller="+">Or <fq>when</fq></f><ve/></p>
+<table><tr><tc>A</tc></tr></table></book></usfx>"#;
+        assert!(messages(xml).is_empty());
+    }
+
+    #[test]
+    fn test_nesting_and_attributes() {
+        let xml = r#"<usfx><book><c id="1"/><p><v id="1"/>In<f caller="+"><v id="2"/>the</f><ve/><tc>A</tc></p></book><c id="2"/></usfx>"#;
+        assert_eq!(
+            messages(xml),
+            [
+                "<book> is missing its id attribute",
+                "<v> must not be inside a note",
+                "<tc> must be a child of <tr>",
+                "<c> must be inside a <book>",
+            ]
+        );
+        let violations = validate(xml.as_bytes()).unwrap();
+        assert_eq!(violations[1].position, 53);
+    }
+
+    #[test]
+    fn test_malformed_xml() {
+        let violations = messages(r#"<usfx><book id="GEN"><w>In</p></book></usfx>"#);
+        assert_eq!(violations.len(), 1);
+    }
+}
@@ -0,0 +1,416 @@
+//! Data model for verses extracted from USFX
+
+use crate::reference::ReferenceRange;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// A single verse extracted from a USFX file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Verse {
+    /// Book code (e.g. `GEN`)
+    pub book: String,
+    /// Chapter number
+    pub chapter: String,
+    /// Verse number (the first verse of a bridge)
+    pub verse: String,
+    /// Last verse number of a verse bridge (e.g. `18` for `17-18`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verse_end: Option<String>,
+    /// Alternate verse number (`<va>`), e.g. from another versification
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_verse: Option<String>,
+    /// Published verse number (`<vp>`) when it differs from the verse number, e.g. `1b`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_verse: Option<String>,
+    /// Verse part for translations that split verses (e.g. `a` for `1a`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subverse: Option<String>,
+    /// Verse text
+    pub text: String,
+    /// Major section heading (`<ms>`) in effect for the verse, with its range (`<mr>`) if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub major_section: Option<String>,
+    /// Style of the paragraph the verse starts in (e.g. `p`, `q2`, `li1`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paragraph: Option<String>,
+    /// Heading of the section (`<s>`) the verse belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    /// Number of section headings read in the book up to the verse's own, which
+    /// tells apart sections that share a heading
+    #[serde(skip)]
+    pub section_number: usize,
+    /// Parallel passage references (`<r>`) of the verse's section, as displayed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<String>,
+    /// Parallel passages parsed from `parallel`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parallel_targets: Vec<ReferenceRange>,
+    /// Speaker label (`<sp>`) in effect for the verse, as in Job or Song of Songs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// Published chapter label (`<cl>` or `<cp>`), e.g. `Psalm 23`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter_label: Option<String>,
+    /// Alternate chapter number (`<ca>`), e.g. the Septuagint numbering of a Psalm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_chapter: Option<String>,
+    /// Acrostic heading (`<qa>`) in effect for the verse, e.g. `Aleph` in Psalm 119
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acrostic: Option<String>,
+    /// Descriptive title (`<d>`) of the verse's chapter, such as a Psalm superscription
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Selah (`<qs>`) interjections, when extracted from the verse text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selah: Option<String>,
+    /// Inline USFX markup between the verse start and end milestones, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    /// Footnotes (`<f>`) attached to the verse, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub footnotes: Vec<Footnote>,
+    /// Cross references (`<x>`) attached to the verse, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cross_references: Vec<CrossReference>,
+    /// Poetic lines (`q`, `q1`..`q4`) of the verse, empty for prose
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub poetry: Vec<PoetryLine>,
+    /// Words marked with `<w>`, `<wg>`, `<wh>`, `<wa>` or `<wl>` and their attributes, when requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+    /// Text of the quotations (`<qt>`, e.g. Old Testament quotations in the New) in the verse
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quotations: Vec<String>,
+    /// Figures (`<fig>`) placed in or just before the verse, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub figures: Vec<Figure>,
+}
+
+/// A paragraph of a book's introduction (`<ip>`, `<is>`, `<imt>`, ...) or peripheral matter
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntroParagraph {
+    /// Book code (e.g. `GEN`)
+    pub book: String,
+    /// Paragraph style (e.g. `ip`, `is1`, `periph`)
+    pub style: String,
+    /// Paragraph text, without notes
+    pub text: String,
+}
+
+/// Descriptive information about a USFX document, collected while it is read
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Metadata {
+    /// Language code (`<languageCode>`), e.g. `eng`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
+    /// Attributes of the root `<usfx>` element
+    pub attributes: BTreeMap<String, String>,
+    /// Identification line (`<id>`) of each book, keyed by book code
+    pub ids: BTreeMap<String, String>,
+    /// Remarks (`<rem>`), in document order
+    pub remarks: Vec<Remark>,
+    /// Identification lines and remarks that state a copyright, e.g. `© 2001 Crossway`
+    pub copyright: Vec<String>,
+    /// Paragraphs of front matter and introductions that state a copyright or the terms of a
+    /// license, e.g. `This work is licensed under a Creative Commons license`
+    pub license: Vec<String>,
+}
+
+/// What a completed parse found in a document
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ParseSummary {
+    /// Names of each book, in document order
+    pub books: Vec<BookTitles>,
+}
+
+/// The names a book gives itself in its running header and table of contents entries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BookTitles {
+    /// Book code (e.g. `GEN`)
+    pub code: String,
+    /// Running header (`<h>`), e.g. `Genesis`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// Long table of contents name (`<toc level="1">`), e.g. `The First Book of Moses`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub long_name: Option<String>,
+    /// Short table of contents name (`<toc level="2">`), e.g. `Genesis`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+    /// Abbreviation (`<toc level="3">`), e.g. `Gen`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abbreviation: Option<String>,
+}
+
+/// A comment (`<rem>`) left in a USFX document
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Remark {
+    /// Book code of the book the remark appears in, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub book: Option<String>,
+    /// Remark text
+    pub text: String,
+}
+
+/// A word marked up with glossary, lexicon or other attributes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Word {
+    /// Text of the word as it appears in the verse
+    pub text: String,
+    /// The element's attributes, e.g. `s` (Strong's number), `l` (lemma) or `gloss`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Word {
+    /// First of the named attributes the word has
+    fn attribute(&self, names: &[&str]) -> Option<&str> {
+        names
+            .iter()
+            .find_map(|name| self.attributes.get(*name))
+            .map(String::as_str)
+    }
+
+    /// Lemma, from the USFX `l` attribute or the USFM 3 `lemma` attribute
+    pub fn lemma(&self) -> Option<&str> {
+        self.attribute(&["l", "lemma"])
+    }
+
+    /// Strong's number, from the USFX `s` attribute or the USFM 3 `strong` attribute
+    pub fn strongs(&self) -> Option<&str> {
+        self.attribute(&["s", "strong"])
+    }
+
+    /// Morphology code, from the `m`, `morph` or `x-morph` attribute
+    pub fn morph(&self) -> Option<&str> {
+        self.attribute(&["m", "morph", "x-morph"])
+    }
+
+    /// Gloss, from the `gloss` or `x-gloss` attribute
+    pub fn gloss(&self) -> Option<&str> {
+        self.attribute(&["gloss", "x-gloss"])
+    }
+}
+
+impl std::fmt::Display for Word {
+    /// Writes the text followed by each attribute, e.g. `God|s=H430`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)?;
+        for (name, value) in &self.attributes {
+            write!(f, "|{name}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// One line of poetry within a verse
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PoetryLine {
+    /// Indentation level, starting at 1
+    pub level: u8,
+    /// Text of the line
+    pub text: String,
+}
+
+/// An illustration (`<fig>`) with its caption
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Figure {
+    /// Caption (`<caption>`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub caption: String,
+    /// Image file, from the `src` attribute or the `<catalog>` entry
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub file: String,
+    /// Reference the figure illustrates (`<reference>`), e.g. `1.31`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub reference: String,
+}
+
+/// A footnote (`<f>`) split into its parts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Footnote {
+    /// Caller from the `caller` attribute (e.g. `+`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub caller: String,
+    /// Origin reference (`<fr>`), e.g. `1:1`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub reference: String,
+    /// Keyword (`<fk>`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub keyword: String,
+    /// Quotation from the verse (`<fq>`, `<fqa>`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub quotation: String,
+    /// Footnote text (`<ft>` and any text outside the other parts)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    /// Every part of the footnote in the order it was read, as the footnote reads
+    #[serde(skip)]
+    pub content: String,
+}
+
+/// A cross reference (`<x>`) split into its parts, with its targets resolved
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CrossReference {
+    /// Caller from the `caller` attribute (e.g. `-`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub caller: String,
+    /// Origin reference (`<xo>`), e.g. `1:1`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub origin: String,
+    /// Keyword (`<xk>`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub keyword: String,
+    /// Quotation (`<xq>`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub quotation: String,
+    /// Target references as displayed (`<xt>`), e.g. `John 1:1; Heb 11:3`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    /// Targets from `<ref tgt>` attributes, or parsed from the displayed text
+    pub targets: Vec<ReferenceRange>,
+}
+
+impl std::fmt::Display for CrossReference {
+    /// Writes the targets separated by `; `, or the displayed text if none were understood
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.targets.is_empty() {
+            return write!(f, "{}", self.text);
+        }
+        for (i, target) in self.targets.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{target}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Figure {
+    /// Writes the caption followed by the file in parentheses, e.g. `The Ark (ark.jpg)`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.caption.is_empty(), self.file.is_empty()) {
+            (_, true) => write!(f, "{}", self.caption),
+            (true, false) => write!(f, "({})", self.file),
+            (false, false) => write!(f, "{} ({})", self.caption, self.file),
+        }
+    }
+}
+
+impl std::fmt::Display for Footnote {
+    /// Writes the footnote as it reads or, for a footnote built from its parts alone, the
+    /// non-empty parts separated by spaces: reference, keyword, quotation, text
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.content.is_empty() {
+            return write!(f, "{}", self.content);
+        }
+        let parts = [&self.reference, &self.keyword, &self.quotation, &self.text];
+        let mut parts = parts.iter().filter(|part| !part.is_empty());
+        if let Some(first) = parts.next() {
+            write!(f, "{first}")?;
+        }
+        for part in parts {
+            write!(f, " {part}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Verse {
+    /// The verse numbers covered by a numeric verse bridge, or `None` if this is not one
+    pub fn bridged_verses(&self) -> Option<RangeInclusive<u32>> {
+        let start = self.verse.parse().ok()?;
+        let end = self.verse_end.as_deref()?.parse().ok()?;
+        (start < end).then_some(start..=end)
+    }
+
+    /// SHA-256 of the verse text with runs of whitespace collapsed to a single space,
+    /// as lowercase hex. Formatting-only changes between releases leave it unchanged.
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (i, word) in self.text.split_whitespace().enumerate() {
+            if i > 0 {
+                hasher.update(b" ");
+            }
+            hasher.update(word.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Append the content of a following verse, as when combining verses into one row
+    pub fn append(&mut self, other: &Verse) {
+        if !self.text.is_empty() && !other.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(&other.text);
+        if let (Some(raw), Some(other)) = (self.raw.as_mut(), other.raw.as_ref()) {
+            raw.push_str(other);
+        }
+        if let Some(other) = &other.selah {
+            let selah = self.selah.get_or_insert_default();
+            if !selah.is_empty() {
+                selah.push(' ');
+            }
+            selah.push_str(other);
+        }
+        self.footnotes.extend(other.footnotes.iter().cloned());
+        self.cross_references
+            .extend(other.cross_references.iter().cloned());
+        self.poetry.extend(other.poetry.iter().cloned());
+        self.figures.extend(other.figures.iter().cloned());
+        self.words.extend(other.words.iter().cloned());
+        self.quotations.extend(other.quotations.iter().cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_ignores_whitespace() {
+        let verse = Verse {
+            text: "Jesus wept.".to_string(),
+            ..Verse::default()
+        };
+        let spaced = Verse {
+            text: " Jesus \u{a0}wept.\n".to_string(),
+            ..Verse::default()
+        };
+        assert_eq!(verse.checksum(), spaced.checksum());
+        assert_eq!(
+            Verse::default().checksum(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_json_skips_empty_fields() {
+        let verse = Verse {
+            book: "GEN".to_string(),
+            chapter: "1".to_string(),
+            verse: "1".to_string(),
+            text: "In the beginning".to_string(),
+            footnotes: vec![Footnote {
+                caller: "+".to_string(),
+                reference: "1:1".to_string(),
+                text: "Or when God began".to_string(),
+                ..Footnote::default()
+            }],
+            ..Verse::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&verse).unwrap(),
+            r#"{"book":"GEN","chapter":"1","verse":"1","text":"In the beginning","footnotes":[{"caller":"+","reference":"1:1","text":"Or when God began"}]}"#
+        );
+        assert_eq!(verse.footnotes[0].to_string(), "1:1 Or when God began");
+    }
+}
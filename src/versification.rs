@@ -0,0 +1,901 @@
+//! Versification schemes and detection of verses missing from a text
+//!
+//! A versification gives the number of chapters in each book and the number of verses in each
+//! chapter. Comparing the verses of a converted text against one shows where verses are absent,
+//! either because the translation omits them (usually with a footnote on a neighbouring verse)
+//! or because they were lost during extraction.
+
+use crate::mapping::VersificationMap;
+use crate::{ParserError, Verse, canon};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Most verses a bridge is taken to cover, the length of Psalm 119, the longest chapter of any
+/// versification; verses a longer bridge claims past it are ignored
+pub const MAX_BRIDGE_VERSES: u32 = 176;
+
+/// Verses per chapter for each book of the King James Version (the Paratext `eng` scheme)
+const KJV: &[(&str, &[u32])] = &[
+    (
+        "GEN",
+        &[
+            31, 25, 24, 26, 32, 22, 24, 22, 29, 32, 32, 20, 18, 24, 21, 16, 27, 33, 38, 18, 34, 24,
+            20, 67, 34, 35, 46, 22, 35, 43, 55, 32, 20, 31, 29, 43, 36, 30, 23, 23, 57, 38, 34, 34,
+            28, 34, 31, 22, 33, 26,
+        ],
+    ),
+    (
+        "EXO",
+        &[
+            22, 25, 22, 31, 23, 30, 25, 32, 35, 29, 10, 51, 22, 31, 27, 36, 16, 27, 25, 26, 36, 31,
+            33, 18, 40, 37, 21, 43, 46, 38, 18, 35, 23, 35, 35, 38, 29, 31, 43, 38,
+        ],
+    ),
+    (
+        "LEV",
+        &[
+            17, 16, 17, 35, 19, 30, 38, 36, 24, 20, 47, 8, 59, 57, 33, 34, 16, 30, 37, 27, 24, 33,
+            44, 23, 55, 46, 34,
+        ],
+    ),
+    (
+        "NUM",
+        &[
+            54, 34, 51, 49, 31, 27, 89, 26, 23, 36, 35, 16, 33, 45, 41, 50, 13, 32, 22, 29, 35, 41,
+            30, 25, 18, 65, 23, 31, 40, 16, 54, 42, 56, 29, 34, 13,
+        ],
+    ),
+    (
+        "DEU",
+        &[
+            46, 37, 29, 49, 33, 25, 26, 20, 29, 22, 32, 32, 18, 29, 23, 22, 20, 22, 21, 20, 23, 30,
+            25, 22, 19, 19, 26, 68, 29, 20, 30, 52, 29, 12,
+        ],
+    ),
+    (
+        "JOS",
+        &[
+            18, 24, 17, 24, 15, 27, 26, 35, 27, 43, 23, 24, 33, 15, 63, 10, 18, 28, 51, 9, 45, 34,
+            16, 33,
+        ],
+    ),
+    (
+        "JDG",
+        &[
+            36, 23, 31, 24, 31, 40, 25, 35, 57, 18, 40, 15, 25, 20, 20, 31, 13, 31, 30, 48, 25,
+        ],
+    ),
+    ("RUT", &[22, 23, 18, 22]),
+    (
+        "1SA",
+        &[
+            28, 36, 21, 22, 12, 21, 17, 22, 27, 27, 15, 25, 23, 52, 35, 23, 58, 30, 24, 42, 15, 23,
+            29, 22, 44, 25, 12, 25, 11, 31, 13,
+        ],
+    ),
+    (
+        "2SA",
+        &[
+            27, 32, 39, 12, 25, 23, 29, 18, 13, 19, 27, 31, 39, 33, 37, 23, 29, 33, 43, 26, 22, 51,
+            39, 25,
+        ],
+    ),
+    (
+        "1KI",
+        &[
+            53, 46, 28, 34, 18, 38, 51, 66, 28, 29, 43, 33, 34, 31, 34, 34, 24, 46, 21, 43, 29, 53,
+        ],
+    ),
+    (
+        "2KI",
+        &[
+            18, 25, 27, 44, 27, 33, 20, 29, 37, 36, 21, 21, 25, 29, 38, 20, 41, 37, 37, 21, 26, 20,
+            37, 20, 30,
+        ],
+    ),
+    (
+        "1CH",
+        &[
+            54, 55, 24, 43, 26, 81, 40, 40, 44, 14, 47, 40, 14, 17, 29, 43, 27, 17, 19, 8, 30, 19,
+            32, 31, 31, 32, 34, 21, 30,
+        ],
+    ),
+    (
+        "2CH",
+        &[
+            17, 18, 17, 22, 14, 42, 22, 18, 31, 19, 23, 16, 22, 15, 19, 14, 19, 34, 11, 37, 20, 12,
+            21, 27, 28, 23, 9, 27, 36, 27, 21, 33, 25, 33, 27, 23,
+        ],
+    ),
+    ("EZR", &[11, 70, 13, 24, 17, 22, 28, 36, 15, 44]),
+    ("NEH", &[11, 20, 32, 23, 19, 19, 73, 18, 38, 39, 36, 47, 31]),
+    ("EST", &[22, 23, 15, 17, 14, 14, 10, 17, 32, 3]),
+    (
+        "JOB",
+        &[
+            22, 13, 26, 21, 27, 30, 21, 22, 35, 22, 20, 25, 28, 22, 35, 22, 16, 21, 29, 29, 34, 30,
+            17, 25, 6, 14, 23, 28, 25, 31, 40, 22, 33, 37, 16, 33, 24, 41, 30, 24, 34, 17,
+        ],
+    ),
+    (
+        "PSA",
+        &[
+            6, 12, 8, 8, 12, 10, 17, 9, 20, 18, 7, 8, 6, 7, 5, 11, 15, 50, 14, 9, 13, 31, 6, 10,
+            22, 12, 14, 9, 11, 12, 24, 11, 22, 22, 28, 12, 40, 22, 13, 17, 13, 11, 5, 26, 17, 11,
+            9, 14, 20, 23, 19, 9, 6, 7, 23, 13, 11, 11, 17, 12, 8, 12, 11, 10, 13, 20, 7, 35, 36,
+            5, 24, 20, 28, 23, 10, 12, 20, 72, 13, 19, 16, 8, 18, 12, 13, 17, 7, 18, 52, 17, 16,
+            15, 5, 23, 11, 13, 12, 9, 9, 5, 8, 28, 22, 35, 45, 48, 43, 13, 31, 7, 10, 10, 9, 8, 18,
+            19, 2, 29, 176, 7, 8, 9, 4, 8, 5, 6, 5, 6, 8, 8, 3, 18, 3, 3, 21, 26, 9, 8, 24, 13, 10,
+            7, 12, 15, 21, 10, 20, 14, 9, 6,
+        ],
+    ),
+    (
+        "PRO",
+        &[
+            33, 22, 35, 27, 23, 35, 27, 36, 18, 32, 31, 28, 25, 35, 33, 33, 28, 24, 29, 30, 31, 29,
+            35, 34, 28, 28, 27, 28, 27, 33, 31,
+        ],
+    ),
+    ("ECC", &[18, 26, 22, 16, 20, 12, 29, 17, 18, 20, 10, 14]),
+    ("SNG", &[17, 17, 11, 16, 16, 13, 13, 14]),
+    (
+        "ISA",
+        &[
+            31, 22, 26, 6, 30, 13, 25, 22, 21, 34, 16, 6, 22, 32, 9, 14, 14, 7, 25, 6, 17, 25, 18,
+            23, 12, 21, 13, 29, 24, 33, 9, 20, 24, 17, 10, 22, 38, 22, 8, 31, 29, 25, 28, 28, 25,
+            13, 15, 22, 26, 11, 23, 15, 12, 17, 13, 12, 21, 14, 21, 22, 11, 12, 19, 12, 25, 24,
+        ],
+    ),
+    (
+        "JER",
+        &[
+            19, 37, 25, 31, 31, 30, 34, 22, 26, 25, 23, 17, 27, 22, 21, 21, 27, 23, 15, 18, 14, 30,
+            40, 10, 38, 24, 22, 17, 32, 24, 40, 44, 26, 22, 19, 32, 21, 28, 18, 16, 18, 22, 13, 30,
+            5, 28, 7, 47, 39, 46, 64, 34,
+        ],
+    ),
+    ("LAM", &[22, 22, 66, 22, 22]),
+    (
+        "EZK",
+        &[
+            28, 10, 27, 17, 17, 14, 27, 18, 11, 22, 25, 28, 23, 23, 8, 63, 24, 32, 14, 49, 32, 31,
+            49, 27, 17, 21, 36, 26, 21, 26, 18, 32, 33, 31, 15, 38, 28, 23, 29, 49, 26, 20, 27, 31,
+            25, 24, 23, 35,
+        ],
+    ),
+    ("DAN", &[21, 49, 30, 37, 31, 28, 28, 27, 27, 21, 45, 13]),
+    (
+        "HOS",
+        &[11, 23, 5, 19, 15, 11, 16, 14, 17, 15, 12, 14, 16, 9],
+    ),
+    ("JOL", &[20, 32, 21]),
+    ("AMO", &[15, 16, 15, 13, 27, 14, 17, 14, 15]),
+    ("OBA", &[21]),
+    ("JON", &[17, 10, 10, 11]),
+    ("MIC", &[16, 13, 12, 13, 15, 16, 20]),
+    ("NAM", &[15, 13, 19]),
+    ("HAB", &[17, 20, 19]),
+    ("ZEP", &[18, 15, 20]),
+    ("HAG", &[15, 23]),
+    (
+        "ZEC",
+        &[21, 13, 10, 14, 11, 15, 14, 23, 17, 12, 17, 14, 9, 21],
+    ),
+    ("MAL", &[14, 17, 18, 6]),
+    (
+        "MAT",
+        &[
+            25, 23, 17, 25, 48, 34, 29, 34, 38, 42, 30, 50, 58, 36, 39, 28, 27, 35, 30, 34, 46, 46,
+            39, 51, 46, 75, 66, 20,
+        ],
+    ),
+    (
+        "MRK",
+        &[
+            45, 28, 35, 41, 43, 56, 37, 38, 50, 52, 33, 44, 37, 72, 47, 20,
+        ],
+    ),
+    (
+        "LUK",
+        &[
+            80, 52, 38, 44, 39, 49, 50, 56, 62, 42, 54, 59, 35, 35, 32, 31, 37, 43, 48, 47, 38, 71,
+            56, 53,
+        ],
+    ),
+    (
+        "JHN",
+        &[
+            51, 25, 36, 54, 47, 71, 53, 59, 41, 42, 57, 50, 38, 31, 27, 33, 26, 40, 42, 31, 25,
+        ],
+    ),
+    (
+        "ACT",
+        &[
+            26, 47, 26, 37, 42, 15, 60, 40, 43, 48, 30, 25, 52, 28, 41, 40, 34, 28, 41, 38, 40, 30,
+            35, 27, 27, 32, 44, 31,
+        ],
+    ),
+    (
+        "ROM",
+        &[
+            32, 29, 31, 25, 21, 23, 25, 39, 33, 21, 36, 21, 14, 23, 33, 27,
+        ],
+    ),
+    (
+        "1CO",
+        &[
+            31, 16, 23, 21, 13, 20, 40, 13, 27, 33, 34, 31, 13, 40, 58, 24,
+        ],
+    ),
+    ("2CO", &[24, 17, 18, 18, 21, 18, 16, 24, 15, 18, 33, 21, 14]),
+    ("GAL", &[24, 21, 29, 31, 26, 18]),
+    ("EPH", &[23, 22, 21, 32, 33, 24]),
+    ("PHP", &[30, 30, 21, 23]),
+    ("COL", &[29, 23, 25, 18]),
+    ("1TH", &[10, 20, 13, 18, 28]),
+    ("2TH", &[12, 17, 18]),
+    ("1TI", &[20, 15, 16, 16, 25, 21]),
+    ("2TI", &[18, 26, 17, 22]),
+    ("TIT", &[16, 15, 15]),
+    ("PHM", &[25]),
+    ("HEB", &[14, 18, 19, 16, 14, 20, 28, 13, 28, 39, 40, 29, 25]),
+    ("JAS", &[27, 26, 18, 17, 20]),
+    ("1PE", &[25, 25, 22, 19, 14]),
+    ("2PE", &[21, 22, 18]),
+    ("1JN", &[10, 29, 24, 21, 21]),
+    ("2JN", &[13]),
+    ("3JN", &[14]),
+    ("JUD", &[25]),
+    (
+        "REV",
+        &[
+            20, 29, 22, 11, 14, 17, 17, 13, 21, 11, 19, 17, 18, 20, 8, 21, 18, 24, 21, 15, 27, 21,
+        ],
+    ),
+];
+
+/// The chapter and verse structure of the books of a Bible
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Versification {
+    name: String,
+    books: Vec<(String, Vec<u32>)>,
+    mapping: VersificationMap,
+}
+
+/// A versification definition in JSON, as published alongside Paratext's `.vrs` files
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonVersification {
+    max_verses: BTreeMap<String, Vec<JsonCount>>,
+    #[serde(default)]
+    mapped_verses: BTreeMap<String, String>,
+}
+
+/// A verse count, which JSON definitions give as a number or a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonCount {
+    Number(u32),
+    Text(String),
+}
+
+impl Versification {
+    /// The versification of the King James Version, followed by most English translations
+    pub fn kjv() -> Self {
+        Self {
+            name: "kjv".to_string(),
+            books: KJV
+                .iter()
+                .map(|(book, verses)| (book.to_string(), verses.to_vec()))
+                .collect(),
+            mapping: VersificationMap::default(),
+        }
+    }
+
+    /// Load a versification from a Paratext `.vrs` file or, for a `.json` extension, a JSON
+    /// definition; the file name without its extension names it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ParserError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let mut versification = if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+        {
+            Self::from_json(&text)?
+        } else {
+            Self::from_vrs(&text)?
+        };
+        if let Some(name) = path.file_stem() {
+            versification.name = name.to_string_lossy().into_owned();
+        }
+        Ok(versification)
+    }
+
+    /// Parse a Paratext `.vrs` definition, whose lines such as `GEN 1:31 2:25 3:24` give the
+    /// verses of each chapter of a book and whose lines such as `GEN 31:55 = GEN 32:1` map its
+    /// verses onto the original versification
+    ///
+    /// Comments (`#`), excluded verses (`-GEN 1:5`) and verse segments (`*GEN 1:1,-,a,b`) are
+    /// skipped.
+    pub fn from_vrs(text: &str) -> Result<Self, ParserError> {
+        let mut books = Vec::new();
+        // Mapping lines keep their line numbers, for the mapping's error messages
+        let mut mappings = String::new();
+        for (number, line) in (1..).zip(text.lines()) {
+            let line = line.trim();
+            // `&` marks a mapping of several verses onto one
+            let line = line.strip_prefix('&').unwrap_or(line);
+            if line.is_empty() || line.starts_with(['#', '-', '*']) {
+                // Skipped
+            } else if line.contains('=') {
+                mappings.push_str(line);
+            } else {
+                books.push(parse_vrs_book(line).ok_or_else(|| {
+                    ParserError::ParseError(format!(
+                        "Invalid versification line {number}: {line} (expected BOOK 1:verses 2:verses ...)"
+                    ))
+                })?);
+            }
+            mappings.push('\n');
+        }
+        Ok(Self {
+            name: "custom".to_string(),
+            books,
+            mapping: VersificationMap::parse(&mappings)?,
+        })
+    }
+
+    /// Parse a JSON definition: an object whose `maxVerses` gives the verses of each chapter
+    /// by book (`{"GEN": [31, 25, ...]}`) and whose optional `mappedVerses` maps verses onto
+    /// the original versification (`{"GEN 31:55": "GEN 32:1"}`)
+    pub fn from_json(text: &str) -> Result<Self, ParserError> {
+        let invalid = |reason: String| {
+            ParserError::ParseError(format!("Invalid versification definition: {reason}"))
+        };
+        let definition: JsonVersification =
+            serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let mut books = Vec::new();
+        for (book, counts) in definition.max_verses {
+            let verses = counts
+                .into_iter()
+                .map(|count| match count {
+                    JsonCount::Number(count) => Some(count),
+                    JsonCount::Text(count) => count.trim().parse().ok(),
+                })
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(|| invalid(format!("verse counts of {book}")))?;
+            let book = canon::usfm_code(&book).map_or(book, str::to_string);
+            books.push((book, verses));
+        }
+        books.sort_by_key(|(book, _)| canon::book_number(book).unwrap_or(usize::MAX));
+        let mappings: String = definition
+            .mapped_verses
+            .iter()
+            .map(|(source, target)| format!("{source} = {target}\n"))
+            .collect();
+        Ok(Self {
+            name: "custom".to_string(),
+            books,
+            mapping: VersificationMap::parse(&mappings)?,
+        })
+    }
+
+    /// Name of the scheme (e.g. `kjv`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Book codes covered by the scheme, in its order
+    pub fn books(&self) -> impl Iterator<Item = &str> {
+        self.books.iter().map(|(book, _)| book.as_str())
+    }
+
+    /// Number of chapters in a book, or `None` if the scheme does not cover it
+    pub fn chapters(&self, book: &str) -> Option<u32> {
+        self.chapter_verses(book).map(|verses| verses.len() as u32)
+    }
+
+    /// Number of verses in a chapter, or `None` if the scheme does not have the chapter
+    pub fn verses(&self, book: &str, chapter: u32) -> Option<u32> {
+        let index = usize::try_from(chapter.checked_sub(1)?).ok()?;
+        self.chapter_verses(book)?.get(index).copied()
+    }
+
+    /// Mapping of the scheme's verses onto the original (Hebrew and Greek) versification, as
+    /// given by a loaded definition; empty for the built-in schemes
+    pub fn mapping(&self) -> &VersificationMap {
+        &self.mapping
+    }
+
+    fn chapter_verses(&self, book: &str) -> Option<&[u32]> {
+        self.books
+            .iter()
+            .find(|(code, _)| code == book)
+            .map(|(_, verses)| verses.as_slice())
+    }
+}
+
+impl FromStr for Versification {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kjv" | "eng" => Ok(Self::kjv()),
+            _ => Err(ParserError::ParseError(format!(
+                "Unknown versification: {s} (expected kjv or eng)"
+            ))),
+        }
+    }
+}
+
+/// Parse a `.vrs` line such as `GEN 1:31 2:25 3:24` into a book code and its verse counts,
+/// which must list the chapters in order
+fn parse_vrs_book(line: &str) -> Option<(String, Vec<u32>)> {
+    let mut parts = line.split_whitespace();
+    let book = parts.next()?;
+    let mut verses = Vec::new();
+    for part in parts {
+        let (chapter, count) = part.split_once(':')?;
+        if chapter.parse::<usize>().ok()? != verses.len() + 1 {
+            return None;
+        }
+        verses.push(count.parse().ok()?);
+    }
+    Some((canon::usfm_code(book).unwrap_or(book).to_string(), verses))
+}
+
+/// A verse the versification expects but the text does not have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingVerse {
+    /// Book code
+    pub book: String,
+    /// Chapter number
+    pub chapter: u32,
+    /// Verse number
+    pub verse: u32,
+    /// Whether a footnote on a neighbouring verse mentions the verse, which marks a deliberate
+    /// omission (such as `MRK 9:44` in translations following the critical text) rather than a
+    /// verse lost during extraction
+    pub footnoted: bool,
+}
+
+impl fmt::Display for MissingVerse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}:{} absent", self.book, self.chapter, self.verse)?;
+        if self.footnoted {
+            write!(f, " (footnoted omission)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A difference between the verse structure of a text and a versification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// A verse the versification expects but the text does not have
+    Missing(MissingVerse),
+    /// A verse the text has beyond the chapters or verses of the versification
+    Extra {
+        /// Book code
+        book: String,
+        /// Chapter number
+        chapter: u32,
+        /// Verse number
+        verse: u32,
+    },
+    /// Verses the text joins into one bridged verse, such as `PRO 30:17-18`
+    Bridged {
+        /// Book code
+        book: String,
+        /// Chapter number
+        chapter: u32,
+        /// First verse of the bridge
+        verse: u32,
+        /// Last verse of the bridge
+        verse_end: u32,
+    },
+}
+
+impl Difference {
+    /// Chapter and verse where the difference starts
+    fn position(&self) -> (u32, u32) {
+        match self {
+            Self::Missing(missing) => (missing.chapter, missing.verse),
+            Self::Extra { chapter, verse, .. } | Self::Bridged { chapter, verse, .. } => {
+                (*chapter, *verse)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    /// One line of a diff against the versification: `-` for missing, `+` for extra and `~`
+    /// for bridged verses
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(missing) => {
+                write!(
+                    f,
+                    "- {} {}:{} missing",
+                    missing.book, missing.chapter, missing.verse
+                )?;
+                if missing.footnoted {
+                    write!(f, " (footnoted omission)")?;
+                }
+                Ok(())
+            }
+            Self::Extra {
+                book,
+                chapter,
+                verse,
+            } => write!(f, "+ {book} {chapter}:{verse} extra"),
+            Self::Bridged {
+                book,
+                chapter,
+                verse,
+                verse_end,
+            } => write!(f, "~ {book} {chapter}:{verse}-{verse_end} bridged"),
+        }
+    }
+}
+
+/// The verses of one chapter present in a text
+#[derive(Debug, Default)]
+struct ChapterCoverage {
+    verses: BTreeSet<u32>,
+    /// Last verse of each bridge, by its first verse
+    bridges: BTreeMap<u32, u32>,
+    /// Parts (`a`, `b`) read of each verse split into subverses, with `""` if the verse was
+    /// also read whole
+    parts: BTreeMap<u32, BTreeSet<String>>,
+    /// Verse numbers mentioned by the footnotes of each verse that has footnotes
+    mentions: BTreeMap<u32, BTreeSet<u32>>,
+}
+
+/// Record of the verses present in a text, for comparison against a versification
+#[derive(Debug, Default)]
+pub struct Coverage {
+    books: BTreeMap<String, BTreeMap<u32, ChapterCoverage>>,
+}
+
+impl Coverage {
+    /// Whether any verse of `verse` (or of its bridge) has already been recorded; a subverse
+    /// part repeats only the same part or the whole verse
+    pub fn contains(&self, verse: &Verse) -> bool {
+        let Some((chapter, verses)) = verse_numbers(verse) else {
+            return false;
+        };
+        let Some(coverage) = self
+            .books
+            .get(&verse.book)
+            .and_then(|chapters| chapters.get(&chapter))
+        else {
+            return false;
+        };
+        match verse.subverse.as_deref() {
+            Some(part) if coverage.verses.contains(verses.start()) => coverage
+                .parts
+                .get(verses.start())
+                .is_none_or(|parts| parts.contains("") || parts.contains(part)),
+            Some(_) => false,
+            None => coverage.verses.range(verses).next().is_some(),
+        }
+    }
+
+    /// Record a verse (or every verse of a bridge) as present
+    pub fn add(&mut self, verse: &Verse) {
+        let Some((chapter, verses)) = verse_numbers(verse) else {
+            return;
+        };
+        let (first, last) = (*verses.start(), *verses.end());
+        let coverage = self
+            .books
+            .entry(verse.book.clone())
+            .or_default()
+            .entry(chapter)
+            .or_default();
+        for number in first..=last {
+            // A verse read whole before its first part is recorded as a part of its own
+            let part = verse.subverse.as_deref().filter(|_| first == last);
+            let whole = coverage.verses.contains(&number) && !coverage.parts.contains_key(&number);
+            if let Some(parts) = coverage.parts.get_mut(&number) {
+                parts.insert(part.unwrap_or_default().to_string());
+            } else if let Some(part) = part {
+                let parts = coverage.parts.entry(number).or_default();
+                parts.insert(part.to_string());
+                if whole {
+                    parts.insert(String::new());
+                }
+            }
+        }
+        coverage.verses.extend(first..=last);
+        if last > first {
+            coverage.bridges.insert(first, last);
+        }
+        // A bridge is the neighbour of the verses on both sides of it
+        for footnote in &verse.footnotes {
+            for text in [&footnote.reference, &footnote.quotation, &footnote.text] {
+                let mentioned = mentioned_verses(text, chapter);
+                for key in [first, last] {
+                    coverage.mentions.entry(key).or_default().extend(&mentioned);
+                }
+            }
+        }
+    }
+
+    /// Number of verses present in each chapter of a book, in chapter order
+    pub fn verse_counts(&self, book: &str) -> BTreeMap<u32, usize> {
+        self.books
+            .get(book)
+            .into_iter()
+            .flatten()
+            .map(|(chapter, coverage)| (*chapter, coverage.verses.len()))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// Verses of the books present in the text that the versification expects but the text
+    /// lacks, in versification order
+    pub fn missing(&self, versification: &Versification) -> Vec<MissingVerse> {
+        let mut missing = Vec::new();
+        for (book, chapters) in &versification.books {
+            let Some(present) = self.books.get(book) else {
+                continue;
+            };
+            for (chapter, &count) in (1..).zip(chapters) {
+                let coverage = present.get(&chapter);
+                for verse in 1..=count {
+                    if coverage.is_some_and(|c| c.verses.contains(&verse)) {
+                        continue;
+                    }
+                    missing.push(MissingVerse {
+                        book: book.clone(),
+                        chapter,
+                        verse,
+                        footnoted: coverage.is_some_and(|c| c.footnoted(verse)),
+                    });
+                }
+            }
+        }
+        missing
+    }
+
+    /// Differences between the verse structure of the books present in the text and the
+    /// versification, in versification order; books the versification does not cover are
+    /// skipped
+    pub fn differences(&self, versification: &Versification) -> Vec<Difference> {
+        let mut differences: Vec<Difference> = self
+            .missing(versification)
+            .into_iter()
+            .map(Difference::Missing)
+            .collect();
+        for (book, expected) in &versification.books {
+            let Some(present) = self.books.get(book) else {
+                continue;
+            };
+            for (&chapter, coverage) in present {
+                let count = usize::try_from(chapter)
+                    .ok()
+                    .and_then(|chapter| expected.get(chapter.checked_sub(1)?))
+                    .copied()
+                    .unwrap_or(0);
+                for &verse in coverage.verses.range(count + 1..) {
+                    differences.push(Difference::Extra {
+                        book: book.clone(),
+                        chapter,
+                        verse,
+                    });
+                }
+                for (&verse, &verse_end) in &coverage.bridges {
+                    differences.push(Difference::Bridged {
+                        book: book.clone(),
+                        chapter,
+                        verse,
+                        verse_end,
+                    });
+                }
+            }
+        }
+        // Stable, so each book stays in versification order with its differences by verse
+        let order = |book: &str| versification.books().position(|code| code == book);
+        differences.sort_by_key(|difference| {
+            let book = match difference {
+                Difference::Missing(missing) => &missing.book,
+                Difference::Extra { book, .. } | Difference::Bridged { book, .. } => book,
+            };
+            (order(book), difference.position())
+        });
+        differences
+    }
+}
+
+impl ChapterCoverage {
+    /// Whether the nearest verse before or after `verse` has a footnote mentioning it
+    fn footnoted(&self, verse: u32) -> bool {
+        let before = self.verses.range(..verse).next_back();
+        let after = self.verses.range(verse..).next();
+        [before, after]
+            .into_iter()
+            .flatten()
+            .any(|v| self.mentions.get(v).is_some_and(|m| m.contains(&verse)))
+    }
+}
+
+/// The chapter of a verse and the verse numbers it covers, if they are numeric, with a bridge
+/// cut short at [`MAX_BRIDGE_VERSES`]
+fn verse_numbers(verse: &Verse) -> Option<(u32, RangeInclusive<u32>)> {
+    let chapter = verse.chapter.parse().ok()?;
+    let first = verse.verse.parse().ok()?;
+    let last = verse
+        .verse_end
+        .as_deref()
+        .and_then(|end| end.parse().ok())
+        .map_or(first, |end: u32| {
+            end.clamp(first, first.saturating_add(MAX_BRIDGE_VERSES - 1))
+        });
+    Some((chapter, first..=last))
+}
+
+/// Verse numbers of `chapter` mentioned in note text, as `44`, `9:44` or `9:44-46`
+fn mentioned_verses(text: &str, chapter: u32) -> Vec<u32> {
+    text.split(|c: char| !c.is_ascii_digit() && c != ':')
+        .filter_map(|token| match token.split_once(':') {
+            Some((c, v)) if c.parse() == Ok(chapter) => v.parse().ok(),
+            Some(_) => None,
+            None => token.parse().ok(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Footnote;
+
+    fn verse(book: &str, chapter: &str, verse: &str) -> Verse {
+        Verse {
+            book: book.to_string(),
+            chapter: chapter.to_string(),
+            verse: verse.to_string(),
+            ..Verse::default()
+        }
+    }
+
+    #[test]
+    fn test_kjv_structure() {
+        let kjv = Versification::kjv();
+        assert_eq!(kjv.books().count(), 66);
+        assert_eq!(kjv.chapters("PSA"), Some(150));
+        assert_eq!(kjv.verses("PSA", 119), Some(176));
+        assert_eq!(kjv.verses("3JN", 1), Some(14));
+        assert_eq!(kjv.verses("GEN", 51), None);
+        let total: u32 = kjv
+            .books()
+            .map(|book| {
+                (1..=kjv.chapters(book).unwrap())
+                    .map(|c| kjv.verses(book, c).unwrap())
+                    .sum::<u32>()
+            })
+            .sum();
+        assert_eq!(total, 31102);
+    }
+
+    #[test]
+    fn test_missing_verses() {
+        let mut coverage = Coverage::default();
+        for number in (1..=43).chain([45, 47]) {
+            coverage.add(&verse("MRK", "9", &number.to_string()));
+        }
+        coverage.add(&Verse {
+            verse_end: Some("50".to_string()),
+            ..verse("MRK", "9", "48")
+        });
+        coverage.add(&Verse {
+            footnotes: vec![Footnote {
+                reference: "9:45".to_string(),
+                text: "Some manuscripts add verse 46, which is identical to 9:44.".to_string(),
+                ..Footnote::default()
+            }],
+            ..verse("MRK", "9", "45")
+        });
+
+        let missing: Vec<String> = coverage
+            .missing(&Versification::kjv())
+            .iter()
+            .filter(|missing| missing.chapter == 9)
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            missing,
+            [
+                "MRK 9:44 absent (footnoted omission)",
+                "MRK 9:46 absent (footnoted omission)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verse_counts() {
+        let mut coverage = Coverage::default();
+        coverage.add(&verse("JUD", "1", "1"));
+        coverage.add(&Verse {
+            verse_end: Some("4".to_string()),
+            ..verse("JUD", "1", "2")
+        });
+        coverage.add(&verse("JUD", "1", "4"));
+        assert_eq!(coverage.verse_counts("JUD"), BTreeMap::from([(1, 4)]));
+        assert!(coverage.verse_counts("GEN").is_empty());
+    }
+
+    #[test]
+    fn test_missing_chapters_and_unfootnoted_verses() {
+        let mut coverage = Coverage::default();
+        coverage.add(&verse("OBA", "1", "1"));
+        let missing = coverage.missing(&Versification::kjv());
+        assert_eq!(missing.len(), 20);
+        assert_eq!(missing[0].to_string(), "OBA 1:2 absent");
+    }
+
+    #[test]
+    fn test_differences() {
+        let mut coverage = Coverage::default();
+        for number in 1..=16 {
+            coverage.add(&verse("PRO", "30", &number.to_string()));
+        }
+        coverage.add(&Verse {
+            verse_end: Some("18".to_string()),
+            ..verse("PRO", "30", "17")
+        });
+        for number in (19..=32).chain([34, 35]) {
+            coverage.add(&verse("PRO", "30", &number.to_string()));
+        }
+        for number in 1..=31 {
+            coverage.add(&verse("PRO", "31", &number.to_string()));
+        }
+        coverage.add(&verse("PRO", "32", "1"));
+
+        let differences: Vec<String> = coverage
+            .differences(&Versification::kjv())
+            .iter()
+            .filter(|difference| difference.position().0 >= 30)
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                "~ PRO 30:17-18 bridged",
+                "- PRO 30:33 missing",
+                "+ PRO 30:34 extra",
+                "+ PRO 30:35 extra",
+                "+ PRO 32:1 extra"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_versification_files() {
+        const VRS: &str = "# Versification  \"Custom\"\n\
+            MAL 1:14 2:17 3:18 4:6\n\
+            OBA 1:21\n\
+            -OBA 1:21\n\
+            MAL 4:1-6 = MAL 3:19-24\n";
+        let vrs = Versification::from_vrs(VRS).unwrap();
+        assert_eq!(vrs.books().collect::<Vec<_>>(), ["MAL", "OBA"]);
+        assert_eq!(vrs.verses("MAL", 4), Some(6));
+        assert_eq!(vrs.mapping().map("MAL", 4, 2), ("MAL".to_string(), 3, 20));
+        assert!(Versification::from_vrs("MAL 1:14 3:18").is_err());
+        assert!(Versification::from_vrs("MAL 4:1-6 = MAL 3:19-23").is_err());
+
+        let json = Versification::from_json(
+            r#"{"maxVerses": {"OBA": ["21"], "MAL": [14, 17, 24]}, "mappedVerses": {"MAL 3:19-24": "MAL 3:19-24"}}"#,
+        )
+        .unwrap();
+        assert_eq!(json.books().collect::<Vec<_>>(), ["OBA", "MAL"]);
+        assert_eq!(json.verses("MAL", 3), Some(24));
+        assert!(Versification::from_json(r#"{"maxVerses": {"OBA": ["x"]}}"#).is_err());
+    }
+}
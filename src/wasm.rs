@@ -0,0 +1,29 @@
+//! JavaScript API for converting documents in the browser, built to WebAssembly with
+//! `wasm-bindgen`
+//!
+//! ```js
+//! import init, { convert } from "./pkg/usfx_to_tsv.js";
+//!
+//! await init();
+//! const tsv = convert(usfx, { granularity: "chapter", columns: ["checksum"] });
+//! ```
+
+use crate::ParserError;
+use crate::options::{ConvertOptions, convert_document, error_message};
+use wasm_bindgen::prelude::*;
+
+fn message(error: ParserError) -> JsError {
+    JsError::new(&error_message(error))
+}
+
+/// Convert a USFX document to TSV (or JSON Lines with `{ format: "json" }`); `options` is an
+/// object of [`ConvertOptions`], or `undefined` for the defaults
+#[wasm_bindgen]
+pub fn convert(usfx: &str, options: JsValue) -> Result<String, JsError> {
+    let options: ConvertOptions = if options.is_undefined() || options.is_null() {
+        ConvertOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+    convert_document(usfx, options.config().map_err(message)?).map_err(message)
+}
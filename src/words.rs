@@ -0,0 +1,610 @@
+//! Word-level statistics of the verse text, for corpus work
+
+use crate::reference::Reference;
+use crate::{OutputFormat, ParserError, Verse};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+/// How verse text is split into tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tokenizer {
+    /// Lowercase each token, so `The` and `the` count as one (default: false)
+    pub lowercase: bool,
+    /// Strip punctuation from both ends of each token and drop tokens of punctuation only, so
+    /// `beginning,` counts as `beginning` (default: false)
+    pub strip_punctuation: bool,
+}
+
+impl Tokenizer {
+    /// The tokens of a text, which are separated by whitespace
+    pub fn tokens<'a>(&self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        let Self {
+            lowercase,
+            strip_punctuation,
+        } = *self;
+        text.split_whitespace().filter_map(move |token| {
+            let token = if strip_punctuation {
+                token.trim_matches(is_punctuation)
+            } else {
+                token
+            };
+            match token {
+                "" => None,
+                _ if lowercase => Some(token.to_lowercase()),
+                _ => Some(token.to_string()),
+            }
+        })
+    }
+}
+
+/// Whether a character is punctuation: ASCII punctuation, general and CJK punctuation, and the
+/// punctuation of the scripts most Bibles are written in
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '\u{a1}' | '\u{a7}' | '\u{ab}' | '\u{b6}' | '\u{b7}' | '\u{bb}' | '\u{bf}'
+                | '\u{37e}' | '\u{387}' // Greek question mark and ano teleia
+                | '\u{55a}'..='\u{55f}' | '\u{589}' // Armenian
+                | '\u{5be}' | '\u{5c0}' | '\u{5c3}' | '\u{5c6}' | '\u{5f3}' | '\u{5f4}' // Hebrew
+                | '\u{60c}' | '\u{61b}' | '\u{61f}' | '\u{66a}'..='\u{66d}' | '\u{6d4}' // Arabic
+                | '\u{964}' | '\u{965}' | '\u{970}' // Devanagari
+                | '\u{1361}'..='\u{1368}' // Ethiopic
+                | '\u{2010}'..='\u{2027}' | '\u{2030}'..='\u{205e}'
+                | '\u{3001}'..='\u{3003}' | '\u{3008}'..='\u{3011}'
+                | '\u{ff01}'..='\u{ff0f}' | '\u{ff1a}'..='\u{ff1f}'
+        )
+}
+
+/// Estimate the number of tokens a byte-pair encoding such as GPT's splits a text into: one per
+/// four bytes of each run of letters, one per three digits and one per punctuation mark
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut letters = 0usize;
+    let mut digits = 0usize;
+    for c in text.chars() {
+        if c.is_numeric() {
+            digits += 1;
+            tokens += letters.div_ceil(4);
+            letters = 0;
+        } else if c.is_whitespace() || is_punctuation(c) {
+            tokens += letters.div_ceil(4) + digits.div_ceil(3) + usize::from(!c.is_whitespace());
+            letters = 0;
+            digits = 0;
+        } else {
+            letters += c.len_utf8();
+            tokens += digits.div_ceil(3);
+            digits = 0;
+        }
+    }
+    tokens + letters.div_ceil(4) + digits.div_ceil(3)
+}
+
+/// Parse n-gram sizes such as `2..4` (two to four tokens, inclusive) or `3`
+pub fn parse_ngram_sizes(text: &str) -> Result<RangeInclusive<usize>, ParserError> {
+    let invalid = || {
+        ParserError::ParseError(format!(
+            "Invalid n-gram sizes: {text} (expected a size such as 2 or a range such as 2..4)"
+        ))
+    };
+    let (first, last) = text.split_once("..").unwrap_or((text, text));
+    let last = last.strip_prefix('=').unwrap_or(last);
+    let first: usize = first.trim().parse().map_err(|_e| invalid())?;
+    let last: usize = last.trim().parse().map_err(|_e| invalid())?;
+    if first == 0 || last < first {
+        return Err(invalid());
+    }
+    Ok(first..=last)
+}
+
+/// Number of occurrences of each token, or each n-gram of tokens, of the verse text
+///
+/// N-grams run on from one verse to the next within a book, but not across books.
+#[derive(Debug, Clone)]
+pub struct Frequencies {
+    tokenizer: Tokenizer,
+    sizes: RangeInclusive<usize>,
+    per_book: bool,
+    /// The last tokens read in the current book, as many as the longest n-gram
+    window: VecDeque<String>,
+    book: Option<String>,
+    /// Counts of each book when counting per book, or of the whole text under an empty book
+    counts: Vec<(String, HashMap<String, usize>)>,
+}
+
+impl Default for Frequencies {
+    fn default() -> Self {
+        Self::new(Tokenizer::default())
+    }
+}
+
+impl Frequencies {
+    /// Empty counts of the single tokens produced by `tokenizer`, over the whole text
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer,
+            sizes: 1..=1,
+            per_book: false,
+            window: VecDeque::new(),
+            book: None,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Count n-grams of these sizes, their tokens joined by spaces, instead of single tokens
+    #[must_use]
+    pub fn ngrams(mut self, sizes: RangeInclusive<usize>) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    /// Count each book separately
+    #[must_use]
+    pub fn per_book(mut self, per_book: bool) -> Self {
+        self.per_book = per_book;
+        self
+    }
+
+    /// Count the tokens or n-grams of a verse's text
+    pub fn add(&mut self, verse: &Verse) {
+        if self.book.as_deref() != Some(verse.book.as_str()) {
+            self.book = Some(verse.book.clone());
+            self.window.clear();
+        }
+        let book = if self.per_book {
+            verse.book.as_str()
+        } else {
+            ""
+        };
+        let index = if let Some(index) = self.counts.iter().position(|(code, _)| code == book) {
+            index
+        } else {
+            self.counts.push((book.to_string(), HashMap::new()));
+            self.counts.len() - 1
+        };
+        let counts = &mut self.counts[index].1;
+        for token in self.tokenizer.tokens(&verse.text) {
+            if self.window.len() == *self.sizes.end() {
+                self.window.pop_front();
+            }
+            self.window.push_back(token);
+            for size in self.sizes.clone() {
+                let Some(start) = self.window.len().checked_sub(size) else {
+                    break;
+                };
+                let ngram = self
+                    .window
+                    .range(start..)
+                    .fold(String::new(), |mut ngram, token| {
+                        if !ngram.is_empty() {
+                            ngram.push(' ');
+                        }
+                        ngram.push_str(token);
+                        ngram
+                    });
+                *counts.entry(ngram).or_default() += 1;
+            }
+        }
+    }
+
+    /// Each token or n-gram with its count, the most frequent first and ties in token order,
+    /// after the book it was counted in (empty unless counting per book); books are in the
+    /// order read
+    pub fn counts(&self) -> Vec<(&str, &str, usize)> {
+        let mut rows = Vec::new();
+        for (book, counts) in &self.counts {
+            let mut counts: Vec<(&str, &str, usize)> = counts
+                .iter()
+                .map(|(token, count)| (book.as_str(), token.as_str(), *count))
+                .collect();
+            counts.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(b.1)));
+            rows.extend(counts);
+        }
+        rows
+    }
+
+    /// Write the counts as TSV rows of token (or n-gram) and count, the most frequent first,
+    /// each starting with the book when counting per book
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> Result<(), ParserError> {
+        for (book, token, count) in self.counts() {
+            if self.per_book {
+                write!(writer, "{book}\t")?;
+            }
+            writeln!(writer, "{token}\t{count}")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The references where each token of the verse text occurs
+#[derive(Debug, Clone, Default)]
+pub struct Concordance {
+    tokenizer: Tokenizer,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// The occurrences of one token in a concordance
+#[derive(Debug, Clone, Default, Serialize)]
+struct Entry {
+    count: usize,
+    references: Vec<Reference>,
+}
+
+/// A concordance entry as written in JSON
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    word: &'a str,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+impl Concordance {
+    /// An empty concordance of the tokens produced by `tokenizer`
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Record the tokens of a verse's text; a verse is listed once for a token however often
+    /// the token occurs in it
+    pub fn add(&mut self, verse: &Verse) {
+        let reference = Reference {
+            book: verse.book.clone(),
+            chapter: verse.chapter.parse().unwrap_or(0),
+            verse: verse.verse.parse().ok(),
+        };
+        for token in self.tokenizer.tokens(&verse.text) {
+            let entry = self.entries.entry(token).or_default();
+            entry.count += 1;
+            if entry.references.last() != Some(&reference) {
+                entry.references.push(reference.clone());
+            }
+        }
+    }
+
+    /// The references of a token, in the order read
+    pub fn references(&self, token: &str) -> &[Reference] {
+        self.entries
+            .get(token)
+            .map_or(&[], |entry| entry.references.as_slice())
+    }
+
+    /// Write the tokens occurring at least `min_count` times, in token order: as TSV rows of
+    /// token, count and `bcv` references separated by `, `, or as a JSON array of objects with
+    /// `word`, `count` and `references`
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        format: OutputFormat,
+        min_count: usize,
+    ) -> Result<(), ParserError> {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.count >= min_count);
+        match format {
+            OutputFormat::Tsv => {
+                for (word, entry) in entries {
+                    let references: Vec<String> =
+                        entry.references.iter().map(ToString::to_string).collect();
+                    writeln!(writer, "{word}\t{}\t{}", entry.count, references.join(", "))?;
+                }
+            }
+            OutputFormat::Json => {
+                let entries: Vec<JsonEntry<'_>> = entries
+                    .map(|(word, entry)| JsonEntry { word, entry })
+                    .collect();
+                serde_json::to_writer_pretty(&mut writer, &entries)
+                    .map_err(|e| ParserError::ParseError(e.to_string()))?;
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Verse, token and vocabulary counts of each book, or each chapter, gathered in one pass
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    tokenizer: Tokenizer,
+    per_chapter: bool,
+    groups: Vec<Tally>,
+}
+
+/// Counts of one book or chapter
+#[derive(Debug, Clone)]
+struct Tally {
+    book: String,
+    /// Chapter, when counting per chapter
+    chapter: Option<String>,
+    verses: usize,
+    counts: HashMap<String, usize>,
+}
+
+/// Statistics of one book or chapter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupStatistics<'a> {
+    /// Book code
+    pub book: &'a str,
+    /// Chapter, when counting per chapter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<&'a str>,
+    /// Number of verses
+    pub verses: usize,
+    /// Number of tokens
+    pub tokens: usize,
+    /// Mean number of tokens per verse
+    pub mean_verse_length: f64,
+    /// Number of distinct tokens
+    pub vocabulary: usize,
+    /// Number of tokens occurring exactly once
+    pub hapax_legomena: usize,
+    /// Vocabulary size divided by the number of tokens
+    pub type_token_ratio: f64,
+}
+
+impl Statistics {
+    /// Empty statistics of the tokens produced by `tokenizer`, per book
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer,
+            ..Self::default()
+        }
+    }
+
+    /// Gather statistics for each chapter rather than each book
+    #[must_use]
+    pub fn per_chapter(mut self, per_chapter: bool) -> Self {
+        self.per_chapter = per_chapter;
+        self
+    }
+
+    /// Count a verse and the tokens of its text
+    pub fn add(&mut self, verse: &Verse) {
+        let chapter = self.per_chapter.then_some(verse.chapter.as_str());
+        let index = if let Some(index) = self
+            .groups
+            .iter()
+            .rposition(|group| group.book == verse.book && group.chapter.as_deref() == chapter)
+        {
+            index
+        } else {
+            self.groups.push(Tally {
+                book: verse.book.clone(),
+                chapter: chapter.map(str::to_string),
+                verses: 0,
+                counts: HashMap::new(),
+            });
+            self.groups.len() - 1
+        };
+        let group = &mut self.groups[index];
+        group.verses += 1;
+        for token in self.tokenizer.tokens(&verse.text) {
+            *group.counts.entry(token).or_default() += 1;
+        }
+    }
+
+    /// Statistics of each book or chapter, in the order read
+    pub fn groups(&self) -> Vec<GroupStatistics<'_>> {
+        self.groups
+            .iter()
+            .map(|group| {
+                let counts = &group.counts;
+                let tokens: usize = counts.values().sum();
+                let ratio = |count: usize, total: usize| {
+                    if total == 0 {
+                        0.0
+                    } else {
+                        count as f64 / total as f64
+                    }
+                };
+                GroupStatistics {
+                    book: &group.book,
+                    chapter: group.chapter.as_deref(),
+                    verses: group.verses,
+                    tokens,
+                    mean_verse_length: ratio(tokens, group.verses),
+                    vocabulary: counts.len(),
+                    hapax_legomena: counts.values().filter(|&&count| count == 1).count(),
+                    type_token_ratio: ratio(counts.len(), tokens),
+                }
+            })
+            .collect()
+    }
+
+    /// Write the statistics as TSV, with a header line and ratios rounded to four decimals,
+    /// or as a JSON array of objects
+    pub fn write<W: Write>(&self, mut writer: W, format: OutputFormat) -> Result<(), ParserError> {
+        let groups = self.groups();
+        match format {
+            OutputFormat::Tsv => {
+                let chapter = if self.per_chapter { "\tchapter" } else { "" };
+                writeln!(
+                    writer,
+                    "book{chapter}\tverses\ttokens\tmean_verse_length\tvocabulary\thapax_legomena\ttype_token_ratio"
+                )?;
+                for group in groups {
+                    write!(writer, "{}\t", group.book)?;
+                    if let Some(chapter) = group.chapter {
+                        write!(writer, "{chapter}\t")?;
+                    }
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{:.4}\t{}\t{}\t{:.4}",
+                        group.verses,
+                        group.tokens,
+                        group.mean_verse_length,
+                        group.vocabulary,
+                        group.hapax_legomena,
+                        group.type_token_ratio
+                    )?;
+                }
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, &groups)
+                    .map_err(|e| ParserError::ParseError(e.to_string()))?;
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frequencies() {
+        let verse = |text: &str| Verse {
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let verses = [
+            verse("In the beginning God created the heavens and the earth."),
+            verse("The earth was formless and empty, “and darkness”"),
+        ];
+        let count = |tokenizer| {
+            let mut frequencies = Frequencies::new(tokenizer);
+            for verse in &verses {
+                frequencies.add(verse);
+            }
+            let mut tsv = Vec::new();
+            frequencies.write_tsv(&mut tsv).unwrap();
+            String::from_utf8(tsv).unwrap()
+        };
+
+        let plain = count(Tokenizer::default());
+        assert!(plain.starts_with("the\t3\nand\t2\nGod\t1\n"));
+        assert!(plain.contains("earth.\t1\n") && plain.contains("“and\t1\n"));
+
+        let normalized = count(Tokenizer {
+            lowercase: true,
+            strip_punctuation: true,
+        });
+        assert!(normalized.starts_with("the\t4\nand\t3\nearth\t2\n"));
+        assert!(!normalized.contains('“') && !normalized.contains(','));
+    }
+
+    #[test]
+    fn test_concordance() {
+        let verse = |chapter: &str, number: &str, text: &str| Verse {
+            book: "GEN".to_string(),
+            chapter: chapter.to_string(),
+            verse: number.to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let mut concordance = Concordance::new(Tokenizer {
+            lowercase: true,
+            strip_punctuation: true,
+        });
+        concordance.add(&verse(
+            "1",
+            "1",
+            "In the beginning God created the heavens and the earth.",
+        ));
+        concordance.add(&verse("1", "2", "The earth was formless and empty."));
+        let references: Vec<String> = concordance
+            .references("the")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(references, ["GEN.1.1", "GEN.1.2"]);
+
+        let mut tsv = Vec::new();
+        concordance.write(&mut tsv, OutputFormat::Tsv, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "and\t2\tGEN.1.1, GEN.1.2\nearth\t2\tGEN.1.1, GEN.1.2\nthe\t4\tGEN.1.1, GEN.1.2\n"
+        );
+        let mut json = Vec::new();
+        concordance.write(&mut json, OutputFormat::Json, 4).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[0]["word"], "the");
+        assert_eq!(json[0]["references"][1]["verse"], 2);
+    }
+
+    #[test]
+    fn test_ngrams() {
+        let verse = |book: &str, text: &str| Verse {
+            book: book.to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let mut frequencies = Frequencies::new(Tokenizer::default())
+            .ngrams(parse_ngram_sizes("2..3").unwrap())
+            .per_book(true);
+        frequencies.add(&verse("GEN", "the earth was"));
+        frequencies.add(&verse("GEN", "the earth"));
+        frequencies.add(&verse("EXO", "the names"));
+        assert_eq!(
+            frequencies.counts(),
+            [
+                ("GEN", "the earth", 2),
+                ("GEN", "earth was", 1),
+                ("GEN", "earth was the", 1),
+                ("GEN", "the earth was", 1),
+                ("GEN", "was the", 1),
+                ("GEN", "was the earth", 1),
+                ("EXO", "the names", 1)
+            ]
+        );
+        assert_eq!(parse_ngram_sizes("3").unwrap(), 3..=3);
+        assert_eq!(parse_ngram_sizes("1..=2").unwrap(), 1..=2);
+        assert!(parse_ngram_sizes("4..2").is_err());
+        assert!(parse_ngram_sizes("0").is_err());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let verse = |book: &str, chapter: &str, text: &str| Verse {
+            book: book.to_string(),
+            chapter: chapter.to_string(),
+            text: text.to_string(),
+            ..Verse::default()
+        };
+        let verses = [
+            verse("JHN", "11", "Jesus wept."),
+            verse("JHN", "11", "Then the Jews said, Behold how he loved him!"),
+            verse("JHN", "12", "Then Jesus six days before the passover"),
+            verse("JUD", "1", "Jude, the servant of Jesus Christ"),
+        ];
+        let tokenizer = Tokenizer {
+            lowercase: true,
+            strip_punctuation: true,
+        };
+        let mut statistics = Statistics::new(tokenizer);
+        for verse in &verses {
+            statistics.add(verse);
+        }
+        let groups = statistics.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            (groups[0].book, groups[0].verses, groups[0].tokens),
+            ("JHN", 3, 18)
+        );
+        assert_eq!((groups[0].vocabulary, groups[0].hapax_legomena), (15, 12));
+        assert!((groups[0].mean_verse_length - 6.0).abs() < 1e-9);
+
+        let mut statistics = Statistics::new(tokenizer).per_chapter(true);
+        for verse in &verses {
+            statistics.add(verse);
+        }
+        let mut tsv = Vec::new();
+        statistics.write(&mut tsv, OutputFormat::Tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().count(), 4);
+        assert_eq!(
+            tsv.lines().nth(1),
+            Some("JHN\t11\t2\t11\t5.5000\t11\t11\t1.0000")
+        );
+    }
+}
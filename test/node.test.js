@@ -0,0 +1,50 @@
+// Tests of the Node.js addon, loaded through package.json as an installed package would be.
+// Build it first with `npm run build`.
+
+const assert = require("node:assert");
+const fs = require("node:fs");
+const os = require("node:os");
+const path = require("node:path");
+const test = require("node:test");
+const { convert, VerseReader } = require("..");
+
+const USFX = `<usfx><book id="GEN"><c id="1"/><s>The Creation</s>
+<p><v bcv="GEN.1.1"/>In the beginning <nd>God</nd><ve/>
+<v bcv="GEN.1.2"/>The earth was formless.<ve/></p></book></usfx>`;
+
+test("convert writes rows", () => {
+  assert.strictEqual(
+    convert(USFX),
+    "GEN\t1\t1\tIn the beginning God\nGEN\t1\t2\tThe earth was formless.\n",
+  );
+  assert.strictEqual(
+    convert(USFX, { granularity: "chapter", divineName: "uppercase" }),
+    "GEN\t1\tIn the beginning GOD The earth was formless.\n",
+  );
+});
+
+test("convert rejects unknown options", () => {
+  assert.throws(() => convert(USFX, { granularty: "chapter" }), /granularty/);
+  assert.throws(() => convert(USFX, { granularity: "page" }), Error);
+});
+
+test("VerseReader hands out verses one at a time", () => {
+  const reader = new VerseReader(USFX);
+  const verse = reader.next();
+  assert.strictEqual(verse.book, "GEN");
+  assert.strictEqual(verse.verse, "1");
+  assert.strictEqual(verse.section, "The Creation");
+  assert.strictEqual(reader.next().text, "The earth was formless.");
+  assert.strictEqual(reader.next(), null);
+  assert.deepStrictEqual(reader.warnings, []);
+});
+
+test("VerseReader streams a file to a callback", () => {
+  const file = path.join(fs.mkdtempSync(path.join(os.tmpdir(), "usfx-")), "gen.xml");
+  fs.writeFileSync(file, USFX);
+  const verses = [];
+  const count = VerseReader.open(file).forEach((verse) => verses.push(verse.verse));
+  assert.strictEqual(count, 2);
+  assert.deepStrictEqual(verses, ["1", "2"]);
+  assert.throws(() => VerseReader.open(`${file}.missing`), Error);
+});